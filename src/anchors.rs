@@ -0,0 +1,246 @@
+//! Text anchors for inline comments.
+//!
+//! An anchor quotes a range of a document version plus a little context on
+//! either side. Offsets are in Unicode characters (not bytes). When the
+//! document changes, anchors are carried forward through a character diff and,
+//! failing that, re-found by searching for the quote.
+
+/// Characters of context captured on each side when the client sends none.
+pub const CONTEXT_CHARS: usize = 32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anchor {
+    pub quote: String,
+    pub prefix: String,
+    pub suffix: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+fn char_len(s: &str) -> usize {
+    s.chars().count()
+}
+
+fn char_slice(chars: &[char], start: usize, end: usize) -> String {
+    chars[start.min(chars.len())..end.min(chars.len())]
+        .iter()
+        .collect()
+}
+
+/// Character offsets of every occurrence of `quote` in `content`.
+fn occurrences(chars: &[char], quote: &[char]) -> Vec<usize> {
+    if quote.is_empty() || quote.len() > chars.len() {
+        return Vec::new();
+    }
+    (0..=chars.len() - quote.len())
+        .filter(|&i| chars[i..i + quote.len()] == *quote)
+        .collect()
+}
+
+// Helper: how many characters of context agree (prefix read backwards, suffix forwards)
+fn context_score(chars: &[char], start: usize, end: usize, prefix: &str, suffix: &str) -> usize {
+    let before = prefix
+        .chars()
+        .rev()
+        .zip(chars[..start].iter().rev())
+        .take_while(|(a, b)| a == *b)
+        .count();
+    let after = suffix
+        .chars()
+        .zip(chars[end..].iter())
+        .take_while(|(a, b)| a == *b)
+        .count();
+    before + after
+}
+
+/// Find `quote` in `content`, preferring the occurrence whose surrounding text
+/// best matches `prefix`/`suffix`, then the one closest to `hint`.
+pub fn find_quote(
+    content: &str,
+    quote: &str,
+    prefix: &str,
+    suffix: &str,
+    hint: Option<usize>,
+) -> Option<(usize, usize)> {
+    let chars: Vec<char> = content.chars().collect();
+    let quote_chars: Vec<char> = quote.chars().collect();
+    occurrences(&chars, &quote_chars)
+        .into_iter()
+        .map(|start| {
+            let end = start + quote_chars.len();
+            let score = context_score(&chars, start, end, prefix, suffix);
+            let distance = hint.map(|h| start.abs_diff(h)).unwrap_or(0);
+            (start, end, score, distance)
+        })
+        .max_by(|a, b| a.2.cmp(&b.2).then(b.3.cmp(&a.3)))
+        .map(|(start, end, _, _)| (start, end))
+}
+
+/// Build an anchor for a new comment. Explicit offsets win when they match
+/// the quote; otherwise the quote is searched for. Missing context is filled
+/// in from the document.
+pub fn locate(
+    content: &str,
+    quote: &str,
+    prefix: Option<&str>,
+    suffix: Option<&str>,
+    start: Option<usize>,
+) -> Option<Anchor> {
+    if quote.is_empty() {
+        return None;
+    }
+    let chars: Vec<char> = content.chars().collect();
+    let quote_len = char_len(quote);
+
+    let explicit = start.filter(|&s| char_slice(&chars, s, s + quote_len) == quote);
+    let (start, end) = match explicit {
+        Some(s) => (s, s + quote_len),
+        None => find_quote(
+            content,
+            quote,
+            prefix.unwrap_or(""),
+            suffix.unwrap_or(""),
+            start,
+        )?,
+    };
+
+    Some(Anchor {
+        quote: quote.to_string(),
+        prefix: prefix
+            .map(|p| p.to_string())
+            .unwrap_or_else(|| char_slice(&chars, start.saturating_sub(CONTEXT_CHARS), start)),
+        suffix: suffix
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| char_slice(&chars, end, end + CONTEXT_CHARS)),
+        start,
+        end,
+    })
+}
+
+/// Map a character offset in `old` to the corresponding offset in `new`.
+fn map_offset(ops: &[similar::DiffOp], offset: usize) -> usize {
+    for op in ops {
+        let (old_start, old_len, new_start, new_len) = match *op {
+            similar::DiffOp::Equal {
+                old_index,
+                new_index,
+                len,
+            } => (old_index, len, new_index, len),
+            similar::DiffOp::Delete {
+                old_index,
+                old_len,
+                new_index,
+            } => (old_index, old_len, new_index, 0),
+            similar::DiffOp::Insert {
+                old_index,
+                new_index,
+                new_len,
+            } => (old_index, 0, new_index, new_len),
+            similar::DiffOp::Replace {
+                old_index,
+                old_len,
+                new_index,
+                new_len,
+            } => (old_index, old_len, new_index, new_len),
+        };
+        if offset < old_start + old_len || (old_len == 0 && offset == old_start) {
+            return match op {
+                similar::DiffOp::Equal { .. } => new_start + (offset - old_start),
+                // Offsets inside removed/replaced text snap to the start of the replacement
+                _ => {
+                    new_start
+                        + if new_len == 0 {
+                            0
+                        } else {
+                            (offset - old_start).min(new_len)
+                        }
+                }
+            };
+        }
+    }
+    // Past the end: keep the same distance from the end of the text
+    ops.last()
+        .map(|op| {
+            let r = op.new_range();
+            let o = op.old_range();
+            r.end + offset.saturating_sub(o.end)
+        })
+        .unwrap_or(offset)
+}
+
+/// Carry an anchor from `old` content to `new` content. Returns the new
+/// offsets, or None if the quoted text no longer exists (orphaned).
+pub fn reanchor(old: &str, new: &str, anchor: &Anchor) -> Option<(usize, usize)> {
+    let new_chars: Vec<char> = new.chars().collect();
+    let quote_len = char_len(&anchor.quote);
+
+    let diff = similar::TextDiff::configure()
+        .timeout(std::time::Duration::from_millis(200))
+        .diff_chars(old, new);
+    let mapped = map_offset(diff.ops(), anchor.start);
+    if char_slice(&new_chars, mapped, mapped + quote_len) == anchor.quote {
+        return Some((mapped, mapped + quote_len));
+    }
+
+    find_quote(
+        new,
+        &anchor.quote,
+        &anchor.prefix,
+        &anchor.suffix,
+        Some(mapped),
+    )
+}
+
+/// Replace the characters in `start..end` of `content` with `replacement`.
+pub fn replace_range(content: &str, start: usize, end: usize, replacement: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    let mut out = char_slice(&chars, 0, start);
+    out.push_str(replacement);
+    out.push_str(&char_slice(&chars, end, chars.len()));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn locate_fills_context_and_prefers_explicit_offset() {
+        let a = locate("one fish two fish", "fish", None, None, Some(13)).unwrap();
+        assert_eq!((a.start, a.end), (13, 17));
+        assert_eq!(a.prefix, "one fish two ");
+        assert_eq!(a.suffix, "");
+
+        // Context picks the second occurrence
+        let a = locate("one fish two fish", "fish", Some("two "), None, None).unwrap();
+        assert_eq!(a.start, 13);
+
+        assert!(locate("one fish", "cat", None, None, None).is_none());
+    }
+
+    #[test]
+    fn reanchor_follows_edits_before_the_quote() {
+        let old = "Intro.\nThe cache is slow.\n";
+        let a = locate(old, "cache is slow", None, None, None).unwrap();
+        let new = "A longer intro paragraph.\nThe cache is slow.\n";
+        let (s, e) = reanchor(old, new, &a).unwrap();
+        assert_eq!(
+            new.chars().skip(s).take(e - s).collect::<String>(),
+            "cache is slow"
+        );
+    }
+
+    #[test]
+    fn reanchor_orphans_removed_text() {
+        let old = "Keep this. Remove that.";
+        let a = locate(old, "Remove that", None, None, None).unwrap();
+        assert!(reanchor(old, "Keep this.", &a).is_none());
+    }
+
+    #[test]
+    fn offsets_are_characters() {
+        let a = locate("héllo wörld", "wörld", None, None, None).unwrap();
+        assert_eq!((a.start, a.end), (6, 11));
+        assert_eq!(replace_range("héllo wörld", 6, 11, "there"), "héllo there");
+    }
+}
@@ -0,0 +1,386 @@
+//! Files attached to a workspace: images, diagrams, PDFs.
+//!
+//! Bytes live on disk under their SHA-256 (`<dir>/ab/abcdef…`), so the same
+//! file uploaded twice is stored once. The `attachments` table maps ids to
+//! blobs with a filename and a content type sniffed from the bytes. Markdown
+//! refers to an attachment as `attachment:<id>`, e.g. `![diagram](attachment:<id>)`;
+//! rendering rewrites that to the URL the attachment is served from.
+
+use rocket::http::{ContentType, Header};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+/// URL scheme for attachment references in markdown.
+pub const SCHEME: &str = "attachment:";
+
+const DEFAULT_MAX_BYTES: usize = 10 * 1024 * 1024;
+
+/// Content-addressed blob storage for attachment bytes.
+#[derive(Clone)]
+pub struct BlobStore {
+    dir: PathBuf,
+    /// Largest accepted upload.
+    pub max_bytes: usize,
+}
+
+impl BlobStore {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: usize) -> Self {
+        BlobStore {
+            dir: dir.into(),
+            max_bytes,
+        }
+    }
+
+    /// `ATTACHMENTS_DIR` (default `attachments`) and `ATTACHMENT_MAX_BYTES`
+    /// (default 10 MiB).
+    pub fn from_env() -> Self {
+        let dir = std::env::var("ATTACHMENTS_DIR").unwrap_or_else(|_| "attachments".to_string());
+        let max_bytes = std::env::var("ATTACHMENT_MAX_BYTES")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_MAX_BYTES);
+        BlobStore::new(dir, max_bytes)
+    }
+
+    fn path_for(&self, sha256: &str) -> PathBuf {
+        self.dir.join(&sha256[..2]).join(sha256)
+    }
+
+    /// Store `bytes` and return their SHA-256, and whether the blob was
+    /// already stored. A stored blob's mtime is refreshed, so GC treats it as
+    /// fresh until the new attachment row is committed.
+    pub fn put(&self, bytes: &[u8]) -> io::Result<(String, bool)> {
+        let sha256 = hex::encode(Sha256::digest(bytes));
+        let path = self.path_for(&sha256);
+        match std::fs::File::options().write(true).open(&path) {
+            Ok(file) => {
+                file.set_modified(SystemTime::now())?;
+                return Ok((sha256, true));
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let parent = path.parent().unwrap_or(&self.dir);
+        std::fs::create_dir_all(parent)?;
+        // Write beside the final name and rename, so readers never see a partial blob
+        let partial = parent.join(format!(".{}.{}", sha256, uuid::Uuid::new_v4()));
+        std::fs::write(&partial, bytes)?;
+        std::fs::rename(&partial, &path)?;
+        Ok((sha256, false))
+    }
+
+    pub fn get(&self, sha256: &str) -> io::Result<Vec<u8>> {
+        if !is_sha256(sha256) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a blob hash"));
+        }
+        std::fs::read(self.path_for(sha256))
+    }
+
+    /// Open a blob for streaming.
+    pub fn open(&self, sha256: &str) -> io::Result<std::fs::File> {
+        if !is_sha256(sha256) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "not a blob hash"));
+        }
+        std::fs::File::open(self.path_for(sha256))
+    }
+
+    /// Delete a blob; returns its size, or `None` if it was not stored.
+    pub fn remove(&self, sha256: &str) -> io::Result<Option<u64>> {
+        if !is_sha256(sha256) {
+            return Ok(None);
+        }
+        let path = self.path_for(sha256);
+        match std::fs::metadata(&path) {
+            Ok(meta) => {
+                std::fs::remove_file(&path)?;
+                Ok(Some(meta.len()))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Delete a blob unless it was written or reused within `min_age`.
+    pub fn remove_settled(&self, sha256: &str, min_age: Duration) -> io::Result<Option<u64>> {
+        if !is_sha256(sha256) {
+            return Ok(None);
+        }
+        match std::fs::metadata(self.path_for(sha256)) {
+            Ok(meta) if meta.modified()?.elapsed().unwrap_or_default() < min_age => Ok(None),
+            Ok(_) => self.remove(sha256),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Hash and size of every stored blob last written at least `min_age` ago.
+    fn settled(&self, min_age: Duration) -> io::Result<Vec<(String, u64)>> {
+        let mut found = Vec::new();
+        let shards = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(found),
+            Err(e) => return Err(e),
+        };
+        for shard in shards {
+            let shard = shard?.path();
+            if !shard.is_dir() {
+                continue;
+            }
+            for entry in std::fs::read_dir(&shard)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                let meta = entry.metadata()?;
+                let age = meta.modified()?.elapsed().unwrap_or_default();
+                if is_sha256(&name) && age >= min_age {
+                    found.push((name, meta.len()));
+                }
+            }
+        }
+        Ok(found)
+    }
+
+    /// Blobs not in `keep`, with their sizes. Blobs written within `min_age`
+    /// are left out: their attachment row may not be committed yet.
+    pub fn orphans(
+        &self,
+        keep: &HashSet<String>,
+        min_age: Duration,
+    ) -> io::Result<Vec<(String, u64)>> {
+        let mut found = self.settled(min_age)?;
+        found.retain(|(sha256, _)| !keep.contains(sha256));
+        Ok(found)
+    }
+}
+
+fn is_sha256(s: &str) -> bool {
+    s.len() == 64
+        && s.bytes()
+            .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// Declared types kept for UTF-8 text uploads; other text is served as `text/plain`.
+const TEXT_TYPES: [&str; 5] = [
+    "text/plain",
+    "text/markdown",
+    "text/csv",
+    "application/json",
+    "application/yaml",
+];
+
+/// The content type to store for an upload. Known binary formats are
+/// recognised from their leading bytes and win over the declared type;
+/// HTML and other unrecognised text is served as plain text.
+pub fn sniff(bytes: &[u8], declared: Option<&str>) -> &'static str {
+    const MAGIC: [(&[u8], &str); 7] = [
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+        (b"PK\x03\x04", "application/zip"),
+        (b"\x1f\x8b", "application/gzip"),
+    ];
+    if let Some((_, t)) = MAGIC.iter().find(|(m, _)| bytes.starts_with(m)) {
+        return t;
+    }
+    if bytes.len() >= 12 && &bytes[..4] == b"RIFF" && &bytes[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+    let Ok(text) = std::str::from_utf8(bytes) else {
+        return "application/octet-stream";
+    };
+    let head: String = text
+        .trim_start_matches('\u{feff}')
+        .chars()
+        .take(1024)
+        .collect::<String>()
+        .to_lowercase();
+    if head.contains("<svg") {
+        return "image/svg+xml";
+    }
+    let declared = declared
+        .and_then(|d| d.split(';').next())
+        .map(|d| d.trim().to_lowercase());
+    TEXT_TYPES
+        .iter()
+        .find(|t| declared.as_deref() == Some(**t))
+        .copied()
+        .unwrap_or("text/plain")
+}
+
+/// A filename safe to store and echo back: the last path component, without
+/// control characters or quotes.
+pub fn clean_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or("");
+    let cleaned: String = base
+        .chars()
+        .filter(|c| !c.is_control() && *c != '"')
+        .collect::<String>()
+        .trim()
+        .to_string();
+    if cleaned.is_empty() || cleaned == "." || cleaned == ".." {
+        "file".to_string()
+    } else {
+        cleaned
+    }
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '-'
+}
+
+/// Ids of every `attachment:<id>` reference in `text`, in order. Scans raw
+/// text rather than parsed markdown, so references in code count too.
+pub fn references(text: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    for (start, _) in text.match_indices(SCHEME) {
+        let rest = &text[start + SCHEME.len()..];
+        let id: String = rest.chars().take_while(|c| is_id_char(*c)).collect();
+        if !id.is_empty() {
+            found.push(id);
+        }
+    }
+    found
+}
+
+/// Where an attachment is served.
+pub fn url(workspace_id: &str, id: &str) -> String {
+    format!("/api/v1/workspaces/{}/attachments/{}", workspace_id, id)
+}
+
+/// Resolve an `attachment:<id>` link destination; other URLs are `None`.
+pub fn resolve_url(workspace_id: &str, dest: &str) -> Option<String> {
+    let id = dest.strip_prefix(SCHEME)?;
+    (!id.is_empty() && id.chars().all(is_id_char)).then(|| url(workspace_id, id))
+}
+
+/// An attachment's bytes, served with its stored type. Blobs never change,
+/// so responses are cacheable forever and carry the hash as ETag.
+pub struct AttachmentFile {
+    pub bytes: Vec<u8>,
+    pub content_type: String,
+    pub filename: String,
+    pub sha256: String,
+}
+
+impl<'r> Responder<'r, 'static> for AttachmentFile {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        let content_type =
+            ContentType::parse_flexible(&self.content_type).unwrap_or(ContentType::Binary);
+        let inline = content_type.top() == "image"
+            || content_type == ContentType::PDF
+            || content_type == ContentType::Plain;
+        let disposition = format!(
+            "{}; filename=\"{}\"",
+            if inline { "inline" } else { "attachment" },
+            self.filename
+        );
+        Response::build()
+            .header(content_type)
+            .header(Header::new("Content-Disposition", disposition))
+            .header(Header::new("ETag", format!("\"{}\"", self.sha256)))
+            .header(Header::new(
+                "Cache-Control",
+                "public, max-age=31536000, immutable",
+            ))
+            .header(Header::new("X-Content-Type-Options", "nosniff"))
+            // SVGs can carry scripts; never let them run
+            .header(Header::new(
+                "Content-Security-Policy",
+                "default-src 'none'; style-src 'unsafe-inline'; sandbox",
+            ))
+            .sized_body(self.bytes.len(), io::Cursor::new(self.bytes))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sniffs_content_types() {
+        assert_eq!(
+            sniff(b"\x89PNG\r\n\x1a\n....", Some("text/plain")),
+            "image/png"
+        );
+        assert_eq!(sniff(b"RIFF\0\0\0\0WEBPVP8 ", None), "image/webp");
+        assert_eq!(
+            sniff(b"<?xml version=\"1.0\"?>\n<svg xmlns=\"x\"/>", None),
+            "image/svg+xml"
+        );
+        assert_eq!(sniff(b"<html><script>", Some("text/html")), "text/plain");
+        assert_eq!(
+            sniff(b"a,b\n1,2\n", Some("text/csv; charset=utf-8")),
+            "text/csv"
+        );
+        assert_eq!(
+            sniff(&[0, 159, 146, 150], Some("image/png")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn finds_references_and_cleans_names() {
+        let md = "![d](attachment:ab-12) and [spec](attachment:cd34 \"t\") attachment: x";
+        assert_eq!(references(md), vec!["ab-12", "cd34"]);
+        assert_eq!(
+            resolve_url("w", "attachment:ab-12").unwrap(),
+            url("w", "ab-12")
+        );
+        assert_eq!(resolve_url("w", "https://example.com"), None);
+        assert_eq!(clean_filename("../../etc/pa\"ss\nwd"), "passwd");
+        assert_eq!(clean_filename("C:\\shots\\diagram.png"), "diagram.png");
+        assert_eq!(clean_filename(".."), "file");
+    }
+
+    #[test]
+    fn stores_blobs_by_hash() {
+        let dir = std::env::temp_dir().join(format!("blobs-{}", uuid::Uuid::new_v4()));
+        let store = BlobStore::new(&dir, 1024);
+        let (sha, existed) = store.put(b"hello").unwrap();
+        assert!(!existed);
+        assert_eq!(store.put(b"hello").unwrap(), (sha.clone(), true));
+        assert_eq!(store.get(&sha).unwrap(), b"hello");
+        let (other, _) = store.put(b"bye").unwrap();
+        let keep = HashSet::from([sha.clone()]);
+        assert!(store
+            .orphans(&keep, Duration::from_secs(3600))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store.orphans(&keep, Duration::ZERO).unwrap(),
+            vec![(other.clone(), 3)]
+        );
+        // Storing the bytes again makes the blob fresh
+        let old = SystemTime::now() - Duration::from_secs(7200);
+        let blob = std::fs::File::options()
+            .write(true)
+            .open(store.path_for(&other));
+        blob.unwrap().set_modified(old).unwrap();
+        assert_eq!(
+            store.orphans(&keep, Duration::from_secs(3600)).unwrap(),
+            vec![(other.clone(), 3)]
+        );
+        store.put(b"bye").unwrap();
+        assert!(store
+            .orphans(&keep, Duration::from_secs(3600))
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            store
+                .remove_settled(&other, Duration::from_secs(3600))
+                .unwrap(),
+            None
+        );
+        assert_eq!(store.remove(&other).unwrap(), Some(3));
+        assert!(store.get(&other).is_err());
+        assert_eq!(store.remove(&other).unwrap(), None);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -42,6 +42,35 @@ impl<'r> FromRequest<'r> for WorkspaceToken {
     }
 }
 
+/// Extracts a commenter token: the secret returned when a comment is created,
+/// which lets its author edit or delete it without the manage key.
+/// Checks the X-Comment-Token header, then the ?comment_token= query param.
+pub struct CommentToken(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for CommentToken {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        if let Some(token) = req.headers().get_one("X-Comment-Token") {
+            let token = token.trim();
+            if !token.is_empty() {
+                return Outcome::Success(CommentToken(token.to_string()));
+            }
+        }
+
+        if let Some(query) = req.uri().query() {
+            for (key, value) in query.segments() {
+                if key == "comment_token" && !value.is_empty() {
+                    return Outcome::Success(CommentToken(value.to_string()));
+                }
+            }
+        }
+
+        Outcome::Error((Status::Unauthorized, "Missing comment token"))
+    }
+}
+
 /// Hash a manage key for storage/comparison.
 pub fn hash_key(key: &str) -> String {
     let mut hasher = Sha256::new();
@@ -54,6 +83,14 @@ pub fn generate_key() -> String {
     format!("adoc_{}", uuid::Uuid::new_v4().to_string().replace("-", ""))
 }
 
+/// Generate a commenter token for a new comment.
+pub fn generate_comment_token() -> String {
+    format!(
+        "adoc_c_{}",
+        uuid::Uuid::new_v4().to_string().replace("-", "")
+    )
+}
+
 /// Verify a token against a stored hash.
 pub fn verify_key(token: &str, stored_hash: &str) -> bool {
     hash_key(token) == stored_hash
@@ -1,9 +1,17 @@
+use crate::anchors::Anchor;
+use crate::doctypes::DocumentType;
+use crate::events::SseEvent;
+use crate::links::WikiLink;
+use crate::templates::Template;
 use rusqlite::{params, Connection};
 use serde_json::Value;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 
+/// Shared SQLite handle. Cloning is cheap and shares the same connection,
+/// so background workers can hold their own handle.
+#[derive(Clone)]
 pub struct Db {
-    pub conn: Mutex<Connection>,
+    pub conn: Arc<Mutex<Connection>>,
 }
 
 impl Db {
@@ -19,7 +27,7 @@ impl Db {
             .expect("Failed to set pragmas");
 
         let db = Db {
-            conn: Mutex::new(conn),
+            conn: Arc::new(Mutex::new(conn)),
         };
         db.migrate();
         db
@@ -84,13 +92,233 @@ impl Db {
                 updated_at TEXT DEFAULT (datetime('now'))
             );
 
+            CREATE TABLE IF NOT EXISTS webhooks (
+                id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                url TEXT NOT NULL,
+                events TEXT DEFAULT '[]',
+                secret TEXT NOT NULL,
+                active INTEGER DEFAULT 1,
+                created_at TEXT DEFAULT (datetime('now')),
+                updated_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook_id TEXT NOT NULL REFERENCES webhooks(id) ON DELETE CASCADE,
+                event_type TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT DEFAULT 'pending',
+                attempts INTEGER DEFAULT 0,
+                response_status INTEGER,
+                last_error TEXT,
+                next_attempt_at TEXT DEFAULT (datetime('now')),
+                delivered_at TEXT,
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS suggestions (
+                id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                author_name TEXT NOT NULL,
+                description TEXT DEFAULT '',
+                base_version INTEGER NOT NULL,
+                quote TEXT,
+                prefix TEXT DEFAULT '',
+                suffix TEXT DEFAULT '',
+                range_start INTEGER,
+                range_end INTEGER,
+                replacement TEXT,
+                proposed_content TEXT,
+                status TEXT DEFAULT 'pending',
+                reviewed_by TEXT,
+                review_reason TEXT,
+                applied_version INTEGER,
+                created_at TEXT DEFAULT (datetime('now')),
+                reviewed_at TEXT
+            );
+
+            CREATE TABLE IF NOT EXISTS document_approvals (
+                id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                version_number INTEGER NOT NULL,
+                reviewer TEXT NOT NULL,
+                decision TEXT NOT NULL,
+                comment TEXT DEFAULT '',
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS reactions (
+                id TEXT PRIMARY KEY,
+                document_id TEXT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                target_type TEXT NOT NULL,
+                target_id TEXT NOT NULL,
+                author_name TEXT NOT NULL,
+                reaction TEXT NOT NULL,
+                created_at TEXT DEFAULT (datetime('now')),
+                UNIQUE(target_type, target_id, author_name, reaction)
+            );
+
+            CREATE TABLE IF NOT EXISTS notifications (
+                id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                recipient TEXT NOT NULL COLLATE NOCASE,
+                kind TEXT NOT NULL DEFAULT 'mention',
+                document_id TEXT REFERENCES documents(id) ON DELETE CASCADE,
+                comment_id TEXT,
+                author_name TEXT DEFAULT '',
+                excerpt TEXT DEFAULT '',
+                read_at TEXT,
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS activity (
+                seq INTEGER PRIMARY KEY AUTOINCREMENT,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                event_type TEXT NOT NULL,
+                document_id TEXT,
+                actor TEXT,
+                data TEXT NOT NULL,
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS templates (
+                id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                slug TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT DEFAULT '',
+                title TEXT DEFAULT '',
+                content TEXT NOT NULL DEFAULT '',
+                tags TEXT DEFAULT '[]',
+                variables TEXT DEFAULT '[]',
+                created_at TEXT DEFAULT (datetime('now')),
+                updated_at TEXT DEFAULT (datetime('now')),
+                UNIQUE(workspace_id, slug)
+            );
+
+            CREATE TABLE IF NOT EXISTS document_types (
+                id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                slug TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT DEFAULT '',
+                schema TEXT NOT NULL DEFAULT '{}',
+                required_headings TEXT DEFAULT '[]',
+                created_at TEXT DEFAULT (datetime('now')),
+                updated_at TEXT DEFAULT (datetime('now')),
+                UNIQUE(workspace_id, slug)
+            );
+
+            CREATE TABLE IF NOT EXISTS document_links (
+                source_id TEXT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                workspace_id TEXT NOT NULL,
+                target_slug TEXT NOT NULL,
+                heading TEXT NOT NULL DEFAULT '',
+                PRIMARY KEY (source_id, target_slug, heading)
+            );
+
+            CREATE TABLE IF NOT EXISTS slug_history (
+                workspace_id TEXT NOT NULL,
+                old_slug TEXT NOT NULL,
+                document_id TEXT NOT NULL REFERENCES documents(id) ON DELETE CASCADE,
+                renamed_at TEXT DEFAULT (datetime('now')),
+                PRIMARY KEY (workspace_id, old_slug)
+            );
+
+            CREATE TABLE IF NOT EXISTS attachments (
+                id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                filename TEXT NOT NULL,
+                content_type TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                sha256 TEXT NOT NULL,
+                uploaded_by TEXT DEFAULT '',
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS git_mirrors (
+                workspace_id TEXT PRIMARY KEY REFERENCES workspaces(id) ON DELETE CASCADE,
+                synced_commit TEXT,
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS git_commits (
+                commit_id TEXT NOT NULL,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                document_id TEXT NOT NULL,
+                version_number INTEGER,
+                created_at TEXT DEFAULT (datetime('now'))
+            );
+
+            CREATE TABLE IF NOT EXISTS git_files (
+                document_id TEXT PRIMARY KEY,
+                workspace_id TEXT NOT NULL REFERENCES workspaces(id) ON DELETE CASCADE,
+                path TEXT NOT NULL
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_attachments_workspace ON attachments(workspace_id, sha256);
+            CREATE INDEX IF NOT EXISTS idx_git_commits_version ON git_commits(document_id, version_number);
+            CREATE INDEX IF NOT EXISTS idx_git_commits_commit ON git_commits(workspace_id, commit_id);
+            CREATE INDEX IF NOT EXISTS idx_attachments_sha256 ON attachments(sha256);
+            CREATE INDEX IF NOT EXISTS idx_document_links_target ON document_links(workspace_id, target_slug);
             CREATE INDEX IF NOT EXISTS idx_documents_workspace ON documents(workspace_id);
             CREATE INDEX IF NOT EXISTS idx_documents_slug ON documents(workspace_id, slug);
             CREATE INDEX IF NOT EXISTS idx_versions_document ON document_versions(document_id, version_number);
             CREATE INDEX IF NOT EXISTS idx_comments_document ON comments(document_id);
             CREATE INDEX IF NOT EXISTS idx_comments_parent ON comments(parent_id);
+            CREATE INDEX IF NOT EXISTS idx_suggestions_document ON suggestions(document_id, status);
+            CREATE INDEX IF NOT EXISTS idx_approvals_document ON document_approvals(document_id, version_number);
+            CREATE INDEX IF NOT EXISTS idx_reactions_document ON reactions(document_id, target_type);
+            CREATE INDEX IF NOT EXISTS idx_notifications_recipient ON notifications(workspace_id, recipient, read_at);
+            CREATE INDEX IF NOT EXISTS idx_activity_workspace ON activity(workspace_id, seq);
+            CREATE INDEX IF NOT EXISTS idx_webhooks_workspace ON webhooks(workspace_id);
+            CREATE INDEX IF NOT EXISTS idx_deliveries_webhook ON webhook_deliveries(webhook_id, created_at);
+            CREATE INDEX IF NOT EXISTS idx_deliveries_pending ON webhook_deliveries(status, next_attempt_at);
             "
         ).expect("Failed to run migrations");
+
+        // Columns added after the initial schema (existing databases need ALTER TABLE)
+        for (table, column, decl) in [
+            ("comments", "anchor_quote", "TEXT"),
+            ("comments", "anchor_prefix", "TEXT DEFAULT ''"),
+            ("comments", "anchor_suffix", "TEXT DEFAULT ''"),
+            ("comments", "anchor_start", "INTEGER"),
+            ("comments", "anchor_end", "INTEGER"),
+            ("comments", "anchor_version", "INTEGER"),
+            ("comments", "anchor_status", "TEXT"),
+            ("workspaces", "workflow", "TEXT"),
+            (
+                "workspaces",
+                "allow_anonymous_comments",
+                "INTEGER DEFAULT 1",
+            ),
+            ("comments", "edit_token_hash", "TEXT"),
+            ("workspaces", "comment_policy", "TEXT"),
+            ("comments", "moderation_status", "TEXT DEFAULT 'approved'"),
+            ("documents", "metadata", "TEXT DEFAULT '{}'"),
+            ("documents", "doc_type", "TEXT"),
+            ("documents", "fields", "TEXT DEFAULT '{}'"),
+        ] {
+            add_column_if_missing(&conn, table, column, decl);
+        }
+    }
+}
+
+fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) {
+    let exists = conn
+        .prepare(&format!(
+            "SELECT 1 FROM pragma_table_info('{}') WHERE name = ?1",
+            table
+        ))
+        .and_then(|mut stmt| stmt.exists(params![column]))
+        .expect("Failed to inspect schema");
+    if !exists {
+        conn.execute_batch(&format!(
+            "ALTER TABLE {} ADD COLUMN {} {}",
+            table, column, decl
+        ))
+        .unwrap_or_else(|e| panic!("Failed to add {}.{}: {}", table, column, e));
     }
 }
 
@@ -115,7 +343,7 @@ pub fn create_workspace(
 pub fn get_workspace(db: &Db, id: &str) -> Result<Option<serde_json::Value>, String> {
     let conn = db.conn.lock().unwrap();
     let mut stmt = conn.prepare(
-        "SELECT id, name, description, is_public, manage_key_hash, created_at, updated_at FROM workspaces WHERE id = ?1"
+        "SELECT id, name, description, is_public, manage_key_hash, created_at, updated_at, allow_anonymous_comments FROM workspaces WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
 
     let result = stmt
@@ -128,6 +356,7 @@ pub fn get_workspace(db: &Db, id: &str) -> Result<Option<serde_json::Value>, Str
                 "manage_key_hash": row.get::<_, String>(4)?,
                 "created_at": row.get::<_, String>(5)?,
                 "updated_at": row.get::<_, String>(6)?,
+                "allow_anonymous_comments": row.get::<_, Option<i32>>(7)?.unwrap_or(1) != 0,
             }))
         })
         .optional()
@@ -167,6 +396,7 @@ pub fn update_workspace(
     name: Option<&str>,
     description: Option<&str>,
     is_public: Option<bool>,
+    allow_anonymous_comments: Option<bool>,
 ) -> Result<bool, String> {
     let conn = db.conn.lock().unwrap();
     let mut sets = Vec::new();
@@ -184,6 +414,10 @@ pub fn update_workspace(
         sets.push("is_public = ?");
         values.push(Box::new(p as i32));
     }
+    if let Some(a) = allow_anonymous_comments {
+        sets.push("allow_anonymous_comments = ?");
+        values.push(Box::new(a as i32));
+    }
 
     if sets.is_empty() {
         return Ok(false);
@@ -216,11 +450,14 @@ pub fn create_document(
     status: &str,
     author_name: &str,
     word_count: i32,
+    metadata: &str,
+    doc_type: Option<&str>,
+    fields: &str,
 ) -> Result<(), String> {
     let conn = db.conn.lock().unwrap();
     conn.execute(
-        "INSERT INTO documents (id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, word_count) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
-        params![id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, word_count],
+        "INSERT INTO documents (id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, word_count, metadata, doc_type, fields) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, word_count, metadata, doc_type, fields],
     ).map_err(|e| e.to_string())?;
 
     // Create initial version (version 1)
@@ -240,7 +477,7 @@ pub fn get_document(
 ) -> Result<Option<serde_json::Value>, String> {
     let conn = db.conn.lock().unwrap();
     let mut stmt = conn.prepare(
-        "SELECT id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, locked_by, locked_at, lock_expires_at, word_count, created_at, updated_at FROM documents WHERE workspace_id = ?1 AND slug = ?2"
+        "SELECT id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, locked_by, locked_at, lock_expires_at, word_count, created_at, updated_at, metadata, doc_type, fields FROM documents WHERE workspace_id = ?1 AND slug = ?2"
     ).map_err(|e| e.to_string())?;
 
     let result = stmt
@@ -265,6 +502,9 @@ pub fn get_document(
                 "word_count": row.get::<_, i32>(13)?,
                 "created_at": row.get::<_, String>(14)?,
                 "updated_at": row.get::<_, String>(15)?,
+                "metadata": parse_metadata(row.get(16)?),
+                "doc_type": row.get::<_, Option<String>>(17)?,
+                "fields": parse_metadata(row.get(18)?),
             }))
         })
         .optional()
@@ -276,7 +516,7 @@ pub fn get_document(
 pub fn get_document_by_id(db: &Db, id: &str) -> Result<Option<serde_json::Value>, String> {
     let conn = db.conn.lock().unwrap();
     let mut stmt = conn.prepare(
-        "SELECT id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, locked_by, locked_at, lock_expires_at, word_count, created_at, updated_at FROM documents WHERE id = ?1"
+        "SELECT id, workspace_id, title, slug, content, content_html, summary, tags, status, author_name, locked_by, locked_at, lock_expires_at, word_count, created_at, updated_at, metadata, doc_type, fields FROM documents WHERE id = ?1"
     ).map_err(|e| e.to_string())?;
 
     let result = stmt
@@ -301,6 +541,9 @@ pub fn get_document_by_id(db: &Db, id: &str) -> Result<Option<serde_json::Value>
                 "word_count": row.get::<_, i32>(13)?,
                 "created_at": row.get::<_, String>(14)?,
                 "updated_at": row.get::<_, String>(15)?,
+                "metadata": parse_metadata(row.get(16)?),
+                "doc_type": row.get::<_, Option<String>>(17)?,
+                "fields": parse_metadata(row.get(18)?),
             }))
         })
         .optional()
@@ -309,21 +552,61 @@ pub fn get_document_by_id(db: &Db, id: &str) -> Result<Option<serde_json::Value>
     Ok(result)
 }
 
+/// Metadata or fields column text → JSON object (`{}` when unset or unreadable).
+fn parse_metadata(text: Option<String>) -> Value {
+    text.and_then(|t| serde_json::from_str(&t).ok())
+        .filter(Value::is_object)
+        .unwrap_or_else(|| serde_json::json!({}))
+}
+
+/// `AND EXISTS (...)` clauses for metadata filters, given as (JSON path, value)
+/// pairs. A filter matches when the value at the path equals the wanted value
+/// (case-insensitively), or, for arrays, when any element does. Parameters are
+/// appended to `values`; placeholders are numbered from `values.len() + 1`.
+fn metadata_filter_sql(
+    filters: &[(String, String)],
+    values: &mut Vec<Box<dyn rusqlite::types::ToSql>>,
+) -> String {
+    let mut sql = String::new();
+    for (path, wanted) in filters {
+        let n = values.len();
+        sql.push_str(&format!(
+            " AND EXISTS (SELECT 1 FROM json_each(documents.metadata, ?{}) \
+             WHERE (CASE type WHEN 'true' THEN 'true' WHEN 'false' THEN 'false' WHEN 'null' THEN 'null' \
+             ELSE CAST(value AS TEXT) END) = ?{} COLLATE NOCASE)",
+            n + 1,
+            n + 2
+        ));
+        values.push(Box::new(path.clone()));
+        values.push(Box::new(wanted.clone()));
+    }
+    sql
+}
+
+/// `metadata` filters are (JSON path, value) pairs, see [`metadata_filter_sql`].
 pub fn list_documents(
     db: &Db,
     workspace_id: &str,
     include_drafts: bool,
+    metadata: &[(String, String)],
 ) -> Result<Vec<serde_json::Value>, String> {
     let conn = db.conn.lock().unwrap();
-    let sql = if include_drafts {
-        "SELECT id, title, slug, summary, tags, status, author_name, word_count, created_at, updated_at FROM documents WHERE workspace_id = ?1 ORDER BY updated_at DESC"
+    let mut values: Vec<Box<dyn rusqlite::types::ToSql>> = vec![Box::new(workspace_id.to_string())];
+    let status_clause = if include_drafts {
+        ""
     } else {
-        "SELECT id, title, slug, summary, tags, status, author_name, word_count, created_at, updated_at FROM documents WHERE workspace_id = ?1 AND status = 'published' ORDER BY updated_at DESC"
+        " AND status = 'published'"
     };
+    let sql = format!(
+        "SELECT id, title, slug, summary, tags, status, author_name, word_count, created_at, updated_at, metadata, doc_type, fields FROM documents WHERE workspace_id = ?1{}{} ORDER BY updated_at DESC",
+        status_clause,
+        metadata_filter_sql(metadata, &mut values)
+    );
 
-    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let params: Vec<&dyn rusqlite::types::ToSql> = values.iter().map(|v| v.as_ref()).collect();
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
     let rows = stmt
-        .query_map(params![workspace_id], |row| {
+        .query_map(params.as_slice(), |row| {
             let tags_str: String = row.get(4)?;
             let tags: serde_json::Value =
                 serde_json::from_str(&tags_str).unwrap_or(serde_json::json!([]));
@@ -338,6 +621,9 @@ pub fn list_documents(
                 "word_count": row.get::<_, i32>(7)?,
                 "created_at": row.get::<_, String>(8)?,
                 "updated_at": row.get::<_, String>(9)?,
+                "metadata": parse_metadata(row.get(10)?),
+                "doc_type": row.get::<_, Option<String>>(11)?,
+                "fields": parse_metadata(row.get(12)?),
             }))
         })
         .map_err(|e| e.to_string())?;
@@ -362,6 +648,9 @@ pub fn update_document(
     author_name: Option<&str>,
     word_count: Option<i32>,
     change_description: Option<&str>,
+    metadata: Option<&str>,
+    doc_type: Option<Option<&str>>,
+    fields: Option<&str>,
 ) -> Result<bool, String> {
     let conn = db.conn.lock().unwrap();
 
@@ -421,6 +710,18 @@ pub fn update_document(
         sets.push("word_count = ?");
         values.push(Box::new(wc));
     }
+    if let Some(m) = metadata {
+        sets.push("metadata = ?");
+        values.push(Box::new(m.to_string()));
+    }
+    if let Some(t) = doc_type {
+        sets.push("doc_type = ?");
+        values.push(Box::new(t.map(|t| t.to_string())));
+    }
+    if let Some(f) = fields {
+        sets.push("fields = ?");
+        values.push(Box::new(f.to_string()));
+    }
 
     if sets.is_empty() {
         return Ok(false);
@@ -437,6 +738,64 @@ pub fn update_document(
     Ok(rows > 0)
 }
 
+/// Change a document's slug and remember the old one. The new slug stops
+/// redirecting to whichever document used to have it.
+pub fn rename_document(
+    db: &Db,
+    doc_id: &str,
+    workspace_id: &str,
+    old_slug: &str,
+    new_slug: &str,
+) -> Result<(), String> {
+    let mut conn = db.conn.lock().unwrap();
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "UPDATE documents SET slug = ?1, updated_at = datetime('now') WHERE id = ?2",
+        params![new_slug, doc_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM slug_history WHERE workspace_id = ?1 AND old_slug = ?2",
+        params![workspace_id, new_slug],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT OR REPLACE INTO slug_history (workspace_id, old_slug, document_id) VALUES (?1, ?2, ?3)",
+        params![workspace_id, old_slug, doc_id],
+    )
+    .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())
+}
+
+/// Ids of the workspace's documents, drafts included, in slug order.
+pub fn document_ids(db: &Db, workspace_id: &str) -> Result<Vec<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id FROM documents WHERE workspace_id = ?1 ORDER BY slug ASC")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// The document that used to have `slug`, if it was renamed.
+pub fn find_renamed_document(
+    db: &Db,
+    workspace_id: &str,
+    slug: &str,
+) -> Result<Option<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT document_id FROM slug_history WHERE workspace_id = ?1 AND old_slug = ?2",
+        params![workspace_id, slug],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
 pub fn delete_document(db: &Db, doc_id: &str) -> Result<bool, String> {
     let conn = db.conn.lock().unwrap();
     let rows = conn
@@ -445,278 +804,2137 @@ pub fn delete_document(db: &Db, doc_id: &str) -> Result<bool, String> {
     Ok(rows > 0)
 }
 
-// --- Version operations ---
+// --- Link operations ---
 
-pub fn list_versions(
+/// Replace the outgoing wiki-links recorded for a document.
+pub fn set_document_links(
     db: &Db,
-    doc_id: &str,
-    limit: i32,
-    offset: i32,
-) -> Result<Vec<serde_json::Value>, String> {
-    let conn = db.conn.lock().unwrap();
-    let mut stmt = conn.prepare(
-        "SELECT id, version_number, summary, author_name, change_description, word_count, created_at FROM document_versions WHERE document_id = ?1 ORDER BY version_number DESC LIMIT ?2 OFFSET ?3"
-    ).map_err(|e| e.to_string())?;
+    source_id: &str,
+    workspace_id: &str,
+    links: &[WikiLink],
+) -> Result<(), String> {
+    let mut conn = db.conn.lock().unwrap();
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM document_links WHERE source_id = ?1",
+        params![source_id],
+    )
+    .map_err(|e| e.to_string())?;
+    for link in links {
+        tx.execute(
+            "INSERT OR IGNORE INTO document_links (source_id, workspace_id, target_slug, heading) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                source_id,
+                workspace_id,
+                link.target,
+                link.heading.as_deref().unwrap_or("")
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())
+}
 
+/// Documents linking to `slug`, one entry per linked heading.
+pub fn list_backlinks(
+    db: &Db,
+    workspace_id: &str,
+    slug: &str,
+    include_drafts: bool,
+) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT d.id, d.title, d.slug, d.status, l.heading FROM document_links l \
+             JOIN documents d ON d.id = l.source_id \
+             WHERE l.workspace_id = ?1 AND l.target_slug = ?2 AND (?3 OR d.status = 'published') \
+             ORDER BY d.title ASC, l.heading ASC",
+        )
+        .map_err(|e| e.to_string())?;
     let rows = stmt
-        .query_map(params![doc_id, limit, offset], |row| {
+        .query_map(params![workspace_id, slug, include_drafts], |row| {
+            let heading: String = row.get(4)?;
             Ok(serde_json::json!({
                 "id": row.get::<_, String>(0)?,
-                "version_number": row.get::<_, i32>(1)?,
-                "summary": row.get::<_, String>(2)?,
-                "author_name": row.get::<_, String>(3)?,
-                "change_description": row.get::<_, String>(4)?,
-                "word_count": row.get::<_, i32>(5)?,
-                "created_at": row.get::<_, String>(6)?,
+                "title": row.get::<_, String>(1)?,
+                "slug": row.get::<_, String>(2)?,
+                "status": row.get::<_, String>(3)?,
+                "heading": if heading.is_empty() { None } else { Some(heading) },
             }))
         })
         .map_err(|e| e.to_string())?;
-
-    let mut versions = Vec::new();
-    for row in rows {
-        versions.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(versions)
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
 }
 
-pub fn get_version(
+/// Ids of documents with a wiki-link to `slug`.
+pub fn documents_linking_to(
     db: &Db,
-    doc_id: &str,
-    version_number: i32,
-) -> Result<Option<serde_json::Value>, String> {
+    workspace_id: &str,
+    slug: &str,
+) -> Result<Vec<String>, String> {
     let conn = db.conn.lock().unwrap();
-    let mut stmt = conn.prepare(
-        "SELECT id, version_number, content, content_html, summary, author_name, change_description, word_count, created_at FROM document_versions WHERE document_id = ?1 AND version_number = ?2"
-    ).map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(
+            "SELECT DISTINCT source_id FROM document_links WHERE workspace_id = ?1 AND target_slug = ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id, slug], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
 
-    let result = stmt
-        .query_row(params![doc_id, version_number], |row| {
+/// Every recorded link in a workspace with its target document's content,
+/// which is null when no document has the target slug.
+pub fn list_links_with_targets(
+    db: &Db,
+    workspace_id: &str,
+    include_drafts: bool,
+) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT s.id, s.title, s.slug, l.target_slug, l.heading, t.content FROM document_links l \
+             JOIN documents s ON s.id = l.source_id \
+             LEFT JOIN documents t ON t.workspace_id = l.workspace_id AND t.slug = l.target_slug \
+             WHERE l.workspace_id = ?1 AND (?2 OR s.status = 'published') \
+             ORDER BY s.title ASC, l.target_slug ASC, l.heading ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id, include_drafts], |row| {
             Ok(serde_json::json!({
-                "id": row.get::<_, String>(0)?,
-                "version_number": row.get::<_, i32>(1)?,
-                "content": row.get::<_, String>(2)?,
-                "content_html": row.get::<_, String>(3)?,
-                "summary": row.get::<_, String>(4)?,
-                "author_name": row.get::<_, String>(5)?,
-                "change_description": row.get::<_, String>(6)?,
-                "word_count": row.get::<_, i32>(7)?,
-                "created_at": row.get::<_, String>(8)?,
+                "source_id": row.get::<_, String>(0)?,
+                "source_title": row.get::<_, String>(1)?,
+                "source_slug": row.get::<_, String>(2)?,
+                "target": row.get::<_, String>(3)?,
+                "heading": row.get::<_, String>(4)?,
+                "target_content": row.get::<_, Option<String>>(5)?,
             }))
         })
-        .optional()
         .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
 
-    Ok(result)
+// --- Template operations ---
+
+fn template_from_row(row: &rusqlite::Row) -> rusqlite::Result<Value> {
+    let json_col = |i: usize| -> rusqlite::Result<Value> {
+        Ok(serde_json::from_str(&row.get::<_, String>(i)?).unwrap_or(serde_json::json!([])))
+    };
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>(0)?,
+        "workspace_id": row.get::<_, String>(1)?,
+        "slug": row.get::<_, String>(2)?,
+        "name": row.get::<_, String>(3)?,
+        "description": row.get::<_, String>(4)?,
+        "title": row.get::<_, String>(5)?,
+        "content": row.get::<_, String>(6)?,
+        "tags": json_col(7)?,
+        "variables": json_col(8)?,
+        "created_at": row.get::<_, String>(9)?,
+        "updated_at": row.get::<_, String>(10)?,
+    }))
 }
 
-// --- Comment operations ---
+const TEMPLATE_COLUMNS: &str =
+    "id, workspace_id, slug, name, description, title, content, tags, variables, created_at, updated_at";
 
-pub fn create_comment(
-    db: &Db,
-    id: &str,
-    document_id: &str,
-    parent_id: Option<&str>,
-    author_name: &str,
-    content: &str,
-) -> Result<(), String> {
+pub fn create_template(db: &Db, id: &str, workspace_id: &str, t: &Template) -> Result<(), String> {
     let conn = db.conn.lock().unwrap();
     conn.execute(
-        "INSERT INTO comments (id, document_id, parent_id, author_name, content) VALUES (?1, ?2, ?3, ?4, ?5)",
-        params![id, document_id, parent_id, author_name, content],
-    ).map_err(|e| e.to_string())?;
+        "INSERT INTO templates (id, workspace_id, slug, name, description, title, content, tags, variables) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            id,
+            workspace_id,
+            t.slug,
+            t.name,
+            t.description,
+            t.title,
+            t.content,
+            serde_json::json!(t.tags).to_string(),
+            serde_json::json!(t.variables).to_string(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
     Ok(())
 }
 
-pub fn list_comments(db: &Db, document_id: &str) -> Result<Vec<serde_json::Value>, String> {
+/// Look a workspace template up by id or slug.
+pub fn get_template(
+    db: &Db,
+    workspace_id: &str,
+    id_or_slug: &str,
+) -> Result<Option<Value>, String> {
     let conn = db.conn.lock().unwrap();
-    let mut stmt = conn.prepare(
-        "SELECT id, document_id, parent_id, author_name, content, resolved, created_at, updated_at FROM comments WHERE document_id = ?1 ORDER BY created_at ASC"
-    ).map_err(|e| e.to_string())?;
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM templates WHERE workspace_id = ?1 AND (id = ?2 OR slug = ?2)",
+            TEMPLATE_COLUMNS
+        ),
+        params![workspace_id, id_or_slug],
+        template_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
 
+pub fn list_templates(db: &Db, workspace_id: &str) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM templates WHERE workspace_id = ?1 ORDER BY name ASC",
+            TEMPLATE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
     let rows = stmt
-        .query_map(params![document_id], |row| {
-            Ok(serde_json::json!({
-                "id": row.get::<_, String>(0)?,
-                "document_id": row.get::<_, String>(1)?,
-                "parent_id": row.get::<_, Option<String>>(2)?,
-                "author_name": row.get::<_, String>(3)?,
-                "content": row.get::<_, String>(4)?,
-                "resolved": row.get::<_, i32>(5)? != 0,
-                "created_at": row.get::<_, String>(6)?,
-                "updated_at": row.get::<_, String>(7)?,
-            }))
-        })
+        .query_map(params![workspace_id], template_from_row)
         .map_err(|e| e.to_string())?;
-
-    let mut comments = Vec::new();
-    for row in rows {
-        comments.push(row.map_err(|e| e.to_string())?);
-    }
-    Ok(comments)
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
 }
 
-// --- Lock operations ---
-
-pub fn acquire_lock(db: &Db, doc_id: &str, editor: &str, ttl_seconds: i32) -> Result<bool, String> {
+pub fn update_template(db: &Db, id: &str, t: &Template) -> Result<bool, String> {
     let conn = db.conn.lock().unwrap();
-
-    // Check if already locked by someone else (and not expired)
-    let current_lock: Option<(Option<String>, Option<String>)> = conn
-        .query_row(
-            "SELECT locked_by, lock_expires_at FROM documents WHERE id = ?1",
-            params![doc_id],
-            |row| Ok((row.get(0)?, row.get(1)?)),
+    let rows = conn
+        .execute(
+            "UPDATE templates SET slug = ?1, name = ?2, description = ?3, title = ?4, content = ?5, \
+             tags = ?6, variables = ?7, updated_at = datetime('now') WHERE id = ?8",
+            params![
+                t.slug,
+                t.name,
+                t.description,
+                t.title,
+                t.content,
+                serde_json::json!(t.tags).to_string(),
+                serde_json::json!(t.variables).to_string(),
+                id,
+            ],
         )
-        .optional()
         .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
 
-    if let Some((locked_by, expires_at)) = current_lock {
-        if let (Some(locked_by), Some(expires_at)) = (locked_by, expires_at) {
-            // Check if lock is still valid
-            let still_locked: bool = conn
-                .query_row("SELECT datetime('now') < ?1", params![expires_at], |row| {
-                    row.get(0)
-                })
-                .map_err(|e| e.to_string())?;
-
-            if still_locked && locked_by != editor {
-                return Ok(false); // someone else has the lock
-            }
-        }
-    }
+pub fn delete_template(db: &Db, id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute("DELETE FROM templates WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
 
-    // Acquire or renew the lock
-    let rows = conn.execute(
-        "UPDATE documents SET locked_by = ?1, locked_at = datetime('now'), lock_expires_at = datetime('now', '+' || ?2 || ' seconds'), updated_at = datetime('now') WHERE id = ?3",
-        params![editor, ttl_seconds, doc_id],
-    ).map_err(|e| e.to_string())?;
+// --- Document type operations ---
 
-    Ok(rows > 0)
+fn doc_type_from_row(row: &rusqlite::Row) -> rusqlite::Result<Value> {
+    let json_col = |i: usize, default: Value| -> rusqlite::Result<Value> {
+        Ok(serde_json::from_str(&row.get::<_, String>(i)?).unwrap_or(default))
+    };
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>(0)?,
+        "workspace_id": row.get::<_, String>(1)?,
+        "slug": row.get::<_, String>(2)?,
+        "name": row.get::<_, String>(3)?,
+        "description": row.get::<_, String>(4)?,
+        "schema": json_col(5, serde_json::json!({}))?,
+        "required_headings": json_col(6, serde_json::json!([]))?,
+        "created_at": row.get::<_, String>(7)?,
+        "updated_at": row.get::<_, String>(8)?,
+    }))
 }
 
-pub fn release_lock(db: &Db, doc_id: &str) -> Result<bool, String> {
+const DOC_TYPE_COLUMNS: &str =
+    "id, workspace_id, slug, name, description, schema, required_headings, created_at, updated_at";
+
+pub fn create_doc_type(
+    db: &Db,
+    id: &str,
+    workspace_id: &str,
+    t: &DocumentType,
+) -> Result<(), String> {
     let conn = db.conn.lock().unwrap();
-    let rows = conn.execute(
-        "UPDATE documents SET locked_by = NULL, locked_at = NULL, lock_expires_at = NULL, updated_at = datetime('now') WHERE id = ?1",
-        params![doc_id],
-    ).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO document_types (id, workspace_id, slug, name, description, schema, required_headings) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![
+            id,
+            workspace_id,
+            t.slug,
+            t.name,
+            t.description,
+            t.schema.to_string(),
+            serde_json::json!(t.required_headings).to_string(),
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Look a document type up by id or slug.
+pub fn get_doc_type(
+    db: &Db,
+    workspace_id: &str,
+    id_or_slug: &str,
+) -> Result<Option<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM document_types WHERE workspace_id = ?1 AND (id = ?2 OR slug = ?2)",
+            DOC_TYPE_COLUMNS
+        ),
+        params![workspace_id, id_or_slug],
+        doc_type_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn list_doc_types(db: &Db, workspace_id: &str) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM document_types WHERE workspace_id = ?1 ORDER BY name ASC",
+            DOC_TYPE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], doc_type_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// The slug is fixed once created: documents refer to their type by it.
+pub fn update_doc_type(db: &Db, id: &str, t: &DocumentType) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "UPDATE document_types SET name = ?1, description = ?2, schema = ?3, \
+             required_headings = ?4, updated_at = datetime('now') WHERE id = ?5",
+            params![
+                t.name,
+                t.description,
+                t.schema.to_string(),
+                serde_json::json!(t.required_headings).to_string(),
+                id,
+            ],
+        )
+        .map_err(|e| e.to_string())?;
     Ok(rows > 0)
 }
 
-/// Renew an existing lock if held by the given editor. Returns Ok(true) if renewed,
-/// Ok(false) if the lock is held by someone else or expired.
-pub fn renew_lock(db: &Db, doc_id: &str, editor: &str, ttl_seconds: i32) -> Result<bool, String> {
+pub fn delete_doc_type(db: &Db, id: &str) -> Result<bool, String> {
     let conn = db.conn.lock().unwrap();
-    // Only renew if the lock is currently held by this editor and not expired
-    let rows = conn.execute(
-        "UPDATE documents SET lock_expires_at = datetime('now', '+' || ?1 || ' seconds'), updated_at = datetime('now') \
-         WHERE id = ?2 AND locked_by = ?3 AND lock_expires_at > datetime('now')",
-        params![ttl_seconds, doc_id, editor],
-    ).map_err(|e| e.to_string())?;
+    let rows = conn
+        .execute("DELETE FROM document_types WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
     Ok(rows > 0)
 }
 
-// --- Comment moderation ---
+/// Documents in a workspace using the given type.
+pub fn count_documents_of_type(db: &Db, workspace_id: &str, slug: &str) -> Result<i64, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT COUNT(*) FROM documents WHERE workspace_id = ?1 AND doc_type = ?2",
+        params![workspace_id, slug],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
 
-pub fn delete_comment(db: &Db, comment_id: &str) -> Result<bool, String> {
+// --- Attachment operations ---
+
+fn attachment_from_row(row: &rusqlite::Row) -> rusqlite::Result<Value> {
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>(0)?,
+        "workspace_id": row.get::<_, String>(1)?,
+        "filename": row.get::<_, String>(2)?,
+        "content_type": row.get::<_, String>(3)?,
+        "size": row.get::<_, i64>(4)?,
+        "sha256": row.get::<_, String>(5)?,
+        "uploaded_by": row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+        "created_at": row.get::<_, String>(7)?,
+    }))
+}
+
+const ATTACHMENT_COLUMNS: &str =
+    "id, workspace_id, filename, content_type, size, sha256, uploaded_by, created_at";
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_attachment(
+    db: &Db,
+    id: &str,
+    workspace_id: &str,
+    filename: &str,
+    content_type: &str,
+    size: usize,
+    sha256: &str,
+    uploaded_by: &str,
+) -> Result<(), String> {
     let conn = db.conn.lock().unwrap();
-    // Delete child comments first (replies), then the comment itself
     conn.execute(
-        "DELETE FROM comments WHERE parent_id = ?1",
-        params![comment_id],
-    ).map_err(|e| e.to_string())?;
-    let rows = conn.execute(
-        "DELETE FROM comments WHERE id = ?1",
-        params![comment_id],
-    ).map_err(|e| e.to_string())?;
-    Ok(rows > 0)
+        "INSERT INTO attachments (id, workspace_id, filename, content_type, size, sha256, uploaded_by) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        params![id, workspace_id, filename, content_type, size as i64, sha256, uploaded_by],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
 }
 
-pub fn update_comment(db: &Db, comment_id: &str, content: Option<&str>, resolved: Option<bool>) -> Result<bool, String> {
+pub fn get_attachment(db: &Db, workspace_id: &str, id: &str) -> Result<Option<Value>, String> {
     let conn = db.conn.lock().unwrap();
-    let mut updates = Vec::new();
-    let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
-
-    if let Some(c) = content {
-        updates.push("content = ?");
-        values.push(Box::new(c.to_string()));
-    }
-    if let Some(r) = resolved {
-        updates.push("resolved = ?");
-        values.push(Box::new(r as i32));
-    }
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM attachments WHERE workspace_id = ?1 AND id = ?2",
+            ATTACHMENT_COLUMNS
+        ),
+        params![workspace_id, id],
+        attachment_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
 
-    if updates.is_empty() {
-        return Ok(false);
-    }
+/// The workspace's attachment with these bytes, if it already has one.
+pub fn find_attachment_by_hash(
+    db: &Db,
+    workspace_id: &str,
+    sha256: &str,
+) -> Result<Option<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM attachments WHERE workspace_id = ?1 AND sha256 = ?2 \
+             ORDER BY created_at ASC LIMIT 1",
+            ATTACHMENT_COLUMNS
+        ),
+        params![workspace_id, sha256],
+        attachment_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
 
-    updates.push("updated_at = datetime('now')");
-    let sql = format!("UPDATE comments SET {} WHERE id = ?", updates.join(", "));
-    values.push(Box::new(comment_id.to_string()));
+pub fn list_attachments(db: &Db, workspace_id: &str) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM attachments WHERE workspace_id = ?1 ORDER BY created_at DESC, filename ASC",
+            ATTACHMENT_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], attachment_from_row)
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
 
-    let params_refs: Vec<&dyn rusqlite::ToSql> = values.iter().map(|v| v.as_ref()).collect();
-    let rows = conn.execute(&sql, params_refs.as_slice()).map_err(|e| e.to_string())?;
+pub fn delete_attachment(db: &Db, id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute("DELETE FROM attachments WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
     Ok(rows > 0)
 }
 
-/// Get the document_id for a comment (to look up workspace for SSE events)
-pub fn get_comment_doc_id(db: &Db, comment_id: &str) -> Result<Option<String>, String> {
+/// Blob hashes used by attachments across all workspaces, leaving out the
+/// attachments in `except_ids`.
+pub fn attachment_hashes(
+    db: &Db,
+    except_ids: &std::collections::HashSet<&str>,
+) -> Result<std::collections::HashSet<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id, sha256 FROM attachments")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut hashes = std::collections::HashSet::new();
+    for row in rows {
+        let (id, sha256) = row.map_err(|e| e.to_string())?;
+        if !except_ids.contains(id.as_str()) {
+            hashes.insert(sha256);
+        }
+    }
+    Ok(hashes)
+}
+
+/// Whether any attachment, in any workspace, uses the blob `sha256`.
+pub fn attachment_hash_in_use(db: &Db, sha256: &str) -> Result<bool, String> {
     let conn = db.conn.lock().unwrap();
     conn.query_row(
-        "SELECT document_id FROM comments WHERE id = ?1",
-        params![comment_id],
-        |row| row.get(0),
+        "SELECT 1 FROM attachments WHERE sha256 = ?1 LIMIT 1",
+        params![sha256],
+        |_| Ok(()),
     )
     .optional()
+    .map(|found| found.is_some())
     .map_err(|e| e.to_string())
 }
 
-// Need this import for .optional()
-use rusqlite::OptionalExtension;
-
-pub fn search_documents(
+/// Current documents whose content mentions `attachment:<id>`.
+pub fn documents_referencing_attachment(
     db: &Db,
     workspace_id: &str,
-    query: &str,
-    limit: i32,
-    offset: i32,
+    attachment_id: &str,
 ) -> Result<Vec<Value>, String> {
     let conn = db.conn.lock().unwrap();
-    let pattern = format!("%{}%", query);
     let mut stmt = conn
         .prepare(
-            "SELECT id, workspace_id, title, slug, summary, status, author_name, word_count, tags, created_at, updated_at
-             FROM documents
-             WHERE workspace_id = ?1 AND status = 'published'
-               AND (title LIKE ?2 OR content LIKE ?2 OR summary LIKE ?2 OR tags LIKE ?2)
-             ORDER BY updated_at DESC
-             LIMIT ?3 OFFSET ?4",
+            "SELECT id, slug, title FROM documents \
+             WHERE workspace_id = ?1 AND instr(content, ?2) > 0 ORDER BY title ASC",
         )
         .map_err(|e| e.to_string())?;
-
+    let needle = format!("{}{}", crate::attachments::SCHEME, attachment_id);
     let rows = stmt
-        .query_map(params![workspace_id, pattern, limit, offset], |row| {
-            let tags_str: String = row.get(8)?;
-            let tags: Value = serde_json::from_str(&tags_str).unwrap_or(Value::Array(vec![]));
+        .query_map(params![workspace_id, needle], |row| {
             Ok(serde_json::json!({
                 "id": row.get::<_, String>(0)?,
-                "workspace_id": row.get::<_, String>(1)?,
+                "slug": row.get::<_, String>(1)?,
                 "title": row.get::<_, String>(2)?,
-                "slug": row.get::<_, String>(3)?,
-                "summary": row.get::<_, String>(4)?,
-                "status": row.get::<_, String>(5)?,
-                "author_name": row.get::<_, String>(6)?,
-                "word_count": row.get::<_, i32>(7)?,
-                "tags": tags,
-                "created_at": row.get::<_, String>(9)?,
-                "updated_at": row.get::<_, String>(10)?,
             }))
         })
         .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
 
-    let mut results = Vec::new();
+/// Every attachment id mentioned anywhere in the workspace: current documents,
+/// past versions (so restores keep working), comments and suggestions.
+pub fn referenced_attachment_ids(
+    db: &Db,
+    workspace_id: &str,
+) -> Result<std::collections::HashSet<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT content FROM documents WHERE workspace_id = ?1 \
+             UNION ALL SELECT v.content FROM document_versions v \
+               JOIN documents d ON d.id = v.document_id WHERE d.workspace_id = ?1 \
+             UNION ALL SELECT c.content FROM comments c \
+               JOIN documents d ON d.id = c.document_id WHERE d.workspace_id = ?1 \
+             UNION ALL SELECT COALESCE(s.replacement, '') || COALESCE(s.proposed_content, '') \
+               FROM suggestions s JOIN documents d ON d.id = s.document_id \
+               WHERE d.workspace_id = ?1 AND s.status = 'pending'",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], |row| row.get::<_, String>(0))
+        .map_err(|e| e.to_string())?;
+    let mut ids = std::collections::HashSet::new();
+    for text in rows {
+        let text = text.map_err(|e| e.to_string())?;
+        ids.extend(crate::attachments::references(&text));
+    }
+    Ok(ids)
+}
+
+// --- Version operations ---
+
+pub fn latest_version_number(db: &Db, doc_id: &str) -> Result<i32, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT COALESCE(MAX(version_number), 0) FROM document_versions WHERE document_id = ?1",
+        params![doc_id],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+pub fn list_versions(
+    db: &Db,
+    doc_id: &str,
+    limit: i32,
+    offset: i32,
+) -> Result<Vec<serde_json::Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn.prepare(
+        "SELECT id, version_number, summary, author_name, change_description, word_count, created_at FROM document_versions WHERE document_id = ?1 ORDER BY version_number DESC LIMIT ?2 OFFSET ?3"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map(params![doc_id, limit, offset], |row| {
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "version_number": row.get::<_, i32>(1)?,
+                "summary": row.get::<_, String>(2)?,
+                "author_name": row.get::<_, String>(3)?,
+                "change_description": row.get::<_, String>(4)?,
+                "word_count": row.get::<_, i32>(5)?,
+                "created_at": row.get::<_, String>(6)?,
+            }))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut versions = Vec::new();
     for row in rows {
-        results.push(row.map_err(|e| e.to_string())?);
+        versions.push(row.map_err(|e| e.to_string())?);
     }
-    Ok(results)
+    Ok(versions)
+}
+
+pub fn get_version(
+    db: &Db,
+    doc_id: &str,
+    version_number: i32,
+) -> Result<Option<serde_json::Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn.prepare(
+        "SELECT id, version_number, content, content_html, summary, author_name, change_description, word_count, created_at FROM document_versions WHERE document_id = ?1 AND version_number = ?2"
+    ).map_err(|e| e.to_string())?;
+
+    let result = stmt
+        .query_row(params![doc_id, version_number], |row| {
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "version_number": row.get::<_, i32>(1)?,
+                "content": row.get::<_, String>(2)?,
+                "content_html": row.get::<_, String>(3)?,
+                "summary": row.get::<_, String>(4)?,
+                "author_name": row.get::<_, String>(5)?,
+                "change_description": row.get::<_, String>(6)?,
+                "word_count": row.get::<_, i32>(7)?,
+                "created_at": row.get::<_, String>(8)?,
+            }))
+        })
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    Ok(result)
+}
+
+// --- Comment operations ---
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_comment(
+    db: &Db,
+    id: &str,
+    document_id: &str,
+    parent_id: Option<&str>,
+    author_name: &str,
+    content: &str,
+    anchor: Option<(&Anchor, i32)>,
+    edit_token_hash: &str,
+    moderation_status: &str,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    let (quote, prefix, suffix, start, end, version, status) = match anchor {
+        Some((a, version)) => (
+            Some(a.quote.as_str()),
+            a.prefix.as_str(),
+            a.suffix.as_str(),
+            Some(a.start as i64),
+            Some(a.end as i64),
+            Some(version),
+            Some("anchored"),
+        ),
+        None => (None, "", "", None, None, None, None),
+    };
+    conn.execute(
+        "INSERT INTO comments (id, document_id, parent_id, author_name, content, anchor_quote, anchor_prefix, anchor_suffix, anchor_start, anchor_end, anchor_version, anchor_status, edit_token_hash, moderation_status) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        params![id, document_id, parent_id, author_name, content, quote, prefix, suffix, start, end, version, status, edit_token_hash, moderation_status],
+    ).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn list_comments(db: &Db, document_id: &str) -> Result<Vec<serde_json::Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn.prepare(
+        "SELECT id, document_id, parent_id, author_name, content, resolved, created_at, updated_at, \
+         anchor_quote, anchor_prefix, anchor_suffix, anchor_start, anchor_end, anchor_version, anchor_status, moderation_status \
+         FROM comments WHERE document_id = ?1 ORDER BY created_at ASC"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map(params![document_id], |row| {
+            let anchor = match row.get::<_, Option<String>>(8)? {
+                Some(quote) => serde_json::json!({
+                    "quote": quote,
+                    "prefix": row.get::<_, Option<String>>(9)?.unwrap_or_default(),
+                    "suffix": row.get::<_, Option<String>>(10)?.unwrap_or_default(),
+                    "start": row.get::<_, Option<i64>>(11)?,
+                    "end": row.get::<_, Option<i64>>(12)?,
+                    "version_number": row.get::<_, Option<i32>>(13)?,
+                    "status": row.get::<_, Option<String>>(14)?,
+                }),
+                None => Value::Null,
+            };
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "document_id": row.get::<_, String>(1)?,
+                "parent_id": row.get::<_, Option<String>>(2)?,
+                "author_name": row.get::<_, String>(3)?,
+                "content": row.get::<_, String>(4)?,
+                "resolved": row.get::<_, i32>(5)? != 0,
+                "created_at": row.get::<_, String>(6)?,
+                "updated_at": row.get::<_, String>(7)?,
+                "anchor": anchor,
+                "moderation_status": row
+                    .get::<_, Option<String>>(15)?
+                    .unwrap_or_else(|| "approved".to_string()),
+            }))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut comments = Vec::new();
+    for row in rows {
+        comments.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(comments)
+}
+
+/// Anchored comments on a document: (comment id, anchor, status).
+pub fn list_comment_anchors(
+    db: &Db,
+    document_id: &str,
+) -> Result<Vec<(String, Anchor, String)>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn.prepare(
+        "SELECT id, anchor_quote, anchor_prefix, anchor_suffix, anchor_start, anchor_end, anchor_status \
+         FROM comments WHERE document_id = ?1 AND anchor_quote IS NOT NULL"
+    ).map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map(params![document_id], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                Anchor {
+                    quote: row.get(1)?,
+                    prefix: row.get::<_, Option<String>>(2)?.unwrap_or_default(),
+                    suffix: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+                    start: row.get::<_, Option<i64>>(4)?.unwrap_or(0) as usize,
+                    end: row.get::<_, Option<i64>>(5)?.unwrap_or(0) as usize,
+                },
+                row.get::<_, Option<String>>(6)?
+                    .unwrap_or_else(|| "anchored".to_string()),
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Record where a comment's anchor sits in `version`. `None` marks it orphaned,
+/// leaving offsets and version pointing at the last version the quote was found in.
+pub fn update_comment_anchor(
+    db: &Db,
+    comment_id: &str,
+    range: Option<(usize, usize)>,
+    version: i32,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    match range {
+        Some((start, end)) => conn.execute(
+            "UPDATE comments SET anchor_start = ?1, anchor_end = ?2, anchor_version = ?3, anchor_status = 'anchored' WHERE id = ?4",
+            params![start as i64, end as i64, version, comment_id],
+        ),
+        None => conn.execute(
+            "UPDATE comments SET anchor_status = 'orphaned' WHERE id = ?1",
+            params![comment_id],
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// --- Lock operations ---
+
+pub fn acquire_lock(db: &Db, doc_id: &str, editor: &str, ttl_seconds: i32) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+
+    // Check if already locked by someone else (and not expired)
+    let current_lock: Option<(Option<String>, Option<String>)> = conn
+        .query_row(
+            "SELECT locked_by, lock_expires_at FROM documents WHERE id = ?1",
+            params![doc_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+
+    if let Some((locked_by, expires_at)) = current_lock {
+        if let (Some(locked_by), Some(expires_at)) = (locked_by, expires_at) {
+            // Check if lock is still valid
+            let still_locked: bool = conn
+                .query_row("SELECT datetime('now') < ?1", params![expires_at], |row| {
+                    row.get(0)
+                })
+                .map_err(|e| e.to_string())?;
+
+            if still_locked && locked_by != editor {
+                return Ok(false); // someone else has the lock
+            }
+        }
+    }
+
+    // Acquire or renew the lock
+    let rows = conn.execute(
+        "UPDATE documents SET locked_by = ?1, locked_at = datetime('now'), lock_expires_at = datetime('now', '+' || ?2 || ' seconds'), updated_at = datetime('now') WHERE id = ?3",
+        params![editor, ttl_seconds, doc_id],
+    ).map_err(|e| e.to_string())?;
+
+    Ok(rows > 0)
+}
+
+pub fn release_lock(db: &Db, doc_id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn.execute(
+        "UPDATE documents SET locked_by = NULL, locked_at = NULL, lock_expires_at = NULL, updated_at = datetime('now') WHERE id = ?1",
+        params![doc_id],
+    ).map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Renew an existing lock if held by the given editor. Returns Ok(true) if renewed,
+/// Ok(false) if the lock is held by someone else or expired.
+pub fn renew_lock(db: &Db, doc_id: &str, editor: &str, ttl_seconds: i32) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    // Only renew if the lock is currently held by this editor and not expired
+    let rows = conn.execute(
+        "UPDATE documents SET lock_expires_at = datetime('now', '+' || ?1 || ' seconds'), updated_at = datetime('now') \
+         WHERE id = ?2 AND locked_by = ?3 AND lock_expires_at > datetime('now')",
+        params![ttl_seconds, doc_id, editor],
+    ).map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+// --- Comment moderation ---
+
+pub fn delete_comment(db: &Db, comment_id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    // Reactions on the comment and its replies go with them
+    conn.execute(
+        "DELETE FROM reactions WHERE target_type = 'comment' AND (target_id = ?1 OR target_id IN (SELECT id FROM comments WHERE parent_id = ?1))",
+        params![comment_id],
+    ).map_err(|e| e.to_string())?;
+    // Delete child comments first (replies), then the comment itself
+    conn.execute(
+        "DELETE FROM comments WHERE parent_id = ?1",
+        params![comment_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let rows = conn
+        .execute("DELETE FROM comments WHERE id = ?1", params![comment_id])
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+pub fn update_comment(
+    db: &Db,
+    comment_id: &str,
+    content: Option<&str>,
+    resolved: Option<bool>,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut updates = Vec::new();
+    let mut values: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
+
+    if let Some(c) = content {
+        updates.push("content = ?");
+        values.push(Box::new(c.to_string()));
+    }
+    if let Some(r) = resolved {
+        updates.push("resolved = ?");
+        values.push(Box::new(r as i32));
+    }
+
+    if updates.is_empty() {
+        return Ok(false);
+    }
+
+    updates.push("updated_at = datetime('now')");
+    let sql = format!("UPDATE comments SET {} WHERE id = ?", updates.join(", "));
+    values.push(Box::new(comment_id.to_string()));
+
+    let params_refs: Vec<&dyn rusqlite::ToSql> = values.iter().map(|v| v.as_ref()).collect();
+    let rows = conn
+        .execute(&sql, params_refs.as_slice())
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Author and content of a comment.
+pub fn get_comment(db: &Db, comment_id: &str) -> Result<Option<(String, String)>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT author_name, content FROM comments WHERE id = ?1",
+        params![comment_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// Get the document_id for a comment (to look up workspace for SSE events)
+pub fn get_comment_doc_id(db: &Db, comment_id: &str) -> Result<Option<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT document_id FROM comments WHERE id = ?1",
+        params![comment_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// How deep a comment sits in its thread (top-level comments are 0).
+pub fn comment_depth(db: &Db, comment_id: &str) -> Result<usize, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "WITH RECURSIVE chain(id, parent_id, depth) AS ( \
+             SELECT id, parent_id, 0 FROM comments WHERE id = ?1 \
+             UNION ALL \
+             SELECT c.id, c.parent_id, chain.depth + 1 FROM comments c JOIN chain ON c.id = chain.parent_id \
+         ) SELECT COALESCE(MAX(depth), 0) FROM chain",
+        params![comment_id],
+        |row| row.get::<_, i64>(0),
+    )
+    .map(|d| d as usize)
+    .map_err(|e| e.to_string())
+}
+
+/// Moderation status of a comment: 'approved' or 'pending'.
+pub fn get_comment_moderation_status(db: &Db, comment_id: &str) -> Result<Option<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT COALESCE(moderation_status, 'approved') FROM comments WHERE id = ?1",
+        params![comment_id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn approve_comment(db: &Db, comment_id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "UPDATE comments SET moderation_status = 'approved', updated_at = datetime('now') \
+             WHERE id = ?1 AND moderation_status = 'pending'",
+            params![comment_id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Comments waiting for approval anywhere in a workspace, oldest first.
+pub fn list_pending_comments(db: &Db, workspace_id: &str) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT c.id, c.document_id, d.title, c.parent_id, c.author_name, c.content, c.created_at \
+             FROM comments c JOIN documents d ON d.id = c.document_id \
+             WHERE d.workspace_id = ?1 AND c.moderation_status = 'pending' \
+             ORDER BY c.created_at ASC, c.rowid ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], |row| {
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "document_id": row.get::<_, String>(1)?,
+                "document_title": row.get::<_, String>(2)?,
+                "parent_id": row.get::<_, Option<String>>(3)?,
+                "author_name": row.get::<_, String>(4)?,
+                "content": row.get::<_, String>(5)?,
+                "created_at": row.get::<_, String>(6)?,
+            }))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Hash of the commenter token issued when the comment was created.
+/// Comments created before tokens existed have none.
+pub fn get_comment_edit_token_hash(db: &Db, comment_id: &str) -> Result<Option<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT edit_token_hash FROM comments WHERE id = ?1",
+        params![comment_id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(|h| h.flatten())
+    .map_err(|e| e.to_string())
+}
+
+// Need this import for .optional()
+use rusqlite::OptionalExtension;
+
+pub fn search_documents(
+    db: &Db,
+    workspace_id: &str,
+    query: &str,
+    limit: i32,
+    offset: i32,
+    metadata: &[(String, String)],
+) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let pattern = format!("%{}%", query);
+    let mut values: Vec<Box<dyn rusqlite::types::ToSql>> = vec![
+        Box::new(workspace_id.to_string()),
+        Box::new(pattern),
+        Box::new(limit),
+        Box::new(offset),
+    ];
+    let sql = format!(
+        "SELECT id, workspace_id, title, slug, summary, status, author_name, word_count, tags, created_at, updated_at, metadata
+         FROM documents
+         WHERE workspace_id = ?1 AND status = 'published'
+           AND (title LIKE ?2 OR content LIKE ?2 OR summary LIKE ?2 OR tags LIKE ?2){}
+         ORDER BY updated_at DESC
+         LIMIT ?3 OFFSET ?4",
+        metadata_filter_sql(metadata, &mut values)
+    );
+    let params: Vec<&dyn rusqlite::types::ToSql> = values.iter().map(|v| v.as_ref()).collect();
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+
+    let rows = stmt
+        .query_map(params.as_slice(), |row| {
+            let tags_str: String = row.get(8)?;
+            let tags: Value = serde_json::from_str(&tags_str).unwrap_or(Value::Array(vec![]));
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "workspace_id": row.get::<_, String>(1)?,
+                "title": row.get::<_, String>(2)?,
+                "slug": row.get::<_, String>(3)?,
+                "summary": row.get::<_, String>(4)?,
+                "status": row.get::<_, String>(5)?,
+                "author_name": row.get::<_, String>(6)?,
+                "word_count": row.get::<_, i32>(7)?,
+                "tags": tags,
+                "created_at": row.get::<_, String>(9)?,
+                "updated_at": row.get::<_, String>(10)?,
+                "metadata": parse_metadata(row.get(11)?),
+            }))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut results = Vec::new();
+    for row in rows {
+        results.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(results)
+}
+
+/// Current tags of a document (for SSE tag filtering). Missing docs have none.
+pub fn get_document_tags(db: &Db, doc_id: &str) -> Result<Vec<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    let tags_str: Option<String> = conn
+        .query_row(
+            "SELECT tags FROM documents WHERE id = ?1",
+            params![doc_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    Ok(tags_str
+        .and_then(|s| serde_json::from_str::<Vec<String>>(&s).ok())
+        .unwrap_or_default())
+}
+
+// --- Webhook operations ---
+
+fn webhook_from_row(row: &rusqlite::Row) -> rusqlite::Result<Value> {
+    let events_str: String = row.get(3)?;
+    let events: Value = serde_json::from_str(&events_str).unwrap_or(serde_json::json!([]));
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>(0)?,
+        "workspace_id": row.get::<_, String>(1)?,
+        "url": row.get::<_, String>(2)?,
+        "events": events,
+        "active": row.get::<_, i32>(4)? != 0,
+        "created_at": row.get::<_, String>(5)?,
+        "updated_at": row.get::<_, String>(6)?,
+    }))
+}
+
+pub fn create_webhook(
+    db: &Db,
+    id: &str,
+    workspace_id: &str,
+    url: &str,
+    events: &str,
+    secret: &str,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "INSERT INTO webhooks (id, workspace_id, url, events, secret) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![id, workspace_id, url, events, secret],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn get_webhook(db: &Db, workspace_id: &str, id: &str) -> Result<Option<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT id, workspace_id, url, events, active, created_at, updated_at FROM webhooks WHERE workspace_id = ?1 AND id = ?2",
+        params![workspace_id, id],
+        webhook_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn list_webhooks(db: &Db, workspace_id: &str) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, workspace_id, url, events, active, created_at, updated_at FROM webhooks WHERE workspace_id = ?1 ORDER BY created_at ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], webhook_from_row)
+        .map_err(|e| e.to_string())?;
+
+    let mut hooks = Vec::new();
+    for row in rows {
+        hooks.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(hooks)
+}
+
+pub fn update_webhook(
+    db: &Db,
+    workspace_id: &str,
+    id: &str,
+    url: Option<&str>,
+    events: Option<&str>,
+    active: Option<bool>,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut sets = Vec::new();
+    let mut values: Vec<Box<dyn rusqlite::types::ToSql>> = Vec::new();
+
+    if let Some(u) = url {
+        sets.push("url = ?");
+        values.push(Box::new(u.to_string()));
+    }
+    if let Some(e) = events {
+        sets.push("events = ?");
+        values.push(Box::new(e.to_string()));
+    }
+    if let Some(a) = active {
+        sets.push("active = ?");
+        values.push(Box::new(a as i32));
+    }
+
+    if sets.is_empty() {
+        return Ok(false);
+    }
+
+    sets.push("updated_at = datetime('now')");
+    let sql = format!(
+        "UPDATE webhooks SET {} WHERE workspace_id = ? AND id = ?",
+        sets.join(", ")
+    );
+    values.push(Box::new(workspace_id.to_string()));
+    values.push(Box::new(id.to_string()));
+
+    let params: Vec<&dyn rusqlite::types::ToSql> = values.iter().map(|v| v.as_ref()).collect();
+    let rows = conn
+        .execute(&sql, params.as_slice())
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+pub fn delete_webhook(db: &Db, workspace_id: &str, id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "DELETE FROM webhooks WHERE workspace_id = ?1 AND id = ?2",
+            params![workspace_id, id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Active webhooks for a workspace as (id, event patterns).
+pub fn active_webhook_filters(
+    db: &Db,
+    workspace_id: &str,
+) -> Result<Vec<(String, Vec<String>)>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare("SELECT id, events FROM webhooks WHERE workspace_id = ?1 AND active = 1")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], |row| {
+            let events_str: String = row.get(1)?;
+            let events: Vec<String> = serde_json::from_str(&events_str).unwrap_or_default();
+            Ok((row.get::<_, String>(0)?, events))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut hooks = Vec::new();
+    for row in rows {
+        hooks.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(hooks)
+}
+
+pub fn create_delivery(
+    db: &Db,
+    id: &str,
+    webhook_id: &str,
+    event_type: &str,
+    payload: &str,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "INSERT INTO webhook_deliveries (id, webhook_id, event_type, payload) VALUES (?1, ?2, ?3, ?4)",
+        params![id, webhook_id, event_type, payload],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// --- Git mirrors ---
+
+/// Start mirroring a workspace. False when it already is.
+pub fn enable_git_mirror(db: &Db, workspace_id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "INSERT OR IGNORE INTO git_mirrors (workspace_id) VALUES (?1)",
+            params![workspace_id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Stop mirroring and forget which versions were committed. The repository
+/// itself is left on disk.
+pub fn disable_git_mirror(db: &Db, workspace_id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "DELETE FROM git_commits WHERE workspace_id = ?1",
+        params![workspace_id],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM git_files WHERE workspace_id = ?1",
+        params![workspace_id],
+    )
+    .map_err(|e| e.to_string())?;
+    let rows = conn
+        .execute(
+            "DELETE FROM git_mirrors WHERE workspace_id = ?1",
+            params![workspace_id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+pub fn get_git_mirror(db: &Db, workspace_id: &str) -> Result<Option<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT workspace_id, synced_commit, created_at,
+                (SELECT COUNT(DISTINCT commit_id) FROM git_commits WHERE workspace_id = ?1)
+         FROM git_mirrors WHERE workspace_id = ?1",
+        params![workspace_id],
+        |row| {
+            Ok(serde_json::json!({
+                "workspace_id": row.get::<_, String>(0)?,
+                "synced_commit": row.get::<_, Option<String>>(1)?,
+                "created_at": row.get::<_, String>(2)?,
+                "commits": row.get::<_, i64>(3)?,
+            }))
+        },
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+/// The last commit whose pushed changes were ingested.
+pub fn set_git_synced_commit(db: &Db, workspace_id: &str, commit_id: &str) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "UPDATE git_mirrors SET synced_commit = ?2 WHERE workspace_id = ?1",
+        params![workspace_id, commit_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// A document version not yet committed to the workspace's mirror.
+pub struct UnmirroredVersion {
+    pub document_id: String,
+    pub slug: String,
+    pub version_number: i32,
+    pub content: String,
+    pub author_name: String,
+    pub change_description: String,
+    pub created_at: String,
+}
+
+/// Versions without a commit, oldest first.
+pub fn unmirrored_versions(db: &Db, workspace_id: &str) -> Result<Vec<UnmirroredVersion>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT v.document_id, d.slug, v.version_number, v.content,
+                    COALESCE(v.author_name, ''), COALESCE(v.change_description, ''), v.created_at
+             FROM document_versions v JOIN documents d ON d.id = v.document_id
+             WHERE d.workspace_id = ?1 AND NOT EXISTS (
+                 SELECT 1 FROM git_commits g
+                 WHERE g.document_id = v.document_id AND g.version_number = v.version_number
+             )
+             ORDER BY v.created_at ASC, v.rowid ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], |row| {
+            Ok(UnmirroredVersion {
+                document_id: row.get(0)?,
+                slug: row.get(1)?,
+                version_number: row.get(2)?,
+                content: row.get(3)?,
+                author_name: row.get(4)?,
+                change_description: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Record that `commit_id` was written by the server or ingested, for a
+/// document version (`None` for renames and deletions).
+pub fn record_git_commit(
+    db: &Db,
+    workspace_id: &str,
+    commit_id: &str,
+    document_id: &str,
+    version_number: Option<i32>,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "INSERT INTO git_commits (commit_id, workspace_id, document_id, version_number) VALUES (?1, ?2, ?3, ?4)",
+        params![commit_id, workspace_id, document_id, version_number],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn git_commit_known(db: &Db, workspace_id: &str, commit_id: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT 1 FROM git_commits WHERE workspace_id = ?1 AND commit_id = ?2 LIMIT 1",
+        params![workspace_id, commit_id],
+        |_| Ok(()),
+    )
+    .optional()
+    .map(|found| found.is_some())
+    .map_err(|e| e.to_string())
+}
+
+/// Mirrored files as (document id, path in the repository, current slug —
+/// `None` once the document is deleted).
+pub fn git_files(
+    db: &Db,
+    workspace_id: &str,
+) -> Result<Vec<(String, String, Option<String>)>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT f.document_id, f.path, d.slug
+             FROM git_files f LEFT JOIN documents d ON d.id = f.document_id
+             WHERE f.workspace_id = ?1
+             ORDER BY f.path ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// Where a document lives in the mirror; `None` forgets it.
+pub fn set_git_file(
+    db: &Db,
+    workspace_id: &str,
+    document_id: &str,
+    path: Option<&str>,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    match path {
+        Some(path) => conn.execute(
+            "INSERT INTO git_files (document_id, workspace_id, path) VALUES (?1, ?2, ?3)
+             ON CONFLICT(document_id) DO UPDATE SET path = excluded.path",
+            params![document_id, workspace_id, path],
+        ),
+        None => conn.execute(
+            "DELETE FROM git_files WHERE document_id = ?1",
+            params![document_id],
+        ),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// A delivery ready to send, joined with its webhook's target and secret.
+pub struct PendingDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event_type: String,
+    pub payload: String,
+    pub attempts: i32,
+    pub url: String,
+    pub secret: String,
+}
+
+pub fn due_deliveries(db: &Db, limit: i32) -> Result<Vec<PendingDelivery>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT d.id, d.webhook_id, d.event_type, d.payload, d.attempts, w.url, w.secret
+             FROM webhook_deliveries d JOIN webhooks w ON w.id = d.webhook_id
+             WHERE d.status = 'pending' AND d.next_attempt_at <= datetime('now') AND w.active = 1
+             ORDER BY d.created_at ASC
+             LIMIT ?1",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![limit], |row| {
+            Ok(PendingDelivery {
+                id: row.get(0)?,
+                webhook_id: row.get(1)?,
+                event_type: row.get(2)?,
+                payload: row.get(3)?,
+                attempts: row.get(4)?,
+                url: row.get(5)?,
+                secret: row.get(6)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut due = Vec::new();
+    for row in rows {
+        due.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(due)
+}
+
+/// Record the outcome of a delivery attempt. `retry_in_secs` of None means
+/// no further attempts: the delivery ends as `delivered` or `failed`.
+pub fn record_delivery_attempt(
+    db: &Db,
+    id: &str,
+    success: bool,
+    response_status: Option<u16>,
+    error: Option<&str>,
+    retry_in_secs: Option<u64>,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    let status = match (success, retry_in_secs) {
+        (true, _) => "delivered",
+        (false, Some(_)) => "pending",
+        (false, None) => "failed",
+    };
+    conn.execute(
+        "UPDATE webhook_deliveries SET attempts = attempts + 1, status = ?1, response_status = ?2, last_error = ?3,
+             next_attempt_at = datetime('now', '+' || ?4 || ' seconds'),
+             delivered_at = CASE WHEN ?1 = 'delivered' THEN datetime('now') ELSE delivered_at END
+         WHERE id = ?5",
+        params![status, response_status, error, retry_in_secs.unwrap_or(0) as i64, id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn list_deliveries(
+    db: &Db,
+    webhook_id: &str,
+    limit: i32,
+    offset: i32,
+) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, event_type, payload, status, attempts, response_status, last_error, next_attempt_at, delivered_at, created_at
+             FROM webhook_deliveries WHERE webhook_id = ?1
+             ORDER BY created_at DESC, rowid DESC LIMIT ?2 OFFSET ?3",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![webhook_id, limit, offset], |row| {
+            let payload_str: String = row.get(2)?;
+            let payload: Value = serde_json::from_str(&payload_str).unwrap_or(Value::Null);
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "event_type": row.get::<_, String>(1)?,
+                "payload": payload,
+                "status": row.get::<_, String>(3)?,
+                "attempts": row.get::<_, i32>(4)?,
+                "response_status": row.get::<_, Option<i32>>(5)?,
+                "last_error": row.get::<_, Option<String>>(6)?,
+                "next_attempt_at": row.get::<_, String>(7)?,
+                "delivered_at": row.get::<_, Option<String>>(8)?,
+                "created_at": row.get::<_, String>(9)?,
+            }))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut deliveries = Vec::new();
+    for row in rows {
+        deliveries.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(deliveries)
+}
+
+/// Event type and payload of an existing delivery (for manual redelivery).
+pub fn get_delivery_payload(
+    db: &Db,
+    webhook_id: &str,
+    delivery_id: &str,
+) -> Result<Option<(String, String)>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT event_type, payload FROM webhook_deliveries WHERE webhook_id = ?1 AND id = ?2",
+        params![webhook_id, delivery_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// --- Suggestion operations ---
+
+/// What a suggestion proposes: a replacement for an anchored range of the
+/// base version, or a whole new body.
+pub enum SuggestedChange<'a> {
+    Range {
+        anchor: &'a Anchor,
+        replacement: &'a str,
+    },
+    Content(&'a str),
+}
+
+pub fn create_suggestion(
+    db: &Db,
+    id: &str,
+    document_id: &str,
+    author_name: &str,
+    description: &str,
+    base_version: i32,
+    change: &SuggestedChange,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    let (quote, prefix, suffix, start, end, replacement, content) = match change {
+        SuggestedChange::Range {
+            anchor,
+            replacement,
+        } => (
+            Some(anchor.quote.as_str()),
+            anchor.prefix.as_str(),
+            anchor.suffix.as_str(),
+            Some(anchor.start as i64),
+            Some(anchor.end as i64),
+            Some(*replacement),
+            None,
+        ),
+        SuggestedChange::Content(c) => (None, "", "", None, None, None, Some(*c)),
+    };
+    conn.execute(
+        "INSERT INTO suggestions (id, document_id, author_name, description, base_version, quote, prefix, suffix, range_start, range_end, replacement, proposed_content) \
+         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        params![id, document_id, author_name, description, base_version, quote, prefix, suffix, start, end, replacement, content],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+const SUGGESTION_COLUMNS: &str = "id, document_id, author_name, description, base_version, quote, prefix, suffix, range_start, range_end, \
+     replacement, proposed_content, status, reviewed_by, review_reason, applied_version, created_at, reviewed_at";
+
+fn suggestion_from_row(row: &rusqlite::Row) -> rusqlite::Result<Value> {
+    let quote: Option<String> = row.get(5)?;
+    let kind = if quote.is_some() { "range" } else { "content" };
+    let range = match quote {
+        Some(q) => serde_json::json!({
+            "quote": q,
+            "prefix": row.get::<_, Option<String>>(6)?.unwrap_or_default(),
+            "suffix": row.get::<_, Option<String>>(7)?.unwrap_or_default(),
+            "start": row.get::<_, Option<i64>>(8)?,
+            "end": row.get::<_, Option<i64>>(9)?,
+        }),
+        None => Value::Null,
+    };
+    Ok(serde_json::json!({
+        "id": row.get::<_, String>(0)?,
+        "document_id": row.get::<_, String>(1)?,
+        "author_name": row.get::<_, String>(2)?,
+        "description": row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+        "base_version": row.get::<_, i32>(4)?,
+        "kind": kind,
+        "range": range,
+        "replacement": row.get::<_, Option<String>>(10)?,
+        "proposed_content": row.get::<_, Option<String>>(11)?,
+        "status": row.get::<_, String>(12)?,
+        "reviewed_by": row.get::<_, Option<String>>(13)?,
+        "review_reason": row.get::<_, Option<String>>(14)?,
+        "applied_version": row.get::<_, Option<i32>>(15)?,
+        "created_at": row.get::<_, String>(16)?,
+        "reviewed_at": row.get::<_, Option<String>>(17)?,
+    }))
+}
+
+pub fn get_suggestion(db: &Db, document_id: &str, id: &str) -> Result<Option<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM suggestions WHERE document_id = ?1 AND id = ?2",
+            SUGGESTION_COLUMNS
+        ),
+        params![document_id, id],
+        suggestion_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+pub fn list_suggestions(
+    db: &Db,
+    document_id: &str,
+    status: Option<&str>,
+) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM suggestions WHERE document_id = ?1 AND (?2 IS NULL OR status = ?2) ORDER BY created_at ASC",
+            SUGGESTION_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![document_id, status], suggestion_from_row)
+        .map_err(|e| e.to_string())?;
+
+    let mut suggestions = Vec::new();
+    for row in rows {
+        suggestions.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(suggestions)
+}
+
+/// Close a pending suggestion. Returns false if it was already reviewed.
+pub fn review_suggestion(
+    db: &Db,
+    id: &str,
+    status: &str,
+    reviewed_by: Option<&str>,
+    reason: Option<&str>,
+    applied_version: Option<i32>,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "UPDATE suggestions SET status = ?1, reviewed_by = ?2, review_reason = ?3, applied_version = ?4, reviewed_at = datetime('now') \
+             WHERE id = ?5 AND status = 'pending'",
+            params![status, reviewed_by, reason, applied_version, id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Put an accepted suggestion back to pending, after its save failed.
+pub fn reopen_suggestion(db: &Db, id: &str) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "UPDATE suggestions SET status = 'pending', reviewed_by = NULL, reviewed_at = NULL \
+         WHERE id = ?1 AND status = 'accepted' AND applied_version IS NULL",
+        params![id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn set_suggestion_applied_version(
+    db: &Db,
+    id: &str,
+    applied_version: Option<i32>,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "UPDATE suggestions SET applied_version = ?2 WHERE id = ?1",
+        params![id, applied_version],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// --- Workflow operations ---
+
+/// The workspace's status workflow as stored JSON, if one is configured.
+pub fn get_workspace_workflow(db: &Db, workspace_id: &str) -> Result<Option<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT workflow FROM workspaces WHERE id = ?1",
+        params![workspace_id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(|w| w.flatten())
+    .map_err(|e| e.to_string())
+}
+
+pub fn set_workspace_workflow(
+    db: &Db,
+    workspace_id: &str,
+    workflow: Option<&str>,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "UPDATE workspaces SET workflow = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![workflow, workspace_id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+pub fn get_workspace_comment_policy(db: &Db, workspace_id: &str) -> Result<Option<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row(
+        "SELECT comment_policy FROM workspaces WHERE id = ?1",
+        params![workspace_id],
+        |row| row.get::<_, Option<String>>(0),
+    )
+    .optional()
+    .map(|p| p.flatten())
+    .map_err(|e| e.to_string())
+}
+
+pub fn set_workspace_comment_policy(
+    db: &Db,
+    workspace_id: &str,
+    policy: Option<&str>,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "UPDATE workspaces SET comment_policy = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![policy, workspace_id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Set a document's status without touching versions (workflow rejections).
+pub fn set_document_status(db: &Db, doc_id: &str, status: &str) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "UPDATE documents SET status = ?1, updated_at = datetime('now') WHERE id = ?2",
+            params![status, doc_id],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+pub fn create_approval(
+    db: &Db,
+    id: &str,
+    document_id: &str,
+    version_number: i32,
+    reviewer: &str,
+    decision: &str,
+    comment: &str,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "INSERT INTO document_approvals (id, document_id, version_number, reviewer, decision, comment) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![id, document_id, version_number, reviewer, decision, comment],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Reviewers whose latest decision on `version_number` is an approval.
+pub fn approved_reviewers(
+    db: &Db,
+    document_id: &str,
+    version_number: i32,
+) -> Result<Vec<String>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT reviewer FROM document_approvals a \
+             WHERE document_id = ?1 AND version_number = ?2 AND decision = 'approved' \
+             AND NOT EXISTS (SELECT 1 FROM document_approvals b WHERE b.document_id = a.document_id \
+                 AND b.version_number = a.version_number AND b.reviewer = a.reviewer AND b.rowid > a.rowid) \
+             ORDER BY rowid ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![document_id, version_number], |row| {
+            row.get::<_, String>(0)
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+pub fn list_approvals(db: &Db, document_id: &str) -> Result<Vec<Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, version_number, reviewer, decision, comment, created_at FROM document_approvals \
+             WHERE document_id = ?1 ORDER BY rowid ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![document_id], |row| {
+            Ok(serde_json::json!({
+                "id": row.get::<_, String>(0)?,
+                "version_number": row.get::<_, i32>(1)?,
+                "reviewer": row.get::<_, String>(2)?,
+                "decision": row.get::<_, String>(3)?,
+                "comment": row.get::<_, Option<String>>(4)?.unwrap_or_default(),
+                "created_at": row.get::<_, String>(5)?,
+            }))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut approvals = Vec::new();
+    for row in rows {
+        approvals.push(row.map_err(|e| e.to_string())?);
+    }
+    Ok(approvals)
+}
+
+// --- Reaction operations ---
+
+/// Votes that cancel each other: an author holds at most one of them per target.
+pub const VOTES: [&str; 2] = ["+1", "-1"];
+
+/// Add a reaction. Returns false if the author already had it. Casting a vote
+/// replaces the author's opposite vote on the same target.
+pub fn add_reaction(
+    db: &Db,
+    id: &str,
+    document_id: &str,
+    target_type: &str,
+    target_id: &str,
+    author_name: &str,
+    reaction: &str,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    if VOTES.contains(&reaction) {
+        conn.execute(
+            "DELETE FROM reactions WHERE target_type = ?1 AND target_id = ?2 AND author_name = ?3 AND reaction IN ('+1', '-1') AND reaction != ?4",
+            params![target_type, target_id, author_name, reaction],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    let rows = conn
+        .execute(
+            "INSERT OR IGNORE INTO reactions (id, document_id, target_type, target_id, author_name, reaction) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![id, document_id, target_type, target_id, author_name, reaction],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+pub fn remove_reaction(
+    db: &Db,
+    target_type: &str,
+    target_id: &str,
+    author_name: &str,
+    reaction: &str,
+) -> Result<bool, String> {
+    let conn = db.conn.lock().unwrap();
+    let rows = conn
+        .execute(
+            "DELETE FROM reactions WHERE target_type = ?1 AND target_id = ?2 AND author_name = ?3 AND reaction = ?4",
+            params![target_type, target_id, author_name, reaction],
+        )
+        .map_err(|e| e.to_string())?;
+    Ok(rows > 0)
+}
+
+/// Aggregated reactions for every `target_type` target on a document, keyed
+/// by target id: `{"reactions": {"+1": {"count", "authors"}}, "score"}` where
+/// score is +1 votes minus -1 votes.
+pub fn reaction_summaries(
+    db: &Db,
+    document_id: &str,
+    target_type: &str,
+) -> Result<std::collections::HashMap<String, Value>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT target_id, reaction, author_name FROM reactions WHERE document_id = ?1 AND target_type = ?2 ORDER BY rowid ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![document_id, target_type], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut summaries: std::collections::HashMap<String, Value> = std::collections::HashMap::new();
+    for row in rows {
+        let (target_id, reaction, author) = row.map_err(|e| e.to_string())?;
+        let summary = summaries
+            .entry(target_id)
+            .or_insert_with(|| serde_json::json!({"reactions": {}, "score": 0}));
+        let entry = summary["reactions"]
+            .as_object_mut()
+            .unwrap()
+            .entry(reaction.clone())
+            .or_insert_with(|| serde_json::json!({"count": 0, "authors": []}));
+        entry["count"] = serde_json::json!(entry["count"].as_i64().unwrap_or(0) + 1);
+        entry["authors"]
+            .as_array_mut()
+            .unwrap()
+            .push(serde_json::json!(author));
+        let delta = match reaction.as_str() {
+            "+1" => 1,
+            "-1" => -1,
+            _ => 0,
+        };
+        summary["score"] = serde_json::json!(summary["score"].as_i64().unwrap_or(0) + delta);
+    }
+    Ok(summaries)
+}
+
+// --- Notification operations ---
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_notification(
+    db: &Db,
+    id: &str,
+    workspace_id: &str,
+    recipient: &str,
+    kind: &str,
+    document_id: &str,
+    comment_id: Option<&str>,
+    author_name: &str,
+    excerpt: &str,
+) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    conn.execute(
+        "INSERT INTO notifications (id, workspace_id, recipient, kind, document_id, comment_id, author_name, excerpt) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![id, workspace_id, recipient, kind, document_id, comment_id, author_name, excerpt],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// A recipient's notifications, newest first, plus their unread count.
+pub fn list_notifications(
+    db: &Db,
+    workspace_id: &str,
+    recipient: &str,
+    unread_only: bool,
+    include_drafts: bool,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<Value>, i64), String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT n.id, n.recipient, n.kind, n.document_id, d.title, d.slug, n.comment_id, n.author_name, n.excerpt, n.read_at, n.created_at \
+             FROM notifications n LEFT JOIN documents d ON d.id = n.document_id \
+             WHERE n.workspace_id = ?1 AND n.recipient = ?2 AND (?3 = 0 OR n.read_at IS NULL) \
+             AND (?6 = 1 OR d.status = 'published') \
+             ORDER BY n.created_at DESC, n.rowid DESC LIMIT ?4 OFFSET ?5",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![
+                workspace_id,
+                recipient,
+                unread_only as i32,
+                limit,
+                offset,
+                include_drafts as i32
+            ],
+            |row| {
+                let read_at: Option<String> = row.get(9)?;
+                Ok(serde_json::json!({
+                    "id": row.get::<_, String>(0)?,
+                    "recipient": row.get::<_, String>(1)?,
+                    "kind": row.get::<_, String>(2)?,
+                    "document_id": row.get::<_, Option<String>>(3)?,
+                    "document_title": row.get::<_, Option<String>>(4)?,
+                    "document_slug": row.get::<_, Option<String>>(5)?,
+                    "comment_id": row.get::<_, Option<String>>(6)?,
+                    "author_name": row.get::<_, String>(7)?,
+                    "excerpt": row.get::<_, String>(8)?,
+                    "read": read_at.is_some(),
+                    "read_at": read_at,
+                    "created_at": row.get::<_, String>(10)?,
+                }))
+            },
+        )
+        .map_err(|e| e.to_string())?;
+
+    let mut notifications = Vec::new();
+    for row in rows {
+        notifications.push(row.map_err(|e| e.to_string())?);
+    }
+    let unread: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM notifications n LEFT JOIN documents d ON d.id = n.document_id \
+             WHERE n.workspace_id = ?1 AND n.recipient = ?2 AND n.read_at IS NULL \
+             AND (?3 = 1 OR d.status = 'published')",
+            params![workspace_id, recipient, include_drafts as i32],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    Ok((notifications, unread))
+}
+
+/// Mark a recipient's notifications read (or unread). `ids` of None means all.
+pub fn mark_notifications(
+    db: &Db,
+    workspace_id: &str,
+    recipient: &str,
+    ids: Option<&[String]>,
+    read: bool,
+) -> Result<usize, String> {
+    let conn = db.conn.lock().unwrap();
+    let set = if read {
+        "read_at = COALESCE(read_at, datetime('now'))"
+    } else {
+        "read_at = NULL"
+    };
+    match ids {
+        None => conn.execute(
+            &format!("UPDATE notifications SET {} WHERE workspace_id = ?1 AND recipient = ?2", set),
+            params![workspace_id, recipient],
+        ),
+        Some(ids) => {
+            let mut total = 0;
+            for id in ids {
+                total += conn
+                    .execute(
+                        &format!(
+                            "UPDATE notifications SET {} WHERE workspace_id = ?1 AND recipient = ?2 AND id = ?3",
+                            set
+                        ),
+                        params![workspace_id, recipient, id],
+                    )
+                    .map_err(|e| e.to_string())?;
+            }
+            Ok(total)
+        }
+    }
+    .map_err(|e| e.to_string())
+}
+
+// --- Activity log ---
+
+pub fn record_activity(db: &Db, evt: &SseEvent) -> Result<(), String> {
+    let conn = db.conn.lock().unwrap();
+    // Events for deleted or unknown workspaces have nowhere to go
+    conn.execute(
+        "INSERT INTO activity (workspace_id, event_type, document_id, actor, data) \
+         SELECT ?1, ?2, ?3, ?4, ?5 WHERE EXISTS (SELECT 1 FROM workspaces WHERE id = ?1)",
+        params![
+            evt.workspace_id,
+            evt.event_type,
+            evt.document_id(),
+            evt.actor(),
+            evt.data.to_string()
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Highest activity sequence number so far (0 when the log is empty).
+pub fn last_activity_seq(db: &Db) -> Result<i64, String> {
+    let conn = db.conn.lock().unwrap();
+    conn.query_row("SELECT COALESCE(MAX(seq), 0) FROM activity", [], |row| {
+        row.get(0)
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// Activity of every workspace after `after`, oldest first, as `(seq, event)`.
+pub fn activity_after(db: &Db, after: i64, limit: i64) -> Result<Vec<(i64, SseEvent)>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT seq, workspace_id, event_type, data FROM activity \
+             WHERE seq > ?1 ORDER BY seq ASC LIMIT ?2",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![after, limit], |row| {
+            let data: String = row.get(3)?;
+            Ok((
+                row.get::<_, i64>(0)?,
+                SseEvent {
+                    workspace_id: row.get(1)?,
+                    event_type: row.get(2)?,
+                    data: serde_json::from_str(&data).unwrap_or(Value::Null),
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+/// One page of a workspace's activity log as `(seq, created_at, event)`,
+/// restricted to `after < seq < before`. Newest first unless `ascending`.
+#[allow(clippy::too_many_arguments)]
+pub fn list_activity(
+    db: &Db,
+    workspace_id: &str,
+    after: Option<i64>,
+    before: Option<i64>,
+    actor: Option<&str>,
+    ascending: bool,
+    limit: i64,
+) -> Result<Vec<(i64, String, SseEvent)>, String> {
+    let conn = db.conn.lock().unwrap();
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT seq, event_type, data, created_at FROM activity \
+             WHERE workspace_id = ?1 AND (?2 IS NULL OR seq > ?2) AND (?3 IS NULL OR seq < ?3) \
+             AND (?4 IS NULL OR actor = ?4 COLLATE NOCASE) ORDER BY seq {} LIMIT ?5",
+            if ascending { "ASC" } else { "DESC" }
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![workspace_id, after, before, actor, limit], |row| {
+            let data: String = row.get(2)?;
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(3)?,
+                SseEvent {
+                    workspace_id: workspace_id.to_string(),
+                    event_type: row.get(1)?,
+                    data: serde_json::from_str(&data).unwrap_or(Value::Null),
+                },
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
 }
@@ -0,0 +1,172 @@
+//! Workspace-defined document types.
+//!
+//! A type gives documents a JSON Schema for their structured `fields` object
+//! and, optionally, headings their markdown body must contain. Documents are
+//! checked against their type whenever they are created or saved.
+
+use pulldown_cmark::{Event, Parser, Tag, TagEnd};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct DocumentType {
+    pub slug: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// JSON Schema for the document's `fields` object.
+    #[serde(default = "default_schema")]
+    pub schema: Value,
+    /// Headings (any level, case-insensitive) the body must contain.
+    #[serde(default)]
+    pub required_headings: Vec<String>,
+}
+
+fn default_schema() -> Value {
+    serde_json::json!({"type": "object"})
+}
+
+/// One reason a document does not fit its type.
+#[derive(Clone, Debug, Serialize, PartialEq)]
+pub struct Problem {
+    /// `fields` or `content`.
+    pub source: &'static str,
+    /// JSON pointer into `fields`, or the missing heading.
+    pub path: String,
+    pub message: String,
+}
+
+/// Text of every heading in `markdown`, in order.
+pub fn headings(markdown: &str) -> Vec<String> {
+    let mut found = Vec::new();
+    let mut current: Option<String> = None;
+    for event in Parser::new(markdown) {
+        match event {
+            Event::Start(Tag::Heading { .. }) => current = Some(String::new()),
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(text) = current.take() {
+                    found.push(text.trim().to_string());
+                }
+            }
+            Event::Text(t) | Event::Code(t) => {
+                if let Some(text) = current.as_mut() {
+                    text.push_str(&t);
+                }
+            }
+            _ => {}
+        }
+    }
+    found
+}
+
+impl DocumentType {
+    /// Check the slug and headings, and that the schema compiles.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".to_string());
+        }
+        let slug_ok =
+            |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_';
+        if self.slug.is_empty() || !self.slug.chars().all(slug_ok) {
+            return Err(format!("invalid slug '{}'", self.slug));
+        }
+        if !self.schema.is_object() {
+            return Err("schema must be a JSON Schema object".to_string());
+        }
+        jsonschema::validator_for(&self.schema).map_err(|e| format!("invalid schema: {}", e))?;
+        if self.required_headings.iter().any(|h| h.trim().is_empty()) {
+            return Err("required headings cannot be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// Everything wrong with `fields` and the markdown `body`; empty when valid.
+    pub fn check(&self, fields: &Value, body: &str) -> Vec<Problem> {
+        let mut problems = Vec::new();
+        if !fields.is_object() {
+            problems.push(Problem {
+                source: "fields",
+                path: String::new(),
+                message: "fields must be an object".to_string(),
+            });
+        } else if let Ok(validator) = jsonschema::validator_for(&self.schema) {
+            // Schemas are checked on save; one that no longer compiles accepts everything
+            problems.extend(validator.iter_errors(fields).map(|e| Problem {
+                source: "fields",
+                path: e.instance_path.to_string(),
+                message: e.to_string(),
+            }));
+        }
+
+        let present: Vec<String> = headings(body).iter().map(|h| h.to_lowercase()).collect();
+        for heading in &self.required_headings {
+            if !present.contains(&heading.trim().to_lowercase()) {
+                problems.push(Problem {
+                    source: "content",
+                    path: heading.clone(),
+                    message: format!("Missing required heading '{}'", heading),
+                });
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn incident() -> DocumentType {
+        DocumentType {
+            slug: "incident".to_string(),
+            name: "Incident".to_string(),
+            description: String::new(),
+            schema: json!({
+                "type": "object",
+                "required": ["severity", "owner"],
+                "properties": {
+                    "severity": {"enum": ["sev1", "sev2", "sev3"]},
+                    "owner": {"type": "string"}
+                }
+            }),
+            required_headings: vec!["Timeline".to_string(), "Root cause".to_string()],
+        }
+    }
+
+    #[test]
+    fn reports_schema_and_heading_problems() {
+        let t = incident();
+        assert!(t.validate().is_ok());
+        let body = "# Outage\n\n## timeline\n\n- 10:00 paged\n";
+        let problems = t.check(&json!({"severity": "sev9"}), body);
+        let paths: Vec<(&str, &str)> = problems
+            .iter()
+            .map(|p| (p.source, p.path.as_str()))
+            .collect();
+        assert_eq!(
+            paths,
+            vec![
+                ("fields", "/severity"),
+                ("fields", ""),
+                ("content", "Root cause")
+            ]
+        );
+        let ok = t.check(
+            &json!({"severity": "sev2", "owner": "ops"}),
+            "## Timeline\n\n## Root `cause`\n",
+        );
+        assert!(ok.is_empty(), "{:?}", ok);
+    }
+
+    #[test]
+    fn rejects_bad_definitions() {
+        let mut t = incident();
+        t.schema = json!({"type": 5});
+        assert!(t.validate().is_err());
+        let mut t = incident();
+        t.slug = "Bad Slug".to_string();
+        assert!(t.validate().is_err());
+        assert!(incident().check(&json!([1]), "").len() == 3);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::db::Db;
 use rocket::serde::json::Value;
 use std::sync::Arc;
 use tokio::sync::broadcast;
@@ -7,8 +8,13 @@ use tokio::sync::broadcast;
 #[derive(Clone)]
 pub struct EventBus {
     sender: Arc<broadcast::Sender<SseEvent>>,
+    /// When set, every emitted event is also appended to the activity log.
+    journal: Option<Db>,
 }
 
+/// Events too short-lived to keep in the activity log.
+const UNJOURNALED: [&str; 1] = ["presence.ping"];
+
 #[derive(Clone, Debug)]
 pub struct SseEvent {
     pub workspace_id: String,
@@ -27,18 +33,208 @@ impl EventBus {
         let (sender, _) = broadcast::channel(256);
         EventBus {
             sender: Arc::new(sender),
+            journal: None,
+        }
+    }
+
+    /// A bus that also persists events to the workspace activity log.
+    pub fn with_journal(db: Db) -> Self {
+        EventBus {
+            journal: Some(db),
+            ..Self::new()
         }
     }
 
     pub fn emit(&self, workspace_id: &str, event_type: &str, data: Value) {
-        let _ = self.sender.send(SseEvent {
+        let evt = SseEvent {
             workspace_id: workspace_id.to_string(),
             event_type: event_type.to_string(),
             data,
-        });
+        };
+        if let Some(db) = &self.journal {
+            if !UNJOURNALED.contains(&event_type) {
+                if let Err(e) = crate::db::record_activity(db, &evt) {
+                    eprintln!("⚠️  Failed to record {} activity: {}", event_type, e);
+                }
+            }
+        }
+        let _ = self.sender.send(evt);
     }
 
     pub fn subscribe(&self) -> broadcast::Receiver<SseEvent> {
         self.sender.subscribe()
     }
 }
+
+impl SseEvent {
+    /// The document this event concerns, if any.
+    /// `document.*` events carry it as `id`; everything else uses `document_id`.
+    pub fn document_id(&self) -> Option<&str> {
+        if let Some(id) = self.data.get("document_id").and_then(|v| v.as_str()) {
+            return Some(id);
+        }
+        if self.event_type.starts_with("document.") {
+            return self.data.get("id").and_then(|v| v.as_str());
+        }
+        None
+    }
+
+    /// Who caused this event, when the payload names someone.
+    pub fn actor(&self) -> Option<&str> {
+        ["author_name", "reviewer", "reviewed_by", "locked_by"]
+            .iter()
+            .filter_map(|f| self.data.get(*f).and_then(|v| v.as_str()))
+            .find(|s| !s.is_empty())
+    }
+}
+
+/// Server-side filter applied to a subscriber's event stream.
+/// Empty lists match everything; non-empty lists must all match.
+#[derive(Clone, Debug, Default)]
+pub struct EventFilter {
+    /// Event type patterns, e.g. `document.*` or `comment.created`.
+    pub types: Vec<String>,
+    /// Only events concerning one of these document ids.
+    pub document_ids: Vec<String>,
+    /// Only events concerning a document carrying at least one of these tags.
+    pub tags: Vec<String>,
+}
+
+/// Split a comma-separated query value into trimmed, non-empty parts.
+pub fn split_list(value: Option<&str>) -> Vec<String> {
+    value
+        .unwrap_or("")
+        .split(',')
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .collect()
+}
+
+/// Match an event type against a pattern. `*` matches everything,
+/// a trailing `.*` matches any event in that namespace.
+pub fn type_matches(pattern: &str, event_type: &str) -> bool {
+    if pattern == "*" {
+        return true;
+    }
+    match pattern.strip_suffix(".*") {
+        Some(prefix) => event_type
+            .strip_prefix(prefix)
+            .is_some_and(|rest| rest.starts_with('.')),
+        None => pattern == event_type,
+    }
+}
+
+impl EventFilter {
+    pub fn new(types: Option<&str>, docs: Option<&str>, tags: Option<&str>) -> Self {
+        EventFilter {
+            types: split_list(types),
+            document_ids: split_list(docs),
+            tags: split_list(tags),
+        }
+    }
+
+    /// Check type and document filters. Tag matching needs the document's
+    /// current tags, so callers pass a lookup used only when tags are filtered.
+    pub fn matches<F>(&self, evt: &SseEvent, lookup_tags: F) -> bool
+    where
+        F: FnOnce(&str) -> Vec<String>,
+    {
+        if !self.types.is_empty() && !self.types.iter().any(|p| type_matches(p, &evt.event_type)) {
+            return false;
+        }
+        if self.document_ids.is_empty() && self.tags.is_empty() {
+            return true;
+        }
+        let doc_id = match evt.document_id() {
+            Some(id) => id,
+            None => return false,
+        };
+        if !self.document_ids.is_empty() && !self.document_ids.iter().any(|d| d == doc_id) {
+            return false;
+        }
+        if !self.tags.is_empty() {
+            let doc_tags = lookup_tags(doc_id);
+            if !self.tags.iter().any(|t| doc_tags.contains(t)) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// [`matches`](Self::matches) for streams: the tag lookup runs on a
+    /// blocking thread so it never stalls the async executor.
+    pub async fn matches_in(&self, db: &Db, evt: &SseEvent) -> bool {
+        let tags = match evt.document_id() {
+            Some(id) if !self.tags.is_empty() => {
+                let (db, id) = (db.clone(), id.to_string());
+                tokio::task::spawn_blocking(move || {
+                    crate::db::get_document_tags(&db, &id).unwrap_or_default()
+                })
+                .await
+                .unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+        self.matches(evt, |_| tags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::serde::json::json;
+
+    fn evt(event_type: &str, data: Value) -> SseEvent {
+        SseEvent {
+            workspace_id: "ws".to_string(),
+            event_type: event_type.to_string(),
+            data,
+        }
+    }
+
+    #[test]
+    fn type_patterns() {
+        assert!(type_matches("*", "lock.acquired"));
+        assert!(type_matches("document.*", "document.updated"));
+        assert!(!type_matches("document.*", "documents.updated"));
+        assert!(type_matches("comment.created", "comment.created"));
+        assert!(!type_matches("comment.created", "comment.deleted"));
+    }
+
+    #[test]
+    fn document_id_from_either_field() {
+        assert_eq!(
+            evt("document.updated", json!({"id": "d1"})).document_id(),
+            Some("d1")
+        );
+        assert_eq!(
+            evt("comment.created", json!({"id": "c1", "document_id": "d2"})).document_id(),
+            Some("d2")
+        );
+        assert_eq!(
+            evt("workspace.created", json!({"id": "ws"})).document_id(),
+            None
+        );
+    }
+
+    #[test]
+    fn filter_by_type_doc_and_tag() {
+        let f = EventFilter::new(Some("document.*"), Some("d1"), None);
+        assert!(f.matches(&evt("document.updated", json!({"id": "d1"})), |_| vec![]));
+        assert!(!f.matches(&evt("document.updated", json!({"id": "d2"})), |_| vec![]));
+        assert!(!f.matches(
+            &evt("comment.created", json!({"document_id": "d1"})),
+            |_| vec![]
+        ));
+
+        let f = EventFilter::new(None, None, Some("rfc, spec"));
+        let tagged = |_: &str| vec!["spec".to_string()];
+        assert!(f.matches(&evt("lock.acquired", json!({"document_id": "d1"})), tagged));
+        assert!(!f.matches(
+            &evt("lock.acquired", json!({"document_id": "d1"})),
+            |_| vec![]
+        ));
+        assert!(!f.matches(&evt("workspace.created", json!({"id": "ws"})), tagged));
+    }
+}
@@ -0,0 +1,301 @@
+//! Workspace export as a zip archive.
+//!
+//! ```text
+//! workspace.json                 name, description, export options
+//! docs/<slug>.md                 front matter (title, tags, status, …) + body
+//! docs/<slug>.versions.json      full version history        (versions=true)
+//! docs/<slug>.comments.json      comments                    (comments=true)
+//! attachments.json               id → file path manifest     (attachments=true)
+//! attachments/<id>/<filename>    attachment bytes
+//! ```
+//!
+//! The archive is written on a blocking thread into a bounded pipe that the
+//! response reads from, so only one document or attachment is held in memory
+//! at a time.
+
+use crate::attachments::BlobStore;
+use crate::db::Db;
+use rocket::http::{ContentType, Header};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use rocket::tokio::io::{AsyncWriteExt, DuplexStream};
+use serde_json::{json, Map, Value};
+use std::io::{self, Write};
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+/// Version of the archive layout, recorded in `workspace.json`.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Front matter keys written from document columns; they win over the
+/// document's own front matter keys of the same name.
+pub const DOCUMENT_KEYS: [&str; 10] = [
+    "title",
+    "slug",
+    "summary",
+    "tags",
+    "status",
+    "author_name",
+    "created_at",
+    "updated_at",
+    "doc_type",
+    "fields",
+];
+
+#[derive(Clone, Copy, Debug)]
+pub struct ExportOptions {
+    pub versions: bool,
+    pub comments: bool,
+    pub attachments: bool,
+}
+
+/// A document as a markdown file: its own front matter merged with the
+/// document's title, slug, tags, status, author and timestamps, then the body.
+pub fn document_markdown(doc: &Value) -> String {
+    let content = doc["content"].as_str().unwrap_or("");
+    let (mut front, body) = crate::frontmatter::parse_lenient(content);
+    for key in DOCUMENT_KEYS {
+        let value = &doc[key];
+        let empty = value.is_null()
+            || value.as_str() == Some("")
+            || value.as_object().is_some_and(|o| o.is_empty());
+        if !empty {
+            front.insert(key.to_string(), value.clone());
+        }
+    }
+    let yaml = serde_yaml::to_string(&Value::Object(front)).unwrap_or_default();
+    format!("---\n{}---\n{}", yaml, body)
+}
+
+fn zip_error(e: zip::result::ZipError) -> String {
+    format!("zip: {}", e)
+}
+
+/// Start a deflated archive entry; write its bytes into `zip` next.
+pub(crate) fn start_file<W: Write>(
+    zip: &mut zip::ZipWriter<zip::write::StreamWriter<W>>,
+    path: &str,
+) -> Result<(), String> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    zip.start_file(path, options).map_err(zip_error)
+}
+
+/// Add an archive entry holding `bytes`.
+pub(crate) fn add_file<W: Write>(
+    zip: &mut zip::ZipWriter<zip::write::StreamWriter<W>>,
+    path: &str,
+    bytes: &[u8],
+) -> Result<(), String> {
+    start_file(zip, path)?;
+    zip.write_all(bytes).map_err(|e| e.to_string())
+}
+
+fn pretty(value: &Value) -> Vec<u8> {
+    serde_json::to_vec_pretty(value).unwrap_or_default()
+}
+
+/// Write the workspace's archive to `out`.
+pub fn write_zip<W: Write>(
+    db: &Db,
+    blobs: &BlobStore,
+    workspace_id: &str,
+    options: ExportOptions,
+    out: W,
+) -> Result<(), String> {
+    let workspace = crate::db::get_workspace(db, workspace_id)?
+        .ok_or_else(|| "Workspace not found".to_string())?;
+    let doc_ids = crate::db::document_ids(db, workspace_id)?;
+    let mut zip = zip::ZipWriter::new_stream(out);
+
+    let manifest = json!({
+        "format_version": FORMAT_VERSION,
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "workspace": {
+            "id": workspace["id"],
+            "name": workspace["name"],
+            "description": workspace["description"],
+            "is_public": workspace["is_public"],
+            "created_at": workspace["created_at"],
+        },
+        "documents": doc_ids.len(),
+        "includes": {
+            "versions": options.versions,
+            "comments": options.comments,
+            "attachments": options.attachments,
+        },
+    });
+    add_file(&mut zip, "workspace.json", &pretty(&manifest))?;
+
+    for doc_id in &doc_ids {
+        let Some(doc) = crate::db::get_document_by_id(db, doc_id)? else {
+            continue;
+        };
+        let slug = doc["slug"].as_str().unwrap_or(doc_id);
+        add_file(
+            &mut zip,
+            &format!("docs/{}.md", slug),
+            document_markdown(&doc).as_bytes(),
+        )?;
+
+        if options.versions {
+            let mut versions = Vec::new();
+            for v in crate::db::list_versions(db, doc_id, i32::MAX, 0)?
+                .iter()
+                .rev()
+            {
+                let number = v["version_number"].as_i64().unwrap_or(0) as i32;
+                if let Some(full) = crate::db::get_version(db, doc_id, number)? {
+                    versions.push(full);
+                }
+            }
+            add_file(
+                &mut zip,
+                &format!("docs/{}.versions.json", slug),
+                &pretty(&Value::Array(versions)),
+            )?;
+        }
+        if options.comments {
+            let comments = crate::db::list_comments(db, doc_id)?;
+            add_file(
+                &mut zip,
+                &format!("docs/{}.comments.json", slug),
+                &pretty(&Value::Array(comments)),
+            )?;
+        }
+    }
+
+    if options.attachments {
+        let rows = crate::db::list_attachments(db, workspace_id)?;
+        let mut entries = Vec::new();
+        for row in rows {
+            let id = row["id"].as_str().unwrap_or("");
+            let sha256 = row["sha256"].as_str().unwrap_or("");
+            let path = format!(
+                "attachments/{}/{}",
+                id,
+                row["filename"].as_str().unwrap_or("file")
+            );
+            let mut blob = match blobs.open(sha256) {
+                Ok(file) => file,
+                // A blob lost from disk should not sink the whole export
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            start_file(&mut zip, &path)?;
+            io::copy(&mut blob, &mut zip).map_err(|e| e.to_string())?;
+            let mut entry: Map<String, Value> = row.as_object().cloned().unwrap_or_default();
+            entry.remove("workspace_id");
+            entry.insert("path".to_string(), json!(path));
+            entries.push(Value::Object(entry));
+        }
+        add_file(
+            &mut zip,
+            "attachments.json",
+            &pretty(&Value::Array(entries)),
+        )?;
+    }
+
+    zip.finish().map_err(zip_error)?;
+    Ok(())
+}
+
+/// Blocking `Write` into the async pipe the response streams from.
+pub struct PipeWriter {
+    handle: rocket::tokio::runtime::Handle,
+    pipe: DuplexStream,
+}
+
+impl Write for PipeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.handle.block_on(self.pipe.write(buf))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.handle.block_on(self.pipe.flush())
+    }
+}
+
+/// A zip archive streamed to the client as it is written.
+pub struct ZipDownload {
+    reader: DuplexStream,
+    filename: String,
+}
+
+impl ZipDownload {
+    /// Start writing the workspace's archive in the background.
+    pub fn start(
+        db: Db,
+        blobs: BlobStore,
+        workspace_id: String,
+        options: ExportOptions,
+        filename: String,
+    ) -> Self {
+        Self::spawn(filename, move |out| {
+            write_zip(&db, &blobs, &workspace_id, options, out)
+                .map_err(|e| format!("Export of {} failed: {}", workspace_id, e))
+        })
+    }
+
+    /// Run `write` on a blocking thread, streaming what it writes.
+    pub fn spawn(
+        filename: String,
+        write: impl FnOnce(PipeWriter) -> Result<(), String> + Send + 'static,
+    ) -> Self {
+        let (writer, reader) = rocket::tokio::io::duplex(64 * 1024);
+        let handle = rocket::tokio::runtime::Handle::current();
+        rocket::tokio::task::spawn_blocking(move || {
+            let out = PipeWriter {
+                handle,
+                pipe: writer,
+            };
+            // The client sees a truncated archive if this fails midway
+            if let Err(e) = write(out) {
+                eprintln!("⚠️  {}", e);
+            }
+        });
+        ZipDownload { reader, filename }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ZipDownload {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .header(ContentType::ZIP)
+            .header(Header::new(
+                "Content-Disposition",
+                format!("attachment; filename=\"{}\"", self.filename),
+            ))
+            .streamed_body(self.reader)
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merges_front_matter() {
+        let doc = json!({
+            "title": "Runbook",
+            "slug": "runbook",
+            "summary": "",
+            "tags": ["ops"],
+            "status": "published",
+            "author_name": "Ana",
+            "created_at": "2024-01-01 10:00:00",
+            "updated_at": "2024-01-02 10:00:00",
+            "doc_type": null,
+            "fields": {},
+            "content": "---\nowner: ops\ntitle: Old\n---\n# Runbook\n",
+        });
+        let md = document_markdown(&doc);
+        let (front, body) = crate::frontmatter::parse(&md).unwrap();
+        assert_eq!(body, "# Runbook\n");
+        assert_eq!(front["title"], "Runbook");
+        assert_eq!(front["owner"], "ops");
+        assert_eq!(front["tags"], json!(["ops"]));
+        assert!(!front.contains_key("summary") && !front.contains_key("doc_type"));
+        assert!(!front.contains_key("fields"));
+    }
+}
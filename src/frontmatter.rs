@@ -0,0 +1,133 @@
+//! YAML front matter at the top of document content.
+//!
+//! Front matter opens with a `---` line as the very first line of the content
+//! and closes at the next `---` or `...` line. It must be a YAML mapping. The
+//! parsed mapping is stored as the document's `metadata`; rendering and word
+//! counts only see the body after it.
+
+use rocket::request::{self, FromRequest, Outcome, Request};
+use serde_json::{Map, Value};
+
+fn is_fence(line: &str, closing: bool) -> bool {
+    let line = line.trim_end();
+    line == "---" || (closing && line == "...")
+}
+
+/// Split `content` into (front matter YAML, body). `None` when the content
+/// does not start with a closed front matter block.
+pub fn split(content: &str) -> Option<(&str, &str)> {
+    let content = content.strip_prefix('\u{feff}').unwrap_or(content);
+    let first_end = content.find('\n')?;
+    if !is_fence(&content[..first_end], false) {
+        return None;
+    }
+    let yaml_start = first_end + 1;
+    let mut pos = yaml_start;
+    while pos <= content.len() {
+        let line_end = content[pos..]
+            .find('\n')
+            .map(|i| pos + i)
+            .unwrap_or(content.len());
+        if is_fence(&content[pos..line_end], true) {
+            let body_start = (line_end + 1).min(content.len());
+            return Some((&content[yaml_start..pos], &content[body_start..]));
+        }
+        if line_end == content.len() {
+            break;
+        }
+        pos = line_end + 1;
+    }
+    None
+}
+
+/// Parse the front matter of `content` into a JSON object and return it with
+/// the body. Content without front matter yields an empty object.
+pub fn parse(content: &str) -> Result<(Map<String, Value>, &str), String> {
+    let Some((yaml, body)) = split(content) else {
+        return Ok((Map::new(), content));
+    };
+    let value: Value =
+        serde_yaml::from_str(yaml).map_err(|e| format!("Invalid front matter: {}", e))?;
+    match value {
+        Value::Object(map) => Ok((map, body)),
+        Value::Null => Ok((Map::new(), body)),
+        _ => Err("Invalid front matter: expected a mapping of fields".to_string()),
+    }
+}
+
+/// Like [`parse`], but content whose front matter does not parse is treated
+/// as having none. Used when re-saving content that was already accepted.
+pub fn parse_lenient(content: &str) -> (Map<String, Value>, &str) {
+    parse(content).unwrap_or_else(|_| (Map::new(), content))
+}
+
+/// SQLite JSON path for a dotted metadata key: `owner.team` → `$."owner"."team"`.
+pub fn json_path(key: &str) -> Result<String, String> {
+    let mut path = String::from("$");
+    for part in key.split('.') {
+        if part.is_empty() || part.contains('"') {
+            return Err(format!("Invalid metadata filter key 'meta.{}'", key));
+        }
+        path.push_str(&format!(".\"{}\"", part));
+    }
+    Ok(path)
+}
+
+/// `meta.<key>=<value>` query parameters, e.g. `?meta.priority=high`.
+/// Keys are returned without the `meta.` prefix.
+pub struct MetaFilters(pub Vec<(String, String)>);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for MetaFilters {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> request::Outcome<Self, Self::Error> {
+        let filters = req
+            .uri()
+            .query()
+            .map(|q| {
+                q.segments()
+                    .filter_map(|(k, v)| {
+                        k.strip_prefix("meta.")
+                            .map(|k| (k.to_string(), v.to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        Outcome::Success(MetaFilters(filters))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_and_parses_front_matter() {
+        let content = "---\r\nowner: alice\npriority: high\ntags: [a, b]\n...\n# Title\n";
+        let (meta, body) = parse(content).unwrap();
+        assert_eq!(meta["owner"], "alice");
+        assert_eq!(meta["tags"][1], "b");
+        assert_eq!(body, "# Title\n");
+
+        // A leading thematic break without a closing fence is just content
+        let (meta, body) = parse("---\nnot front matter\n").unwrap();
+        assert!(meta.is_empty());
+        assert_eq!(body, "---\nnot front matter\n");
+
+        let (meta, body) = parse("---\n---\nbody").unwrap();
+        assert!(meta.is_empty());
+        assert_eq!(body, "body");
+    }
+
+    #[test]
+    fn rejects_non_mappings_and_bad_keys() {
+        assert!(parse("---\n- a\n- b\n---\n").is_err());
+        assert!(parse("---\nkey: [unclosed\n---\n").is_err());
+        assert_eq!(parse_lenient("---\n- a\n---\nx").1, "---\n- a\n---\nx");
+
+        assert_eq!(json_path("owner.team").unwrap(), "$.\"owner\".\"team\"");
+        assert!(json_path("a..b").is_err());
+        assert!(json_path("a\"b").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Mirroring workspaces to bare git repositories on disk.
+//!
+//! Each mirrored workspace has `<GIT_MIRROR_DIR>/<workspace_id>.git` with one
+//! `<slug>.md` per document at the top of `main`. Every document version
+//! becomes a commit authored by its `author_name`, with the version's
+//! `change_description` as the message; renames and deletions get commits of
+//! their own. A background worker commits new versions as documents change.
+//!
+//! Commits pushed to the repository are read back by [`pushed_commits`] and
+//! turned into versions by the sync endpoint. The `git_commits` table records
+//! every commit the server wrote or ingested, so each is handled once.
+
+use crate::db::Db;
+use crate::events::EventBus;
+use git2::{FileMode, Oid, Repository, Signature, Time};
+use rocket::fairing::AdHoc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, MutexGuard};
+use tokio::sync::broadcast::error::RecvError;
+
+/// The mirrored branch.
+pub const BRANCH: &str = "refs/heads/main";
+
+const COMMITTER: &str = "Agent Docs";
+/// Documents only know author names; git also wants an address.
+const EMAIL: &str = "noreply@agent-docs.invalid";
+
+/// Where mirrors live, with a lock serialising writes to them.
+#[derive(Clone)]
+pub struct GitMirrors {
+    dir: PathBuf,
+    lock: Arc<Mutex<()>>,
+}
+
+impl GitMirrors {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        GitMirrors {
+            dir: dir.into(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    /// `GIT_MIRROR_DIR` (default `git`).
+    pub fn from_env() -> Self {
+        GitMirrors::new(std::env::var("GIT_MIRROR_DIR").unwrap_or_else(|_| "git".to_string()))
+    }
+
+    /// The workspace's bare repository.
+    pub fn path(&self, workspace_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.git", workspace_id))
+    }
+
+    /// Hold while reading or writing a mirror.
+    pub fn lock(&self) -> MutexGuard<'_, ()> {
+        self.lock.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Open the workspace's repository, creating it on first use.
+    pub fn open(&self, workspace_id: &str) -> Result<Repository, String> {
+        let path = self.path(workspace_id);
+        if path.exists() {
+            return Repository::open_bare(&path).map_err(|e| e.to_string());
+        }
+        let repo = Repository::init_bare(&path).map_err(|e| e.to_string())?;
+        repo.set_head(BRANCH).map_err(|e| e.to_string())?;
+        Ok(repo)
+    }
+}
+
+/// A pushed commit, with the `.md` files it changed.
+#[derive(Debug)]
+pub struct PushedCommit {
+    pub id: String,
+    pub author: String,
+    pub message: String,
+    pub files: Vec<PushedFile>,
+}
+
+/// A file as it was before and after a pushed commit; `None` when absent.
+#[derive(Debug)]
+pub struct PushedFile {
+    pub path: String,
+    pub base: Option<String>,
+    pub content: Option<String>,
+}
+
+/// Tip of `main`, `None` before the first commit.
+pub fn head(repo: &Repository) -> Option<Oid> {
+    repo.refname_to_id(BRANCH).ok()
+}
+
+// Helper: stored timestamps are UTC `YYYY-MM-DD HH:MM:SS`
+fn git_time(created_at: &str) -> Time {
+    let seconds = chrono::NaiveDateTime::parse_from_str(created_at, "%Y-%m-%d %H:%M:%S")
+        .map(|t| t.and_utc().timestamp())
+        .unwrap_or_else(|_| chrono::Utc::now().timestamp());
+    Time::new(seconds, 0)
+}
+
+/// A file change in a commit: write `Some(content)` or remove with `None`.
+type Change<'a> = (&'a str, Option<&'a [u8]>);
+
+// Helper: commit `changes` on top of `main` and move the branch, refusing if
+// someone pushed in between
+fn commit(
+    repo: &Repository,
+    changes: &[Change],
+    author: &str,
+    time: &Time,
+    message: &str,
+) -> Result<Oid, String> {
+    let err = |e: git2::Error| e.to_string();
+    let parent = match head(repo) {
+        Some(id) => Some(repo.find_commit(id).map_err(err)?),
+        None => None,
+    };
+    let base = match &parent {
+        Some(c) => Some(c.tree().map_err(err)?),
+        None => None,
+    };
+    let mut tree = repo.treebuilder(base.as_ref()).map_err(err)?;
+    for (path, content) in changes {
+        match content {
+            Some(bytes) => {
+                let blob = repo.blob(bytes).map_err(err)?;
+                tree.insert(path, blob, FileMode::Blob.into())
+                    .map_err(err)?;
+            }
+            None => {
+                if tree.get(path).map_err(err)?.is_some() {
+                    tree.remove(path).map_err(err)?;
+                }
+            }
+        }
+    }
+    let tree = repo.find_tree(tree.write().map_err(err)?).map_err(err)?;
+    let author = Signature::new(
+        if author.is_empty() { COMMITTER } else { author },
+        EMAIL,
+        time,
+    )
+    .map_err(err)?;
+    let committer = Signature::new(COMMITTER, EMAIL, time).map_err(err)?;
+    let parents: Vec<&git2::Commit> = parent.iter().collect();
+    let id = repo
+        .commit(None, &author, &committer, message, &tree, &parents)
+        .map_err(err)?;
+    match &parent {
+        Some(p) => repo.reference_matching(BRANCH, id, true, p.id(), message),
+        None => repo.reference(BRANCH, id, false, message),
+    }
+    .map_err(err)?;
+    Ok(id)
+}
+
+// Helper: the text of `path` in `tree`
+fn file_at(repo: &Repository, tree: Option<&git2::Tree>, path: &str) -> Option<String> {
+    let entry = tree?.get_name(path)?;
+    let blob = repo.find_blob(entry.id()).ok()?;
+    Some(String::from_utf8_lossy(blob.content()).into_owned())
+}
+
+/// Commit every document version the mirror does not have yet, after moving
+/// renamed documents and removing deleted ones. Call with the lock held.
+/// Returns how many commits were written.
+pub fn commit_pending(db: &Db, repo: &Repository, workspace_id: &str) -> Result<usize, String> {
+    let mut written = 0;
+    let now = Time::new(chrono::Utc::now().timestamp(), 0);
+
+    for (doc_id, path, slug) in crate::db::git_files(db, workspace_id)? {
+        let new_path = match slug {
+            Some(slug) if format!("{}.md", slug) == path => continue,
+            Some(slug) => Some(format!("{}.md", slug)),
+            None => None,
+        };
+        let tree = match head(repo) {
+            Some(id) => Some(
+                repo.find_commit(id)
+                    .and_then(|c| c.tree())
+                    .map_err(|e| e.to_string())?,
+            ),
+            None => None,
+        };
+        let content = file_at(repo, tree.as_ref(), &path).unwrap_or_default();
+        let mut changes: Vec<Change> = vec![(path.as_str(), None)];
+        let message = match &new_path {
+            Some(new_path) => {
+                changes.push((new_path.as_str(), Some(content.as_bytes())));
+                format!("Rename {} to {}", path, new_path)
+            }
+            None => format!("Delete {}", path),
+        };
+        let id = commit(repo, &changes, "", &now, &message)?;
+        crate::db::record_git_commit(db, workspace_id, &id.to_string(), &doc_id, None)?;
+        crate::db::set_git_file(db, workspace_id, &doc_id, new_path.as_deref())?;
+        written += 1;
+    }
+
+    for v in crate::db::unmirrored_versions(db, workspace_id)? {
+        let path = format!("{}.md", v.slug);
+        let changes: Vec<Change> = vec![(path.as_str(), Some(v.content.as_bytes()))];
+        let message = match v.change_description.trim() {
+            "" if v.version_number == 1 => format!("Create {}", path),
+            "" => format!("Update {}", path),
+            description => description.to_string(),
+        };
+        let time = git_time(&v.created_at);
+        let id = commit(repo, &changes, &v.author_name, &time, &message)?;
+        crate::db::record_git_commit(
+            db,
+            workspace_id,
+            &id.to_string(),
+            &v.document_id,
+            Some(v.version_number),
+        )?;
+        crate::db::set_git_file(db, workspace_id, &v.document_id, Some(&path))?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// Bring the workspace's mirror up to date with its documents.
+pub fn mirror(db: &Db, mirrors: &GitMirrors, workspace_id: &str) -> Result<usize, String> {
+    let _guard = mirrors.lock();
+    let repo = mirrors.open(workspace_id)?;
+    commit_pending(db, &repo, workspace_id)
+}
+
+/// Commits on `main` after `since` that the server neither wrote nor
+/// ingested, oldest first, with the top-level `.md` files each one changed
+/// relative to its first parent.
+pub fn pushed_commits(
+    db: &Db,
+    repo: &Repository,
+    workspace_id: &str,
+    since: Option<&str>,
+) -> Result<Vec<PushedCommit>, String> {
+    let err = |e: git2::Error| e.to_string();
+    let Some(tip) = head(repo) else {
+        return Ok(Vec::new());
+    };
+    let mut walk = repo.revwalk().map_err(err)?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+        .map_err(err)?;
+    walk.push(tip).map_err(err)?;
+    if let Some(since) = since.and_then(|s| Oid::from_str(s).ok()) {
+        // A commit lost to a force-push no longer bounds the walk
+        if repo.find_commit(since).is_ok() {
+            walk.hide(since).map_err(err)?;
+        }
+    }
+
+    let mut pushed = Vec::new();
+    for id in walk {
+        let id = id.map_err(err)?;
+        if crate::db::git_commit_known(db, workspace_id, &id.to_string())? {
+            continue;
+        }
+        let commit = repo.find_commit(id).map_err(err)?;
+        let tree = commit.tree().map_err(err)?;
+        let base = match commit.parent(0) {
+            Ok(parent) => Some(parent.tree().map_err(err)?),
+            Err(_) => None,
+        };
+        let mut names: Vec<String> = tree
+            .iter()
+            .chain(base.iter().flat_map(|t| t.iter()))
+            .filter_map(|entry| entry.name().map(str::to_string))
+            .filter(|name| name.ends_with(".md"))
+            .collect();
+        names.sort();
+        names.dedup();
+        let files = names
+            .into_iter()
+            .filter_map(|path| {
+                let before = base
+                    .as_ref()
+                    .and_then(|t| t.get_name(&path))
+                    .map(|e| e.id());
+                let after = tree.get_name(&path).map(|e| e.id());
+                (before != after).then(|| PushedFile {
+                    base: file_at(repo, base.as_ref(), &path),
+                    content: file_at(repo, Some(&tree), &path),
+                    path,
+                })
+            })
+            .collect();
+        pushed.push(PushedCommit {
+            id: id.to_string(),
+            author: commit.author().name().unwrap_or("").to_string(),
+            message: commit.message().unwrap_or("").trim().to_string(),
+            files,
+        });
+    }
+    Ok(pushed)
+}
+
+/// Background worker: commits new versions of mirrored workspaces as their
+/// documents change.
+pub async fn run_worker(db: Db, event_bus: EventBus, mirrors: GitMirrors) {
+    let mut rx = event_bus.subscribe();
+    loop {
+        let evt = match rx.recv().await {
+            Ok(evt) => evt,
+            Err(RecvError::Lagged(n)) => {
+                eprintln!("⚠️  Git mirror worker missed {} events", n);
+                continue;
+            }
+            Err(RecvError::Closed) => break,
+        };
+        if !evt.event_type.starts_with("document.") {
+            continue;
+        }
+        match crate::db::get_git_mirror(&db, &evt.workspace_id) {
+            Ok(Some(_)) => {}
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("⚠️  Git mirror lookup failed: {}", e);
+                continue;
+            }
+        }
+        let (db, mirrors) = (db.clone(), mirrors.clone());
+        let workspace_id = evt.workspace_id.clone();
+        let result =
+            tokio::task::spawn_blocking(move || mirror(&db, &mirrors, &workspace_id)).await;
+        match result {
+            Ok(Err(e)) => eprintln!("⚠️  Failed to mirror {}: {}", evt.workspace_id, e),
+            Err(e) => eprintln!("⚠️  Git mirror task failed: {}", e),
+            Ok(Ok(_)) => {}
+        }
+    }
+}
+
+/// Fairing that starts the mirror worker once Rocket has launched.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Git mirror worker", |rocket| {
+        Box::pin(async move {
+            let (Some(db), Some(bus), Some(mirrors)) = (
+                rocket.state::<Db>(),
+                rocket.state::<EventBus>(),
+                rocket.state::<GitMirrors>(),
+            ) else {
+                return;
+            };
+            tokio::spawn(run_worker(db.clone(), bus.clone(), mirrors.clone()));
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commits_and_reads_back_changes() {
+        let dir = std::env::temp_dir().join(format!("gitmirror-{}", uuid::Uuid::new_v4()));
+        let mirrors = GitMirrors::new(&dir);
+        let repo = mirrors.open("ws").unwrap();
+        assert!(head(&repo).is_none());
+        let time = git_time("2024-05-01 12:00:00");
+        assert_eq!(time.seconds(), 1714564800);
+
+        let first = commit(
+            &repo,
+            &[("a.md", Some(b"one"))],
+            "Ana",
+            &time,
+            "Create a.md",
+        )
+        .unwrap();
+        let second = commit(
+            &repo,
+            &[("a.md", Some(b"two")), ("b.md", Some(b"bee"))],
+            "",
+            &time,
+            "Edit",
+        )
+        .unwrap();
+        let commit = repo.find_commit(second).unwrap();
+        assert_eq!(commit.parent_id(0).unwrap(), first);
+        assert_eq!(commit.author().name(), Some(COMMITTER));
+        assert_eq!(
+            repo.find_commit(first).unwrap().author().name(),
+            Some("Ana")
+        );
+
+        let db = Db::new(":memory:");
+        let pushed = pushed_commits(&db, &repo, "ws", Some(&first.to_string())).unwrap();
+        assert_eq!(pushed.len(), 1);
+        assert_eq!(pushed[0].message, "Edit");
+        let files: Vec<(&str, Option<&str>, Option<&str>)> = pushed[0]
+            .files
+            .iter()
+            .map(|f| (f.path.as_str(), f.base.as_deref(), f.content.as_deref()))
+            .collect();
+        assert_eq!(
+            files,
+            vec![
+                ("a.md", Some("one"), Some("two")),
+                ("b.md", None, Some("bee"))
+            ]
+        );
+        std::fs::remove_dir_all(dir).ok();
+    }
+}
@@ -0,0 +1,624 @@
+//! Bulk import of a zip of markdown files: a docs folder, an Obsidian vault,
+//! or an archive from the export endpoint.
+//!
+//! Every `.md` file becomes a document. The title comes from front matter
+//! `title`, else the first `# heading`, else the filename; the slug from front
+//! matter `slug`, else the filename. The directory a file sits in is kept as
+//! the `folder` front matter key. Relative links between files become
+//! `[[slug]]` wiki-links, and files that links or `![[embeds]]` point at become
+//! attachments. Export archives (with a top-level `workspace.json`) import
+//! from `docs/`, and their `attachment:<id>` references are remapped through
+//! `attachments.json`.
+
+use crate::export::DOCUMENT_KEYS;
+use pulldown_cmark::{Event, HeadingLevel, LinkType, Options, Parser, Tag, TagEnd};
+use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::io::Read;
+use std::ops::Range;
+
+const DEFAULT_MAX_BYTES: usize = 50 * 1024 * 1024;
+
+/// Largest accepted archive, and the most it may unpack to:
+/// `IMPORT_MAX_BYTES` (default 50 MiB).
+pub fn max_bytes() -> usize {
+    std::env::var("IMPORT_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEFAULT_MAX_BYTES)
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    Invalid(String),
+    TooLarge,
+}
+
+/// A markdown file to import.
+#[derive(Debug)]
+pub struct Note {
+    /// Path within the archive, e.g. `guides/setup.md`.
+    pub path: String,
+    /// Directory of the file; `None` at the top level.
+    pub folder: Option<String>,
+    /// Filename without its extension.
+    pub stem: String,
+    pub title: String,
+    /// Document keys from the front matter (slug, summary, tags, status, …).
+    pub document: Map<String, Value>,
+    /// The rest of the front matter, plus `folder`.
+    pub metadata: Map<String, Value>,
+    pub body: String,
+}
+
+impl Note {
+    /// Stored content: the remaining front matter, then `body`.
+    pub fn content(&self, body: &str) -> String {
+        if self.metadata.is_empty() {
+            return body.to_string();
+        }
+        let yaml = serde_yaml::to_string(&Value::Object(self.metadata.clone())).unwrap_or_default();
+        format!("---\n{}---\n{}", yaml, body)
+    }
+}
+
+/// A file the import refuses, with an error code.
+#[derive(Debug)]
+pub struct Rejected {
+    pub path: String,
+    pub code: &'static str,
+    pub error: String,
+}
+
+/// An unpacked archive.
+#[derive(Debug, Default)]
+pub struct Archive {
+    pub notes: Vec<Note>,
+    pub rejected: Vec<Rejected>,
+    /// Entries that are never imported: hidden files and export sidecars.
+    pub skipped: Vec<String>,
+    /// Everything else by path; imported when a note refers to it.
+    files: HashMap<String, Vec<u8>>,
+    /// Attachment ids of an export archive → path of the file.
+    exported: HashMap<String, String>,
+}
+
+/// A note body with its links rewritten.
+#[derive(Debug)]
+pub struct Converted {
+    pub body: String,
+    /// Links turned into or pointed at wiki-links.
+    pub links: usize,
+    /// References rewritten to `attachment:<id>`.
+    pub attachments: usize,
+    /// Relative link targets that are not in the archive.
+    pub unresolved: Vec<String>,
+}
+
+// Helper: hidden entries (`.obsidian/`, `.DS_Store`) and macOS resource forks
+fn is_hidden(path: &str) -> bool {
+    path.split('/')
+        .any(|part| part.starts_with('.') || part == "__MACOSX")
+}
+
+fn is_markdown(path: &str) -> bool {
+    let lower = path.to_lowercase();
+    lower.ends_with(".md") || lower.ends_with(".markdown")
+}
+
+/// Unpack a zip. A single directory wrapping every entry is dropped, so
+/// zipping a folder imports its contents.
+pub fn read(bytes: &[u8], max_bytes: usize) -> Result<Archive, ReadError> {
+    let mut zip = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+        .map_err(|e| ReadError::Invalid(format!("Not a zip archive: {}", e)))?;
+    let mut archive = Archive::default();
+    let mut names = Vec::new();
+    for i in 0..zip.len() {
+        let file = zip
+            .by_index(i)
+            .map_err(|e| ReadError::Invalid(format!("Unreadable entry: {}", e)))?;
+        if file.is_dir() {
+            continue;
+        }
+        match file.enclosed_name() {
+            Some(path) => {
+                let path = path
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                names.push((i, path));
+            }
+            None => archive.skipped.push(file.name().to_string()),
+        }
+    }
+    let wrapper = names
+        .first()
+        .and_then(|(_, path)| path.split_once('/'))
+        .map(|(dir, _)| format!("{}/", dir))
+        .filter(|dir| names.iter().all(|(_, path)| path.starts_with(dir)));
+    if let Some(dir) = wrapper {
+        for (_, path) in names.iter_mut() {
+            path.drain(..dir.len());
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut total = 0usize;
+    for (i, path) in names {
+        if is_hidden(&path) {
+            archive.skipped.push(path);
+            continue;
+        }
+        let mut file = zip
+            .by_index(i)
+            .map_err(|e| ReadError::Invalid(format!("Unreadable entry: {}", e)))?;
+        let mut bytes = Vec::new();
+        let remaining = (max_bytes - total) as u64 + 1;
+        file.by_ref()
+            .take(remaining)
+            .read_to_end(&mut bytes)
+            .map_err(|e| ReadError::Invalid(format!("Unreadable entry {}: {}", path, e)))?;
+        total += bytes.len();
+        if total > max_bytes {
+            return Err(ReadError::TooLarge);
+        }
+        entries.push((path, bytes));
+    }
+
+    let export = entries.iter().any(|(path, _)| path == "workspace.json");
+    if export {
+        if let Some((_, manifest)) = entries.iter().find(|(p, _)| p == "attachments.json") {
+            let rows: Vec<Value> = serde_json::from_slice(manifest).unwrap_or_default();
+            for row in rows {
+                if let (Some(id), Some(path)) = (row["id"].as_str(), row["path"].as_str()) {
+                    archive.exported.insert(id.to_string(), path.to_string());
+                }
+            }
+        }
+    }
+
+    for (path, bytes) in entries {
+        let doc_path = if export {
+            match path.strip_prefix("docs/") {
+                Some(p) if is_markdown(p) => Some(p.to_string()),
+                _ if path.starts_with("attachments/") => None,
+                _ => {
+                    archive.skipped.push(path);
+                    continue;
+                }
+            }
+        } else {
+            Some(path.clone()).filter(|p| is_markdown(p))
+        };
+        let Some(doc_path) = doc_path else {
+            archive.files.insert(path, bytes);
+            continue;
+        };
+        let note = String::from_utf8(bytes)
+            .map_err(|_| ("INVALID_ENCODING", "Not UTF-8 text".to_string()))
+            .and_then(|text| parse_note(&doc_path, &text).map_err(|e| ("INVALID_FRONT_MATTER", e)));
+        match note {
+            Ok(note) => archive.notes.push(note),
+            Err((code, error)) => archive.rejected.push(Rejected {
+                path: doc_path,
+                code,
+                error,
+            }),
+        }
+    }
+    archive.notes.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(archive)
+}
+
+// Helper: text of the first level-one heading
+fn first_heading(markdown: &str) -> Option<String> {
+    let mut text: Option<String> = None;
+    for event in Parser::new_ext(markdown, Options::empty()) {
+        match event {
+            Event::Start(Tag::Heading {
+                level: HeadingLevel::H1,
+                ..
+            }) => text = Some(String::new()),
+            Event::Text(s) | Event::Code(s) => {
+                if let Some(t) = text.as_mut() {
+                    t.push_str(&s);
+                }
+            }
+            Event::End(TagEnd::Heading(_)) => {
+                if let Some(t) = text.take().filter(|t| !t.trim().is_empty()) {
+                    return Some(t.trim().to_string());
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split a markdown file into a [`Note`].
+pub fn parse_note(path: &str, text: &str) -> Result<Note, String> {
+    let (front, body) = crate::frontmatter::parse(text)?;
+    let (folder, filename) = match path.rsplit_once('/') {
+        Some((dir, name)) => (Some(dir.to_string()), name),
+        None => (None, path),
+    };
+    let stem = filename
+        .rsplit_once('.')
+        .map(|(s, _)| s)
+        .unwrap_or(filename)
+        .to_string();
+    let (document, mut metadata): (Map<String, Value>, Map<String, Value>) = front
+        .into_iter()
+        .partition(|(k, _)| DOCUMENT_KEYS.contains(&k.as_str()));
+    if let Some(f) = &folder {
+        metadata
+            .entry("folder".to_string())
+            .or_insert_with(|| json!(f));
+    }
+    let title = document
+        .get("title")
+        .and_then(Value::as_str)
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+        .or_else(|| first_heading(body))
+        .unwrap_or_else(|| stem.clone());
+    Ok(Note {
+        path: path.to_string(),
+        folder,
+        stem,
+        title,
+        document,
+        metadata,
+        body: body.to_string(),
+    })
+}
+
+// Helper: decode %XX escapes in a link destination
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes
+            .get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+// Helper: URLs, anchors and `attachment:` references are not archive paths
+fn is_external(dest: &str) -> bool {
+    if dest.is_empty() || dest.starts_with('#') || dest.starts_with("//") {
+        return true;
+    }
+    match dest.find(':') {
+        Some(i) => dest[..i]
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)),
+        None => false,
+    }
+}
+
+// Helper: join a relative path onto a directory, resolving `.` and `..`
+fn join(dir: Option<&str>, path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = dir.map(|d| d.split('/').collect()).unwrap_or_default();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            p => parts.push(p),
+        }
+    }
+    Some(parts.join("/")).filter(|p| !p.is_empty())
+}
+
+impl Archive {
+    /// Paths of the files notes may refer to.
+    pub fn file_paths(&self) -> impl Iterator<Item = &String> {
+        self.files.keys()
+    }
+
+    pub fn file(&self, path: &str) -> Option<&[u8]> {
+        self.files.get(path).map(Vec::as_slice)
+    }
+
+    // Helper: archive paths a link from `folder` may mean — relative to the
+    // linking file first, then to the archive root
+    fn candidates(folder: Option<&str>, target: &str) -> Vec<String> {
+        let mut found = Vec::new();
+        if let Some(rooted) = target.strip_prefix('/') {
+            found.extend(join(None, rooted));
+        } else {
+            found.extend(join(folder, target));
+            if folder.is_some() && !target.starts_with("./") && !target.starts_with("../") {
+                found.extend(join(None, target));
+            }
+        }
+        found
+    }
+
+    // Helper: the note a link target means, trying `<target>.md` for
+    // extensionless Obsidian-style links
+    fn find_note(&self, folder: Option<&str>, target: &str) -> Option<&Note> {
+        let candidates = Self::candidates(folder, target);
+        candidates
+            .iter()
+            .flat_map(|c| [c.clone(), format!("{}.md", c)])
+            .find_map(|c| self.notes.iter().find(|n| n.path == c))
+    }
+
+    fn find_file(&self, folder: Option<&str>, target: &str) -> Option<String> {
+        Self::candidates(folder, target)
+            .into_iter()
+            .find(|c| self.files.contains_key(c))
+    }
+
+    /// Rewrite `note`'s links. `slugs` maps note paths to the slugs they are
+    /// imported as; `attach` stores a file by path and returns its attachment id.
+    pub fn convert(
+        &self,
+        note: &Note,
+        slugs: &HashMap<String, String>,
+        mut attach: impl FnMut(&str) -> Option<String>,
+    ) -> Converted {
+        let folder = note.folder.as_deref();
+        let slug_of = |n: &Note| slugs.get(&n.path).cloned();
+        let mut converted = Converted {
+            body: String::new(),
+            links: 0,
+            attachments: 0,
+            unresolved: Vec::new(),
+        };
+
+        // Markdown links and images with relative destinations
+        let body = note.body.as_str();
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        let parser = Parser::new_ext(body, Options::empty()).into_offset_iter();
+        for (event, range) in parser {
+            let (is_image, dest) = match event {
+                Event::Start(Tag::Link {
+                    link_type: LinkType::Inline,
+                    dest_url,
+                    ..
+                }) => (false, dest_url),
+                Event::Start(Tag::Image {
+                    link_type: LinkType::Inline,
+                    dest_url,
+                    ..
+                }) => (true, dest_url),
+                _ => continue,
+            };
+            if is_external(&dest) || edits.iter().any(|(r, _)| r.end > range.start) {
+                continue;
+            }
+            let (path, fragment) = match dest.split_once('#') {
+                Some((p, f)) => (p, Some(percent_decode(f))),
+                None => (dest.as_ref(), None),
+            };
+            let path = percent_decode(path.split('?').next().unwrap_or(path));
+            let source = &body[range.clone()];
+            let note_slug = if is_image {
+                None
+            } else {
+                self.find_note(folder, &path).and_then(slug_of)
+            };
+            if let Some(slug) = note_slug {
+                let label = source
+                    .rfind("](")
+                    .map(|end| &source[1..end])
+                    .unwrap_or("")
+                    .replace(['[', ']', '|'], "")
+                    .replace('\n', " ");
+                let label = label.trim();
+                let mut link = format!("[[{}", slug);
+                if let Some(f) = fragment.filter(|f| !f.is_empty()) {
+                    link.push('#');
+                    link.push_str(&f);
+                }
+                if !label.is_empty() && label != slug {
+                    link.push('|');
+                    link.push_str(label);
+                }
+                link.push_str("]]");
+                edits.push((range, link));
+                converted.links += 1;
+            } else if let Some(file) = self.find_file(folder, &path) {
+                let Some(start) = source.rfind(dest.as_ref()) else {
+                    continue;
+                };
+                if let Some(id) = attach(&file) {
+                    let at = range.start + start;
+                    edits.push((
+                        at..at + dest.len(),
+                        format!("{}{}", crate::attachments::SCHEME, id),
+                    ));
+                    converted.attachments += 1;
+                }
+            } else {
+                converted.unresolved.push(dest.to_string());
+            }
+        }
+        let mut out = String::with_capacity(body.len());
+        let mut last = 0;
+        for (range, replacement) in edits {
+            out.push_str(&body[last..range.start]);
+            out.push_str(&replacement);
+            last = range.end;
+        }
+        out.push_str(&body[last..]);
+
+        // Obsidian embeds of files: ![[diagram.png]]
+        let embed = Regex::new(r"!\[\[([^\[\]|#\n]+)(?:\|([^\[\]\n]*))?\]\]").unwrap();
+        let out = embed
+            .replace_all(&out, |caps: &regex::Captures| {
+                let target = caps[1].trim();
+                let id = self.find_file(folder, target).and_then(|p| attach(&p));
+                match id {
+                    Some(id) => {
+                        converted.attachments += 1;
+                        let alt = caps.get(2).map(|m| m.as_str()).unwrap_or(target);
+                        format!("![{}]({}{})", alt, crate::attachments::SCHEME, id)
+                    }
+                    None => caps[0].to_string(),
+                }
+            })
+            .into_owned();
+
+        // Wiki-links by file name or path: [[Setup Guide]], [[guides/setup]]
+        let (out, retargeted) = crate::links::retarget(&out, |target| {
+            let lower = target.to_lowercase();
+            let note = self.find_note(folder, target).or_else(|| {
+                let mut by_stem = self.notes.iter().filter(|n| n.stem.to_lowercase() == lower);
+                by_stem.next().filter(|_| by_stem.next().is_none())
+            })?;
+            slug_of(note).filter(|slug| slug != target)
+        });
+        converted.links += retargeted;
+
+        // attachment:<id> references of an export archive
+        let mut out = out;
+        for old in crate::attachments::references(&out) {
+            let Some(path) = self.exported.get(&old) else {
+                continue;
+            };
+            if let Some(id) = attach(path) {
+                let scheme = crate::attachments::SCHEME;
+                out = out.replace(&format!("{}{}", scheme, old), &format!("{}{}", scheme, id));
+                converted.attachments += 1;
+            }
+        }
+        converted.body = out;
+        converted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn zip_of(files: &[(&str, &str)]) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for (path, content) in files {
+            zip.start_file(*path, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            zip.write_all(content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    #[test]
+    fn parses_titles_folders_and_document_keys() {
+        let note = parse_note(
+            "guides/setup.md",
+            "---\ntags: [ops]\nowner: ana\n---\n# Getting Started\n\nText",
+        )
+        .unwrap();
+        assert_eq!(note.title, "Getting Started");
+        assert_eq!(note.stem, "setup");
+        assert_eq!(note.folder.as_deref(), Some("guides"));
+        assert_eq!(note.document["tags"], json!(["ops"]));
+        assert_eq!(note.metadata["folder"], "guides");
+        assert!(note.content(&note.body).contains("owner: ana"));
+        assert!(!note.content(&note.body).contains("tags"));
+
+        let note = parse_note("Meeting Notes.md", "---\ntitle: Weekly\n---\nbody").unwrap();
+        assert_eq!(note.title, "Weekly");
+        assert_eq!(note.content(&note.body), "body");
+        assert_eq!(parse_note("x.md", "plain").unwrap().title, "x");
+        assert!(parse_note("x.md", "---\n[oops\n---\n").is_err());
+    }
+
+    #[test]
+    fn reads_archives() {
+        let bytes = zip_of(&[
+            ("vault/index.md", "# Home"),
+            ("vault/guides/setup.md", "# Setup"),
+            ("vault/img/logo.png", "png"),
+            ("vault/.obsidian/app.json", "{}"),
+            ("vault/bad.md", "---\n: [\n---\n"),
+        ]);
+        let archive = read(&bytes, 1024).unwrap();
+        let paths: Vec<&str> = archive.notes.iter().map(|n| n.path.as_str()).collect();
+        assert_eq!(paths, vec!["guides/setup.md", "index.md"]);
+        assert_eq!(archive.file("img/logo.png"), Some(&b"png"[..]));
+        assert_eq!(archive.skipped, vec![".obsidian/app.json"]);
+        assert_eq!(archive.rejected[0].code, "INVALID_FRONT_MATTER");
+        assert!(matches!(read(&bytes, 10), Err(ReadError::TooLarge)));
+        assert!(matches!(read(b"nope", 10), Err(ReadError::Invalid(_))));
+
+        let export = zip_of(&[
+            ("workspace.json", "{}"),
+            ("docs/a.md", "![x](attachment:old-id)"),
+            ("docs/a.versions.json", "[]"),
+            (
+                "attachments.json",
+                r#"[{"id": "old-id", "path": "attachments/old-id/x.png"}]"#,
+            ),
+            ("attachments/old-id/x.png", "png"),
+        ]);
+        let archive = read(&export, 1024).unwrap();
+        assert_eq!(archive.notes[0].path, "a.md");
+        assert_eq!(archive.skipped.len(), 3);
+        let converted = archive.convert(&archive.notes[0], &HashMap::new(), |path| {
+            assert_eq!(path, "attachments/old-id/x.png");
+            Some("new-id".to_string())
+        });
+        assert_eq!(converted.body, "![x](attachment:new-id)");
+    }
+
+    #[test]
+    fn converts_links() {
+        let bytes = zip_of(&[
+            ("index.md", "See [setup](guides/setup.md#install), [[Setup]], [[Missing]] and `[x](guides/setup.md)`.\n\n![logo](img/logo%20v2.png) ![[img/logo v2.png|Logo]] [web](https://example.com) [gone](old.md)"),
+            ("guides/setup.md", "Back [home](../index.md) or [[index|home]]"),
+            ("img/logo v2.png", "png"),
+        ]);
+        let archive = read(&bytes, 4096).unwrap();
+        let slugs: HashMap<String, String> = [
+            ("index.md".to_string(), "index".to_string()),
+            ("guides/setup.md".to_string(), "setup-guide".to_string()),
+        ]
+        .into();
+        let mut attached = Vec::new();
+        let index = archive.notes.iter().find(|n| n.path == "index.md").unwrap();
+        let converted = archive.convert(index, &slugs, |path| {
+            attached.push(path.to_string());
+            Some("a1".to_string())
+        });
+        assert_eq!(
+            converted.body,
+            "See [[setup-guide#install|setup]], [[setup-guide]], [[Missing]] and `[x](guides/setup.md)`.\n\n\
+             ![logo](attachment:a1) ![Logo](attachment:a1) [web](https://example.com) [gone](old.md)"
+        );
+        assert_eq!(converted.links, 2);
+        assert_eq!(converted.attachments, 2);
+        assert_eq!(converted.unresolved, vec!["old.md"]);
+        assert_eq!(attached, vec!["img/logo v2.png", "img/logo v2.png"]);
+
+        let setup = archive
+            .notes
+            .iter()
+            .find(|n| n.path == "guides/setup.md")
+            .unwrap();
+        let converted = archive.convert(setup, &slugs, |_| None);
+        assert_eq!(converted.body, "Back [[index|home]] or [[index|home]]");
+        assert_eq!(converted.links, 1);
+    }
+}
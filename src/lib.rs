@@ -1,8 +1,26 @@
+#![recursion_limit = "256"]
+
+pub mod anchors;
+pub mod attachments;
 pub mod auth;
 pub mod db;
+pub mod doctypes;
 pub mod events;
+pub mod export;
+pub mod frontmatter;
+pub mod gitmirror;
+pub mod import;
+pub mod links;
+pub mod mentions;
+pub mod moderation;
+pub mod negotiate;
 pub mod rate_limit;
 pub mod routes;
+pub mod site;
+pub mod templates;
+pub mod webhooks;
+pub mod workflow;
+pub mod ws;
 
 use rocket::fs::FileServer;
 use rocket::serde::json::{json, Json, Value};
@@ -23,6 +41,11 @@ fn not_found(_req: &Request) -> Json<Value> {
     Json(json!({"error": "Not found", "code": "NOT_FOUND"}))
 }
 
+#[catch(413)]
+fn payload_too_large(_req: &Request) -> Json<Value> {
+    Json(json!({"error": "Request body too large", "code": "PAYLOAD_TOO_LARGE"}))
+}
+
 #[catch(422)]
 fn unprocessable(_req: &Request) -> Json<Value> {
     Json(json!({"error": "Invalid request body", "code": "UNPROCESSABLE_ENTITY"}))
@@ -47,6 +70,24 @@ pub async fn spa_fallback(_path: std::path::PathBuf) -> Option<rocket::fs::Named
 }
 
 pub fn build_rocket(db: db::Db) -> rocket::Rocket<rocket::Build> {
+    build_rocket_with_blobs(db, attachments::BlobStore::from_env())
+}
+
+/// Like [`build_rocket`], with attachment bytes stored in `blobs`.
+pub fn build_rocket_with_blobs(
+    db: db::Db,
+    blobs: attachments::BlobStore,
+) -> rocket::Rocket<rocket::Build> {
+    build_rocket_with_storage(db, blobs, gitmirror::GitMirrors::from_env())
+}
+
+/// Like [`build_rocket`], with attachment bytes stored in `blobs` and git
+/// mirrors kept under `mirrors`.
+pub fn build_rocket_with_storage(
+    db: db::Db,
+    blobs: attachments::BlobStore,
+    mirrors: gitmirror::GitMirrors,
+) -> rocket::Rocket<rocket::Build> {
     let static_dir = std::env::var("STATIC_DIR").unwrap_or_else(|_| "../frontend/dist".to_string());
     let has_frontend = std::path::Path::new(&static_dir)
         .join("index.html")
@@ -60,12 +101,21 @@ pub fn build_rocket(db: db::Db) -> rocket::Rocket<rocket::Build> {
     let rate_limiter = rate_limit::RateLimiter::new(Duration::from_secs(3600), rate_limit);
 
     // SSE event bus
-    let event_bus = events::EventBus::new();
+    let event_bus = events::EventBus::with_journal(db.clone());
+
+    // Multipart uploads may carry a whole attachment plus the form around it
+    let figment = rocket::Config::figment()
+        .merge(("limits.file", blobs.max_bytes))
+        .merge(("limits.data-form", blobs.max_bytes + 64 * 1024));
 
-    let mut rocket = rocket::build()
+    let mut rocket = rocket::custom(figment)
         .manage(db)
+        .manage(blobs)
+        .manage(mirrors)
         .manage(rate_limiter)
         .manage(event_bus)
+        .attach(webhooks::fairing())
+        .attach(gitmirror::fairing())
         .mount(
             "/api/v1",
             rocket::routes![
@@ -76,6 +126,8 @@ pub fn build_rocket(db: db::Db) -> rocket::Rocket<rocket::Build> {
                 routes::create_document,
                 routes::list_documents,
                 routes::get_document,
+                routes::get_backlinks,
+                routes::list_broken_links,
                 routes::update_document,
                 routes::delete_document,
                 routes::list_versions,
@@ -83,17 +135,68 @@ pub fn build_rocket(db: db::Db) -> rocket::Rocket<rocket::Build> {
                 routes::get_diff,
                 routes::create_comment,
                 routes::list_comments,
+                routes::add_document_reaction,
+                routes::remove_document_reaction,
+                routes::add_comment_reaction,
+                routes::remove_comment_reaction,
+                routes::create_suggestion,
+                routes::list_suggestions,
+                routes::accept_suggestion,
+                routes::reject_suggestion,
                 routes::acquire_lock,
                 routes::release_lock,
                 routes::renew_lock,
                 routes::delete_comment,
                 routes::update_comment,
+                routes::list_templates,
+                routes::get_template,
+                routes::create_template,
+                routes::update_template,
+                routes::delete_template,
+                routes::render_template,
+                routes::list_doc_types,
+                routes::get_doc_type,
+                routes::create_doc_type,
+                routes::update_doc_type,
+                routes::delete_doc_type,
+                routes::validate_doc_type,
+                routes::upload_attachment_form,
+                routes::upload_attachment,
+                routes::list_attachments,
+                routes::get_attachment,
+                routes::delete_attachment,
+                routes::collect_attachments,
+                routes::export_workspace,
+                routes::import_documents,
+                routes::enable_git_mirror,
+                routes::get_git_mirror,
+                routes::disable_git_mirror,
+                routes::sync_git_mirror,
+                routes::get_comment_policy,
+                routes::set_comment_policy,
+                routes::list_pending_comments,
+                routes::approve_comment,
                 routes::search_documents,
                 routes::restore_version,
+                routes::get_activity,
+                routes::get_inbox,
+                routes::mark_inbox_read,
+                routes::get_workflow,
+                routes::set_workflow,
+                routes::list_approvals,
+                routes::review_document,
                 routes::health,
                 routes::openapi_spec,
                 routes::llms_txt,
                 routes::event_stream,
+                routes::document_event_stream,
+                routes::websocket,
+                routes::create_webhook,
+                routes::list_webhooks,
+                routes::update_webhook,
+                routes::delete_webhook,
+                routes::list_webhook_deliveries,
+                routes::redeliver_webhook,
             ],
         )
         .register(
@@ -101,14 +204,18 @@ pub fn build_rocket(db: db::Db) -> rocket::Rocket<rocket::Build> {
             catchers![
                 unauthorized,
                 not_found,
+                payload_too_large,
                 unprocessable,
                 too_many_requests,
                 internal_error,
             ],
         );
 
-    // Root-level /llms.txt (before SPA fallback)
-    rocket = rocket.mount("/", rocket::routes![routes::root_llms_txt]);
+    // Root-level /llms.txt and old-slug redirects (before SPA fallback)
+    rocket = rocket.mount(
+        "/",
+        rocket::routes![routes::root_llms_txt, routes::redirect_renamed_document],
+    );
 
     if has_frontend {
         eprintln!("📁 Serving frontend from {}", static_dir);
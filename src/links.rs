@@ -0,0 +1,210 @@
+//! `[[slug]]` wiki-links between documents of a workspace.
+//!
+//! A link is `[[slug]]`, `[[slug#heading]]`, optionally followed by `|label`.
+//! Links inside code spans and code blocks are left alone.
+
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use std::ops::Range;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct WikiLink {
+    /// Slug of the linked document.
+    pub target: String,
+    /// Heading within the target, as written.
+    pub heading: Option<String>,
+    pub label: Option<String>,
+}
+
+/// A link occurrence: the whole `[[...]]` and the target slug's byte ranges.
+struct Occurrence {
+    span: Range<usize>,
+    target: Range<usize>,
+    link: WikiLink,
+}
+
+// Helper: byte ranges of code spans and code blocks
+fn code_ranges(markdown: &str) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut block_depth = 0;
+    for (event, range) in Parser::new_ext(markdown, Options::empty()).into_offset_iter() {
+        match event {
+            Event::Start(Tag::CodeBlock(_)) => {
+                if block_depth == 0 {
+                    ranges.push(range);
+                }
+                block_depth += 1;
+            }
+            Event::End(pulldown_cmark::TagEnd::CodeBlock) => block_depth -= 1,
+            Event::Code(_) => ranges.push(range),
+            _ => {}
+        }
+    }
+    ranges
+}
+
+fn occurrences(markdown: &str) -> Vec<Occurrence> {
+    let code = code_ranges(markdown);
+    let in_code = |i: usize| code.iter().any(|r| r.contains(&i));
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(open) = markdown[pos..].find("[[").map(|i| pos + i) {
+        let inner_start = open + 2;
+        let Some(close) = markdown[inner_start..].find("]]").map(|i| inner_start + i) else {
+            break;
+        };
+        let inner = &markdown[inner_start..close];
+        if in_code(open) || inner.contains(['[', ']', '\n']) {
+            pos = inner_start;
+            continue;
+        }
+        let (reference, label) = match inner.split_once('|') {
+            Some((r, l)) => (r, Some(l.trim().to_string()).filter(|l| !l.is_empty())),
+            None => (inner, None),
+        };
+        let (slug, heading) = match reference.split_once('#') {
+            Some((s, h)) => (s, Some(h.trim().to_string()).filter(|h| !h.is_empty())),
+            None => (reference, None),
+        };
+        let target = slug.trim();
+        if target.is_empty() {
+            pos = close + 2;
+            continue;
+        }
+        let target_start = inner_start + (slug.len() - slug.trim_start().len());
+        found.push(Occurrence {
+            span: open..close + 2,
+            target: target_start..target_start + target.len(),
+            link: WikiLink {
+                target: target.to_string(),
+                heading,
+                label,
+            },
+        });
+        pos = close + 2;
+    }
+    found
+}
+
+/// Every wiki-link in `markdown`, in order of appearance.
+pub fn extract(markdown: &str) -> Vec<WikiLink> {
+    occurrences(markdown).into_iter().map(|o| o.link).collect()
+}
+
+/// URL fragment for a heading: lowercase words joined by `-`.
+pub fn anchor(heading: &str) -> String {
+    heading
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+pub(crate) fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Replace wiki-links with HTML links to the workspace's document pages, ready
+/// for the markdown renderer.
+pub fn resolve(workspace_id: &str, markdown: &str) -> String {
+    resolve_with(markdown, |slug| {
+        Some(format!("/workspace/{}/doc/{}", workspace_id, slug))
+    })
+}
+
+/// Replace wiki-links with HTML links to `page(slug)`. Links to slugs without
+/// a page become plain `<span class="wiki-link missing">` labels.
+pub fn resolve_with(markdown: &str, page: impl Fn(&str) -> Option<String>) -> String {
+    let mut out = String::with_capacity(markdown.len());
+    let mut last = 0;
+    for o in occurrences(markdown) {
+        out.push_str(&markdown[last..o.span.start]);
+        let link = &o.link;
+        let label = link.label.clone().unwrap_or_else(|| match &link.heading {
+            Some(h) => format!("{}#{}", link.target, h),
+            None => link.target.clone(),
+        });
+        match page(&link.target) {
+            Some(mut href) => {
+                if let Some(h) = &link.heading {
+                    href.push('#');
+                    href.push_str(&anchor(h));
+                }
+                out.push_str(&format!(
+                    "<a class=\"wiki-link\" href=\"{}\">{}</a>",
+                    escape_html(&href),
+                    escape_html(&label)
+                ));
+            }
+            None => out.push_str(&format!(
+                "<span class=\"wiki-link missing\">{}</span>",
+                escape_html(&label)
+            )),
+        }
+        last = o.span.end;
+    }
+    out.push_str(&markdown[last..]);
+    out
+}
+
+/// Point links at `old` to `new`, keeping headings and labels. `None` when
+/// nothing links to `old`.
+pub fn rewrite(markdown: &str, old: &str, new: &str) -> Option<String> {
+    let (out, changed) = retarget(markdown, |target| (target == old).then(|| new.to_string()));
+    (changed > 0).then_some(out)
+}
+
+/// Replace the target of every link for which `f` returns a new one, keeping
+/// headings and labels. Returns the new markdown and how many links changed.
+pub fn retarget(markdown: &str, mut f: impl FnMut(&str) -> Option<String>) -> (String, usize) {
+    let mut out = String::with_capacity(markdown.len());
+    let mut last = 0;
+    let mut changed = 0;
+    for o in occurrences(markdown) {
+        let Some(new) = f(&o.link.target) else {
+            continue;
+        };
+        out.push_str(&markdown[last..o.target.start]);
+        out.push_str(&new);
+        last = o.target.end;
+        changed += 1;
+    }
+    out.push_str(&markdown[last..]);
+    (out, changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_links_outside_code() {
+        let md = "See [[setup]] and [[api-guide#Auth Tokens|the auth docs]].\n\n\
+                  `[[not-a-link]]`\n\n```\n[[nor-this]]\n```\n[[ ]] [[a\nb]]";
+        let links = extract(md);
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].target, "setup");
+        assert_eq!(links[1].heading.as_deref(), Some("Auth Tokens"));
+        assert_eq!(links[1].label.as_deref(), Some("the auth docs"));
+    }
+
+    #[test]
+    fn resolves_and_rewrites() {
+        let html = resolve("ws1", "Read [[guide#Getting Started]] & [[x|<b>]]");
+        assert_eq!(
+            html,
+            "Read <a class=\"wiki-link\" href=\"/workspace/ws1/doc/guide#getting-started\">guide#Getting Started</a> \
+             & <a class=\"wiki-link\" href=\"/workspace/ws1/doc/x\">&lt;b&gt;</a>"
+        );
+
+        let md = "[[old]], [[ old#Intro|intro ]], [[older]] and `[[old]]`";
+        assert_eq!(
+            rewrite(md, "old", "new").unwrap(),
+            "[[new]], [[ new#Intro|intro ]], [[older]] and `[[old]]`"
+        );
+        assert_eq!(rewrite(md, "missing", "new"), None);
+    }
+}
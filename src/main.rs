@@ -1,11 +1,47 @@
 use agent_docs::db::Db;
 
+const SITE_USAGE: &str = "usage: agent-docs site <workspace_id> <out_dir> [--template <file.html>]";
+
+// Helper: `agent-docs site` — write a workspace's static site to a directory
+fn generate_site(db: &Db, args: &[String]) -> Result<(), String> {
+    let mut positional = Vec::new();
+    let mut template = agent_docs::site::SiteTemplate::default();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg == "--template" {
+            let path = args.next().ok_or(SITE_USAGE)?;
+            let html = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+            template = agent_docs::site::SiteTemplate::parse(&html)?;
+        } else {
+            positional.push(arg);
+        }
+    }
+    let [workspace_id, out_dir] = positional[..] else {
+        return Err(SITE_USAGE.to_string());
+    };
+    let site = agent_docs::site::build(db, workspace_id, &template)?;
+    let blobs = agent_docs::attachments::BlobStore::from_env();
+    let written = site.write_dir(&blobs, std::path::Path::new(out_dir))?;
+    eprintln!("🌐 Wrote {} files to {}", written, out_dir);
+    Ok(())
+}
+
 #[rocket::main]
 #[allow(clippy::result_large_err)]
 async fn main() -> Result<(), rocket::Error> {
     dotenvy::dotenv().ok();
 
     let db_path = std::env::var("DATABASE_PATH").unwrap_or_else(|_| "agent_docs.db".to_string());
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("site") {
+        let db = Db::new(&db_path);
+        if let Err(e) = generate_site(&db, &args[1..]) {
+            eprintln!("❌ {}", e);
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     eprintln!("📄 Agent Docs starting...");
     eprintln!("💾 Database: {}", db_path);
 
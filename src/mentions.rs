@@ -0,0 +1,100 @@
+//! `@name` mentions in comment and document markdown.
+
+use pulldown_cmark::{Event, Parser};
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// Names mentioned in `markdown`, in order of first appearance, without
+/// duplicates (compared case-insensitively). Mentions inside code spans and
+/// code blocks are ignored, as are email addresses.
+pub fn extract(markdown: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    let mut in_code_block = false;
+
+    for event in Parser::new(markdown) {
+        let text = match event {
+            Event::Start(pulldown_cmark::Tag::CodeBlock(_)) => {
+                in_code_block = true;
+                continue;
+            }
+            Event::End(pulldown_cmark::TagEnd::CodeBlock) => {
+                in_code_block = false;
+                continue;
+            }
+            Event::Text(t) if !in_code_block => t,
+            _ => continue,
+        };
+
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            let preceded_ok = i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_');
+            if chars[i] != '@' || !preceded_ok {
+                i += 1;
+                continue;
+            }
+            let start = i + 1;
+            let mut end = start;
+            while end < chars.len() && is_name_char(chars[end]) {
+                end += 1;
+            }
+            // Sentence punctuation is not part of the name: "ask @bob."
+            let name: String = chars[start..end]
+                .iter()
+                .collect::<String>()
+                .trim_end_matches(['.', '-'])
+                .to_string();
+            if !name.is_empty() && !names.iter().any(|n| n.eq_ignore_ascii_case(&name)) {
+                names.push(name);
+            }
+            i = end.max(start);
+        }
+    }
+    names
+}
+
+/// Mentions present in `new` that were not already in `old`.
+pub fn added(old: &str, new: &str) -> Vec<String> {
+    let before = extract(old);
+    extract(new)
+        .into_iter()
+        .filter(|n| !before.iter().any(|b| b.eq_ignore_ascii_case(n)))
+        .collect()
+}
+
+/// The line around the first mention of `name`, for notification previews.
+pub fn excerpt(markdown: &str, name: &str) -> String {
+    let needle = format!("@{}", name).to_lowercase();
+    let line = markdown
+        .lines()
+        .find(|l| l.to_lowercase().contains(&needle))
+        .unwrap_or("")
+        .trim();
+    if line.chars().count() > 200 {
+        format!("{}…", line.chars().take(200).collect::<String>())
+    } else {
+        line.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_names_and_skips_code_and_emails() {
+        let md = "Hey @alice and @Bob.\nMail me at me@example.com\n`@notme` and @alice again\n\n```\n@nor_me\n```\n";
+        assert_eq!(extract(md), vec!["alice", "Bob"]);
+    }
+
+    #[test]
+    fn added_only_reports_new_mentions() {
+        assert_eq!(added("cc @alice", "cc @alice @carol-2"), vec!["carol-2"]);
+        assert_eq!(
+            excerpt("intro\n  please review @carol-2 \n", "carol-2"),
+            "please review @carol-2"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Per-workspace comment policy: spam and abuse limits for comments posted
+//! without the manage key.
+//!
+//! Length and thread-depth limits apply to everyone. Rate limits, blocked
+//! words/patterns and the moderation queue only apply to anonymous commenters
+//! (and to authors editing through their comment token).
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct CommentPolicy {
+    /// Longest comment accepted, in characters.
+    #[serde(default = "default_max_length")]
+    pub max_length: usize,
+    /// Deepest reply allowed; top-level comments are depth 0.
+    #[serde(default = "default_max_depth")]
+    pub max_depth: usize,
+    /// Words or phrases refused anywhere in a comment (case-insensitive, whole words).
+    #[serde(default)]
+    pub blocked_words: Vec<String>,
+    /// Regular expressions refused anywhere in a comment.
+    #[serde(default)]
+    pub blocked_patterns: Vec<String>,
+    /// Comments per hour from one IP address.
+    #[serde(default = "default_rate_limit_per_ip")]
+    pub rate_limit_per_ip: u64,
+    /// Comments per hour on one document.
+    #[serde(default = "default_rate_limit_per_document")]
+    pub rate_limit_per_document: u64,
+    /// Hold anonymous comments as `pending` until a moderator approves them.
+    #[serde(default)]
+    pub hold_anonymous: bool,
+}
+
+fn default_max_length() -> usize {
+    10_000
+}
+
+fn default_max_depth() -> usize {
+    10
+}
+
+fn default_rate_limit_per_ip() -> u64 {
+    30
+}
+
+fn default_rate_limit_per_document() -> u64 {
+    120
+}
+
+impl Default for CommentPolicy {
+    fn default() -> Self {
+        CommentPolicy {
+            max_length: default_max_length(),
+            max_depth: default_max_depth(),
+            blocked_words: Vec::new(),
+            blocked_patterns: Vec::new(),
+            rate_limit_per_ip: default_rate_limit_per_ip(),
+            rate_limit_per_document: default_rate_limit_per_document(),
+            hold_anonymous: false,
+        }
+    }
+}
+
+/// Why a comment was refused.
+#[derive(Debug, PartialEq)]
+pub enum Violation {
+    TooLong { length: usize, max: usize },
+    TooDeep { max: usize },
+    Blocked(String),
+}
+
+impl Violation {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Violation::TooLong { .. } => "COMMENT_TOO_LONG",
+            Violation::TooDeep { .. } => "THREAD_TOO_DEEP",
+            Violation::Blocked(_) => "COMMENT_BLOCKED",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            Violation::TooLong { length, max } => {
+                format!("Comment is {} characters; the limit is {}", length, max)
+            }
+            Violation::TooDeep { max } => {
+                format!("Replies can only be nested {} levels deep", max)
+            }
+            Violation::Blocked(term) => format!("Comment contains blocked content ('{}')", term),
+        }
+    }
+}
+
+// Helper: whole-word, case-insensitive search
+fn contains_word(haystack: &str, word: &str) -> bool {
+    let word = word.to_lowercase();
+    if word.is_empty() {
+        return false;
+    }
+    let is_boundary = |c: Option<char>| c.is_none_or(|c| !c.is_alphanumeric());
+    haystack.match_indices(&word).any(|(i, _)| {
+        is_boundary(haystack[..i].chars().next_back())
+            && is_boundary(haystack[i + word.len()..].chars().next())
+    })
+}
+
+impl CommentPolicy {
+    /// Check limits are sane and every pattern compiles.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_length == 0 {
+            return Err("max_length must be at least 1".to_string());
+        }
+        for p in &self.blocked_patterns {
+            Regex::new(p).map_err(|e| format!("blocked pattern '{}': {}", p, e))?;
+        }
+        Ok(())
+    }
+
+    pub fn check_length(&self, content: &str) -> Result<(), Violation> {
+        let length = content.chars().count();
+        if length > self.max_length {
+            return Err(Violation::TooLong {
+                length,
+                max: self.max_length,
+            });
+        }
+        Ok(())
+    }
+
+    /// `depth` is the depth the new comment would have.
+    pub fn check_depth(&self, depth: usize) -> Result<(), Violation> {
+        if depth > self.max_depth {
+            return Err(Violation::TooDeep {
+                max: self.max_depth,
+            });
+        }
+        Ok(())
+    }
+
+    /// The first blocked word or pattern found in `content`.
+    pub fn check_blocked(&self, content: &str) -> Result<(), Violation> {
+        let lower = content.to_lowercase();
+        if let Some(word) = self.blocked_words.iter().find(|w| contains_word(&lower, w)) {
+            return Err(Violation::Blocked(word.clone()));
+        }
+        // Patterns were checked on save; one that no longer compiles is skipped
+        let hit = self
+            .blocked_patterns
+            .iter()
+            .find(|p| Regex::new(p).is_ok_and(|re| re.is_match(content)));
+        match hit {
+            Some(p) => Err(Violation::Blocked(p.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked_words_match_whole_words_only() {
+        let policy = CommentPolicy {
+            blocked_words: vec!["spam".to_string(), "buy now".to_string()],
+            blocked_patterns: vec![r"https?://\S+\.example".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.validate().is_ok());
+        assert_eq!(
+            policy.check_blocked("Total SPAM here").unwrap_err(),
+            Violation::Blocked("spam".to_string())
+        );
+        assert!(policy.check_blocked("spammy but fine").is_ok());
+        assert!(policy.check_blocked("Please buy now!").is_err());
+        assert!(policy.check_blocked("see http://x.example").is_err());
+    }
+
+    #[test]
+    fn limits_and_invalid_patterns() {
+        let policy = CommentPolicy {
+            max_length: 5,
+            max_depth: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.check_length("héllo!").unwrap_err().code(),
+            "COMMENT_TOO_LONG"
+        );
+        assert!(policy.check_length("héllo").is_ok());
+        assert!(policy.check_depth(1).is_ok());
+        assert_eq!(policy.check_depth(2).unwrap_err().code(), "THREAD_TOO_DEEP");
+
+        let bad = CommentPolicy {
+            blocked_patterns: vec!["(".to_string()],
+            ..Default::default()
+        };
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,178 @@
+//! Response formats for documents, picked by `?format=` or the `Accept` header.
+//!
+//! `?format=` wins when given. Otherwise each format is weighed by the most
+//! specific `Accept` entry matching it; ties go to the more specific match,
+//! then to the order of [`Format::ALL`], so `*/*` (and no header) is JSON and
+//! a browser's `text/html,…,*/*;q=0.8` gets the HTML page.
+
+use rocket::http::{Accept, ContentType, Header, Status};
+use rocket::request::Request;
+use rocket::response::{self, Responder, Response};
+use serde_json::{Map, Value};
+use std::io::Cursor;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    Json,
+    Markdown,
+    Html,
+    Text,
+}
+
+impl Format {
+    pub const ALL: [Format; 4] = [Format::Json, Format::Markdown, Format::Html, Format::Text];
+
+    /// A `?format=` value: `json`, `markdown` (`md`), `html` or `text` (`txt`, `plain`).
+    pub fn from_param(value: &str) -> Option<Format> {
+        match value.to_ascii_lowercase().as_str() {
+            "json" => Some(Format::Json),
+            "markdown" | "md" => Some(Format::Markdown),
+            "html" => Some(Format::Html),
+            "text" | "txt" | "plain" => Some(Format::Text),
+            _ => None,
+        }
+    }
+
+    fn media_type(self) -> (&'static str, &'static str) {
+        match self {
+            Format::Json => ("application", "json"),
+            Format::Markdown => ("text", "markdown"),
+            Format::Html => ("text", "html"),
+            Format::Text => ("text", "plain"),
+        }
+    }
+
+    pub fn content_type(self) -> ContentType {
+        match self {
+            Format::Json => ContentType::JSON,
+            Format::Markdown => ContentType::Markdown,
+            Format::Html => ContentType::HTML,
+            Format::Text => ContentType::Plain,
+        }
+    }
+
+    /// The best format for `accept`; JSON without the header, `None` when
+    /// nothing acceptable is offered.
+    pub fn negotiate(accept: Option<&Accept>) -> Option<Format> {
+        let Some(accept) = accept else {
+            return Some(Format::Json);
+        };
+        let mut best: Option<(f32, u8, Format)> = None;
+        for format in Format::ALL {
+            let (top, sub) = format.media_type();
+            // The most specific entry matching this format decides its weight
+            let matched = accept
+                .iter()
+                .filter_map(|q| {
+                    let m = q.media_type();
+                    let specificity = if m.top() == "*" && m.sub() == "*" {
+                        0
+                    } else if m.top() == top && m.sub() == "*" {
+                        1
+                    } else if m.top() == top && m.sub() == sub {
+                        2
+                    } else {
+                        return None;
+                    };
+                    Some((specificity, q.weight_or(1.0)))
+                })
+                .max_by_key(|(specificity, _)| *specificity);
+            let Some((specificity, weight)) = matched else {
+                continue;
+            };
+            if weight <= 0.0 {
+                continue;
+            }
+            let better = match best {
+                None => true,
+                Some((w, s, _)) => weight > w || (weight == w && specificity > s),
+            };
+            if better {
+                best = Some((weight, specificity, format));
+            }
+        }
+        best.map(|(_, _, format)| format)
+    }
+}
+
+/// Keep only the comma-separated top-level `fields` of `value`. Names that
+/// are neither in `value` nor in `known` are an error.
+pub fn select_fields(value: &Value, fields: &str, known: &[&str]) -> Result<Value, String> {
+    let mut selected = Map::new();
+    for name in fields.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        if !known.contains(&name) && value.get(name).is_none() {
+            return Err(format!("Unknown field '{}'", name));
+        }
+        if let Some(v) = value.get(name) {
+            selected.insert(name.to_string(), v.clone());
+        }
+    }
+    Ok(Value::Object(selected))
+}
+
+/// A body in the negotiated format, marked `Vary: Accept` for caches.
+pub struct Negotiated {
+    pub status: Status,
+    pub content_type: ContentType,
+    pub body: String,
+}
+
+impl Negotiated {
+    pub fn json(status: Status, value: &Value) -> Self {
+        Negotiated {
+            status,
+            content_type: ContentType::JSON,
+            body: value.to_string(),
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Negotiated {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .status(self.status)
+            .header(self.content_type)
+            .header(Header::new("Vary", "Accept"))
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn pick(header: &str) -> Option<Format> {
+        Format::negotiate(Some(&header.parse::<Accept>().unwrap()))
+    }
+
+    #[test]
+    fn negotiates_accept() {
+        assert_eq!(Format::negotiate(None), Some(Format::Json));
+        assert_eq!(pick("*/*"), Some(Format::Json));
+        assert_eq!(pick("text/markdown"), Some(Format::Markdown));
+        assert_eq!(pick("text/*"), Some(Format::Markdown));
+        assert_eq!(
+            pick("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"),
+            Some(Format::Html)
+        );
+        assert_eq!(
+            pick("application/json;q=0.5, text/plain"),
+            Some(Format::Text)
+        );
+        assert_eq!(pick("text/*;q=0.9, text/markdown;q=0"), Some(Format::Html));
+        assert_eq!(pick("image/png"), None);
+    }
+
+    #[test]
+    fn selects_fields() {
+        let doc = json!({"id": "d1", "title": "Runbook", "content": "# Runbook"});
+        let known = ["redirected_from"];
+        assert_eq!(
+            select_fields(&doc, "title, id,redirected_from", &known).unwrap(),
+            json!({"title": "Runbook", "id": "d1"})
+        );
+        assert!(select_fields(&doc, "title,nope", &known).is_err());
+    }
+}
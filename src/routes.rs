@@ -1,27 +1,147 @@
-use crate::auth::{generate_key, hash_key, verify_key, WorkspaceToken};
+use crate::attachments::{AttachmentFile, BlobStore};
+use crate::auth::{
+    generate_comment_token, generate_key, hash_key, verify_key, CommentToken, WorkspaceToken,
+};
 use crate::db::Db;
-use crate::events::EventBus;
+use crate::doctypes::{DocumentType, Problem};
+use crate::events::{EventBus, EventFilter};
+use crate::export::{ExportOptions, ZipDownload};
+use crate::frontmatter::MetaFilters;
+use crate::gitmirror::GitMirrors;
+use crate::moderation::{CommentPolicy, Violation};
+use crate::negotiate::{Format, Negotiated};
 use crate::rate_limit::{ClientIp, RateLimiter};
-use rocket::http::Status;
+use crate::templates::Template;
+use crate::workflow::{TransitionError, Workflow};
+use crate::ws::{Channel, Session, WebSocketKey};
+use rocket::data::{ByteUnit, Data};
+use rocket::form::Form;
+use rocket::fs::TempFile;
+use rocket::http::{ContentType, Status};
 use rocket::response::stream::{Event, EventStream};
 use rocket::serde::json::{json, Json, Value};
 use rocket::tokio::select;
 use rocket::tokio::time::{interval, Duration};
-use rocket::{delete, get, patch, post, Shutdown, State};
+use rocket::{delete, get, patch, post, put, Shutdown, State};
 
-// Helper: render markdown to HTML
-fn render_markdown(content: &str) -> String {
-    use pulldown_cmark::{html, Options, Parser};
+// Helper: render markdown to HTML, pointing `attachment:<id>` images and
+// links at the workspace's attachment URLs
+fn render_markdown(ws_id: &str, content: &str) -> String {
+    render_markdown_with(content, |dest| crate::attachments::resolve_url(ws_id, dest))
+}
+
+// Helper: render comment markdown. Anyone may comment, so raw HTML is shown
+// as text and script URLs are dropped.
+fn render_comment(ws_id: &str, content: &str) -> String {
+    render_markdown_opts(content, true, |dest| {
+        let scheme = dest
+            .split(':')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+        if dest.contains(':') && ["javascript", "vbscript", "data"].contains(&scheme.as_str()) {
+            return Some("#".to_string());
+        }
+        crate::attachments::resolve_url(ws_id, dest)
+    })
+}
+
+/// Render markdown to HTML, replacing image and link destinations for which
+/// `url` returns one.
+pub(crate) fn render_markdown_with(content: &str, url: impl Fn(&str) -> Option<String>) -> String {
+    render_markdown_opts(content, false, url)
+}
+
+// Helper: `render_markdown_with`. Comments escape raw HTML and get no
+// heading ids, which would clash with the document's own.
+fn render_markdown_opts(
+    content: &str,
+    comment: bool,
+    url: impl Fn(&str) -> Option<String>,
+) -> String {
+    use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
     let mut options = Options::empty();
     options.insert(Options::ENABLE_TABLES);
     options.insert(Options::ENABLE_STRIKETHROUGH);
     options.insert(Options::ENABLE_TASKLISTS);
-    let parser = Parser::new_ext(content, options);
+    let attachment_url =
+        |dest: CowStr<'_>| -> String { url(&dest).unwrap_or_else(|| dest.to_string()) };
+    let parser = Parser::new_ext(content, options).map(|event| match event {
+        Event::Html(raw) | Event::InlineHtml(raw) if comment => Event::Text(raw),
+        Event::Start(Tag::Image {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Image {
+            link_type,
+            dest_url: attachment_url(dest_url).into(),
+            title,
+            id,
+        }),
+        Event::Start(Tag::Link {
+            link_type,
+            dest_url,
+            title,
+            id,
+        }) => Event::Start(Tag::Link {
+            link_type,
+            dest_url: attachment_url(dest_url).into(),
+            title,
+            id,
+        }),
+        other => other,
+    });
+    let mut events: Vec<Event> = parser.collect();
+    if !comment {
+        // Give headings the ids that `[[slug#heading]]` links point at
+        let mut used = std::collections::HashSet::new();
+        for i in 0..events.len() {
+            if !matches!(events[i], Event::Start(Tag::Heading { id: None, .. })) {
+                continue;
+            }
+            let mut text = String::new();
+            for event in &events[i + 1..] {
+                match event {
+                    Event::End(TagEnd::Heading(_)) => break,
+                    Event::Text(t) | Event::Code(t) => text.push_str(t),
+                    _ => {}
+                }
+            }
+            let base = crate::links::anchor(&text);
+            if base.is_empty() {
+                continue;
+            }
+            let mut anchor = base.clone();
+            let mut n = 1;
+            while !used.insert(anchor.clone()) {
+                anchor = format!("{}-{}", base, n);
+                n += 1;
+            }
+            if let Event::Start(Tag::Heading { id, .. }) = &mut events[i] {
+                *id = Some(anchor.into());
+            }
+        }
+    }
     let mut html_output = String::new();
-    html::push_html(&mut html_output, parser);
+    html::push_html(&mut html_output, events.into_iter());
     html_output
 }
 
+// Helper: render document markdown, resolving [[wiki-links]] to workspace URLs
+fn render_document(ws_id: &str, markdown: &str) -> String {
+    render_markdown(ws_id, &crate::links::resolve(ws_id, markdown))
+}
+
+// Helper: record a document's outgoing wiki-links after a save
+fn record_links(db: &Db, ws_id: &str, doc_id: &str, markdown: &str) {
+    let links = crate::links::extract(markdown);
+    if let Err(e) = crate::db::set_document_links(db, doc_id, ws_id, &links) {
+        eprintln!("⚠️  Failed to record links of {}: {}", doc_id, e);
+    }
+}
+
 // Helper: generate slug from title
 fn slugify(title: &str) -> String {
     title
@@ -40,6 +160,461 @@ fn word_count(content: &str) -> i32 {
     content.split_whitespace().count() as i32
 }
 
+// Helper: split document content into front matter metadata (a JSON object) and body
+fn parse_front_matter(content: &str) -> Result<(Value, &str), (Status, Json<Value>)> {
+    match crate::frontmatter::parse(content) {
+        Ok((metadata, body)) => Ok((Value::Object(metadata), body)),
+        Err(e) => Err((
+            Status::UnprocessableEntity,
+            Json(json!({"error": e, "code": "INVALID_FRONT_MATTER"})),
+        )),
+    }
+}
+
+// Helper: turn `meta.<key>` filter keys into JSON paths for the db layer
+fn metadata_filters(filters: MetaFilters) -> Result<MetaFilters, (Status, Json<Value>)> {
+    let mut paths = Vec::new();
+    for (key, value) in filters.0 {
+        match crate::frontmatter::json_path(&key) {
+            Ok(path) => paths.push((path, value)),
+            Err(e) => {
+                return Err((
+                    Status::BadRequest,
+                    Json(json!({"error": e, "code": "INVALID_FILTER"})),
+                ))
+            }
+        }
+    }
+    Ok(MetaFilters(paths))
+}
+
+// Helper: line-level diff between two texts → (insertions, removals, unified
+// diff). The unified diff is only rendered when `labels` are given.
+fn diff_stats(
+    from: &str,
+    to: &str,
+    labels: Option<(&str, &str)>,
+) -> (usize, usize, Option<String>) {
+    let diff = similar::TextDiff::from_lines(from, to);
+    let unified = labels
+        .map(|(from_label, to_label)| diff.unified_diff().header(from_label, to_label).to_string());
+
+    let mut insertions = 0usize;
+    let mut removals = 0usize;
+    for change in diff.iter_all_changes() {
+        match change.tag() {
+            similar::ChangeTag::Insert => insertions += 1,
+            similar::ChangeTag::Delete => removals += 1,
+            similar::ChangeTag::Equal => {}
+        }
+    }
+    (insertions, removals, unified)
+}
+
+// Helper: largest unified diff (bytes) embedded in document.updated events.
+// EVENT_DIFF_MAX_BYTES=0 disables diffs in events.
+fn event_diff_limit() -> usize {
+    std::env::var("EVENT_DIFF_MAX_BYTES")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(8192)
+}
+
+/// Payload for `document.updated`, built by comparing the document before and
+/// after a save. Shared by PATCH and restore so both emit the same schema.
+fn document_updated_event(
+    db: &Db,
+    before: &Value,
+    author_name: Option<&str>,
+    change_description: Option<&str>,
+    restored_from_version: Option<i32>,
+) -> Value {
+    let doc_id = before["id"].as_str().unwrap_or("");
+    let after = crate::db::get_document_by_id(db, doc_id)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| before.clone());
+    let version_number = crate::db::latest_version_number(db, doc_id).unwrap_or(0);
+
+    let changed: Vec<&str> = [
+        "title", "slug", "content", "summary", "tags", "status", "doc_type", "fields",
+    ]
+    .into_iter()
+    .filter(|f| before[*f] != after[*f])
+    .collect();
+
+    let old_content = before["content"].as_str().unwrap_or("");
+    let new_content = after["content"].as_str().unwrap_or("");
+    let content_changed = changed.contains(&"content");
+    let limit = event_diff_limit();
+    let to_label = format!("version {}", version_number);
+    let (insertions, removals, unified) = if content_changed {
+        diff_stats(
+            old_content,
+            new_content,
+            (limit > 0).then_some(("before", to_label.as_str())),
+        )
+    } else {
+        (0, 0, None)
+    };
+    // Only a diff cut for size counts as truncated; a disabled one is just absent
+    let (diff, diff_truncated) = match unified {
+        Some(unified) if unified.len() <= limit => (json!(unified), false),
+        Some(_) => (Value::Null, true),
+        None => (Value::Null, false),
+    };
+
+    json!({
+        "id": doc_id,
+        "title": after["title"],
+        "slug": after["slug"],
+        "status": after["status"],
+        "tags": after["tags"],
+        "author_name": author_name,
+        "changed": changed,
+        "content_changed": content_changed,
+        "version_number": version_number,
+        "change_description": change_description,
+        "word_count": after["word_count"],
+        "word_count_delta": after["word_count"].as_i64().unwrap_or(0) - before["word_count"].as_i64().unwrap_or(0),
+        "stats": {
+            "insertions": insertions,
+            "removals": removals,
+        },
+        "diff": diff,
+        "diff_truncated": diff_truncated,
+        "restored_from_version": restored_from_version,
+    })
+}
+
+/// Resolve an `anchor` (of a new comment or suggestion) against the document.
+/// The quote is located in `version_number` (default: latest) and carried
+/// forward to the current content if that version is older.
+fn resolve_anchor(
+    db: &Db,
+    doc: &Value,
+    anchor: &Value,
+) -> Result<(crate::anchors::Anchor, i32), (Status, Json<Value>)> {
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let current = doc["content"].as_str().unwrap_or("");
+    let latest = crate::db::latest_version_number(db, doc_id)
+        .map_err(|e| (Status::InternalServerError, Json(json!({"error": e}))))?;
+    let version = anchor
+        .get("version_number")
+        .and_then(|v| v.as_i64())
+        .map(|v| v as i32)
+        .unwrap_or(latest);
+
+    let base = if version == latest {
+        current.to_string()
+    } else {
+        match crate::db::get_version(db, doc_id, version) {
+            Ok(Some(v)) => v["content"].as_str().unwrap_or("").to_string(),
+            Ok(None) => {
+                return Err((
+                    Status::NotFound,
+                    Json(
+                        json!({"error": format!("Version {} not found", version), "code": "NOT_FOUND"}),
+                    ),
+                ))
+            }
+            Err(e) => return Err((Status::InternalServerError, Json(json!({"error": e})))),
+        }
+    };
+
+    let start = anchor
+        .get("start")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    let end = anchor
+        .get("end")
+        .and_then(|v| v.as_u64())
+        .map(|v| v as usize);
+    // Offsets alone are enough: the quote is read from the document
+    let quote = match (anchor.get("quote").and_then(|v| v.as_str()), start, end) {
+        (Some(q), _, _) => q.to_string(),
+        (None, Some(s), Some(e)) if s < e => base.chars().skip(s).take(e - s).collect(),
+        _ => {
+            return Err((
+                Status::BadRequest,
+                Json(
+                    json!({"error": "anchor requires quote, or start and end offsets", "code": "VALIDATION_ERROR"}),
+                ),
+            ))
+        }
+    };
+
+    let prefix = anchor.get("prefix").and_then(|v| v.as_str());
+    let suffix = anchor.get("suffix").and_then(|v| v.as_str());
+    let mut located = crate::anchors::locate(&base, &quote, prefix, suffix, start).ok_or((
+        Status::UnprocessableEntity,
+        Json(json!({
+            "error": format!("Quoted text not found in version {}", version),
+            "code": "ANCHOR_NOT_FOUND",
+        })),
+    ))?;
+
+    if version != latest {
+        let (start, end) = crate::anchors::reanchor(&base, current, &located).ok_or((
+            Status::Conflict,
+            Json(json!({
+                "error": "Quoted text no longer exists in the current version",
+                "code": "ANCHOR_ORPHANED",
+            })),
+        ))?;
+        located.start = start;
+        located.end = end;
+    }
+    Ok((located, latest))
+}
+
+/// Move anchored comments from `old_content` to `new_content` after a save.
+/// Comments whose quote disappeared are flagged orphaned and announced with
+/// `comment.orphaned`; previously orphaned ones re-attach if the quote returns.
+fn reanchor_comments(
+    db: &Db,
+    event_bus: &EventBus,
+    ws_id: &str,
+    doc_id: &str,
+    old_content: &str,
+    new_content: &str,
+) {
+    if old_content == new_content {
+        return;
+    }
+    let anchors = match crate::db::list_comment_anchors(db, doc_id) {
+        Ok(a) => a,
+        Err(e) => {
+            eprintln!("⚠️  Failed to load comment anchors for {}: {}", doc_id, e);
+            return;
+        }
+    };
+    let version = crate::db::latest_version_number(db, doc_id).unwrap_or(0);
+
+    for (comment_id, anchor, status) in anchors {
+        let range = if status == "orphaned" {
+            crate::anchors::find_quote(
+                new_content,
+                &anchor.quote,
+                &anchor.prefix,
+                &anchor.suffix,
+                Some(anchor.start),
+            )
+        } else {
+            crate::anchors::reanchor(old_content, new_content, &anchor)
+        };
+        if let Err(e) = crate::db::update_comment_anchor(db, &comment_id, range, version) {
+            eprintln!("⚠️  Failed to re-anchor comment {}: {}", comment_id, e);
+            continue;
+        }
+        if range.is_none() && status != "orphaned" {
+            event_bus.emit(
+                ws_id,
+                "comment.orphaned",
+                json!({
+                    "id": comment_id,
+                    "comment_id": comment_id,
+                    "document_id": doc_id,
+                    "quote": anchor.quote,
+                    "version_number": version,
+                }),
+            );
+        }
+    }
+}
+
+/// Notify everyone newly `@mentioned` when `old_text` became `new_text`
+/// (pass "" as `old_text` for new content). Authors are not notified of
+/// their own mentions.
+#[allow(clippy::too_many_arguments)]
+fn notify_mentions(
+    db: &Db,
+    event_bus: &EventBus,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: Option<&str>,
+    author_name: &str,
+    old_text: &str,
+    new_text: &str,
+) {
+    for recipient in crate::mentions::added(old_text, new_text) {
+        if recipient.eq_ignore_ascii_case(author_name) {
+            continue;
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        let excerpt = crate::mentions::excerpt(new_text, &recipient);
+        if let Err(e) = crate::db::create_notification(
+            db,
+            &id,
+            ws_id,
+            &recipient,
+            "mention",
+            doc_id,
+            comment_id,
+            author_name,
+            &excerpt,
+        ) {
+            eprintln!("⚠️  Failed to store mention of {}: {}", recipient, e);
+            continue;
+        }
+        event_bus.emit(
+            ws_id,
+            "mention.created",
+            json!({
+                "id": id,
+                "recipient": recipient,
+                "document_id": doc_id,
+                "comment_id": comment_id,
+                "author_name": author_name,
+                "excerpt": excerpt,
+            }),
+        );
+    }
+}
+
+/// Save new content for a document through the normal versioning path and
+/// announce it: `document.updated` plus comment re-anchoring. Used by restore,
+/// accepted suggestions, link rewrites, imports and git sync. Content that no
+/// longer fits the document's type is refused. Returns the `document.updated`
+/// payload.
+#[allow(clippy::too_many_arguments)]
+fn save_document_content(
+    db: &Db,
+    event_bus: &EventBus,
+    ws_id: &str,
+    before: &Value,
+    content: &str,
+    author_name: Option<&str>,
+    change_description: &str,
+    restored_from_version: Option<i32>,
+) -> Result<Value, (Status, Json<Value>)> {
+    let doc_id = before["id"].as_str().unwrap_or("");
+    let (metadata, body) = crate::frontmatter::parse_lenient(content);
+    check_content_type(db, ws_id, before, body)?;
+    let content_html = render_document(ws_id, body);
+    crate::db::update_document(
+        db,
+        doc_id,
+        None,
+        Some(content),
+        Some(&content_html),
+        None,
+        None,
+        None,
+        author_name,
+        Some(word_count(body)),
+        Some(change_description),
+        Some(&Value::Object(metadata).to_string()),
+        None,
+        None,
+    )
+    .map_err(|e| (Status::InternalServerError, Json(json!({"error": e}))))?;
+    record_links(db, ws_id, doc_id, body);
+
+    let data = document_updated_event(
+        db,
+        before,
+        author_name,
+        Some(change_description),
+        restored_from_version,
+    );
+    event_bus.emit(ws_id, "document.updated", data.clone());
+    let old_content = before["content"].as_str().unwrap_or("");
+    reanchor_comments(db, event_bus, ws_id, doc_id, old_content, content);
+    notify_mentions(
+        db,
+        event_bus,
+        ws_id,
+        doc_id,
+        None,
+        author_name.unwrap_or(""),
+        old_content,
+        content,
+    );
+    Ok(data)
+}
+
+// Helper: new markdown for a typed document must still satisfy its type
+fn check_content_type(
+    db: &Db,
+    ws_id: &str,
+    before: &Value,
+    markdown: &str,
+) -> Result<(), (Status, Json<Value>)> {
+    match before["doc_type"].as_str() {
+        Some(t) => check_doc_type(db, ws_id, t, &before["fields"], markdown),
+        None => Ok(()),
+    }
+}
+
+// Helper: the workspace's status workflow, if configured
+fn load_workflow(db: &Db, ws_id: &str) -> Result<Option<Workflow>, (Status, Json<Value>)> {
+    match crate::db::get_workspace_workflow(db, ws_id) {
+        Ok(Some(raw)) => serde_json::from_str(&raw).map(Some).map_err(|e| {
+            (
+                Status::InternalServerError,
+                Json(json!({"error": format!("Stored workflow is invalid: {}", e)})),
+            )
+        }),
+        Ok(None) => Ok(None),
+        Err(e) => Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+}
+
+// Helper: load the workspace comment policy (defaults when none is stored)
+fn load_comment_policy(db: &Db, ws_id: &str) -> Result<CommentPolicy, (Status, Json<Value>)> {
+    match crate::db::get_workspace_comment_policy(db, ws_id) {
+        Ok(Some(raw)) => serde_json::from_str(&raw).map_err(|e| {
+            (
+                Status::InternalServerError,
+                Json(json!({"error": format!("Stored comment policy is invalid: {}", e)})),
+            )
+        }),
+        Ok(None) => Ok(CommentPolicy::default()),
+        Err(e) => Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+}
+
+// Helper: 422 response for a comment the policy refuses
+fn policy_violation(v: Violation) -> (Status, Json<Value>) {
+    (
+        Status::UnprocessableEntity,
+        Json(json!({"error": v.message(), "code": v.code()})),
+    )
+}
+
+// Helper: 409/422 response for a refused status change
+fn transition_error(err: TransitionError) -> (Status, Json<Value>) {
+    let status = match err {
+        TransitionError::ApprovalsRequired { .. } => Status::Conflict,
+        _ => Status::UnprocessableEntity,
+    };
+    (
+        status,
+        Json(json!({"error": err.message(), "code": err.code()})),
+    )
+}
+
+// Helper: new documents have no approvals, so they cannot start in a gated state
+fn check_initial_status(
+    workflow: Option<&Workflow>,
+    status: &str,
+) -> Result<(), (Status, Json<Value>)> {
+    let Some(wf) = workflow else {
+        return Ok(());
+    };
+    wf.check_transition(status, status, 0)
+        .map_err(transition_error)?;
+    if wf.needs_approval(status) {
+        return Err(transition_error(TransitionError::ApprovalsRequired {
+            to: status.to_string(),
+            have: 0,
+            need: wf.required_approvals(),
+        }));
+    }
+    Ok(())
+}
+
 // Helper: verify workspace auth
 fn verify_workspace_auth(
     db: &Db,
@@ -63,6 +638,75 @@ fn verify_workspace_auth(
     Ok(())
 }
 
+// Helper: resolve a document route's `<doc_id>` segment, which may be the
+// document's id, its slug or a slug it was renamed from. Documents of other
+// workspaces are reported as not found, never as forbidden.
+fn document_in_workspace(
+    db: &Db,
+    ws_id: &str,
+    doc_ref: &str,
+) -> Result<Value, (Status, Json<Value>)> {
+    match find_document(db, ws_id, doc_ref) {
+        Ok(Some((doc, _))) => Ok(doc),
+        Ok(None) => Err((
+            Status::NotFound,
+            Json(json!({"error": "Document not found", "code": "NOT_FOUND"})),
+        )),
+        Err(e) => Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+}
+
+// Helper: check the comment is on this document in this workspace, and that
+// the caller holds the manage key or the comment's own token.
+// Returns the document's id and whether the caller is a moderator (manage key).
+fn authorize_comment(
+    db: &Db,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: &str,
+    token: Option<&WorkspaceToken>,
+    comment_token: Option<&CommentToken>,
+) -> Result<(String, bool), (Status, Json<Value>)> {
+    let doc = document_in_workspace(db, ws_id, doc_id)?;
+    let doc_id = doc["id"].as_str().unwrap_or("").to_string();
+    match crate::db::get_comment_doc_id(db, comment_id) {
+        Ok(Some(d)) if d == doc_id => {}
+        Ok(_) => {
+            return Err((
+                Status::NotFound,
+                Json(json!({"error": "Comment not found", "code": "NOT_FOUND"})),
+            ))
+        }
+        Err(e) => return Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+
+    if let Some(token) = token {
+        if verify_workspace_auth(db, ws_id, token).is_ok() {
+            return Ok((doc_id, true));
+        }
+    }
+    if let Some(ct) = comment_token {
+        if let Ok(Some(stored)) = crate::db::get_comment_edit_token_hash(db, comment_id) {
+            if verify_key(&ct.0, &stored) {
+                return Ok((doc_id, false));
+            }
+        }
+    }
+    if token.is_none() && comment_token.is_none() {
+        return Err((
+            Status::Unauthorized,
+            Json(json!({
+                "error": "Manage key or comment token required",
+                "code": "UNAUTHORIZED",
+            })),
+        ));
+    }
+    Err((
+        Status::Forbidden,
+        Json(json!({"error": "Invalid manage key or comment token", "code": "FORBIDDEN"})),
+    ))
+}
+
 // --- Workspace routes ---
 
 #[post("/workspaces", format = "json", data = "<body>")]
@@ -166,6 +810,7 @@ pub fn update_workspace(
     id: &str,
     token: WorkspaceToken,
     body: Json<Value>,
+    event_bus: &State<EventBus>,
 ) -> (Status, Json<Value>) {
     if let Err((status, err)) = verify_workspace_auth(db, id, &token) {
         return (status, Json(err));
@@ -174,9 +819,47 @@ pub fn update_workspace(
     let name = body.get("name").and_then(|v| v.as_str());
     let description = body.get("description").and_then(|v| v.as_str());
     let is_public = body.get("is_public").and_then(|v| v.as_bool());
+    let allow_anonymous_comments = body
+        .get("allow_anonymous_comments")
+        .and_then(|v| v.as_bool());
 
-    match crate::db::update_workspace(db, id, name, description, is_public) {
-        Ok(true) => (Status::Ok, Json(json!({"status": "updated"}))),
+    match crate::db::update_workspace(
+        db,
+        id,
+        name,
+        description,
+        is_public,
+        allow_anonymous_comments,
+    ) {
+        Ok(true) => {
+            let changed: Vec<&str> = [
+                ("name", name.is_some()),
+                ("description", description.is_some()),
+                ("is_public", is_public.is_some()),
+                (
+                    "allow_anonymous_comments",
+                    allow_anonymous_comments.is_some(),
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(field, set)| set.then_some(field))
+            .collect();
+            if let Ok(Some(ws)) = crate::db::get_workspace(db, id) {
+                event_bus.emit(
+                    id,
+                    "workspace.updated",
+                    json!({
+                        "id": id,
+                        "name": ws["name"],
+                        "description": ws["description"],
+                        "is_public": ws["is_public"],
+                        "allow_anonymous_comments": ws["allow_anonymous_comments"],
+                        "changed": changed,
+                    }),
+                );
+            }
+            (Status::Ok, Json(json!({"status": "updated"})))
+        }
         Ok(false) => (
             Status::BadRequest,
             Json(json!({"error": "No fields to update"})),
@@ -199,39 +882,91 @@ pub fn create_document(
         return (status, Json(err));
     }
 
-    let title = match body.get("title").and_then(|v| v.as_str()) {
-        Some(t) if !t.trim().is_empty() => t.trim().to_string(),
-        _ => {
-            return (
-                Status::BadRequest,
-                Json(json!({"error": "title is required", "code": "VALIDATION_ERROR"})),
-            )
-        }
-    };
-
-    let content = body
-        .get("content")
+    let author_name = body
+        .get("author_name")
         .and_then(|v| v.as_str())
         .unwrap_or("")
         .to_string();
+    let requested_title = body
+        .get("title")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+
+    let from_template = body.get("from_template").and_then(|v| v.as_str());
+    let (title, content, template_tags) = match from_template {
+        Some(reference) => {
+            if body.get("content").is_some() {
+                return (
+                    Status::BadRequest,
+                    Json(
+                        json!({"error": "Send content or from_template, not both", "code": "VALIDATION_ERROR"}),
+                    ),
+                );
+            }
+            let template = match find_template(db, ws_id, reference) {
+                Ok(Some((_, t))) => t,
+                Ok(None) => {
+                    return (
+                        Status::UnprocessableEntity,
+                        Json(json!({
+                            "error": format!("Template '{}' not found", reference),
+                            "code": "TEMPLATE_NOT_FOUND",
+                        })),
+                    )
+                }
+                Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+            };
+            let bindings = match template_bindings(body.get("variables"), &author_name) {
+                Ok(b) => b,
+                Err(err) => return err,
+            };
+            match template.instantiate(requested_title, &bindings) {
+                Ok((title, content)) => (title, content, Some(template.tags)),
+                Err(missing) => return missing_variables(missing),
+            }
+        }
+        None => (
+            requested_title.unwrap_or("").to_string(),
+            body.get("content")
+                .and_then(|v| v.as_str())
+                .unwrap_or("")
+                .to_string(),
+            None,
+        ),
+    };
+    if title.is_empty() {
+        return (
+            Status::BadRequest,
+            Json(json!({"error": "title is required", "code": "VALIDATION_ERROR"})),
+        );
+    }
+
     let summary = body
         .get("summary")
         .and_then(|v| v.as_str())
         .unwrap_or("")
         .to_string();
+    let workflow = match load_workflow(db, ws_id) {
+        Ok(wf) => wf,
+        Err(err) => return err,
+    };
+    let default_status = workflow
+        .as_ref()
+        .map(|wf| wf.initial_state())
+        .unwrap_or("draft");
     let status_val = body
         .get("status")
         .and_then(|v| v.as_str())
-        .unwrap_or("draft")
-        .to_string();
-    let author_name = body
-        .get("author_name")
-        .and_then(|v| v.as_str())
-        .unwrap_or("")
+        .unwrap_or(default_status)
         .to_string();
+    if let Err(err) = check_initial_status(workflow.as_ref(), &status_val) {
+        return err;
+    }
     let tags = body
         .get("tags")
         .map(|v| v.to_string())
+        .or_else(|| template_tags.map(|t| json!(t).to_string()))
         .unwrap_or("[]".to_string());
 
     // Custom slug or auto-generate
@@ -241,8 +976,26 @@ pub fn create_document(
         .map(|s| s.to_string())
         .unwrap_or_else(|| slugify(&title));
 
-    let content_html = render_markdown(&content);
-    let wc = word_count(&content);
+    let doc_type = body.get("doc_type").and_then(|v| v.as_str());
+    let fields = body.get("fields").cloned().unwrap_or_else(|| json!({}));
+    if !fields.is_object() {
+        return (
+            Status::BadRequest,
+            Json(json!({"error": "fields must be an object", "code": "VALIDATION_ERROR"})),
+        );
+    }
+
+    let (metadata, markdown) = match parse_front_matter(&content) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+    if let Some(t) = doc_type {
+        if let Err(err) = check_doc_type(db, ws_id, t, &fields, markdown) {
+            return err;
+        }
+    }
+    let content_html = render_document(ws_id, markdown);
+    let wc = word_count(markdown);
     let id = uuid::Uuid::new_v4().to_string();
 
     match crate::db::create_document(
@@ -258,13 +1011,29 @@ pub fn create_document(
         &status_val,
         &author_name,
         wc,
+        &metadata.to_string(),
+        doc_type,
+        &fields.to_string(),
     ) {
         Ok(()) => {
             event_bus.emit(
                 ws_id,
                 "document.created",
-                json!({"id": id, "title": title, "slug": slug, "author_name": author_name}),
+                json!({
+                    "id": id,
+                    "title": title,
+                    "slug": slug,
+                    "status": status_val,
+                    "tags": serde_json::from_str::<Value>(&tags).unwrap_or(json!([])),
+                    "author_name": author_name,
+                    "version_number": 1,
+                    "word_count": wc,
+                    "doc_type": doc_type,
+                    "from_template": from_template,
+                }),
             );
+            record_links(db, ws_id, &id, markdown);
+            notify_mentions(db, event_bus, ws_id, &id, None, &author_name, "", &content);
             (
                 Status::Created,
                 Json(json!({
@@ -275,21 +1044,29 @@ pub fn create_document(
                     "status": status_val,
                     "word_count": wc,
                     "author_name": author_name,
+                    "metadata": metadata,
+                    "doc_type": doc_type,
+                    "fields": fields,
+                    "from_template": from_template,
                 })),
             )
         }
-        Err(e) if e.contains("UNIQUE constraint") => (
-            Status::Conflict,
-            Json(
-                json!({"error": "A document with this slug already exists", "code": "DUPLICATE_SLUG"}),
-            ),
-        ),
+        Err(e) if e.contains("UNIQUE constraint") => duplicate_slug(),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
 #[get("/workspaces/<ws_id>/docs?<key>")]
-pub fn list_documents(db: &State<Db>, ws_id: &str, key: Option<&str>) -> (Status, Json<Value>) {
+pub fn list_documents(
+    db: &State<Db>,
+    ws_id: &str,
+    key: Option<&str>,
+    meta: MetaFilters,
+) -> (Status, Json<Value>) {
+    let meta = match metadata_filters(meta) {
+        Ok(m) => m,
+        Err(err) => return err,
+    };
     // Public default: only published docs
     // If a valid manage key is provided, include drafts.
     let include_drafts = if let Some(k) = key {
@@ -299,25 +1076,367 @@ pub fn list_documents(db: &State<Db>, ws_id: &str, key: Option<&str>) -> (Status
         false
     };
 
-    match crate::db::list_documents(db, ws_id, include_drafts) {
+    match crate::db::list_documents(db, ws_id, include_drafts, &meta.0) {
         Ok(docs) => (Status::Ok, Json(json!(docs))),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
-#[get("/workspaces/<ws_id>/docs/<slug>")]
-pub fn get_document(db: &State<Db>, ws_id: &str, slug: &str) -> (Status, Json<Value>) {
-    match crate::db::get_document(db, ws_id, slug) {
-        Ok(Some(doc)) => (Status::Ok, Json(doc)),
-        Ok(None) => (
-            Status::NotFound,
-            Json(json!({"error": "Document not found", "code": "NOT_FOUND"})),
-        ),
-        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+// Helper: a workspace document by id, by its current slug, or by an old slug it
+// was renamed from. The second value is the old slug when the lookup went
+// through the history.
+fn find_document(
+    db: &Db,
+    ws_id: &str,
+    slug: &str,
+) -> Result<Option<(Value, Option<String>)>, String> {
+    if let Some(doc) = crate::db::get_document_by_id(db, slug)? {
+        if doc["workspace_id"].as_str() == Some(ws_id) {
+            return Ok(Some((doc, None)));
+        }
+    }
+    if let Some(doc) = crate::db::get_document(db, ws_id, slug)? {
+        return Ok(Some((doc, None)));
+    }
+    match crate::db::find_renamed_document(db, ws_id, slug)? {
+        Some(id) => {
+            Ok(crate::db::get_document_by_id(db, &id)?.map(|doc| (doc, Some(slug.to_string()))))
+        }
+        None => Ok(None),
     }
 }
 
-#[patch("/workspaces/<ws_id>/docs/<doc_id>", format = "json", data = "<body>")]
+/// Accepts the id or slug. Old slugs keep working: the current document is
+/// returned with `redirected_from` set to the slug that was asked for.
+/// `?format=` or `Accept` picks JSON, raw markdown, a standalone HTML page or
+/// plain text; `?fields=` trims the JSON to the listed keys.
+#[get("/workspaces/<ws_id>/docs/<slug>?<format>&<fields>")]
+pub fn get_document(
+    db: &State<Db>,
+    ws_id: &str,
+    slug: &str,
+    format: Option<&str>,
+    fields: Option<&str>,
+    accept: Option<&rocket::http::Accept>,
+) -> Negotiated {
+    let format = match format {
+        Some(f) => match Format::from_param(f) {
+            Some(format) => format,
+            None => {
+                return Negotiated::json(
+                    Status::BadRequest,
+                    &json!({
+                        "error": format!("Unsupported format '{}' (json, markdown, html, text)", f),
+                        "code": "UNSUPPORTED_FORMAT",
+                    }),
+                )
+            }
+        },
+        None => match Format::negotiate(accept) {
+            Some(format) => format,
+            None => {
+                return Negotiated::json(
+                    Status::NotAcceptable,
+                    &json!({
+                        "error": "Documents are available as application/json, text/markdown, text/html or text/plain",
+                        "code": "NOT_ACCEPTABLE",
+                    }),
+                )
+            }
+        },
+    };
+    let mut doc = match find_document(db, ws_id, slug) {
+        Ok(Some((mut doc, redirected_from))) => {
+            if let Some(old) = redirected_from {
+                doc["redirected_from"] = json!(old);
+            }
+            doc
+        }
+        Ok(None) => {
+            return Negotiated::json(
+                Status::NotFound,
+                &json!({"error": "Document not found", "code": "NOT_FOUND"}),
+            )
+        }
+        Err(e) => return Negotiated::json(Status::InternalServerError, &json!({"error": e})),
+    };
+    let content = doc["content"].as_str().unwrap_or("");
+    let body = match format {
+        Format::Markdown => content.to_string(),
+        Format::Text => crate::site::plain_text(content),
+        Format::Html => crate::site::standalone_page(
+            doc["title"].as_str().unwrap_or(""),
+            doc["content_html"].as_str().unwrap_or(""),
+        ),
+        Format::Json => {
+            let doc_id = doc["id"].as_str().unwrap_or("").to_string();
+            let summary = crate::db::reaction_summaries(db, &doc_id, "document")
+                .ok()
+                .and_then(|mut m| m.remove(&doc_id))
+                .unwrap_or_else(empty_reactions);
+            doc["reactions"] = summary["reactions"].clone();
+            doc["vote_score"] = summary["score"].clone();
+            if let Some(fields) = fields {
+                doc = match crate::negotiate::select_fields(&doc, fields, &["redirected_from"]) {
+                    Ok(selected) => selected,
+                    Err(e) => {
+                        return Negotiated::json(
+                            Status::BadRequest,
+                            &json!({"error": e, "code": "UNKNOWN_FIELD"}),
+                        )
+                    }
+                };
+            }
+            return Negotiated::json(Status::Ok, &doc);
+        }
+    };
+    Negotiated {
+        status: Status::Ok,
+        content_type: format.content_type(),
+        body,
+    }
+}
+
+/// The current slug of a renamed document, for `/workspace/<ws_id>/doc/<slug>`
+/// paths (path segments 1 and 3). Forwards when the slug is not an old one.
+pub struct RenamedSlug(String);
+
+#[rocket::async_trait]
+impl<'r> rocket::request::FromRequest<'r> for RenamedSlug {
+    type Error = ();
+
+    async fn from_request(req: &'r rocket::Request<'_>) -> rocket::request::Outcome<Self, ()> {
+        use rocket::outcome::Outcome;
+        let Some(db) = req.rocket().state::<Db>() else {
+            return Outcome::Forward(Status::NotFound);
+        };
+        let (Some(Ok(ws_id)), Some(Ok(slug))) = (req.param::<&str>(1), req.param::<&str>(3)) else {
+            return Outcome::Forward(Status::NotFound);
+        };
+        match find_document(db, ws_id, slug) {
+            Ok(Some((doc, Some(_)))) => {
+                Outcome::Success(RenamedSlug(doc["slug"].as_str().unwrap_or("").to_string()))
+            }
+            _ => Outcome::Forward(Status::NotFound),
+        }
+    }
+}
+
+/// Document pages under an old slug redirect permanently to the current one;
+/// everything else falls through to the frontend.
+#[get("/workspace/<ws_id>/doc/<_slug>", rank = 5)]
+pub fn redirect_renamed_document(
+    ws_id: &str,
+    _slug: &str,
+    renamed: RenamedSlug,
+) -> rocket::response::Redirect {
+    rocket::response::Redirect::moved(format!("/workspace/{}/doc/{}", ws_id, renamed.0))
+}
+
+fn duplicate_slug() -> (Status, Json<Value>) {
+    (
+        Status::Conflict,
+        Json(
+            json!({"error": "A document with this slug already exists", "code": "DUPLICATE_SLUG"}),
+        ),
+    )
+}
+
+// Helper: after a rename, point every [[wiki-link]] at the old slug to the new
+// one. Each linking document gets a new version. Returns how many changed.
+fn rewrite_links_to(
+    db: &Db,
+    event_bus: &EventBus,
+    ws_id: &str,
+    old_slug: &str,
+    new_slug: &str,
+    author_name: Option<&str>,
+) -> usize {
+    let sources = match crate::db::documents_linking_to(db, ws_id, old_slug) {
+        Ok(ids) => ids,
+        Err(e) => {
+            eprintln!("⚠️  Failed to find links to {}: {}", old_slug, e);
+            return 0;
+        }
+    };
+    let description = format!("Update links: {} → {}", old_slug, new_slug);
+    let mut rewritten = 0;
+    for source_id in sources {
+        let Ok(Some(before)) = crate::db::get_document_by_id(db, &source_id) else {
+            continue;
+        };
+        let content = before["content"].as_str().unwrap_or("");
+        let Some(updated) = crate::links::rewrite(content, old_slug, new_slug) else {
+            continue;
+        };
+        match save_document_content(
+            db,
+            event_bus,
+            ws_id,
+            &before,
+            &updated,
+            author_name,
+            &description,
+            None,
+        ) {
+            Ok(_) => rewritten += 1,
+            Err((_, Json(e))) => {
+                eprintln!(
+                    "⚠️  Failed to update links in {}: {}",
+                    source_id, e["error"]
+                )
+            }
+        }
+    }
+    rewritten
+}
+
+// Helper: whether an optional manage key unlocks drafts
+fn key_grants_drafts(db: &Db, ws_id: &str, key: Option<&str>) -> bool {
+    key.is_some_and(|k| verify_workspace_auth(db, ws_id, &WorkspaceToken(k.to_string())).is_ok())
+}
+
+/// Documents whose `[[wiki-links]]` point at this one (published only; all with key).
+#[get("/workspaces/<ws_id>/docs/<slug>/backlinks?<key>")]
+pub fn get_backlinks(
+    db: &State<Db>,
+    ws_id: &str,
+    slug: &str,
+    key: Option<&str>,
+) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, slug) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let slug = doc["slug"].as_str().unwrap_or("");
+    let include_drafts = key_grants_drafts(db, ws_id, key);
+    match crate::db::list_backlinks(db, ws_id, slug, include_drafts) {
+        Ok(backlinks) => (
+            Status::Ok,
+            Json(json!({"slug": slug, "count": backlinks.len(), "backlinks": backlinks})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Wiki-links whose target document or heading does not exist.
+#[get("/workspaces/<ws_id>/links/broken?<key>")]
+pub fn list_broken_links(db: &State<Db>, ws_id: &str, key: Option<&str>) -> (Status, Json<Value>) {
+    match crate::db::get_workspace(db, ws_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    let include_drafts = key_grants_drafts(db, ws_id, key);
+    let links = match crate::db::list_links_with_targets(db, ws_id, include_drafts) {
+        Ok(links) => links,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let broken: Vec<Value> = links
+        .into_iter()
+        .filter_map(|mut link| {
+            let heading = link["heading"].as_str().unwrap_or("").to_string();
+            let reason = match link["target_content"].as_str() {
+                None => "missing_document",
+                Some(_) if heading.is_empty() => return None,
+                Some(content) => {
+                    let wanted = crate::links::anchor(&heading);
+                    let body = crate::frontmatter::parse_lenient(content).1;
+                    if crate::doctypes::headings(body)
+                        .iter()
+                        .any(|h| crate::links::anchor(h) == wanted)
+                    {
+                        return None;
+                    }
+                    "missing_heading"
+                }
+            };
+            let obj = link.as_object_mut()?;
+            obj.remove("target_content");
+            obj.insert(
+                "heading".to_string(),
+                json!(Some(heading).filter(|h| !h.is_empty())),
+            );
+            obj.insert("reason".to_string(), json!(reason));
+            Some(link)
+        })
+        .collect();
+    (
+        Status::Ok,
+        Json(json!({"count": broken.len(), "links": broken})),
+    )
+}
+
+// Helper: `doc_type` in an update; `null` removes the type, absent keeps it
+fn doc_type_change(body: &Value) -> Result<Option<Option<&str>>, (Status, Json<Value>)> {
+    match body.get("doc_type") {
+        None => Ok(None),
+        Some(Value::Null) => Ok(Some(None)),
+        Some(Value::String(t)) => Ok(Some(Some(t.as_str()))),
+        Some(_) => Err((
+            Status::BadRequest,
+            Json(json!({"error": "doc_type must be a string or null", "code": "VALIDATION_ERROR"})),
+        )),
+    }
+}
+
+// Helper: what PATCH refuses about an update to `before` — the status
+// transition, front matter and the document type
+fn check_document_update(
+    db: &Db,
+    ws_id: &str,
+    before: &Value,
+    body: &Value,
+) -> Result<(), (Status, Json<Value>)> {
+    let doc_id = before["id"].as_str().unwrap_or("");
+    let content = body.get("content").and_then(|v| v.as_str());
+    if let Some(to) = body.get("status").and_then(|v| v.as_str()) {
+        if let Some(wf) = load_workflow(db, ws_id)? {
+            // Saving content creates a new version, which has no approvals yet
+            let approvals = if content.is_some() {
+                0
+            } else {
+                let version = crate::db::latest_version_number(db, doc_id).unwrap_or(0);
+                crate::db::approved_reviewers(db, doc_id, version)
+                    .map(|r| r.len())
+                    .unwrap_or(0)
+            };
+            let from = before["status"].as_str().unwrap_or("");
+            wf.check_transition(from, to, approvals)
+                .map_err(transition_error)?;
+        }
+    }
+
+    let parsed = content.map(parse_front_matter).transpose()?;
+    let doc_type_change = doc_type_change(body)?;
+    let fields = body.get("fields");
+    if fields.is_some_and(|f| !f.is_object()) {
+        return Err((
+            Status::BadRequest,
+            Json(json!({"error": "fields must be an object", "code": "VALIDATION_ERROR"})),
+        ));
+    }
+    if doc_type_change.is_some() || fields.is_some() || parsed.is_some() {
+        let doc_type = doc_type_change.unwrap_or(before["doc_type"].as_str());
+        if let Some(t) = doc_type {
+            let current = before["content"].as_str().unwrap_or("");
+            let markdown = match &parsed {
+                Some((_, markdown)) => markdown,
+                None => crate::frontmatter::parse_lenient(current).1,
+            };
+            let fields = fields.unwrap_or(&before["fields"]);
+            check_doc_type(db, ws_id, t, fields, markdown)?;
+        }
+    }
+    Ok(())
+}
+
+#[patch("/workspaces/<ws_id>/docs/<doc_id>", format = "json", data = "<body>")]
 pub fn update_document(
     db: &State<Db>,
     ws_id: &str,
@@ -330,20 +1449,11 @@ pub fn update_document(
         return (status, Json(err));
     }
 
-    // Verify document belongs to workspace
-    if let Ok(Some(doc)) = crate::db::get_document_by_id(db, doc_id) {
-        if doc["workspace_id"].as_str() != Some(ws_id) {
-            return (
-                Status::NotFound,
-                Json(json!({"error": "Document not found in this workspace"})),
-            );
-        }
-    } else {
-        return (
-            Status::NotFound,
-            Json(json!({"error": "Document not found"})),
-        );
-    }
+    let before = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = before["id"].as_str().unwrap_or("");
 
     let title = body.get("title").and_then(|v| v.as_str());
     let content = body.get("content").and_then(|v| v.as_str());
@@ -353,8 +1463,58 @@ pub fn update_document(
     let author_name = body.get("author_name").and_then(|v| v.as_str());
     let change_description = body.get("change_description").and_then(|v| v.as_str());
 
-    let content_html = content.map(render_markdown);
-    let wc = content.map(word_count);
+    let old_slug = before["slug"].as_str().unwrap_or("").to_string();
+    let new_slug = match body.get("slug") {
+        None => None,
+        Some(Value::String(s)) if !s.is_empty() && slugify(s) == *s => {
+            Some(s.as_str()).filter(|s| *s != old_slug)
+        }
+        Some(_) => {
+            return (
+                Status::BadRequest,
+                Json(json!({
+                    "error": "slug must be lowercase letters, digits and dashes",
+                    "code": "VALIDATION_ERROR",
+                })),
+            )
+        }
+    };
+    if let Some(s) = new_slug {
+        match crate::db::get_document(db, ws_id, s) {
+            Ok(None) => {}
+            Ok(Some(_)) => return duplicate_slug(),
+            Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+        }
+    }
+
+    if let Err(err) = check_document_update(db, ws_id, &before, &body) {
+        return err;
+    }
+    let parsed = match content.map(parse_front_matter).transpose() {
+        Ok(p) => p,
+        Err(err) => return err,
+    };
+    let doc_type_change = match doc_type_change(&body) {
+        Ok(change) => change,
+        Err(err) => return err,
+    };
+    let fields = body.get("fields");
+
+    let content_html = parsed
+        .as_ref()
+        .map(|(_, markdown)| render_document(ws_id, markdown));
+    let wc = parsed.as_ref().map(|(_, markdown)| word_count(markdown));
+    let markdown = parsed.as_ref().map(|(_, markdown)| *markdown);
+    let metadata = parsed.map(|(meta, _)| meta.to_string());
+    let fields = fields.map(|f| f.to_string());
+
+    if let Some(s) = new_slug {
+        match crate::db::rename_document(db, doc_id, ws_id, &old_slug, s) {
+            Ok(()) => {}
+            Err(e) if e.contains("UNIQUE constraint") => return duplicate_slug(),
+            Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+        }
+    }
 
     match crate::db::update_document(
         db,
@@ -368,16 +1528,69 @@ pub fn update_document(
         author_name,
         wc,
         change_description,
+        metadata.as_deref(),
+        doc_type_change,
+        fields.as_deref(),
     ) {
-        Ok(true) => {
-            event_bus.emit(
-                ws_id,
-                "document.updated",
-                json!({"id": doc_id, "title": title, "author_name": author_name}),
-            );
-            (Status::Ok, Json(json!({"status": "updated"})))
+        Ok(updated) if updated || new_slug.is_some() => {
+            let data = document_updated_event(db, &before, author_name, change_description, None);
+            let version_number = data["version_number"].clone();
+            let mut links_updated = 0;
+            if let Some(s) = new_slug {
+                event_bus.emit(
+                    ws_id,
+                    "document.renamed",
+                    json!({
+                        "id": doc_id,
+                        "from": old_slug,
+                        "to": s,
+                        "author_name": author_name,
+                    }),
+                );
+            }
+            if before["status"] != data["status"] {
+                event_bus.emit(
+                    ws_id,
+                    "document.status_changed",
+                    json!({
+                        "id": doc_id,
+                        "from": before["status"],
+                        "to": data["status"],
+                        "author_name": author_name,
+                        "version_number": version_number,
+                    }),
+                );
+            }
+            event_bus.emit(ws_id, "document.updated", data);
+            if let Some(new_content) = content {
+                record_links(db, ws_id, doc_id, markdown.unwrap_or(new_content));
+                let old_content = before["content"].as_str().unwrap_or("");
+                reanchor_comments(db, event_bus, ws_id, doc_id, old_content, new_content);
+                notify_mentions(
+                    db,
+                    event_bus,
+                    ws_id,
+                    doc_id,
+                    None,
+                    author_name.unwrap_or(""),
+                    old_content,
+                    new_content,
+                );
+            }
+            if let Some(s) = new_slug {
+                links_updated = rewrite_links_to(db, event_bus, ws_id, &old_slug, s, author_name);
+            }
+            (
+                Status::Ok,
+                Json(json!({
+                    "status": "updated",
+                    "version_number": version_number,
+                    "slug": new_slug.unwrap_or(&old_slug),
+                    "links_updated": links_updated,
+                })),
+            )
         }
-        Ok(false) => (
+        Ok(_) => (
             Status::BadRequest,
             Json(json!({"error": "No fields to update"})),
         ),
@@ -397,14 +1610,24 @@ pub fn delete_document(
         return (status, Json(err));
     }
 
+    let existing = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = existing["id"].as_str().unwrap_or("");
+
     match crate::db::delete_document(db, doc_id) {
         Ok(true) => {
-            event_bus.emit(ws_id, "document.deleted", json!({"id": doc_id}));
+            event_bus.emit(
+                ws_id,
+                "document.deleted",
+                json!({"id": doc_id, "title": existing["title"], "slug": existing["slug"]}),
+            );
             (Status::Ok, Json(json!({"status": "deleted"})))
         }
         Ok(false) => (
             Status::NotFound,
-            Json(json!({"error": "Document not found"})),
+            Json(json!({"error": "Document not found", "code": "NOT_FOUND"})),
         ),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
@@ -412,14 +1635,19 @@ pub fn delete_document(
 
 // --- Version routes ---
 
-#[get("/workspaces/<_ws_id>/docs/<doc_id>/versions?<limit>&<offset>")]
+#[get("/workspaces/<ws_id>/docs/<doc_id>/versions?<limit>&<offset>")]
 pub fn list_versions(
     db: &State<Db>,
-    _ws_id: &str,
+    ws_id: &str,
     doc_id: &str,
     limit: Option<i32>,
     offset: Option<i32>,
 ) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
     let limit = limit.unwrap_or(20).min(100);
     let offset = offset.unwrap_or(0);
 
@@ -429,13 +1657,18 @@ pub fn list_versions(
     }
 }
 
-#[get("/workspaces/<_ws_id>/docs/<doc_id>/versions/<version_num>")]
+#[get("/workspaces/<ws_id>/docs/<doc_id>/versions/<version_num>")]
 pub fn get_version(
     db: &State<Db>,
-    _ws_id: &str,
+    ws_id: &str,
     doc_id: &str,
     version_num: i32,
 ) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
     match crate::db::get_version(db, doc_id, version_num) {
         Ok(Some(version)) => (Status::Ok, Json(version)),
         Ok(None) => (
@@ -446,14 +1679,19 @@ pub fn get_version(
     }
 }
 
-#[get("/workspaces/<_ws_id>/docs/<doc_id>/diff?<from>&<to>")]
+#[get("/workspaces/<ws_id>/docs/<doc_id>/diff?<from>&<to>")]
 pub fn get_diff(
     db: &State<Db>,
-    _ws_id: &str,
+    ws_id: &str,
     doc_id: &str,
     from: i32,
     to: i32,
 ) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
     let from_version = match crate::db::get_version(db, doc_id, from) {
         Ok(Some(v)) => v,
         Ok(None) => {
@@ -479,23 +1717,10 @@ pub fn get_diff(
     let from_content = from_version["content"].as_str().unwrap_or("");
     let to_content = to_version["content"].as_str().unwrap_or("");
 
-    // Generate unified diff
-    let diff = similar::TextDiff::from_lines(from_content, to_content);
-    let unified = diff
-        .unified_diff()
-        .header(&format!("version {}", from), &format!("version {}", to))
-        .to_string();
-
-    // Count insertions and removals from the diff
-    let mut insertions = 0usize;
-    let mut removals = 0usize;
-    for change in diff.iter_all_changes() {
-        match change.tag() {
-            similar::ChangeTag::Insert => insertions += 1,
-            similar::ChangeTag::Delete => removals += 1,
-            similar::ChangeTag::Equal => {}
-        }
-    }
+    let (from_label, to_label) = (format!("version {}", from), format!("version {}", to));
+    let (insertions, removals, unified) =
+        diff_stats(from_content, to_content, Some((&from_label, &to_label)));
+    let unified = unified.unwrap_or_default();
 
     (
         Status::Ok,
@@ -513,18 +1738,97 @@ pub fn get_diff(
 
 // --- Comment routes ---
 
+// Helper: the abuse controls on comments and suggestions — the workspace's
+// anonymous switch, then the comment policy's rate limits for callers without
+// the manage key (both share the same buckets). Returns the policy and whether
+// the caller is a moderator.
+fn guard_contribution(
+    db: &Db,
+    ws_id: &str,
+    doc_id: &str,
+    token: Option<&WorkspaceToken>,
+    client_ip: &ClientIp,
+    rate_limiter: &RateLimiter,
+) -> Result<(CommentPolicy, bool), (Status, Json<Value>)> {
+    let anonymous_allowed = crate::db::get_workspace(db, ws_id)
+        .ok()
+        .flatten()
+        .and_then(|ws| ws["allow_anonymous_comments"].as_bool())
+        .unwrap_or(true);
+    let is_moderator = token.is_some_and(|t| verify_workspace_auth(db, ws_id, t).is_ok());
+    if !anonymous_allowed && !is_moderator {
+        match token {
+            Some(token) => {
+                if let Err((status, err)) = verify_workspace_auth(db, ws_id, token) {
+                    return Err((status, Json(err)));
+                }
+            }
+            None => {
+                return Err((
+                    Status::Unauthorized,
+                    Json(json!({
+                        "error": "This workspace does not accept anonymous comments — send the manage key",
+                        "code": "ANONYMOUS_COMMENTS_DISABLED",
+                    })),
+                ))
+            }
+        }
+    }
+    let policy = load_comment_policy(db, ws_id)?;
+    if !is_moderator {
+        for (key, limit) in [
+            (
+                format!("comment:{}:ip:{}", ws_id, client_ip.0),
+                policy.rate_limit_per_ip,
+            ),
+            (
+                format!("comment:{}:doc:{}", ws_id, doc_id),
+                policy.rate_limit_per_document,
+            ),
+        ] {
+            let rl = rate_limiter.check(&key, limit);
+            if !rl.allowed {
+                return Err((
+                    Status::TooManyRequests,
+                    Json(json!({
+                        "error": "Too many comments — try again later",
+                        "code": "RATE_LIMIT_EXCEEDED",
+                        "retry_after_secs": rl.reset_secs,
+                    })),
+                ));
+            }
+        }
+    }
+    Ok((policy, is_moderator))
+}
+
 #[post(
     "/workspaces/<ws_id>/docs/<doc_id>/comments",
     format = "json",
     data = "<body>"
 )]
+#[allow(clippy::too_many_arguments)]
 pub fn create_comment(
     db: &State<Db>,
     ws_id: &str,
     doc_id: &str,
+    token: Option<WorkspaceToken>,
     body: Json<Value>,
+    client_ip: ClientIp,
+    rate_limiter: &State<RateLimiter>,
     event_bus: &State<EventBus>,
 ) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let (policy, is_moderator) =
+        match guard_contribution(db, ws_id, doc_id, token.as_ref(), &client_ip, rate_limiter) {
+            Ok(guard) => guard,
+            Err(err) => return err,
+        };
+
     let author_name = match body.get("author_name").and_then(|v| v.as_str()) {
         Some(n) if !n.trim().is_empty() => n.trim().to_string(),
         _ => {
@@ -549,7 +1853,60 @@ pub fn create_comment(
         .get("parent_id")
         .and_then(|v| v.as_str())
         .map(|s| s.to_string());
+    if let Err(v) = policy.check_length(&content) {
+        return policy_violation(v);
+    }
+    if !is_moderator {
+        if let Err(v) = policy.check_blocked(&content) {
+            return policy_violation(v);
+        }
+    }
+    if let Some(pid) = &parent_id {
+        match crate::db::get_comment_doc_id(db, pid) {
+            Ok(Some(d)) if d == doc_id => {
+                let depth = crate::db::comment_depth(db, pid).unwrap_or(0) + 1;
+                if let Err(v) = policy.check_depth(depth) {
+                    return policy_violation(v);
+                }
+            }
+            Ok(_) => {
+                return (
+                    Status::UnprocessableEntity,
+                    Json(json!({
+                        "error": "parent_id is not a comment on this document",
+                        "code": "INVALID_PARENT",
+                    })),
+                )
+            }
+            Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+        }
+    }
     let id = uuid::Uuid::new_v4().to_string();
+    let edit_token = generate_comment_token();
+    let moderation_status = if policy.hold_anonymous && !is_moderator {
+        "pending"
+    } else {
+        "approved"
+    };
+
+    let anchor = match body.get("anchor").filter(|a| !a.is_null()) {
+        Some(spec) => match resolve_anchor(db, &doc, spec) {
+            Ok(a) => Some(a),
+            Err(err) => return err,
+        },
+        None => None,
+    };
+    let anchor_json = anchor.as_ref().map(|(a, version)| {
+        json!({
+            "quote": a.quote,
+            "prefix": a.prefix,
+            "suffix": a.suffix,
+            "start": a.start,
+            "end": a.end,
+            "version_number": version,
+            "status": "anchored",
+        })
+    });
 
     match crate::db::create_comment(
         db,
@@ -558,12 +1915,56 @@ pub fn create_comment(
         parent_id.as_deref(),
         &author_name,
         &content,
+        anchor.as_ref().map(|(a, v)| (a, *v)),
+        &hash_key(&edit_token),
+        moderation_status,
     ) {
+        Ok(()) if moderation_status == "pending" => {
+            event_bus.emit(
+                ws_id,
+                "comment.held",
+                json!({
+                    "id": id,
+                    "document_id": doc_id,
+                    "parent_id": parent_id,
+                    "author_name": author_name,
+                }),
+            );
+            (
+                Status::Accepted,
+                Json(json!({
+                    "id": id,
+                    "document_id": doc_id,
+                    "parent_id": parent_id,
+                    "author_name": author_name,
+                    "content": content,
+                    "anchor": anchor_json,
+                    "moderation_status": moderation_status,
+                    "edit_token": edit_token,
+                })),
+            )
+        }
         Ok(()) => {
             event_bus.emit(
                 ws_id,
                 "comment.created",
-                json!({"id": id, "document_id": doc_id, "author_name": author_name}),
+                json!({
+                    "id": id,
+                    "document_id": doc_id,
+                    "parent_id": parent_id,
+                    "author_name": author_name,
+                    "anchor": anchor_json,
+                }),
+            );
+            notify_mentions(
+                db,
+                event_bus,
+                ws_id,
+                doc_id,
+                Some(&id),
+                &author_name,
+                "",
+                &content,
             );
             (
                 Status::Created,
@@ -573,6 +1974,9 @@ pub fn create_comment(
                     "parent_id": parent_id,
                     "author_name": author_name,
                     "content": content,
+                    "anchor": anchor_json,
+                    "moderation_status": moderation_status,
+                    "edit_token": edit_token,
                 })),
             )
         }
@@ -580,67 +1984,3235 @@ pub fn create_comment(
     }
 }
 
-#[get("/workspaces/<_ws_id>/docs/<doc_id>/comments")]
-pub fn list_comments(db: &State<Db>, _ws_id: &str, doc_id: &str) -> (Status, Json<Value>) {
-    match crate::db::list_comments(db, doc_id) {
-        Ok(comments) => (Status::Ok, Json(json!(comments))),
-        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
-    }
+// Helper: latest created/updated timestamp in a comment and its replies
+fn thread_activity(comment: &Value) -> String {
+    let own = [&comment["created_at"], &comment["updated_at"]]
+        .into_iter()
+        .filter_map(|v| v.as_str())
+        .max()
+        .unwrap_or("")
+        .to_string();
+    comment["replies"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(thread_activity)
+        .chain(std::iter::once(own))
+        .max()
+        .unwrap_or_default()
 }
 
-// --- Lock routes ---
+/// Nest a flat, chronological comment list into threads. Each node gains
+/// `depth`, `replies`, `reply_count` (all descendants) and `last_activity_at`.
+/// Replies whose parent no longer exists become roots.
+fn build_comment_threads(comments: Vec<Value>) -> Vec<Value> {
+    let ids: std::collections::HashSet<String> = comments
+        .iter()
+        .filter_map(|c| c["id"].as_str().map(|s| s.to_string()))
+        .collect();
+    let mut children: std::collections::HashMap<String, Vec<Value>> =
+        std::collections::HashMap::new();
+    let mut roots = Vec::new();
+    for comment in comments {
+        match comment["parent_id"].as_str() {
+            Some(parent) if ids.contains(parent) => children
+                .entry(parent.to_string())
+                .or_default()
+                .push(comment),
+            _ => roots.push(comment),
+        }
+    }
 
-#[post(
-    "/workspaces/<ws_id>/docs/<doc_id>/lock",
-    format = "json",
-    data = "<body>"
-)]
-pub fn acquire_lock(
+    fn attach(
+        mut node: Value,
+        depth: usize,
+        children: &mut std::collections::HashMap<String, Vec<Value>>,
+    ) -> Value {
+        let id = node["id"].as_str().unwrap_or("").to_string();
+        let replies: Vec<Value> = children
+            .remove(&id)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| attach(c, depth + 1, children))
+            .collect();
+        let descendants: usize = replies
+            .iter()
+            .map(|r| 1 + r["reply_count"].as_u64().unwrap_or(0) as usize)
+            .sum();
+        node["depth"] = json!(depth);
+        node["reply_count"] = json!(descendants);
+        node["replies"] = json!(replies);
+        node["last_activity_at"] = json!(thread_activity(&node));
+        node
+    }
+
+    roots
+        .into_iter()
+        .map(|root| attach(root, 0, &mut children))
+        .collect()
+}
+
+/// List comments with rendered `content_html` and reaction counts.
+/// Flat (chronological array) by default; `threaded=true` returns
+/// `{threads, total, limit, offset}` with replies nested under each root.
+/// `resolved` filters comments (threads, when threaded); `sort=activity`
+/// orders by most recent activity first.
+/// Comments held for moderation are only listed for the manage key.
+#[get("/workspaces/<ws_id>/docs/<doc_id>/comments?<threaded>&<resolved>&<sort>&<limit>&<offset>")]
+#[allow(clippy::too_many_arguments)]
+pub fn list_comments(
     db: &State<Db>,
     ws_id: &str,
     doc_id: &str,
-    token: WorkspaceToken,
-    body: Json<Value>,
-    event_bus: &State<EventBus>,
+    token: Option<WorkspaceToken>,
+    threaded: Option<bool>,
+    resolved: Option<bool>,
+    sort: Option<&str>,
+    limit: Option<usize>,
+    offset: Option<usize>,
 ) -> (Status, Json<Value>) {
-    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
-        return (status, Json(err));
-    }
-
-    let editor = body
-        .get("editor")
-        .and_then(|v| v.as_str())
-        .unwrap_or("anonymous");
-    let ttl = body
-        .get("ttl_seconds")
-        .and_then(|v| v.as_i64())
-        .unwrap_or(60) as i32;
-
-    match crate::db::acquire_lock(db, doc_id, editor, ttl) {
-        Ok(true) => {
-            event_bus.emit(
-                ws_id,
-                "lock.acquired",
-                json!({"document_id": doc_id, "locked_by": editor, "ttl_seconds": ttl}),
-            );
-            (
-                Status::Ok,
-                Json(json!({"status": "locked", "locked_by": editor, "ttl_seconds": ttl})),
+    let by_activity = match sort.unwrap_or("created") {
+        "created" => false,
+        "activity" => true,
+        other => {
+            return (
+                Status::BadRequest,
+                Json(
+                    json!({"error": format!("Unknown sort '{}' (created or activity)", other), "code": "VALIDATION_ERROR"}),
+                ),
             )
         }
-        Ok(false) => (
+    };
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let mut summaries = match crate::db::reaction_summaries(db, doc_id, "comment") {
+        Ok(s) => s,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let mut comments = match crate::db::list_comments(db, doc_id) {
+        Ok(c) => c,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let is_moderator = token
+        .as_ref()
+        .is_some_and(|t| verify_workspace_auth(db, ws_id, t).is_ok());
+    if !is_moderator {
+        comments.retain(|c| c["moderation_status"] == "approved");
+    }
+    for comment in comments.iter_mut() {
+        let summary = comment["id"]
+            .as_str()
+            .and_then(|id| summaries.remove(id))
+            .unwrap_or_else(empty_reactions);
+        comment["reactions"] = summary["reactions"].clone();
+        comment["vote_score"] = summary["score"].clone();
+        comment["content_html"] = json!(render_comment(
+            ws_id,
+            comment["content"].as_str().unwrap_or("")
+        ));
+    }
+
+    let mut items = if threaded.unwrap_or(false) {
+        build_comment_threads(comments)
+    } else {
+        comments
+    };
+    if let Some(r) = resolved {
+        items.retain(|c| c["resolved"].as_bool() == Some(r));
+    }
+    if by_activity {
+        items.sort_by_cached_key(|c| std::cmp::Reverse(thread_activity(c)));
+    }
+
+    let total = items.len();
+    let offset = offset.unwrap_or(0);
+    let page: Vec<Value> = match limit {
+        Some(l) => items
+            .into_iter()
+            .skip(offset)
+            .take(l.clamp(1, 500))
+            .collect(),
+        None => items.into_iter().skip(offset).collect(),
+    };
+
+    if threaded.unwrap_or(false) {
+        (
+            Status::Ok,
+            Json(json!({
+                "threads": page,
+                "total": total,
+                "limit": limit,
+                "offset": offset,
+            })),
+        )
+    } else {
+        (Status::Ok, Json(json!(page)))
+    }
+}
+
+// --- Reaction routes ---
+
+fn empty_reactions() -> Value {
+    json!({"reactions": {}, "score": 0})
+}
+
+// Helper: validate the reaction and author in a request body
+fn reaction_fields(
+    author_name: Option<&str>,
+    reaction: Option<&str>,
+) -> Result<(String, String), (Status, Json<Value>)> {
+    let author = match author_name.map(str::trim) {
+        Some(a) if !a.is_empty() => a.to_string(),
+        _ => {
+            return Err((
+                Status::BadRequest,
+                Json(json!({"error": "author_name is required", "code": "VALIDATION_ERROR"})),
+            ))
+        }
+    };
+    match reaction.map(str::trim) {
+        Some(r) if !r.is_empty() && r.chars().count() <= 32 && !r.contains(char::is_whitespace) => {
+            Ok((author, r.to_string()))
+        }
+        _ => Err((
+            Status::BadRequest,
+            Json(json!({
+                "error": "reaction is required: an emoji or short token like +1, -1, needs-info (max 32 chars, no spaces)",
+                "code": "VALIDATION_ERROR",
+            })),
+        )),
+    }
+}
+
+/// Add (`add == true`) or remove a reaction on a document or one of its
+/// comments, and announce the new count.
+#[allow(clippy::too_many_arguments)]
+fn change_reaction(
+    db: &Db,
+    event_bus: &EventBus,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: Option<&str>,
+    author_name: Option<&str>,
+    reaction: Option<&str>,
+    add: bool,
+) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    if let Some(cid) = comment_id {
+        match crate::db::get_comment_doc_id(db, cid) {
+            Ok(Some(d)) if d == doc_id => {}
+            Ok(_) => {
+                return (
+                    Status::NotFound,
+                    Json(json!({"error": "Comment not found", "code": "NOT_FOUND"})),
+                )
+            }
+            Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+        }
+    }
+    let (author, reaction) = match reaction_fields(author_name, reaction) {
+        Ok(f) => f,
+        Err(err) => return err,
+    };
+    let (target_type, target_id) = match comment_id {
+        Some(cid) => ("comment", cid),
+        None => ("document", doc_id),
+    };
+
+    let changed = if add {
+        let id = uuid::Uuid::new_v4().to_string();
+        crate::db::add_reaction(db, &id, doc_id, target_type, target_id, &author, &reaction)
+    } else {
+        crate::db::remove_reaction(db, target_type, target_id, &author, &reaction)
+    };
+    let changed = match changed {
+        Ok(c) => c,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    if !add && !changed {
+        return (
+            Status::NotFound,
+            Json(json!({"error": "Reaction not found", "code": "NOT_FOUND"})),
+        );
+    }
+
+    let summary = crate::db::reaction_summaries(db, doc_id, target_type)
+        .ok()
+        .and_then(|mut m| m.remove(target_id))
+        .unwrap_or_else(empty_reactions);
+    let data = json!({
+        "document_id": doc_id,
+        "target_type": target_type,
+        "target_id": target_id,
+        "author_name": author,
+        "reaction": reaction,
+        "count": summary["reactions"][&reaction]["count"].as_i64().unwrap_or(0),
+        "vote_score": summary["score"],
+    });
+    if changed {
+        event_bus.emit(
+            ws_id,
+            if add {
+                "reaction.added"
+            } else {
+                "reaction.removed"
+            },
+            data.clone(),
+        );
+    }
+    let mut body = data;
+    body["reactions"] = summary["reactions"].clone();
+    let status = if add && changed {
+        Status::Created
+    } else {
+        Status::Ok
+    };
+    (status, Json(body))
+}
+
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/reactions",
+    format = "json",
+    data = "<body>"
+)]
+pub fn add_document_reaction(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    let author = body.get("author_name").and_then(|v| v.as_str());
+    let reaction = body.get("reaction").and_then(|v| v.as_str());
+    change_reaction(db, event_bus, ws_id, doc_id, None, author, reaction, true)
+}
+
+#[delete("/workspaces/<ws_id>/docs/<doc_id>/reactions?<author_name>&<reaction>")]
+pub fn remove_document_reaction(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    author_name: Option<&str>,
+    reaction: Option<&str>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    change_reaction(
+        db,
+        event_bus,
+        ws_id,
+        doc_id,
+        None,
+        author_name,
+        reaction,
+        false,
+    )
+}
+
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/comments/<comment_id>/reactions",
+    format = "json",
+    data = "<body>"
+)]
+pub fn add_comment_reaction(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: &str,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    let author = body.get("author_name").and_then(|v| v.as_str());
+    let reaction = body.get("reaction").and_then(|v| v.as_str());
+    change_reaction(
+        db,
+        event_bus,
+        ws_id,
+        doc_id,
+        Some(comment_id),
+        author,
+        reaction,
+        true,
+    )
+}
+
+#[delete(
+    "/workspaces/<ws_id>/docs/<doc_id>/comments/<comment_id>/reactions?<author_name>&<reaction>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn remove_comment_reaction(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: &str,
+    author_name: Option<&str>,
+    reaction: Option<&str>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    change_reaction(
+        db,
+        event_bus,
+        ws_id,
+        doc_id,
+        Some(comment_id),
+        author_name,
+        reaction,
+        false,
+    )
+}
+
+// --- Suggestion routes ---
+
+// Helper: load a pending suggestion on a document
+fn pending_suggestion(
+    db: &Db,
+    doc_id: &str,
+    suggestion_id: &str,
+) -> Result<Value, (Status, Json<Value>)> {
+    match crate::db::get_suggestion(db, doc_id, suggestion_id) {
+        Ok(Some(s)) if s["status"] == "pending" => Ok(s),
+        Ok(Some(s)) => Err((
+            Status::Conflict,
+            Json(json!({
+                "error": format!("Suggestion already {}", s["status"].as_str().unwrap_or("reviewed")),
+                "code": "ALREADY_REVIEWED",
+            })),
+        )),
+        Ok(None) => Err((
+            Status::NotFound,
+            Json(json!({"error": "Suggestion not found", "code": "NOT_FOUND"})),
+        )),
+        Err(e) => Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+}
+
+/// Propose a change without write access: either `anchor` + `replacement`
+/// for a text range, or `content` for a whole new body (against `base_version`).
+/// Anonymous suggestions go through the same controls as anonymous comments.
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/suggestions",
+    format = "json",
+    data = "<body>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn create_suggestion(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    token: Option<WorkspaceToken>,
+    body: Json<Value>,
+    client_ip: ClientIp,
+    rate_limiter: &State<RateLimiter>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    let author_name = match body.get("author_name").and_then(|v| v.as_str()) {
+        Some(n) if !n.trim().is_empty() => n.trim().to_string(),
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": "author_name is required"})),
+            )
+        }
+    };
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let (policy, is_moderator) =
+        match guard_contribution(db, ws_id, doc_id, token.as_ref(), &client_ip, rate_limiter) {
+            Ok(guard) => guard,
+            Err(err) => return err,
+        };
+    let description = body
+        .get("description")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .trim()
+        .to_string();
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let anchor_spec = body.get("anchor").filter(|a| !a.is_null());
+    let replacement = body.get("replacement").and_then(|v| v.as_str());
+    let proposed = body.get("content").and_then(|v| v.as_str());
+    if !is_moderator {
+        for text in [Some(description.as_str()), replacement, proposed]
+            .into_iter()
+            .flatten()
+        {
+            if let Err(v) = policy.check_blocked(text) {
+                return policy_violation(v);
+            }
+        }
+    }
+
+    let result = match (anchor_spec, replacement, proposed) {
+        (Some(spec), Some(replacement), None) => {
+            let (anchor, base_version) = match resolve_anchor(db, &doc, spec) {
+                Ok(a) => a,
+                Err(err) => return err,
+            };
+            crate::db::create_suggestion(
+                db,
+                &id,
+                doc_id,
+                &author_name,
+                &description,
+                base_version,
+                &crate::db::SuggestedChange::Range {
+                    anchor: &anchor,
+                    replacement,
+                },
+            )
+            .map(|_| base_version)
+        }
+        (None, None, Some(content)) => {
+            let latest = crate::db::latest_version_number(db, doc_id).unwrap_or(0);
+            let base_version = body
+                .get("base_version")
+                .and_then(|v| v.as_i64())
+                .map(|v| v as i32)
+                .unwrap_or(latest);
+            if base_version < 1 || base_version > latest {
+                return (
+                    Status::NotFound,
+                    Json(
+                        json!({"error": format!("Version {} not found", base_version), "code": "NOT_FOUND"}),
+                    ),
+                );
+            }
+            crate::db::create_suggestion(
+                db,
+                &id,
+                doc_id,
+                &author_name,
+                &description,
+                base_version,
+                &crate::db::SuggestedChange::Content(content),
+            )
+            .map(|_| base_version)
+        }
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(json!({
+                    "error": "Provide either anchor and replacement, or content",
+                    "code": "VALIDATION_ERROR",
+                })),
+            )
+        }
+    };
+
+    match result {
+        Ok(base_version) => {
+            let kind = if anchor_spec.is_some() {
+                "range"
+            } else {
+                "content"
+            };
+            event_bus.emit(
+                ws_id,
+                "suggestion.created",
+                json!({
+                    "id": id,
+                    "suggestion_id": id,
+                    "document_id": doc_id,
+                    "author_name": author_name,
+                    "kind": kind,
+                    "base_version": base_version,
+                    "description": description,
+                }),
+            );
+            match crate::db::get_suggestion(db, doc_id, &id) {
+                Ok(Some(s)) => (Status::Created, Json(s)),
+                _ => (Status::Created, Json(json!({"id": id}))),
+            }
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[get("/workspaces/<ws_id>/docs/<doc_id>/suggestions?<status>")]
+pub fn list_suggestions(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    status: Option<&str>,
+) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    match crate::db::list_suggestions(db, doc_id, status) {
+        Ok(suggestions) => (Status::Ok, Json(json!(suggestions))),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Apply a pending suggestion as a new version. Range suggestions are carried
+/// forward from their base version; whole-content suggestions against an older
+/// version conflict unless `force` is set.
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/suggestions/<suggestion_id>/accept",
+    data = "<body>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn accept_suggestion(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    suggestion_id: &str,
+    token: WorkspaceToken,
+    body: Option<Json<Value>>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let before = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = before["id"].as_str().unwrap_or("");
+    let suggestion = match pending_suggestion(db, doc_id, suggestion_id) {
+        Ok(s) => s,
+        Err(err) => return err,
+    };
+    let body = body.map(|b| b.into_inner()).unwrap_or(json!({}));
+    let reviewed_by = body.get("reviewed_by").and_then(|v| v.as_str());
+    let force = body.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+
+    let current = before["content"].as_str().unwrap_or("");
+    let base_version = suggestion["base_version"].as_i64().unwrap_or(0) as i32;
+    let latest = crate::db::latest_version_number(db, doc_id).unwrap_or(0);
+    let conflict = |msg: String| {
+        (
             Status::Conflict,
-            Json(json!({"error": "Document is locked by another editor", "code": "LOCK_CONFLICT"})),
+            Json(json!({"error": msg, "code": "SUGGESTION_CONFLICT", "current_version": latest})),
+        )
+    };
+
+    let new_content = if suggestion["kind"] == "range" {
+        let range = &suggestion["range"];
+        let anchor = crate::anchors::Anchor {
+            quote: range["quote"].as_str().unwrap_or("").to_string(),
+            prefix: range["prefix"].as_str().unwrap_or("").to_string(),
+            suffix: range["suffix"].as_str().unwrap_or("").to_string(),
+            start: range["start"].as_u64().unwrap_or(0) as usize,
+            end: range["end"].as_u64().unwrap_or(0) as usize,
+        };
+        let base = if base_version == latest {
+            current.to_string()
+        } else {
+            match crate::db::get_version(db, doc_id, base_version) {
+                Ok(Some(v)) => v["content"].as_str().unwrap_or("").to_string(),
+                Ok(None) => {
+                    return conflict(format!("Base version {} no longer exists", base_version))
+                }
+                Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+            }
+        };
+        match crate::anchors::reanchor(&base, current, &anchor) {
+            Some((start, end)) => crate::anchors::replace_range(
+                current,
+                start,
+                end,
+                suggestion["replacement"].as_str().unwrap_or(""),
+            ),
+            None => {
+                return conflict("The suggested range no longer exists in the document".to_string())
+            }
+        }
+    } else {
+        if base_version != latest && !force {
+            return conflict(format!(
+                "Document changed since version {} (now {}); pass force to overwrite",
+                base_version, latest
+            ));
+        }
+        suggestion["proposed_content"]
+            .as_str()
+            .unwrap_or("")
+            .to_string()
+    };
+
+    let description = suggestion["description"].as_str().unwrap_or("");
+    let change_desc = if description.is_empty() {
+        format!("Accepted suggestion {}", suggestion_id)
+    } else {
+        format!("Accepted suggestion: {}", description)
+    };
+    let author = suggestion["author_name"].as_str();
+
+    // Claim the suggestion before saving so a concurrent accept cannot apply it twice
+    match crate::db::review_suggestion(db, suggestion_id, "accepted", reviewed_by, None, None) {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                Status::Conflict,
+                Json(json!({"error": "Suggestion already reviewed", "code": "ALREADY_REVIEWED"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    let data = match save_document_content(
+        db,
+        event_bus,
+        ws_id,
+        &before,
+        &new_content,
+        author,
+        &change_desc,
+        None,
+    ) {
+        Ok(d) => d,
+        Err(err) => {
+            if let Err(e) = crate::db::reopen_suggestion(db, suggestion_id) {
+                eprintln!("⚠️  Failed to reopen suggestion {}: {}", suggestion_id, e);
+            }
+            return err;
+        }
+    };
+    let version_number = data["version_number"].as_i64().map(|v| v as i32);
+    if let Err(e) = crate::db::set_suggestion_applied_version(db, suggestion_id, version_number) {
+        return (Status::InternalServerError, Json(json!({"error": e})));
+    }
+    event_bus.emit(
+        ws_id,
+        "suggestion.accepted",
+        json!({
+            "id": suggestion_id,
+            "suggestion_id": suggestion_id,
+            "document_id": doc_id,
+            "author_name": author,
+            "reviewed_by": reviewed_by,
+            "version_number": version_number,
+        }),
+    );
+    (
+        Status::Ok,
+        Json(json!({"status": "accepted", "version_number": version_number})),
+    )
+}
+
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/suggestions/<suggestion_id>/reject",
+    data = "<body>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn reject_suggestion(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    suggestion_id: &str,
+    token: WorkspaceToken,
+    body: Option<Json<Value>>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let suggestion = match pending_suggestion(db, doc_id, suggestion_id) {
+        Ok(s) => s,
+        Err(err) => return err,
+    };
+    let body = body.map(|b| b.into_inner()).unwrap_or(json!({}));
+    let reviewed_by = body.get("reviewed_by").and_then(|v| v.as_str());
+    let reason = body.get("reason").and_then(|v| v.as_str());
+
+    match crate::db::review_suggestion(db, suggestion_id, "rejected", reviewed_by, reason, None) {
+        Ok(true) => {
+            event_bus.emit(
+                ws_id,
+                "suggestion.rejected",
+                json!({
+                    "id": suggestion_id,
+                    "suggestion_id": suggestion_id,
+                    "document_id": doc_id,
+                    "author_name": suggestion["author_name"],
+                    "reviewed_by": reviewed_by,
+                    "reason": reason,
+                }),
+            );
+            (Status::Ok, Json(json!({"status": "rejected"})))
+        }
+        Ok(false) => (
+            Status::Conflict,
+            Json(json!({"error": "Suggestion already reviewed", "code": "ALREADY_REVIEWED"})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+// --- Lock routes ---
+
+/// A lock operation, shared by the REST lock routes and WebSocket `lock.*` messages.
+#[derive(Clone, Copy, PartialEq)]
+pub enum LockOp {
+    Acquire,
+    Renew,
+    Release,
+}
+
+/// Longest lock a single acquire or renew can ask for.
+const MAX_LOCK_TTL: i64 = 3600;
+
+/// Apply a lock operation to a workspace document (id or slug) and announce
+/// it. `ttl_seconds` defaults to 60 and is clamped to `1..=3600`. Returns the
+/// event payload.
+pub fn apply_lock(
+    db: &Db,
+    event_bus: &EventBus,
+    ws_id: &str,
+    doc_ref: &str,
+    op: LockOp,
+    editor: Option<&str>,
+    ttl_seconds: Option<i64>,
+) -> Result<Value, (Status, Json<Value>)> {
+    let doc = document_in_workspace(db, ws_id, doc_ref)?;
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let editor = editor.unwrap_or("anonymous");
+    let ttl = ttl_seconds.unwrap_or(60).clamp(1, MAX_LOCK_TTL) as i32;
+
+    let (result, event, conflict) = match op {
+        LockOp::Acquire => (
+            crate::db::acquire_lock(db, doc_id, editor, ttl),
+            "lock.acquired",
+            (
+                Status::Conflict,
+                "Document is locked by another editor",
+                "LOCK_CONFLICT",
+            ),
+        ),
+        LockOp::Renew => (
+            crate::db::renew_lock(db, doc_id, editor, ttl),
+            "lock.renewed",
+            (
+                Status::Conflict,
+                "Lock not held by this editor or expired",
+                "LOCK_CONFLICT",
+            ),
+        ),
+        LockOp::Release => (
+            crate::db::release_lock(db, doc_id),
+            "lock.released",
+            (Status::NotFound, "Document not found", "NOT_FOUND"),
+        ),
+    };
+    match result {
+        Ok(true) => {
+            let data = match op {
+                LockOp::Release => json!({"document_id": doc_id}),
+                _ => json!({"document_id": doc_id, "locked_by": editor, "ttl_seconds": ttl}),
+            };
+            event_bus.emit(ws_id, event, data.clone());
+            Ok(data)
+        }
+        Ok(false) => {
+            let (status, error, code) = conflict;
+            Err((status, Json(json!({"error": error, "code": code}))))
+        }
+        Err(e) => Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+}
+
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/lock",
+    format = "json",
+    data = "<body>"
+)]
+pub fn acquire_lock(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let editor = body.get("editor").and_then(|v| v.as_str());
+    let ttl = body.get("ttl_seconds").and_then(|v| v.as_i64());
+    match apply_lock(db, event_bus, ws_id, doc_id, LockOp::Acquire, editor, ttl) {
+        Ok(data) => (
+            Status::Ok,
+            Json(
+                json!({"status": "locked", "locked_by": data["locked_by"], "ttl_seconds": data["ttl_seconds"]}),
+            ),
+        ),
+        Err(err) => err,
+    }
+}
+
+#[delete("/workspaces/<ws_id>/docs/<doc_id>/lock")]
+pub fn release_lock(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    match apply_lock(db, event_bus, ws_id, doc_id, LockOp::Release, None, None) {
+        Ok(_) => (Status::Ok, Json(json!({"status": "unlocked"}))),
+        Err(err) => err,
+    }
+}
+
+// --- Lock renew ---
+
+#[post(
+    "/workspaces/<ws_id>/docs/<doc_id>/lock/renew",
+    format = "json",
+    data = "<body>"
+)]
+pub fn renew_lock(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let editor = body.get("editor").and_then(|v| v.as_str());
+    let ttl = body.get("ttl_seconds").and_then(|v| v.as_i64());
+    match apply_lock(db, event_bus, ws_id, doc_id, LockOp::Renew, editor, ttl) {
+        Ok(data) => (
+            Status::Ok,
+            Json(
+                json!({"status": "renewed", "locked_by": data["locked_by"], "ttl_seconds": data["ttl_seconds"]}),
+            ),
+        ),
+        Err(err) => err,
+    }
+}
+
+// --- Comment moderation ---
+
+#[delete("/workspaces/<ws_id>/docs/<doc_id>/comments/<comment_id>")]
+pub fn delete_comment(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: &str,
+    token: Option<WorkspaceToken>,
+    comment_token: Option<CommentToken>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    let doc_id = match authorize_comment(
+        db,
+        ws_id,
+        doc_id,
+        comment_id,
+        token.as_ref(),
+        comment_token.as_ref(),
+    ) {
+        Ok((doc_id, _)) => doc_id,
+        Err(err) => return err,
+    };
+
+    match crate::db::delete_comment(db, comment_id) {
+        Ok(true) => {
+            event_bus.emit(
+                ws_id,
+                "comment.deleted",
+                json!({"id": comment_id, "comment_id": comment_id, "document_id": doc_id}),
+            );
+            (Status::Ok, Json(json!({"status": "deleted"})))
+        }
+        Ok(false) => (
+            Status::NotFound,
+            Json(json!({"error": "Comment not found"})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[patch(
+    "/workspaces/<ws_id>/docs/<doc_id>/comments/<comment_id>",
+    format = "json",
+    data = "<body>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn update_comment(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: &str,
+    token: Option<WorkspaceToken>,
+    comment_token: Option<CommentToken>,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    let (doc_id, is_moderator) = match authorize_comment(
+        db,
+        ws_id,
+        doc_id,
+        comment_id,
+        token.as_ref(),
+        comment_token.as_ref(),
+    ) {
+        Ok(authorized) => authorized,
+        Err(err) => return err,
+    };
+
+    let content = body.get("content").and_then(|v| v.as_str());
+    let resolved = body.get("resolved").and_then(|v| v.as_bool());
+
+    if content.is_none() && resolved.is_none() {
+        return (
+            Status::UnprocessableEntity,
+            Json(json!({"error": "Provide content and/or resolved", "code": "MISSING_FIELDS"})),
+        );
+    }
+    // Comment tokens cover the author's own words; resolving is for moderators
+    if resolved.is_some() && !is_moderator {
+        return (
+            Status::Forbidden,
+            Json(
+                json!({"error": "Resolving comments requires the manage key", "code": "FORBIDDEN"}),
+            ),
+        );
+    }
+    if let Some(c) = content {
+        let policy = match load_comment_policy(db, ws_id) {
+            Ok(p) => p,
+            Err(err) => return err,
+        };
+        let checked = policy.check_length(c).and_then(|_| {
+            if is_moderator {
+                Ok(())
+            } else {
+                policy.check_blocked(c)
+            }
+        });
+        if let Err(v) = checked {
+            return policy_violation(v);
+        }
+    }
+
+    let previous = crate::db::get_comment(db, comment_id).ok().flatten();
+    match crate::db::update_comment(db, comment_id, content, resolved) {
+        Ok(true) => {
+            if let (Some(new), Some((author, old))) = (content, &previous) {
+                notify_mentions(
+                    db,
+                    event_bus,
+                    ws_id,
+                    &doc_id,
+                    Some(comment_id),
+                    author,
+                    old,
+                    new,
+                );
+            }
+            let data = json!({
+                "id": comment_id,
+                "comment_id": comment_id,
+                "document_id": doc_id,
+                "content_changed": content.is_some(),
+                "resolved": resolved,
+            });
+            event_bus.emit(ws_id, "comment.updated", data);
+            (Status::Ok, Json(json!({"status": "updated"})))
+        }
+        Ok(false) => (
+            Status::NotFound,
+            Json(json!({"error": "Comment not found"})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[get("/workspaces/<ws_id>/comment-policy")]
+pub fn get_comment_policy(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    match load_comment_policy(db, ws_id) {
+        Ok(policy) => (Status::Ok, Json(json!({"policy": policy}))),
+        Err(err) => err,
+    }
+}
+
+/// Replace the workspace comment policy. Omitted fields take their defaults;
+/// `{"policy": null}` restores the defaults entirely.
+#[put("/workspaces/<ws_id>/comment-policy", format = "json", data = "<body>")]
+pub fn set_comment_policy(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+
+    let policy = if body.get("policy").is_some_and(|p| p.is_null()) {
+        None
+    } else {
+        let raw = body.get("policy").unwrap_or(&body).clone();
+        match serde_json::from_value::<CommentPolicy>(raw) {
+            Ok(p) => Some(p),
+            Err(e) => {
+                return (
+                    Status::BadRequest,
+                    Json(
+                        json!({"error": format!("Invalid comment policy: {}", e), "code": "VALIDATION_ERROR"}),
+                    ),
+                )
+            }
+        }
+    };
+    if let Some(Err(e)) = policy.as_ref().map(|p| p.validate()) {
+        return (
+            Status::BadRequest,
+            Json(
+                json!({"error": format!("Invalid comment policy: {}", e), "code": "VALIDATION_ERROR"}),
+            ),
+        );
+    }
+
+    let stored = policy.as_ref().map(|p| json!(p).to_string());
+    match crate::db::set_workspace_comment_policy(db, ws_id, stored.as_deref()) {
+        Ok(_) => {
+            if let Ok(Some(ws)) = crate::db::get_workspace(db, ws_id) {
+                event_bus.emit(
+                    ws_id,
+                    "workspace.updated",
+                    json!({
+                        "id": ws_id,
+                        "name": ws["name"],
+                        "description": ws["description"],
+                        "is_public": ws["is_public"],
+                        "changed": ["comment_policy"],
+                    }),
+                );
+            }
+            (
+                Status::Ok,
+                Json(json!({"policy": policy.unwrap_or_default()})),
+            )
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// The moderation queue: comments held under `hold_anonymous`, oldest first.
+/// Approve them with `.../approve`, or reject them with DELETE.
+#[get("/workspaces/<ws_id>/comments/pending")]
+pub fn list_pending_comments(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    match crate::db::list_pending_comments(db, ws_id) {
+        Ok(pending) => (
+            Status::Ok,
+            Json(json!({"count": pending.len(), "comments": pending})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[post("/workspaces/<ws_id>/docs/<doc_id>/comments/<comment_id>/approve")]
+pub fn approve_comment(
+    db: &State<Db>,
+    ws_id: &str,
+    doc_id: &str,
+    comment_id: &str,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    let doc_id = match authorize_comment(db, ws_id, doc_id, comment_id, Some(&token), None) {
+        Ok((doc_id, _)) => doc_id,
+        Err(err) => return err,
+    };
+    match crate::db::approve_comment(db, comment_id) {
+        Ok(true) => {}
+        Ok(false) => {
+            return (
+                Status::Conflict,
+                Json(json!({"error": "Comment is not awaiting moderation", "code": "NOT_PENDING"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+
+    let comment = crate::db::list_comments(db, &doc_id)
+        .ok()
+        .and_then(|all| all.into_iter().find(|c| c["id"] == comment_id))
+        .unwrap_or_else(|| json!({"id": comment_id}));
+    // Announce it now that it is visible, as if it had just been posted
+    event_bus.emit(
+        ws_id,
+        "comment.created",
+        json!({
+            "id": comment_id,
+            "document_id": doc_id,
+            "parent_id": comment["parent_id"],
+            "author_name": comment["author_name"],
+            "anchor": comment["anchor"],
+        }),
+    );
+    if let (Some(author), Some(content)) =
+        (comment["author_name"].as_str(), comment["content"].as_str())
+    {
+        notify_mentions(
+            db,
+            event_bus,
+            ws_id,
+            &doc_id,
+            Some(comment_id),
+            author,
+            "",
+            content,
+        );
+    }
+    (Status::Ok, Json(comment))
+}
+
+// --- Template routes ---
+
+// Helper: a workspace template (by id or slug) or a built-in one.
+// `builtin:<slug>` skips workspace templates. Returns the stored row, if any.
+fn find_template(
+    db: &Db,
+    ws_id: &str,
+    reference: &str,
+) -> Result<Option<(Option<Value>, Template)>, String> {
+    if let Some(slug) = reference.strip_prefix("builtin:") {
+        return Ok(crate::templates::builtins()
+            .into_iter()
+            .find(|t| t.slug == slug)
+            .map(|t| (None, t)));
+    }
+    if let Some(row) = crate::db::get_template(db, ws_id, reference)? {
+        let template = serde_json::from_value(row.clone()).map_err(|e| e.to_string())?;
+        return Ok(Some((Some(row), template)));
+    }
+    Ok(crate::templates::builtins()
+        .into_iter()
+        .find(|t| t.slug == reference)
+        .map(|t| (None, t)))
+}
+
+// Helper: API shape of a template, with every variable it uses
+fn describe_template(row: Option<&Value>, t: &Template) -> Value {
+    let required = t.required_variables();
+    let variables: Vec<Value> = t
+        .all_variables()
+        .into_iter()
+        .map(|v| {
+            json!({
+                "name": v.name,
+                "default": v.default,
+                "description": v.description,
+                "required": required.contains(&v.name),
+            })
+        })
+        .collect();
+    json!({
+        "id": row.map(|r| r["id"].clone()).unwrap_or_else(|| json!(format!("builtin:{}", t.slug))),
+        "builtin": row.is_none(),
+        "slug": t.slug,
+        "name": t.name,
+        "description": t.description,
+        "title": t.title,
+        "content": t.content,
+        "tags": t.tags,
+        "variables": variables,
+        "created_at": row.map(|r| r["created_at"].clone()),
+        "updated_at": row.map(|r| r["updated_at"].clone()),
+    })
+}
+
+// Helper: variable bindings from a request, plus the built-in `date` and `author_name`
+fn template_bindings(
+    variables: Option<&Value>,
+    author_name: &str,
+) -> Result<std::collections::HashMap<String, String>, (Status, Json<Value>)> {
+    let mut bindings = std::collections::HashMap::new();
+    if let Some(vars) = variables.filter(|v| !v.is_null()) {
+        let Some(obj) = vars.as_object() else {
+            return Err((
+                Status::BadRequest,
+                Json(
+                    json!({"error": "variables must be an object of name → value", "code": "VALIDATION_ERROR"}),
+                ),
+            ));
+        };
+        for (name, value) in obj {
+            let value = match value {
+                Value::String(s) => s.clone(),
+                Value::Number(_) | Value::Bool(_) => value.to_string(),
+                _ => {
+                    return Err((
+                        Status::BadRequest,
+                        Json(json!({
+                            "error": format!("variable '{}' must be a string, number or boolean", name),
+                            "code": "VALIDATION_ERROR",
+                        })),
+                    ))
+                }
+            };
+            bindings.insert(name.clone(), value);
+        }
+    }
+    bindings
+        .entry("date".to_string())
+        .or_insert_with(|| chrono::Utc::now().format("%Y-%m-%d").to_string());
+    bindings
+        .entry("author_name".to_string())
+        .or_insert_with(|| author_name.to_string());
+    Ok(bindings)
+}
+
+fn missing_variables(missing: Vec<String>) -> (Status, Json<Value>) {
+    (
+        Status::UnprocessableEntity,
+        Json(json!({
+            "error": format!("Missing template variables: {}", missing.join(", ")),
+            "code": "MISSING_VARIABLES",
+            "missing": missing,
+        })),
+    )
+}
+
+// Helper: build a template from a request body, filling gaps from `base` (for PATCH)
+fn template_from_body(
+    body: &Value,
+    base: Option<Template>,
+) -> Result<Template, (Status, Json<Value>)> {
+    let text = |field: &str| {
+        body.get(field)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    };
+    let invalid = |msg: String| {
+        (
+            Status::BadRequest,
+            Json(json!({"error": msg, "code": "VALIDATION_ERROR"})),
+        )
+    };
+    let name = text("name").or(base.as_ref().map(|b| b.name.clone()));
+    let content = text("content").or(base.as_ref().map(|b| b.content.clone()));
+    let (Some(name), Some(content)) = (name, content) else {
+        return Err(invalid("name and content are required".to_string()));
+    };
+    let tags = match body.get("tags") {
+        Some(v) => serde_json::from_value(v.clone())
+            .map_err(|_| invalid("tags must be an array of strings".to_string()))?,
+        None => base.as_ref().map(|b| b.tags.clone()).unwrap_or_default(),
+    };
+    let variables = match body.get("variables") {
+        Some(v) => serde_json::from_value(v.clone())
+            .map_err(|e| invalid(format!("Invalid variables: {}", e)))?,
+        None => base
+            .as_ref()
+            .map(|b| b.variables.clone())
+            .unwrap_or_default(),
+    };
+    let template = Template {
+        slug: text("slug")
+            .or(base.as_ref().map(|b| b.slug.clone()))
+            .unwrap_or_else(|| slugify(&name)),
+        name: name.trim().to_string(),
+        description: text("description")
+            .or(base.as_ref().map(|b| b.description.clone()))
+            .unwrap_or_default(),
+        title: text("title")
+            .or(base.as_ref().map(|b| b.title.clone()))
+            .unwrap_or_default(),
+        content,
+        tags,
+        variables,
+    };
+    template
+        .validate()
+        .map_err(|e| invalid(format!("Invalid template: {}", e)))?;
+    Ok(template)
+}
+
+/// Workspace templates followed by the built-in library.
+#[get("/workspaces/<ws_id>/templates")]
+pub fn list_templates(db: &State<Db>, ws_id: &str) -> (Status, Json<Value>) {
+    match crate::db::get_workspace(db, ws_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    let rows = match crate::db::list_templates(db, ws_id) {
+        Ok(rows) => rows,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let mut templates: Vec<Value> = rows
+        .iter()
+        .filter_map(|row| {
+            serde_json::from_value::<Template>(row.clone())
+                .ok()
+                .map(|t| describe_template(Some(row), &t))
+        })
+        .collect();
+    templates.extend(
+        crate::templates::builtins()
+            .iter()
+            .map(|t| describe_template(None, t)),
+    );
+    (Status::Ok, Json(json!({"templates": templates})))
+}
+
+#[get("/workspaces/<ws_id>/templates/<template_id>")]
+pub fn get_template(db: &State<Db>, ws_id: &str, template_id: &str) -> (Status, Json<Value>) {
+    match find_template(db, ws_id, template_id) {
+        Ok(Some((row, t))) => (Status::Ok, Json(describe_template(row.as_ref(), &t))),
+        Ok(None) => (
+            Status::NotFound,
+            Json(json!({"error": "Template not found", "code": "NOT_FOUND"})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[post("/workspaces/<ws_id>/templates", format = "json", data = "<body>")]
+pub fn create_template(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let template = match template_from_body(&body, None) {
+        Ok(t) => t,
+        Err(err) => return err,
+    };
+    let id = uuid::Uuid::new_v4().to_string();
+    match crate::db::create_template(db, &id, ws_id, &template) {
+        Ok(()) => {
+            event_bus.emit(
+                ws_id,
+                "template.created",
+                json!({"id": id, "slug": template.slug, "name": template.name}),
+            );
+            let row = crate::db::get_template(db, ws_id, &id).ok().flatten();
+            (
+                Status::Created,
+                Json(describe_template(row.as_ref(), &template)),
+            )
+        }
+        Err(e) if e.contains("UNIQUE constraint") => (
+            Status::Conflict,
+            Json(
+                json!({"error": "A template with this slug already exists", "code": "DUPLICATE_SLUG"}),
+            ),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+// Helper: a workspace template that may be changed (built-ins are read-only)
+fn editable_template(
+    db: &Db,
+    ws_id: &str,
+    template_id: &str,
+) -> Result<(Value, Template), (Status, Json<Value>)> {
+    match find_template(db, ws_id, template_id) {
+        Ok(Some((Some(row), t))) => Ok((row, t)),
+        Ok(Some((None, _))) => Err((
+            Status::Forbidden,
+            Json(json!({"error": "Built-in templates are read-only", "code": "READ_ONLY"})),
+        )),
+        Ok(None) => Err((
+            Status::NotFound,
+            Json(json!({"error": "Template not found", "code": "NOT_FOUND"})),
+        )),
+        Err(e) => Err((Status::InternalServerError, Json(json!({"error": e})))),
+    }
+}
+
+#[patch(
+    "/workspaces/<ws_id>/templates/<template_id>",
+    format = "json",
+    data = "<body>"
+)]
+pub fn update_template(
+    db: &State<Db>,
+    ws_id: &str,
+    template_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let (row, current) = match editable_template(db, ws_id, template_id) {
+        Ok(found) => found,
+        Err(err) => return err,
+    };
+    let template = match template_from_body(&body, Some(current)) {
+        Ok(t) => t,
+        Err(err) => return err,
+    };
+    let id = row["id"].as_str().unwrap_or_default();
+    match crate::db::update_template(db, id, &template) {
+        Ok(_) => {
+            event_bus.emit(
+                ws_id,
+                "template.updated",
+                json!({"id": id, "slug": template.slug, "name": template.name}),
+            );
+            let row = crate::db::get_template(db, ws_id, id).ok().flatten();
+            (Status::Ok, Json(describe_template(row.as_ref(), &template)))
+        }
+        Err(e) if e.contains("UNIQUE constraint") => (
+            Status::Conflict,
+            Json(
+                json!({"error": "A template with this slug already exists", "code": "DUPLICATE_SLUG"}),
+            ),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[delete("/workspaces/<ws_id>/templates/<template_id>")]
+pub fn delete_template(
+    db: &State<Db>,
+    ws_id: &str,
+    template_id: &str,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let (row, template) = match editable_template(db, ws_id, template_id) {
+        Ok(found) => found,
+        Err(err) => return err,
+    };
+    let id = row["id"].as_str().unwrap_or_default();
+    match crate::db::delete_template(db, id) {
+        Ok(_) => {
+            event_bus.emit(
+                ws_id,
+                "template.deleted",
+                json!({"id": id, "slug": template.slug, "name": template.name}),
+            );
+            (Status::Ok, Json(json!({"status": "deleted"})))
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Preview a template with bindings without creating a document.
+#[post(
+    "/workspaces/<ws_id>/templates/<template_id>/render",
+    format = "json",
+    data = "<body>"
+)]
+pub fn render_template(
+    db: &State<Db>,
+    ws_id: &str,
+    template_id: &str,
+    body: Json<Value>,
+) -> (Status, Json<Value>) {
+    let template = match find_template(db, ws_id, template_id) {
+        Ok(Some((_, t))) => t,
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Template not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let author_name = body
+        .get("author_name")
+        .and_then(|v| v.as_str())
+        .unwrap_or("");
+    let bindings = match template_bindings(body.get("variables"), author_name) {
+        Ok(b) => b,
+        Err(err) => return err,
+    };
+    let title = body
+        .get("title")
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|t| !t.is_empty());
+    match template.instantiate(title, &bindings) {
+        Ok((title, content)) => {
+            let (metadata, body) = crate::frontmatter::parse_lenient(&content);
+            (
+                Status::Ok,
+                Json(json!({
+                    "title": title,
+                    "content_html": render_document(ws_id, body),
+                    "metadata": metadata,
+                    "content": content,
+                    "tags": template.tags,
+                })),
+            )
+        }
+        Err(missing) => missing_variables(missing),
+    }
+}
+
+// --- Document type routes ---
+
+// Helper: a workspace document type by id or slug, with its stored row
+fn find_doc_type(
+    db: &Db,
+    ws_id: &str,
+    reference: &str,
+) -> Result<Option<(Value, DocumentType)>, String> {
+    match crate::db::get_doc_type(db, ws_id, reference)? {
+        Some(row) => {
+            let doc_type = serde_json::from_value(row.clone()).map_err(|e| e.to_string())?;
+            Ok(Some((row, doc_type)))
+        }
+        None => Ok(None),
+    }
+}
+
+fn doc_type_not_found() -> (Status, Json<Value>) {
+    (
+        Status::NotFound,
+        Json(json!({"error": "Document type not found", "code": "NOT_FOUND"})),
+    )
+}
+
+fn document_problems(problems: Vec<Problem>) -> (Status, Json<Value>) {
+    (
+        Status::UnprocessableEntity,
+        Json(json!({
+            "error": format!("Document does not match its type ({} problem(s))", problems.len()),
+            "code": "DOCUMENT_INVALID",
+            "errors": problems,
+        })),
+    )
+}
+
+// Helper: check fields and markdown body against the named document type
+fn check_doc_type(
+    db: &Db,
+    ws_id: &str,
+    slug: &str,
+    fields: &Value,
+    markdown: &str,
+) -> Result<(), (Status, Json<Value>)> {
+    let doc_type = match find_doc_type(db, ws_id, slug) {
+        Ok(Some((_, t))) => t,
+        Ok(None) => {
+            return Err((
+                Status::UnprocessableEntity,
+                Json(json!({
+                    "error": format!("Document type '{}' not found", slug),
+                    "code": "UNKNOWN_DOC_TYPE",
+                })),
+            ))
+        }
+        Err(e) => return Err((Status::InternalServerError, Json(json!({"error": e})))),
+    };
+    let problems = doc_type.check(fields, markdown);
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(document_problems(problems))
+    }
+}
+
+// Helper: build a document type from a request body, filling gaps from `base` (for PATCH)
+fn doc_type_from_body(
+    body: &Value,
+    base: Option<DocumentType>,
+) -> Result<DocumentType, (Status, Json<Value>)> {
+    let text = |field: &str| {
+        body.get(field)
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    };
+    let invalid = |msg: String| {
+        (
+            Status::BadRequest,
+            Json(json!({"error": msg, "code": "VALIDATION_ERROR"})),
+        )
+    };
+    let Some(name) = text("name").or(base.as_ref().map(|b| b.name.clone())) else {
+        return Err(invalid("name is required".to_string()));
+    };
+    let required_headings = match body.get("required_headings") {
+        Some(v) => serde_json::from_value(v.clone())
+            .map_err(|_| invalid("required_headings must be an array of strings".to_string()))?,
+        None => base
+            .as_ref()
+            .map(|b| b.required_headings.clone())
+            .unwrap_or_default(),
+    };
+    let doc_type = DocumentType {
+        // The slug is fixed once created
+        slug: base
+            .as_ref()
+            .map(|b| b.slug.clone())
+            .or(text("slug"))
+            .unwrap_or_else(|| slugify(&name)),
+        name: name.trim().to_string(),
+        description: text("description")
+            .or(base.as_ref().map(|b| b.description.clone()))
+            .unwrap_or_default(),
+        schema: body
+            .get("schema")
+            .cloned()
+            .or(base.as_ref().map(|b| b.schema.clone()))
+            .unwrap_or_else(|| json!({"type": "object"})),
+        required_headings,
+    };
+    doc_type
+        .validate()
+        .map_err(|e| invalid(format!("Invalid document type: {}", e)))?;
+    Ok(doc_type)
+}
+
+#[get("/workspaces/<ws_id>/doc-types")]
+pub fn list_doc_types(db: &State<Db>, ws_id: &str) -> (Status, Json<Value>) {
+    match crate::db::get_workspace(db, ws_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    match crate::db::list_doc_types(db, ws_id) {
+        Ok(types) => (Status::Ok, Json(json!({"doc_types": types}))),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[get("/workspaces/<ws_id>/doc-types/<type_id>")]
+pub fn get_doc_type(db: &State<Db>, ws_id: &str, type_id: &str) -> (Status, Json<Value>) {
+    match crate::db::get_doc_type(db, ws_id, type_id) {
+        Ok(Some(row)) => (Status::Ok, Json(row)),
+        Ok(None) => doc_type_not_found(),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[post("/workspaces/<ws_id>/doc-types", format = "json", data = "<body>")]
+pub fn create_doc_type(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let doc_type = match doc_type_from_body(&body, None) {
+        Ok(t) => t,
+        Err(err) => return err,
+    };
+    let id = uuid::Uuid::new_v4().to_string();
+    match crate::db::create_doc_type(db, &id, ws_id, &doc_type) {
+        Ok(()) => {
+            event_bus.emit(
+                ws_id,
+                "doc_type.created",
+                json!({"id": id, "slug": doc_type.slug, "name": doc_type.name}),
+            );
+            match crate::db::get_doc_type(db, ws_id, &id) {
+                Ok(Some(row)) => (Status::Created, Json(row)),
+                _ => (
+                    Status::Created,
+                    Json(json!({"id": id, "slug": doc_type.slug})),
+                ),
+            }
+        }
+        Err(e) if e.contains("UNIQUE constraint") => (
+            Status::Conflict,
+            Json(
+                json!({"error": "A document type with this slug already exists", "code": "DUPLICATE_SLUG"}),
+            ),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Change a type's name, description, schema or required headings. Existing
+/// documents are checked against the new definition the next time they are saved.
+#[patch(
+    "/workspaces/<ws_id>/doc-types/<type_id>",
+    format = "json",
+    data = "<body>"
+)]
+pub fn update_doc_type(
+    db: &State<Db>,
+    ws_id: &str,
+    type_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let (row, current) = match find_doc_type(db, ws_id, type_id) {
+        Ok(Some(found)) => found,
+        Ok(None) => return doc_type_not_found(),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let doc_type = match doc_type_from_body(&body, Some(current)) {
+        Ok(t) => t,
+        Err(err) => return err,
+    };
+    let id = row["id"].as_str().unwrap_or_default();
+    match crate::db::update_doc_type(db, id, &doc_type) {
+        Ok(_) => {
+            event_bus.emit(
+                ws_id,
+                "doc_type.updated",
+                json!({"id": id, "slug": doc_type.slug, "name": doc_type.name}),
+            );
+            match crate::db::get_doc_type(db, ws_id, id) {
+                Ok(Some(row)) => (Status::Ok, Json(row)),
+                _ => (Status::Ok, Json(json!({"status": "updated"}))),
+            }
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Types still used by documents cannot be deleted.
+#[delete("/workspaces/<ws_id>/doc-types/<type_id>")]
+pub fn delete_doc_type(
+    db: &State<Db>,
+    ws_id: &str,
+    type_id: &str,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let (row, doc_type) = match find_doc_type(db, ws_id, type_id) {
+        Ok(Some(found)) => found,
+        Ok(None) => return doc_type_not_found(),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    match crate::db::count_documents_of_type(db, ws_id, &doc_type.slug) {
+        Ok(0) => {}
+        Ok(n) => {
+            return (
+                Status::Conflict,
+                Json(json!({
+                    "error": format!("{} document(s) still use this type", n),
+                    "code": "DOC_TYPE_IN_USE",
+                    "documents": n,
+                })),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    let id = row["id"].as_str().unwrap_or_default();
+    match crate::db::delete_doc_type(db, id) {
+        Ok(_) => {
+            event_bus.emit(
+                ws_id,
+                "doc_type.deleted",
+                json!({"id": id, "slug": doc_type.slug, "name": doc_type.name}),
+            );
+            (Status::Ok, Json(json!({"status": "deleted"})))
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Dry run: check `fields` and `content` against a type without saving anything.
+#[post(
+    "/workspaces/<ws_id>/doc-types/<type_id>/validate",
+    format = "json",
+    data = "<body>"
+)]
+pub fn validate_doc_type(
+    db: &State<Db>,
+    ws_id: &str,
+    type_id: &str,
+    body: Json<Value>,
+) -> (Status, Json<Value>) {
+    let doc_type = match find_doc_type(db, ws_id, type_id) {
+        Ok(Some((_, t))) => t,
+        Ok(None) => return doc_type_not_found(),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let fields = body.get("fields").cloned().unwrap_or_else(|| json!({}));
+    let content = body.get("content").and_then(|v| v.as_str()).unwrap_or("");
+    let (metadata, markdown) = match parse_front_matter(content) {
+        Ok(parsed) => parsed,
+        Err(err) => return err,
+    };
+    let problems = doc_type.check(&fields, markdown);
+    (
+        Status::Ok,
+        Json(json!({
+            "valid": problems.is_empty(),
+            "errors": problems,
+            "metadata": metadata,
+        })),
+    )
+}
+
+// --- Attachment routes ---
+
+// Helper: an attachment row with its URL and a markdown snippet to embed it
+fn attachment_json(ws_id: &str, mut row: Value) -> Value {
+    let id = row["id"].as_str().unwrap_or("").to_string();
+    let filename = row["filename"].as_str().unwrap_or("").to_string();
+    let image = row["content_type"]
+        .as_str()
+        .is_some_and(|t| t.starts_with("image/"));
+    row["url"] = json!(crate::attachments::url(ws_id, &id));
+    row["markdown"] = json!(format!(
+        "{}[{}]({}{})",
+        if image { "!" } else { "" },
+        filename.replace(['[', ']'], ""),
+        crate::attachments::SCHEME,
+        id
+    ));
+    row
+}
+
+fn attachment_not_found() -> (Status, Json<Value>) {
+    (
+        Status::NotFound,
+        Json(json!({"error": "Attachment not found", "code": "NOT_FOUND"})),
+    )
+}
+
+fn attachment_too_large(max_bytes: usize) -> (Status, Json<Value>) {
+    (
+        Status::PayloadTooLarge,
+        Json(json!({
+            "error": format!("Attachments are limited to {} bytes", max_bytes),
+            "code": "ATTACHMENT_TOO_LARGE",
+            "max_bytes": max_bytes,
+        })),
+    )
+}
+
+// Helper: store uploaded bytes as a workspace attachment. Bytes the workspace
+// already has return the existing attachment (200) rather than a copy.
+#[allow(clippy::too_many_arguments)]
+fn store_attachment(
+    db: &Db,
+    blobs: &BlobStore,
+    event_bus: &EventBus,
+    ws_id: &str,
+    filename: Option<&str>,
+    declared_type: Option<&str>,
+    bytes: &[u8],
+    uploaded_by: Option<&str>,
+) -> (Status, Json<Value>) {
+    if bytes.is_empty() {
+        return (
+            Status::BadRequest,
+            Json(json!({"error": "Attachment is empty", "code": "VALIDATION_ERROR"})),
+        );
+    }
+    if bytes.len() > blobs.max_bytes {
+        return attachment_too_large(blobs.max_bytes);
+    }
+    let (sha256, _) = match blobs.put(bytes) {
+        Ok(stored) => stored,
+        Err(e) => {
+            return (
+                Status::InternalServerError,
+                Json(json!({"error": format!("Failed to store attachment: {}", e)})),
+            )
+        }
+    };
+    match crate::db::find_attachment_by_hash(db, ws_id, &sha256) {
+        Ok(Some(existing)) => return (Status::Ok, Json(attachment_json(ws_id, existing))),
+        Ok(None) => {}
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+
+    let id = uuid::Uuid::new_v4().to_string();
+    let filename = crate::attachments::clean_filename(filename.unwrap_or(""));
+    let content_type = crate::attachments::sniff(bytes, declared_type);
+    let uploaded_by = uploaded_by.map(str::trim).unwrap_or("");
+    if let Err(e) = crate::db::create_attachment(
+        db,
+        &id,
+        ws_id,
+        &filename,
+        content_type,
+        bytes.len(),
+        &sha256,
+        uploaded_by,
+    ) {
+        return (Status::InternalServerError, Json(json!({"error": e})));
+    }
+    event_bus.emit(
+        ws_id,
+        "attachment.created",
+        json!({
+            "id": id,
+            "filename": filename,
+            "content_type": content_type,
+            "size": bytes.len(),
+            "uploaded_by": uploaded_by,
+        }),
+    );
+    match crate::db::get_attachment(db, ws_id, &id) {
+        Ok(Some(row)) => (Status::Created, Json(attachment_json(ws_id, row))),
+        _ => (Status::Created, Json(json!({"id": id}))),
+    }
+}
+
+/// `multipart/form-data` upload: the file in a `file` part.
+#[derive(rocket::FromForm)]
+pub struct AttachmentForm<'r> {
+    file: TempFile<'r>,
+    uploaded_by: Option<String>,
+}
+
+/// Upload a file as `multipart/form-data`. The stored content type is sniffed
+/// from the bytes; uploads over `ATTACHMENT_MAX_BYTES` are refused with 413.
+#[post(
+    "/workspaces/<ws_id>/attachments",
+    format = "multipart/form-data",
+    data = "<form>"
+)]
+pub async fn upload_attachment_form(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    form: Form<AttachmentForm<'_>>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    use rocket::tokio::io::AsyncReadExt;
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let file = &form.file;
+    if file.len() as usize > blobs.max_bytes {
+        return attachment_too_large(blobs.max_bytes);
+    }
+    let mut bytes = Vec::new();
+    let read = match file.open().await {
+        Ok(mut reader) => reader.read_to_end(&mut bytes).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = read {
+        return (
+            Status::InternalServerError,
+            Json(json!({"error": format!("Failed to read upload: {}", e)})),
+        );
+    }
+    let filename = file
+        .raw_name()
+        .map(|n| n.dangerous_unsafe_unsanitized_raw().as_str());
+    let declared_type = file.content_type().map(|t| t.to_string());
+    store_attachment(
+        db,
+        blobs,
+        event_bus,
+        ws_id,
+        filename,
+        declared_type.as_deref(),
+        &bytes,
+        form.uploaded_by.as_deref(),
+    )
+}
+
+/// Upload a file as the raw request body, named by `?filename=`.
+#[post(
+    "/workspaces/<ws_id>/attachments?<filename>&<uploaded_by>",
+    data = "<data>",
+    rank = 2
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn upload_attachment(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    filename: Option<&str>,
+    uploaded_by: Option<&str>,
+    content_type: Option<&ContentType>,
+    token: WorkspaceToken,
+    data: Data<'_>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let limit = ByteUnit::from(blobs.max_bytes as u64 + 1);
+    let bytes = match data.open(limit).into_bytes().await {
+        Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+        Ok(_) => return attachment_too_large(blobs.max_bytes),
+        Err(e) => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": format!("Failed to read upload: {}", e)})),
+            )
+        }
+    };
+    let declared_type = content_type.map(|t| t.to_string());
+    store_attachment(
+        db,
+        blobs,
+        event_bus,
+        ws_id,
+        filename,
+        declared_type.as_deref(),
+        &bytes,
+        uploaded_by,
+    )
+}
+
+#[get("/workspaces/<ws_id>/attachments")]
+pub fn list_attachments(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    match crate::db::list_attachments(db, ws_id) {
+        Ok(rows) => {
+            let total_bytes: i64 = rows.iter().filter_map(|r| r["size"].as_i64()).sum();
+            let attachments: Vec<Value> = rows
+                .into_iter()
+                .map(|r| attachment_json(ws_id, r))
+                .collect();
+            (
+                Status::Ok,
+                Json(json!({
+                    "count": attachments.len(),
+                    "total_bytes": total_bytes,
+                    "attachments": attachments,
+                })),
+            )
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// The attachment's bytes, with its sniffed content type.
+#[get("/workspaces/<ws_id>/attachments/<attachment_id>")]
+pub fn get_attachment(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    attachment_id: &str,
+) -> Result<AttachmentFile, (Status, Json<Value>)> {
+    let row = match crate::db::get_attachment(db, ws_id, attachment_id) {
+        Ok(Some(row)) => row,
+        Ok(None) => return Err(attachment_not_found()),
+        Err(e) => return Err((Status::InternalServerError, Json(json!({"error": e})))),
+    };
+    let sha256 = row["sha256"].as_str().unwrap_or("").to_string();
+    match blobs.get(&sha256) {
+        Ok(bytes) => Ok(AttachmentFile {
+            bytes,
+            content_type: row["content_type"].as_str().unwrap_or("").to_string(),
+            filename: row["filename"].as_str().unwrap_or("file").to_string(),
+            sha256,
+        }),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(attachment_not_found()),
+        Err(e) => Err((
+            Status::InternalServerError,
+            Json(json!({"error": e.to_string()})),
+        )),
+    }
+}
+
+// Helper: delete an attachment row, and its blob once nothing else uses it.
+// Returns whether the blob went too.
+fn remove_attachment(db: &Db, blobs: &BlobStore, row: &Value) -> Result<bool, String> {
+    let id = row["id"].as_str().unwrap_or("");
+    let sha256 = row["sha256"].as_str().unwrap_or("");
+    crate::db::delete_attachment(db, id)?;
+    if crate::db::attachment_hash_in_use(db, sha256)? {
+        return Ok(false);
+    }
+    blobs
+        .remove(sha256)
+        .map(|removed| removed.is_some())
+        .map_err(|e| e.to_string())
+}
+
+/// Delete an attachment. Refused with 409 while current documents embed it,
+/// unless `force=true`.
+#[delete("/workspaces/<ws_id>/attachments/<attachment_id>?<force>")]
+pub fn delete_attachment(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    attachment_id: &str,
+    force: Option<bool>,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let row = match crate::db::get_attachment(db, ws_id, attachment_id) {
+        Ok(Some(row)) => row,
+        Ok(None) => return attachment_not_found(),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    if !force.unwrap_or(false) {
+        match crate::db::documents_referencing_attachment(db, ws_id, attachment_id) {
+            Ok(docs) if !docs.is_empty() => {
+                return (
+                    Status::Conflict,
+                    Json(json!({
+                        "error": "Attachment is used by documents — pass force=true to delete anyway",
+                        "code": "ATTACHMENT_IN_USE",
+                        "documents": docs,
+                    })),
+                )
+            }
+            Ok(_) => {}
+            Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+        }
+    }
+    match remove_attachment(db, blobs, &row) {
+        Ok(blob_deleted) => {
+            event_bus.emit(
+                ws_id,
+                "attachment.deleted",
+                json!({"id": attachment_id, "filename": row["filename"]}),
+            );
+            (
+                Status::Ok,
+                Json(json!({"status": "deleted", "blob_deleted": blob_deleted})),
+            )
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Garbage-collect: delete the workspace's attachments that no document,
+/// past version, comment or pending suggestion mentions, then blobs on disk
+/// that no attachment in any workspace uses. The blob sweep is global: it
+/// also frees blobs left behind by other workspaces. Only things at least
+/// `min_age_minutes` old (default 60) are collected, so fresh uploads
+/// survive until they are used.
+#[post("/workspaces/<ws_id>/attachments/gc?<dry_run>&<min_age_minutes>")]
+pub fn collect_attachments(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    dry_run: Option<bool>,
+    min_age_minutes: Option<u32>,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let dry_run = dry_run.unwrap_or(false);
+    let min_age = min_age_minutes.unwrap_or(60);
+    let cutoff = (chrono::Utc::now() - chrono::Duration::minutes(min_age as i64))
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string();
+
+    let (referenced, rows) = match crate::db::referenced_attachment_ids(db, ws_id)
+        .and_then(|ids| Ok((ids, crate::db::list_attachments(db, ws_id)?)))
+    {
+        Ok(found) => found,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let unused: Vec<Value> = rows
+        .into_iter()
+        .filter(|r| {
+            !referenced.contains(r["id"].as_str().unwrap_or(""))
+                && r["created_at"].as_str().unwrap_or("") <= cutoff.as_str()
+        })
+        .collect();
+
+    if !dry_run {
+        for row in &unused {
+            if let Err(e) = crate::db::delete_attachment(db, row["id"].as_str().unwrap_or("")) {
+                return (Status::InternalServerError, Json(json!({"error": e})));
+            }
+            event_bus.emit(
+                ws_id,
+                "attachment.deleted",
+                json!({"id": row["id"], "filename": row["filename"]}),
+            );
+        }
+    }
+
+    // Blobs used by any workspace's remaining attachments stay
+    let dropped: std::collections::HashSet<&str> =
+        unused.iter().filter_map(|r| r["id"].as_str()).collect();
+    let keep = match crate::db::attachment_hashes(db, &dropped) {
+        Ok(hashes) => hashes,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let orphans = match blobs.orphans(&keep, Duration::from_secs(min_age as u64 * 60)) {
+        Ok(found) => found,
+        Err(e) => {
+            return (
+                Status::InternalServerError,
+                Json(json!({"error": e.to_string()})),
+            )
+        }
+    };
+    let orphans: Vec<(String, u64)> = if dry_run {
+        orphans
+    } else {
+        // Re-check the age: an upload may have reused the blob since the scan
+        orphans
+            .into_iter()
+            .filter(|(sha256, _)| {
+                match blobs.remove_settled(sha256, Duration::from_secs(min_age as u64 * 60)) {
+                    Ok(removed) => removed.is_some(),
+                    Err(e) => {
+                        eprintln!("⚠️  Failed to delete blob {}: {}", sha256, e);
+                        false
+                    }
+                }
+            })
+            .collect()
+    };
+
+    (
+        Status::Ok,
+        Json(json!({
+            "dry_run": dry_run,
+            "attachments_removed": unused
+                .into_iter()
+                .map(|r| attachment_json(ws_id, r))
+                .collect::<Vec<_>>(),
+            "blobs_removed": orphans.len(),
+            "bytes_freed": orphans.iter().map(|(_, size)| size).sum::<u64>(),
+        })),
+    )
+}
+
+// --- Export ---
+
+/// Download the whole workspace, drafts included, as a zip of markdown files
+/// with front matter. `versions` and `comments` add JSON sidecars per
+/// document; attachments are included unless `attachments=false`.
+/// `format=site` downloads the published documents as a static HTML site
+/// instead, laid out by the HTML attachment `template` if given.
+#[get("/workspaces/<ws_id>/export?<format>&<versions>&<comments>&<attachments>&<template>")]
+#[allow(clippy::too_many_arguments)]
+pub fn export_workspace(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    format: Option<&str>,
+    versions: Option<bool>,
+    comments: Option<bool>,
+    attachments: Option<bool>,
+    template: Option<&str>,
+    token: WorkspaceToken,
+) -> Result<ZipDownload, (Status, Json<Value>)> {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return Err((status, Json(err)));
+    }
+    let site = match format.unwrap_or("zip") {
+        "zip" => false,
+        "site" => true,
+        other => {
+            return Err((
+                Status::BadRequest,
+                Json(json!({
+                    "error": format!("Unsupported export format '{}' (zip, site)", other),
+                    "code": "UNSUPPORTED_FORMAT",
+                })),
+            ))
+        }
+    };
+    let name = crate::db::get_workspace(db, ws_id)
+        .ok()
+        .flatten()
+        .and_then(|ws| ws["name"].as_str().map(slugify))
+        .filter(|s| !s.is_empty())
+        .unwrap_or_else(|| "workspace".to_string());
+    let date = chrono::Utc::now().format("%Y%m%d");
+    if site {
+        let template = match template {
+            Some(id) => site_template(db, blobs, ws_id, id)?,
+            None => crate::site::SiteTemplate::default(),
+        };
+        let site = crate::site::build(db, ws_id, &template)
+            .map_err(|e| (Status::InternalServerError, Json(json!({"error": e}))))?;
+        let blobs = blobs.inner().clone();
+        let ws_id = ws_id.to_string();
+        return Ok(ZipDownload::spawn(
+            format!("{}-site-{}.zip", name, date),
+            move |out| {
+                site.write_zip(&blobs, out)
+                    .map_err(|e| format!("Site export of {} failed: {}", ws_id, e))
+            },
+        ));
+    }
+    let filename = format!("{}-{}.zip", name, date);
+    let options = ExportOptions {
+        versions: versions.unwrap_or(false),
+        comments: comments.unwrap_or(false),
+        attachments: attachments.unwrap_or(true),
+    };
+    Ok(ZipDownload::start(
+        db.inner().clone(),
+        blobs.inner().clone(),
+        ws_id.to_string(),
+        options,
+        filename,
+    ))
+}
+
+// Helper: a site template uploaded as an attachment
+fn site_template(
+    db: &Db,
+    blobs: &BlobStore,
+    ws_id: &str,
+    id: &str,
+) -> Result<crate::site::SiteTemplate, (Status, Json<Value>)> {
+    let invalid = |error: String| {
+        (
+            Status::BadRequest,
+            Json(json!({"error": error, "code": "INVALID_TEMPLATE"})),
+        )
+    };
+    let attachment = match crate::db::get_attachment(db, ws_id, id) {
+        Ok(Some(a)) => a,
+        Ok(None) => return Err(invalid(format!("Template attachment '{}' not found", id))),
+        Err(e) => return Err((Status::InternalServerError, Json(json!({"error": e})))),
+    };
+    let bytes = blobs
+        .get(attachment["sha256"].as_str().unwrap_or(""))
+        .map_err(|e| {
+            (
+                Status::InternalServerError,
+                Json(json!({"error": e.to_string()})),
+            )
+        })?;
+    let html =
+        String::from_utf8(bytes).map_err(|_| invalid("Template is not valid UTF-8".to_string()))?;
+    crate::site::SiteTemplate::parse(&html).map_err(invalid)
+}
+
+// --- Import ---
+
+/// What an import does with a file whose slug the workspace already uses.
+#[derive(Clone, Copy, PartialEq)]
+enum OnConflict {
+    Skip,
+    Update,
+    Rename,
+}
+
+// Helper: the `POST /docs` body for an imported note
+fn imported_document(note: &crate::import::Note, slug: &str, content: &str, author: &str) -> Value {
+    let mut body = json!({
+        "title": note.title,
+        "slug": slug,
+        "content": content,
+        "author_name": author,
+    });
+    for key in ["summary", "status", "doc_type", "fields"] {
+        if let Some(value) = note.document.get(key).filter(|v| !v.is_null()) {
+            body[key] = value.clone();
+        }
+    }
+    // `tags: ops, oncall` is as common in the wild as a YAML list
+    match note.document.get("tags") {
+        Some(Value::String(list)) => {
+            let tags: Vec<&str> = list
+                .split(',')
+                .map(str::trim)
+                .filter(|t| !t.is_empty())
+                .collect();
+            body["tags"] = json!(tags);
+        }
+        Some(tags @ Value::Array(_)) => body["tags"] = tags.clone(),
+        _ => {}
+    }
+    body
+}
+
+// Helper: the `PATCH` body that brings `before` in line with an imported
+// document, holding only what differs
+fn imported_update(before: &Value, body: Value) -> Value {
+    let mut update = serde_json::Map::new();
+    for (key, value) in body.as_object().into_iter().flatten() {
+        let same = match key.as_str() {
+            "slug" => true,
+            "author_name" => false,
+            _ => before.get(key) == Some(value),
+        };
+        if !same {
+            update.insert(key.clone(), value.clone());
+        }
+    }
+    Value::Object(update)
+}
+
+// Helper: what `POST /docs` would refuse about an imported document
+fn check_imported_document(
+    db: &Db,
+    ws_id: &str,
+    workflow: Option<&Workflow>,
+    default_status: &str,
+    body: &Value,
+) -> Result<(), (Status, Json<Value>)> {
+    check_initial_status(workflow, body["status"].as_str().unwrap_or(default_status))?;
+    let fields = body.get("fields").cloned().unwrap_or_else(|| json!({}));
+    if !fields.is_object() {
+        return Err((
+            Status::BadRequest,
+            Json(json!({"error": "fields must be an object", "code": "VALIDATION_ERROR"})),
+        ));
+    }
+    if let Some(t) = body["doc_type"].as_str() {
+        let (_, markdown) = parse_front_matter(body["content"].as_str().unwrap_or(""))?;
+        check_doc_type(db, ws_id, t, &fields, markdown)?;
+    }
+    Ok(())
+}
+
+// Helper: copy a refusal's error, code and problems into an import result
+fn import_failure(entry: &mut Value, err: &Value) {
+    entry["result"] = json!("failed");
+    for key in ["error", "code", "errors"] {
+        if let Some(v) = err.get(key) {
+            entry[key] = v.clone();
+        }
+    }
+}
+
+/// Import a zip of markdown files (a docs folder, an Obsidian vault or an
+/// export archive) as documents, with a result per file. `dry_run=true`
+/// reports what would happen without writing anything. When a slug is taken,
+/// `on_conflict` skips the file (default), saves it as a new version of the
+/// existing document (`update`), or picks a free slug (`rename`).
+#[post(
+    "/workspaces/<ws_id>/import?<dry_run>&<on_conflict>&<author_name>",
+    data = "<data>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn import_documents(
+    db: &State<Db>,
+    blobs: &State<BlobStore>,
+    ws_id: &str,
+    dry_run: Option<bool>,
+    on_conflict: Option<&str>,
+    author_name: Option<&str>,
+    token: WorkspaceToken,
+    data: Data<'_>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let dry_run = dry_run.unwrap_or(false);
+    let on_conflict = match on_conflict.unwrap_or("skip") {
+        "skip" => OnConflict::Skip,
+        "update" => OnConflict::Update,
+        "rename" => OnConflict::Rename,
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(json!({
+                    "error": "on_conflict must be skip, update or rename",
+                    "code": "VALIDATION_ERROR",
+                })),
+            )
+        }
+    };
+    let max_bytes = crate::import::max_bytes();
+    let bytes = match data
+        .open(ByteUnit::from(max_bytes as u64 + 1))
+        .into_bytes()
+        .await
+    {
+        Ok(bytes) if bytes.is_complete() => bytes.into_inner(),
+        Ok(_) => return import_too_large(max_bytes),
+        Err(e) => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": format!("Failed to read upload: {}", e)})),
+            )
+        }
+    };
+
+    // Unzipping and writing every note is blocking work
+    let (db, blobs, event_bus) = (
+        db.inner().clone(),
+        blobs.inner().clone(),
+        event_bus.inner().clone(),
+    );
+    let ws_id = ws_id.to_string();
+    let author_name = author_name.map(str::to_string);
+    rocket::tokio::task::spawn_blocking(move || {
+        import_archive(
+            State::from(&db),
+            &blobs,
+            State::from(&event_bus),
+            &ws_id,
+            token,
+            &bytes,
+            dry_run,
+            on_conflict,
+            author_name.as_deref(),
+        )
+    })
+    .await
+    .unwrap_or_else(|e| {
+        (
+            Status::InternalServerError,
+            Json(json!({"error": format!("Import failed: {}", e)})),
+        )
+    })
+}
+
+// Helper: 413 for an import over `max_bytes`
+fn import_too_large(max_bytes: usize) -> (Status, Json<Value>) {
+    (
+        Status::PayloadTooLarge,
+        Json(json!({
+            "error": format!("Imports are limited to {} bytes", max_bytes),
+            "code": "IMPORT_TOO_LARGE",
+            "max_bytes": max_bytes,
+        })),
+    )
+}
+
+// Helper: the blocking part of `import_documents`, from the uploaded bytes on
+#[allow(clippy::too_many_arguments)]
+fn import_archive(
+    db: &State<Db>,
+    blobs: &BlobStore,
+    event_bus: &State<EventBus>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    bytes: &[u8],
+    dry_run: bool,
+    on_conflict: OnConflict,
+    author_name: Option<&str>,
+) -> (Status, Json<Value>) {
+    use crate::import::ReadError;
+    let max_bytes = crate::import::max_bytes();
+    let archive = match crate::import::read(bytes, max_bytes) {
+        Ok(archive) if archive.notes.is_empty() && archive.rejected.is_empty() => {
+            return (
+                Status::BadRequest,
+                Json(json!({
+                    "error": "Archive contains no markdown files",
+                    "code": "INVALID_ARCHIVE",
+                })),
+            )
+        }
+        Ok(archive) => archive,
+        Err(ReadError::TooLarge) => return import_too_large(max_bytes),
+        Err(ReadError::Invalid(e)) => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": e, "code": "INVALID_ARCHIVE"})),
+            )
+        }
+    };
+    let workflow = match load_workflow(db, ws_id) {
+        Ok(wf) => wf,
+        Err(err) => return err,
+    };
+    let default_status = workflow
+        .as_ref()
+        .map(|wf| wf.initial_state())
+        .unwrap_or("draft")
+        .to_string();
+
+    // Slugs come from front matter or the filename. A clash within the archive
+    // is prefixed with the folder, then numbered; a clash with an existing
+    // document is resolved by `on_conflict`.
+    let mut claimed = std::collections::HashSet::new();
+    let mut slugs = std::collections::HashMap::new();
+    let mut existing = std::collections::HashMap::new();
+    for note in &archive.notes {
+        let base = [
+            note.document.get("slug").and_then(Value::as_str),
+            Some(note.stem.as_str()),
+            Some(note.title.as_str()),
+        ]
+        .into_iter()
+        .flatten()
+        .map(slugify)
+        .find(|s| !s.is_empty())
+        .unwrap_or_else(|| "untitled".to_string());
+        let base = match &note.folder {
+            Some(folder) if claimed.contains(&base) => slugify(&format!("{} {}", folder, base)),
+            _ => base,
+        };
+        let mut n = 1;
+        let slug = loop {
+            let candidate = match n {
+                1 => base.clone(),
+                _ => format!("{}-{}", base, n),
+            };
+            n += 1;
+            if claimed.contains(&candidate) {
+                continue;
+            }
+            match crate::db::get_document(db, ws_id, &candidate) {
+                Ok(None) => break candidate,
+                Ok(Some(_)) if on_conflict == OnConflict::Rename => continue,
+                Ok(Some(doc)) => {
+                    existing.insert(note.path.clone(), doc);
+                    break candidate;
+                }
+                Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+            }
+        };
+        claimed.insert(slug.clone());
+        slugs.insert(note.path.clone(), slug);
+    }
+
+    // Files are stored as attachments the first time a note refers to them
+    let mut attached: std::collections::HashMap<String, Result<Value, Value>> =
+        std::collections::HashMap::new();
+    let mut attach = |path: &str| -> Option<String> {
+        if !attached.contains_key(path) {
+            let bytes = archive.file(path).unwrap_or_default();
+            let outcome = if bytes.len() > blobs.max_bytes {
+                Err(attachment_too_large(blobs.max_bytes).1 .0)
+            } else if dry_run {
+                Ok(json!({"id": null}))
+            } else {
+                let (status, Json(row)) = store_attachment(
+                    db,
+                    blobs,
+                    event_bus,
+                    ws_id,
+                    Some(path),
+                    None,
+                    bytes,
+                    author_name,
+                );
+                if status.code < 300 {
+                    Ok(row)
+                } else {
+                    Err(row)
+                }
+            };
+            attached.insert(path.to_string(), outcome);
+        }
+        match &attached[path] {
+            Ok(row) => Some(row["id"].as_str().unwrap_or(path).to_string()),
+            Err(_) => None,
+        }
+    };
+
+    let mut files = Vec::new();
+    for note in &archive.notes {
+        let slug = &slugs[&note.path];
+        let mut entry = json!({
+            "path": note.path,
+            "slug": slug,
+            "title": note.title,
+            "folder": note.folder,
+        });
+        let before = existing.get(&note.path);
+        if let (Some(doc), OnConflict::Skip) = (before, on_conflict) {
+            entry["result"] = json!("skipped");
+            entry["id"] = doc["id"].clone();
+            entry["code"] = json!("DUPLICATE_SLUG");
+            files.push(entry);
+            continue;
+        }
+
+        let converted = archive.convert(note, &slugs, &mut attach);
+        entry["links_converted"] = json!(converted.links);
+        entry["attachments"] = json!(converted.attachments);
+        if !converted.unresolved.is_empty() {
+            entry["unresolved_links"] = json!(converted.unresolved);
+        }
+        let content = note.content(&converted.body);
+        let author = note
+            .document
+            .get("author_name")
+            .and_then(Value::as_str)
+            .or(author_name)
+            .unwrap_or("");
+        let body = imported_document(note, slug, &content, author);
+
+        match before {
+            Some(before) => {
+                entry["id"] = before["id"].clone();
+                let mut update = imported_update(before, body);
+                update["change_description"] = json!(format!("Imported from {}", note.path));
+                let changed = |key: &str| update.get(key).is_some();
+                if ![
+                    "title", "content", "summary", "tags", "status", "doc_type", "fields",
+                ]
+                .into_iter()
+                .any(changed)
+                {
+                    entry["result"] = json!("unchanged");
+                    files.push(entry);
+                    continue;
+                }
+                for key in ["status", "doc_type"] {
+                    if changed(key) {
+                        entry[format!("{}_change", key)] =
+                            json!({"from": before[key], "to": update[key]});
+                    }
+                }
+                let outcome = if dry_run {
+                    check_document_update(db, ws_id, before, &update)
+                } else {
+                    let token = WorkspaceToken(token.0.clone());
+                    let doc_id = before["id"].as_str().unwrap_or("");
+                    match update_document(db, ws_id, doc_id, token, Json(update), event_bus) {
+                        (status, _) if status == Status::Ok => Ok(()),
+                        err => Err(err),
+                    }
+                };
+                match outcome {
+                    Ok(()) => entry["result"] = json!("updated"),
+                    Err((_, Json(err))) => import_failure(&mut entry, &err),
+                }
+            }
+            None if dry_run => {
+                match check_imported_document(db, ws_id, workflow.as_ref(), &default_status, &body)
+                {
+                    Ok(()) => entry["result"] = json!("created"),
+                    Err((_, Json(err))) => import_failure(&mut entry, &err),
+                }
+            }
+            None => {
+                let token = WorkspaceToken(token.0.clone());
+                match create_document(db, ws_id, token, Json(body), event_bus) {
+                    (status, Json(created)) if status == Status::Created => {
+                        entry["result"] = json!("created");
+                        entry["id"] = created["id"].clone();
+                    }
+                    (_, Json(err)) => import_failure(&mut entry, &err),
+                }
+            }
+        }
+        files.push(entry);
+    }
+
+    for rejected in &archive.rejected {
+        files.push(json!({
+            "path": rejected.path,
+            "result": "failed",
+            "error": rejected.error,
+            "code": rejected.code,
+        }));
+    }
+    let mut paths: Vec<&String> = archive.file_paths().collect();
+    paths.sort();
+    for path in paths {
+        let entry = match attached.get(path.as_str()) {
+            Some(Ok(row)) => json!({"path": path, "result": "attached", "id": row["id"]}),
+            Some(Err(err)) => {
+                let mut entry = json!({"path": path});
+                import_failure(&mut entry, err);
+                entry
+            }
+            None => json!({"path": path, "result": "ignored"}),
+        };
+        files.push(entry);
+    }
+    for path in &archive.skipped {
+        files.push(json!({"path": path, "result": "ignored"}));
+    }
+
+    let mut summary = serde_json::Map::new();
+    for result in [
+        "created",
+        "updated",
+        "unchanged",
+        "skipped",
+        "failed",
+        "attached",
+        "ignored",
+    ] {
+        let count = files.iter().filter(|f| f["result"] == result).count();
+        summary.insert(result.to_string(), json!(count));
+    }
+    (
+        Status::Ok,
+        Json(json!({
+            "dry_run": dry_run,
+            "summary": summary,
+            "files": files,
+        })),
+    )
+}
+
+// --- Git mirror ---
+
+fn git_mirror_not_enabled() -> (Status, Json<Value>) {
+    (
+        Status::NotFound,
+        Json(json!({"error": "Workspace is not mirrored to git", "code": "NOT_FOUND"})),
+    )
+}
+
+// Helper: a mirror's settings with its repository path and the tip of `main`
+fn git_mirror_json(mirrors: &GitMirrors, ws_id: &str, mut mirror: Value) -> Value {
+    let path = mirrors.path(ws_id);
+    mirror["path"] = json!(std::fs::canonicalize(&path)
+        .unwrap_or(path)
+        .to_string_lossy());
+    mirror["branch"] = json!("main");
+    mirror["head"] = json!(mirrors
+        .open(ws_id)
+        .ok()
+        .and_then(|repo| crate::gitmirror::head(&repo))
+        .map(|id| id.to_string()));
+    mirror
+}
+
+/// Mirror the workspace to a bare git repository, committing every version
+/// so far. Commits pushed before this point are not ingested.
+#[put("/workspaces/<ws_id>/git")]
+pub fn enable_git_mirror(
+    db: &State<Db>,
+    mirrors: &State<GitMirrors>,
+    ws_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let enabled = {
+        let _guard = mirrors.lock();
+        let repo = match mirrors.open(ws_id) {
+            Ok(repo) => repo,
+            Err(e) => {
+                return (
+                    Status::InternalServerError,
+                    Json(json!({"error": format!("Failed to open repository: {}", e)})),
+                )
+            }
+        };
+        crate::db::enable_git_mirror(db, ws_id).and_then(|created| {
+            if created {
+                if let Some(tip) = crate::gitmirror::head(&repo) {
+                    crate::db::set_git_synced_commit(db, ws_id, &tip.to_string())?;
+                }
+            }
+            crate::gitmirror::commit_pending(db, &repo, ws_id)?;
+            Ok(created)
+        })
+    };
+    match (enabled, crate::db::get_git_mirror(db, ws_id)) {
+        (Ok(created), Ok(Some(mirror))) => (
+            if created { Status::Created } else { Status::Ok },
+            Json(git_mirror_json(mirrors, ws_id, mirror)),
+        ),
+        (Err(e), _) | (_, Err(e)) => (Status::InternalServerError, Json(json!({"error": e}))),
+        (_, Ok(None)) => git_mirror_not_enabled(),
+    }
+}
+
+/// The mirror's repository, tip, and what is waiting in either direction:
+/// versions not yet committed and pushed commits not yet synced.
+#[get("/workspaces/<ws_id>/git")]
+pub fn get_git_mirror(
+    db: &State<Db>,
+    mirrors: &State<GitMirrors>,
+    ws_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    // Read under the lock so the counts agree with a worker mid-commit
+    let _guard = mirrors.lock();
+    let mirror = match crate::db::get_git_mirror(db, ws_id) {
+        Ok(Some(mirror)) => mirror,
+        Ok(None) => return git_mirror_not_enabled(),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let pending = {
+        mirrors.open(ws_id).and_then(|repo| {
+            let since = mirror["synced_commit"].as_str();
+            let pushed = crate::gitmirror::pushed_commits(db, &repo, ws_id, since)?;
+            let versions = crate::db::unmirrored_versions(db, ws_id)?;
+            Ok((versions.len(), pushed.len()))
+        })
+    };
+    match pending {
+        Ok((versions, pushed)) => {
+            let mut mirror = git_mirror_json(mirrors, ws_id, mirror);
+            mirror["pending_versions"] = json!(versions);
+            mirror["pushed_commits"] = json!(pushed);
+            (Status::Ok, Json(mirror))
+        }
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Stop mirroring. The repository stays on disk.
+#[delete("/workspaces/<ws_id>/git")]
+pub fn disable_git_mirror(
+    db: &State<Db>,
+    mirrors: &State<GitMirrors>,
+    ws_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let _guard = mirrors.lock();
+    match crate::db::disable_git_mirror(db, ws_id) {
+        Ok(true) => (Status::Ok, Json(json!({"status": "disabled"}))),
+        Ok(false) => git_mirror_not_enabled(),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Ingest commits pushed to the mirror as new versions, then commit whatever
+/// the mirror is missing. A pushed change whose parent does not hold the
+/// document's current content is a conflict and is not applied: the document
+/// changed through the API since that commit's base.
+#[post("/workspaces/<ws_id>/git/sync?<dry_run>")]
+pub fn sync_git_mirror(
+    db: &State<Db>,
+    mirrors: &State<GitMirrors>,
+    ws_id: &str,
+    dry_run: Option<bool>,
+    token: WorkspaceToken,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let dry_run = dry_run.unwrap_or(false);
+    let mirror = match crate::db::get_git_mirror(db, ws_id) {
+        Ok(Some(mirror)) => mirror,
+        Ok(None) => return git_mirror_not_enabled(),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let _guard = mirrors.lock();
+    let repo = match mirrors.open(ws_id) {
+        Ok(repo) => repo,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let tip = crate::gitmirror::head(&repo);
+    let since = mirror["synced_commit"].as_str();
+    let (pushed, files) = match crate::gitmirror::pushed_commits(db, &repo, ws_id, since)
+        .and_then(|pushed| Ok((pushed, crate::db::git_files(db, ws_id)?)))
+    {
+        Ok(found) => found,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    let documents: std::collections::HashMap<String, String> = files
+        .into_iter()
+        .filter(|(_, _, slug)| slug.is_some())
+        .map(|(doc_id, path, _)| (path, doc_id))
+        .collect();
+
+    // What each document holds as the commits are applied in order; a dry
+    // run never saves, so it tracks contents here
+    let mut contents: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut results = Vec::new();
+    for commit in &pushed {
+        for file in &commit.files {
+            let mut entry = json!({"commit": commit.id, "path": file.path});
+            let before = documents
+                .get(&file.path)
+                .and_then(|id| crate::db::get_document_by_id(db, id).ok().flatten());
+            let (Some(before), Some(content)) = (before, &file.content) else {
+                let (error, code) = match file.content {
+                    Some(_) => (
+                        "Only files of existing documents are synced",
+                        "UNKNOWN_DOCUMENT",
+                    ),
+                    None => ("Delete documents through the API", "DELETE_NOT_SUPPORTED"),
+                };
+                entry["result"] = json!("skipped");
+                entry["error"] = json!(error);
+                entry["code"] = json!(code);
+                results.push(entry);
+                continue;
+            };
+            let doc_id = before["id"].as_str().unwrap_or("").to_string();
+            entry["document_id"] = json!(doc_id);
+            entry["slug"] = before["slug"].clone();
+            let current = contents
+                .get(&doc_id)
+                .map(String::as_str)
+                .or(before["content"].as_str())
+                .unwrap_or("");
+            if current == content {
+                entry["result"] = json!("unchanged");
+                results.push(entry);
+                continue;
+            }
+            if file.base.as_deref() != Some(current) {
+                entry["result"] = json!("conflict");
+                entry["error"] =
+                    json!("The document changed through the API since this commit's parent");
+                entry["code"] = json!("GIT_CONFLICT");
+                results.push(entry);
+                continue;
+            }
+            let description = match commit.message.as_str() {
+                "" => format!("Synced from git {}", &commit.id[..7]),
+                message => message.to_string(),
+            };
+            // A dry run only checks what saving would refuse
+            let saved = parse_front_matter(content).and_then(|(_, markdown)| {
+                if dry_run {
+                    return check_content_type(db, ws_id, &before, markdown).map(|_| None);
+                }
+                let event = save_document_content(
+                    db,
+                    event_bus,
+                    ws_id,
+                    &before,
+                    content,
+                    Some(&commit.author),
+                    &description,
+                    None,
+                )?;
+                let version = event["version_number"].as_i64().unwrap_or(0) as i32;
+                Ok(Some(version))
+            });
+            match saved {
+                Ok(version) => {
+                    entry["result"] = json!("ingested");
+                    contents.insert(doc_id.clone(), content.clone());
+                    if let Some(version) = version {
+                        entry["version_number"] = json!(version);
+                        if let Err(e) = crate::db::record_git_commit(
+                            db,
+                            ws_id,
+                            &commit.id,
+                            &doc_id,
+                            Some(version),
+                        ) {
+                            entry["result"] = json!("failed");
+                            entry["error"] = json!(e);
+                        }
+                    }
+                }
+                Err((_, Json(err))) => {
+                    entry["result"] = json!("failed");
+                    entry["error"] = err["error"].clone();
+                    entry["code"] = err["code"].clone();
+                }
+            }
+            results.push(entry);
+        }
+    }
+
+    let mut committed = 0;
+    if !dry_run {
+        let written = match tip {
+            Some(tip) => crate::db::set_git_synced_commit(db, ws_id, &tip.to_string()),
+            None => Ok(()),
+        }
+        .and_then(|_| crate::gitmirror::commit_pending(db, &repo, ws_id));
+        match written {
+            Ok(n) => committed = n,
+            Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+        }
+    }
+    let mut summary = serde_json::Map::new();
+    for result in ["ingested", "unchanged", "conflict", "skipped", "failed"] {
+        let count = results.iter().filter(|r| r["result"] == result).count();
+        summary.insert(result.to_string(), json!(count));
+    }
+    summary.insert("committed".to_string(), json!(committed));
+    (
+        Status::Ok,
+        Json(json!({
+            "dry_run": dry_run,
+            "commits": pushed.len(),
+            "head": crate::gitmirror::head(&repo).map(|id| id.to_string()),
+            "summary": summary,
+            "files": results,
+        })),
+    )
+}
+
+// --- Search ---
+
+#[get("/workspaces/<ws_id>/search?<q>&<limit>&<offset>")]
+pub fn search_documents(
+    db: &State<Db>,
+    ws_id: &str,
+    q: &str,
+    limit: Option<i32>,
+    offset: Option<i32>,
+    meta: MetaFilters,
+) -> (Status, Json<Value>) {
+    let limit = limit.unwrap_or(20).min(100);
+    let offset = offset.unwrap_or(0);
+    let meta = match metadata_filters(meta) {
+        Ok(m) => m,
+        Err(err) => return err,
+    };
+
+    match crate::db::search_documents(db, ws_id, q, limit, offset, &meta.0) {
+        Ok(docs) => (
+            Status::Ok,
+            Json(json!({ "query": q, "results": docs, "count": docs.len() })),
         ),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
-#[delete("/workspaces/<ws_id>/docs/<doc_id>/lock")]
-pub fn release_lock(
+// --- Restore version ---
+
+#[post("/workspaces/<ws_id>/docs/<doc_id>/versions/<version_num>/restore")]
+pub fn restore_version(
     db: &State<Db>,
     ws_id: &str,
     doc_id: &str,
+    version_num: i32,
     token: WorkspaceToken,
     event_bus: &State<EventBus>,
 ) -> (Status, Json<Value>) {
@@ -648,27 +5220,190 @@ pub fn release_lock(
         return (status, Json(err));
     }
 
-    match crate::db::release_lock(db, doc_id) {
-        Ok(true) => {
-            event_bus.emit(ws_id, "lock.released", json!({"document_id": doc_id}));
-            (Status::Ok, Json(json!({"status": "unlocked"})))
+    let before = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = before["id"].as_str().unwrap_or("");
+
+    // Get the version to restore
+    let version = match crate::db::get_version(db, doc_id, version_num) {
+        Ok(Some(v)) => v,
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": format!("Version {} not found", version_num)})),
+            )
         }
-        Ok(false) => (
-            Status::NotFound,
-            Json(json!({"error": "Document not found"})),
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+
+    let content = version["content"].as_str().unwrap_or("");
+    let change_desc = format!("Restored from version {}", version_num);
+
+    match save_document_content(
+        db,
+        event_bus,
+        ws_id,
+        &before,
+        content,
+        None,
+        &change_desc,
+        Some(version_num),
+    ) {
+        Ok(data) => (
+            Status::Ok,
+            Json(json!({
+                "status": "restored",
+                "from_version": version_num,
+                "version_number": data["version_number"],
+                "word_count": word_count(content),
+            })),
+        ),
+        Err(err) => err,
+    }
+}
+
+// --- Workflow routes ---
+
+#[get("/workspaces/<ws_id>/workflow")]
+pub fn get_workflow(db: &State<Db>, ws_id: &str) -> (Status, Json<Value>) {
+    match crate::db::get_workspace(db, ws_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    match load_workflow(db, ws_id) {
+        Ok(wf) => (
+            Status::Ok,
+            Json(json!({"workflow": wf, "preset": Workflow::preset()})),
         ),
+        Err(err) => err,
+    }
+}
+
+/// Configure the workspace workflow. The body is a workflow object,
+/// `{"preset": "default"}`, or `{"workflow": null}` to go back to free-form statuses.
+#[put("/workspaces/<ws_id>/workflow", format = "json", data = "<body>")]
+pub fn set_workflow(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+    event_bus: &State<EventBus>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+
+    let workflow = if body.get("preset").is_some() {
+        match body["preset"].as_str() {
+            Some("default") => Some(Workflow::preset()),
+            _ => {
+                return (
+                    Status::BadRequest,
+                    Json(
+                        json!({"error": "Unknown preset (available: default)", "code": "VALIDATION_ERROR"}),
+                    ),
+                )
+            }
+        }
+    } else if body.get("workflow").is_some_and(|w| w.is_null()) {
+        None
+    } else {
+        let raw = body.get("workflow").unwrap_or(&body).clone();
+        match serde_json::from_value::<Workflow>(raw) {
+            Ok(wf) => Some(wf),
+            Err(e) => {
+                return (
+                    Status::BadRequest,
+                    Json(
+                        json!({"error": format!("Invalid workflow: {}", e), "code": "VALIDATION_ERROR"}),
+                    ),
+                )
+            }
+        }
+    };
+    if let Some(Err(e)) = workflow.as_ref().map(|wf| wf.validate()) {
+        return (
+            Status::BadRequest,
+            Json(json!({"error": format!("Invalid workflow: {}", e), "code": "VALIDATION_ERROR"})),
+        );
+    }
+
+    let stored = workflow.as_ref().map(|wf| json!(wf).to_string());
+    match crate::db::set_workspace_workflow(db, ws_id, stored.as_deref()) {
+        Ok(_) => {
+            if let Ok(Some(ws)) = crate::db::get_workspace(db, ws_id) {
+                event_bus.emit(
+                    ws_id,
+                    "workspace.updated",
+                    json!({
+                        "id": ws_id,
+                        "name": ws["name"],
+                        "description": ws["description"],
+                        "is_public": ws["is_public"],
+                        "changed": ["workflow"],
+                    }),
+                );
+            }
+            (Status::Ok, Json(json!({"workflow": workflow})))
+        }
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
-// --- Lock renew ---
+// Helper: approval state of a document's current version
+fn approval_summary(db: &Db, wf: Option<&Workflow>, doc: &Value) -> Result<Value, String> {
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let version = crate::db::latest_version_number(db, doc_id)?;
+    let approved_by = crate::db::approved_reviewers(db, doc_id, version)?;
+    let required = wf.map(|w| w.required_approvals()).unwrap_or(0);
+    Ok(json!({
+        "document_id": doc_id,
+        "status": doc["status"],
+        "version_number": version,
+        "required": required,
+        "approvals": approved_by.len(),
+        "approved_by": approved_by,
+        "satisfied": approved_by.len() >= required,
+    }))
+}
 
+#[get("/workspaces/<ws_id>/docs/<doc_id>/approvals")]
+pub fn list_approvals(db: &State<Db>, ws_id: &str, doc_id: &str) -> (Status, Json<Value>) {
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = doc["id"].as_str().unwrap_or("");
+    let workflow = match load_workflow(db, ws_id) {
+        Ok(wf) => wf,
+        Err(err) => return err,
+    };
+    let summary = approval_summary(db, workflow.as_ref(), &doc);
+    match (summary, crate::db::list_approvals(db, doc_id)) {
+        (Ok(mut summary), Ok(history)) => {
+            summary["history"] = json!(history);
+            (Status::Ok, Json(summary))
+        }
+        (Err(e), _) | (_, Err(e)) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Record a reviewer's decision on the current version. A rejection sends
+/// the document back to the workflow's `reject_to` state.
 #[post(
-    "/workspaces/<ws_id>/docs/<doc_id>/lock/renew",
+    "/workspaces/<ws_id>/docs/<doc_id>/approvals",
     format = "json",
     data = "<body>"
 )]
-pub fn renew_lock(
+pub fn review_document(
     db: &State<Db>,
     ws_id: &str,
     doc_id: &str,
@@ -679,182 +5414,573 @@ pub fn renew_lock(
     if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
         return (status, Json(err));
     }
+    let doc = match document_in_workspace(db, ws_id, doc_id) {
+        Ok(doc) => doc,
+        Err(err) => return err,
+    };
+    let doc_id = &doc["id"].as_str().unwrap_or("").to_string();
+    let workflow = match load_workflow(db, ws_id) {
+        Ok(Some(wf)) => wf,
+        Ok(None) => {
+            return (
+                Status::Conflict,
+                Json(
+                    json!({"error": "This workspace has no workflow configured", "code": "NO_WORKFLOW"}),
+                ),
+            )
+        }
+        Err(err) => return err,
+    };
 
-    let editor = body
-        .get("editor")
+    let reviewer = match body.get("reviewer").and_then(|v| v.as_str()) {
+        Some(r) if !r.trim().is_empty() => r.trim().to_string(),
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": "reviewer is required", "code": "VALIDATION_ERROR"})),
+            )
+        }
+    };
+    if !workflow.is_reviewer(&reviewer) {
+        return (
+            Status::Forbidden,
+            Json(
+                json!({"error": format!("'{}' is not a reviewer in this workspace", reviewer), "code": "NOT_A_REVIEWER"}),
+            ),
+        );
+    }
+    let decision = match body
+        .get("decision")
         .and_then(|v| v.as_str())
-        .unwrap_or("anonymous");
-    let ttl = body
-        .get("ttl_seconds")
-        .and_then(|v| v.as_i64())
-        .unwrap_or(60) as i32;
+        .unwrap_or("approve")
+    {
+        "approve" | "approved" => "approved",
+        "reject" | "rejected" => "rejected",
+        other => {
+            return (
+                Status::BadRequest,
+                Json(
+                    json!({"error": format!("Unknown decision '{}' (approve or reject)", other), "code": "VALIDATION_ERROR"}),
+                ),
+            )
+        }
+    };
+    let comment = body
+        .get("comment")
+        .and_then(|v| v.as_str())
+        .unwrap_or("")
+        .trim()
+        .to_string();
+    if decision == "rejected" && comment.is_empty() {
+        return (
+            Status::BadRequest,
+            Json(
+                json!({"error": "comment is required when rejecting", "code": "VALIDATION_ERROR"}),
+            ),
+        );
+    }
 
-    match crate::db::renew_lock(db, doc_id, editor, ttl) {
-        Ok(true) => {
-            event_bus.emit(
-                ws_id,
-                "lock.renewed",
-                json!({"document_id": doc_id, "locked_by": editor, "ttl_seconds": ttl}),
-            );
-            (
-                Status::Ok,
-                Json(json!({"status": "renewed", "locked_by": editor, "ttl_seconds": ttl})),
+    let version = crate::db::latest_version_number(db, doc_id).unwrap_or(0);
+    let id = uuid::Uuid::new_v4().to_string();
+    if let Err(e) =
+        crate::db::create_approval(db, &id, doc_id, version, &reviewer, decision, &comment)
+    {
+        return (Status::InternalServerError, Json(json!({"error": e})));
+    }
+
+    let from = doc["status"].as_str().unwrap_or("").to_string();
+    let reject_to = workflow.reject_state().to_string();
+    if decision == "rejected" && from != reject_to {
+        if let Err(e) = crate::db::set_document_status(db, doc_id, &reject_to) {
+            return (Status::InternalServerError, Json(json!({"error": e})));
+        }
+        event_bus.emit(
+            ws_id,
+            "document.status_changed",
+            json!({
+                "id": doc_id,
+                "from": from,
+                "to": reject_to,
+                "author_name": reviewer,
+                "version_number": version,
+                "reason": comment,
+            }),
+        );
+    }
+
+    let doc = crate::db::get_document_by_id(db, doc_id)
+        .ok()
+        .flatten()
+        .unwrap_or(doc);
+    let summary = match approval_summary(db, Some(&workflow), &doc) {
+        Ok(s) => s,
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    };
+    event_bus.emit(
+        ws_id,
+        &format!("approval.{}", decision),
+        json!({
+            "id": id,
+            "document_id": doc_id,
+            "reviewer": reviewer,
+            "comment": comment,
+            "version_number": version,
+            "approvals": summary["approvals"],
+            "required": summary["required"],
+        }),
+    );
+    (
+        Status::Created,
+        Json(json!({"id": id, "decision": decision, "summary": summary})),
+    )
+}
+
+// --- Inbox routes ---
+
+/// A name's mention notifications. Mentions in unpublished documents need the
+/// manage key.
+#[get("/workspaces/<ws_id>/inbox?<for>&<key>&<unread>&<limit>&<offset>")]
+pub fn get_inbox(
+    db: &State<Db>,
+    ws_id: &str,
+    r#for: Option<&str>,
+    key: Option<&str>,
+    unread: Option<bool>,
+    limit: Option<i64>,
+    offset: Option<i64>,
+) -> (Status, Json<Value>) {
+    let recipient = match r#for.map(str::trim) {
+        Some(r) if !r.is_empty() => r.trim_start_matches('@'),
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(
+                    json!({"error": "for is required (the name to read the inbox of)", "code": "VALIDATION_ERROR"}),
+                ),
+            )
+        }
+    };
+    match crate::db::get_workspace(db, ws_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+
+    let limit = limit.unwrap_or(50).clamp(1, 200);
+    let offset = offset.unwrap_or(0).max(0);
+    match crate::db::list_notifications(
+        db,
+        ws_id,
+        recipient,
+        unread.unwrap_or(false),
+        key_grants_drafts(db, ws_id, key),
+        limit,
+        offset,
+    ) {
+        Ok((notifications, unread_count)) => (
+            Status::Ok,
+            Json(json!({
+                "for": recipient,
+                "unread_count": unread_count,
+                "notifications": notifications,
+                "limit": limit,
+                "offset": offset,
+            })),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+/// Mark notifications read: `{"for", "ids"?, "read"?}`. Without `ids` the
+/// whole inbox is marked; `"read": false` marks them unread again.
+#[post("/workspaces/<ws_id>/inbox/read", format = "json", data = "<body>")]
+pub fn mark_inbox_read(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    let recipient = match body.get("for").and_then(|v| v.as_str()).map(str::trim) {
+        Some(r) if !r.is_empty() => r.trim_start_matches('@').to_string(),
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": "for is required", "code": "VALIDATION_ERROR"})),
+            )
+        }
+    };
+    let ids: Option<Vec<String>> = body.get("ids").and_then(|v| v.as_array()).map(|a| {
+        a.iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect()
+    });
+    let read = body.get("read").and_then(|v| v.as_bool()).unwrap_or(true);
+
+    match crate::db::mark_notifications(db, ws_id, &recipient, ids.as_deref(), read) {
+        Ok(updated) => (Status::Ok, Json(json!({"updated": updated, "read": read}))),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+// --- Activity feed ---
+
+/// Workspace timeline from the persisted activity log. Accepts the same
+/// `types`/`docs`/`tags` filters as the event stream, plus `actor`.
+/// Newest first, paged with `before=<seq>`; `order=asc&after=<seq>` polls
+/// for anything newer than the last seen entry. Events about unpublished
+/// documents need the manage key.
+#[get(
+    "/workspaces/<ws_id>/activity?<key>&<types>&<docs>&<tags>&<actor>&<before>&<after>&<order>&<limit>"
+)]
+#[allow(clippy::too_many_arguments)]
+pub fn get_activity(
+    db: &State<Db>,
+    ws_id: &str,
+    key: Option<&str>,
+    types: Option<&str>,
+    docs: Option<&str>,
+    tags: Option<&str>,
+    actor: Option<&str>,
+    before: Option<i64>,
+    after: Option<i64>,
+    order: Option<&str>,
+    limit: Option<usize>,
+) -> (Status, Json<Value>) {
+    match crate::db::get_workspace(db, ws_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+    let ascending = match order.unwrap_or("desc") {
+        "desc" => false,
+        "asc" => true,
+        other => {
+            return (
+                Status::BadRequest,
+                Json(
+                    json!({"error": format!("Unknown order '{}' (asc or desc)", other), "code": "VALIDATION_ERROR"}),
+                ),
+            )
+        }
+    };
+    let limit = limit.unwrap_or(50).clamp(1, 200);
+    let filter = EventFilter::new(types, docs, tags);
+    let include_drafts = key_grants_drafts(db, ws_id, key);
+    let mut published: std::collections::HashMap<String, bool> = std::collections::HashMap::new();
+
+    // Filters other than actor run in Rust, so scan the log in batches, up to
+    // MAX_SCAN rows per request; a sparse match returns a cursor to resume from
+    const BATCH: i64 = 500;
+    const MAX_SCAN: usize = 5000;
+    let (mut after, mut before) = (after, before);
+    let mut items = Vec::new();
+    let mut scanned = 0;
+    let mut more = false;
+    while items.len() < limit {
+        if scanned >= MAX_SCAN {
+            more = true;
+            break;
+        }
+        let batch =
+            match crate::db::list_activity(db, ws_id, after, before, actor, ascending, BATCH) {
+                Ok(b) => b,
+                Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+            };
+        let fetched = batch.len();
+        scanned += fetched;
+        for (seq, created_at, evt) in batch {
+            if ascending {
+                after = Some(seq);
+            } else {
+                before = Some(seq);
+            }
+            if !include_drafts {
+                if let Some(doc_id) = evt.document_id() {
+                    let visible = *published.entry(doc_id.to_string()).or_insert_with(|| {
+                        matches!(
+                            crate::db::get_document_by_id(db, doc_id),
+                            Ok(Some(doc)) if doc["status"] == "published"
+                        )
+                    });
+                    if !visible {
+                        continue;
+                    }
+                }
+            }
+            let lookup = |id: &str| crate::db::get_document_tags(db, id).unwrap_or_default();
+            if !filter.matches(&evt, lookup) {
+                continue;
+            }
+            items.push(json!({
+                "seq": seq,
+                "event": evt.event_type,
+                "document_id": evt.document_id(),
+                "actor": evt.actor(),
+                "data": evt.data,
+                "created_at": created_at,
+            }));
+            if items.len() == limit {
+                more = true;
+                break;
+            }
+        }
+        if fetched < BATCH as usize {
+            break;
+        }
+    }
+
+    // A full page or a capped scan may have more behind it; the cursor
+    // continues after the last row scanned
+    let next = match (ascending, after, before) {
+        (true, Some(seq), _) | (false, _, Some(seq)) if more => {
+            json!({ if ascending { "after" } else { "before" }: seq })
+        }
+        _ => Value::Null,
+    };
+    (Status::Ok, Json(json!({"items": items, "next": next})))
+}
+
+// --- Webhooks ---
+
+// Helper: parse an optional `events` array of event type patterns
+fn parse_event_patterns(body: &Value) -> Result<Option<String>, (Status, Json<Value>)> {
+    match body.get("events") {
+        None | Some(Value::Null) => Ok(None),
+        Some(Value::Array(items)) if items.iter().all(|v| v.is_string()) => {
+            Ok(Some(Value::Array(items.clone()).to_string()))
+        }
+        Some(_) => Err((
+            Status::BadRequest,
+            Json(
+                json!({"error": "events must be an array of event type patterns", "code": "VALIDATION_ERROR"}),
+            ),
+        )),
+    }
+}
+
+// Helper: validate a webhook target URL
+fn validate_webhook_url(url: &str) -> Result<(), (Status, Json<Value>)> {
+    if url.starts_with("http://") || url.starts_with("https://") {
+        Ok(())
+    } else {
+        Err((
+            Status::BadRequest,
+            Json(json!({"error": "url must be an http(s) URL", "code": "VALIDATION_ERROR"})),
+        ))
+    }
+}
+
+#[post("/workspaces/<ws_id>/webhooks", format = "json", data = "<body>")]
+pub fn create_webhook(
+    db: &State<Db>,
+    ws_id: &str,
+    token: WorkspaceToken,
+    body: Json<Value>,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+
+    let url = match body.get("url").and_then(|v| v.as_str()) {
+        Some(u) if !u.trim().is_empty() => u.trim().to_string(),
+        _ => {
+            return (
+                Status::BadRequest,
+                Json(json!({"error": "url is required", "code": "VALIDATION_ERROR"})),
             )
         }
-        Ok(false) => (
-            Status::Conflict,
-            Json(json!({"error": "Lock not held by this editor or expired", "code": "LOCK_CONFLICT"})),
-        ),
+    };
+    if let Err(e) = validate_webhook_url(&url) {
+        return e;
+    }
+    let events = match parse_event_patterns(&body) {
+        Ok(e) => e.unwrap_or("[]".to_string()),
+        Err(e) => return e,
+    };
+    let secret = body
+        .get("secret")
+        .and_then(|v| v.as_str())
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+        .unwrap_or_else(crate::webhooks::generate_secret);
+
+    let id = uuid::Uuid::new_v4().to_string();
+    match crate::db::create_webhook(db, &id, ws_id, &url, &events, &secret) {
+        Ok(()) => {
+            let mut hook = crate::db::get_webhook(db, ws_id, &id)
+                .ok()
+                .flatten()
+                .unwrap_or(json!({"id": id, "url": url}));
+            // Secret is only returned at creation time
+            hook["secret"] = json!(secret);
+            (Status::Created, Json(hook))
+        }
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
-// --- Comment moderation ---
-
-#[delete("/workspaces/<ws_id>/docs/<_doc_id>/comments/<comment_id>")]
-pub fn delete_comment(
-    db: &State<Db>,
-    ws_id: &str,
-    _doc_id: &str,
-    comment_id: &str,
-    token: WorkspaceToken,
-    event_bus: &State<EventBus>,
-) -> (Status, Json<Value>) {
+#[get("/workspaces/<ws_id>/webhooks")]
+pub fn list_webhooks(db: &State<Db>, ws_id: &str, token: WorkspaceToken) -> (Status, Json<Value>) {
     if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
         return (status, Json(err));
     }
 
-    match crate::db::delete_comment(db, comment_id) {
-        Ok(true) => {
-            event_bus.emit(ws_id, "comment.deleted", json!({"comment_id": comment_id}));
-            (Status::Ok, Json(json!({"status": "deleted"})))
-        }
-        Ok(false) => (
-            Status::NotFound,
-            Json(json!({"error": "Comment not found"})),
-        ),
+    match crate::db::list_webhooks(db, ws_id) {
+        Ok(hooks) => (Status::Ok, Json(json!(hooks))),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
 #[patch(
-    "/workspaces/<ws_id>/docs/<_doc_id>/comments/<comment_id>",
+    "/workspaces/<ws_id>/webhooks/<webhook_id>",
     format = "json",
     data = "<body>"
 )]
-pub fn update_comment(
+pub fn update_webhook(
     db: &State<Db>,
     ws_id: &str,
-    _doc_id: &str,
-    comment_id: &str,
+    webhook_id: &str,
     token: WorkspaceToken,
     body: Json<Value>,
-    event_bus: &State<EventBus>,
 ) -> (Status, Json<Value>) {
     if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
         return (status, Json(err));
     }
 
-    let content = body.get("content").and_then(|v| v.as_str());
-    let resolved = body.get("resolved").and_then(|v| v.as_bool());
+    let url = body.get("url").and_then(|v| v.as_str());
+    if let Some(u) = url {
+        if let Err(e) = validate_webhook_url(u) {
+            return e;
+        }
+    }
+    let events = match parse_event_patterns(&body) {
+        Ok(e) => e,
+        Err(e) => return e,
+    };
+    let active = body.get("active").and_then(|v| v.as_bool());
 
-    if content.is_none() && resolved.is_none() {
+    if url.is_none() && events.is_none() && active.is_none() {
         return (
-            Status::UnprocessableEntity,
-            Json(json!({"error": "Provide content and/or resolved", "code": "MISSING_FIELDS"})),
+            Status::BadRequest,
+            Json(json!({"error": "No fields to update"})),
         );
     }
 
-    match crate::db::update_comment(db, comment_id, content, resolved) {
-        Ok(true) => {
-            let mut data = json!({"comment_id": comment_id});
-            if let Some(r) = resolved {
-                data["resolved"] = json!(r);
-            }
-            event_bus.emit(ws_id, "comment.updated", data);
-            (Status::Ok, Json(json!({"status": "updated"})))
-        }
+    match crate::db::update_webhook(db, ws_id, webhook_id, url, events.as_deref(), active) {
+        Ok(true) => (Status::Ok, Json(json!({"status": "updated"}))),
         Ok(false) => (
             Status::NotFound,
-            Json(json!({"error": "Comment not found"})),
+            Json(json!({"error": "Webhook not found", "code": "NOT_FOUND"})),
         ),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
-// --- Search ---
+#[delete("/workspaces/<ws_id>/webhooks/<webhook_id>")]
+pub fn delete_webhook(
+    db: &State<Db>,
+    ws_id: &str,
+    webhook_id: &str,
+    token: WorkspaceToken,
+) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
 
-#[get("/workspaces/<ws_id>/search?<q>&<limit>&<offset>")]
-pub fn search_documents(
+    match crate::db::delete_webhook(db, ws_id, webhook_id) {
+        Ok(true) => (Status::Ok, Json(json!({"status": "deleted"}))),
+        Ok(false) => (
+            Status::NotFound,
+            Json(json!({"error": "Webhook not found", "code": "NOT_FOUND"})),
+        ),
+        Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+}
+
+#[get("/workspaces/<ws_id>/webhooks/<webhook_id>/deliveries?<limit>&<offset>")]
+pub fn list_webhook_deliveries(
     db: &State<Db>,
     ws_id: &str,
-    q: &str,
+    webhook_id: &str,
     limit: Option<i32>,
     offset: Option<i32>,
+    token: WorkspaceToken,
 ) -> (Status, Json<Value>) {
+    if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
+        return (status, Json(err));
+    }
+    match crate::db::get_webhook(db, ws_id, webhook_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Webhook not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
+
     let limit = limit.unwrap_or(20).min(100);
     let offset = offset.unwrap_or(0);
 
-    match crate::db::search_documents(db, ws_id, q, limit, offset) {
-        Ok(docs) => (
-            Status::Ok,
-            Json(json!({ "query": q, "results": docs, "count": docs.len() })),
-        ),
+    match crate::db::list_deliveries(db, webhook_id, limit, offset) {
+        Ok(deliveries) => (Status::Ok, Json(json!(deliveries))),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
 }
 
-// --- Restore version ---
-
-#[post("/workspaces/<ws_id>/docs/<doc_id>/versions/<version_num>/restore")]
-pub fn restore_version(
+/// Queue a fresh delivery of a past payload. The original delivery record is kept.
+#[post("/workspaces/<ws_id>/webhooks/<webhook_id>/deliveries/<delivery_id>/redeliver")]
+pub fn redeliver_webhook(
     db: &State<Db>,
     ws_id: &str,
-    doc_id: &str,
-    version_num: i32,
+    webhook_id: &str,
+    delivery_id: &str,
     token: WorkspaceToken,
 ) -> (Status, Json<Value>) {
     if let Err((status, err)) = verify_workspace_auth(db, ws_id, &token) {
         return (status, Json(err));
     }
+    match crate::db::get_webhook(db, ws_id, webhook_id) {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                Status::NotFound,
+                Json(json!({"error": "Webhook not found", "code": "NOT_FOUND"})),
+            )
+        }
+        Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
+    }
 
-    // Get the version to restore
-    let version = match crate::db::get_version(db, doc_id, version_num) {
-        Ok(Some(v)) => v,
+    let (event_type, payload) = match crate::db::get_delivery_payload(db, webhook_id, delivery_id) {
+        Ok(Some(p)) => p,
         Ok(None) => {
             return (
                 Status::NotFound,
-                Json(json!({"error": format!("Version {} not found", version_num)})),
+                Json(json!({"error": "Delivery not found", "code": "NOT_FOUND"})),
             )
         }
         Err(e) => return (Status::InternalServerError, Json(json!({"error": e}))),
     };
 
-    let content = version["content"].as_str().unwrap_or("");
-    let content_html = render_markdown(content);
-    let wc = word_count(content);
-    let change_desc = format!("Restored from version {}", version_num);
-
-    match crate::db::update_document(
-        db,
-        doc_id,
-        None,
-        Some(content),
-        Some(&content_html),
-        None,
-        None,
-        None,
-        None,
-        Some(wc),
-        Some(&change_desc),
-    ) {
-        Ok(_) => (
-            Status::Ok,
-            Json(json!({
-                "status": "restored",
-                "from_version": version_num,
-                "word_count": wc,
-            })),
+    let id = uuid::Uuid::new_v4().to_string();
+    match crate::db::create_delivery(db, &id, webhook_id, &event_type, &payload) {
+        Ok(()) => (
+            Status::Accepted,
+            Json(json!({"status": "queued", "delivery_id": id, "redelivery_of": delivery_id})),
         ),
         Err(e) => (Status::InternalServerError, Json(json!({"error": e}))),
     }
@@ -895,137 +6021,502 @@ pub fn openapi_spec() -> (Status, (rocket::http::ContentType, String)) {
         "paths": {
             "/workspaces": {
                 "post": {
-                    "summary": "Create workspace",
-                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateWorkspace" } } } },
-                    "responses": { "201": { "description": "Workspace created (includes manage_key)" } }
+                    "summary": "Create workspace",
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateWorkspace" } } } },
+                    "responses": { "201": { "description": "Workspace created (includes manage_key)" } }
+                },
+                "get": {
+                    "summary": "List public workspaces",
+                    "responses": { "200": { "description": "Array of public workspaces" } }
+                }
+            },
+            "/workspaces/{workspace_id}": {
+                "get": {
+                    "summary": "Get workspace",
+                    "parameters": [{ "name": "workspace_id", "in": "path", "required": true, "schema": { "type": "string" } }],
+                    "responses": { "200": { "description": "Workspace details" } }
+                },
+                "patch": {
+                    "summary": "Update workspace (name, description, is_public, allow_anonymous_comments)",
+                    "security": [{ "ManageKey": [] }],
+                    "parameters": [{ "name": "workspace_id", "in": "path", "required": true, "schema": { "type": "string" } }],
+                    "responses": { "200": { "description": "Updated" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs": {
+                "post": {
+                    "summary": "Create document (from content, or from_template with variables)",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateDocument" } } } },
+                    "responses": {
+                        "201": { "description": "Document created" },
+                        "422": { "description": "Unknown template or document type, missing variables, invalid front matter, DOCUMENT_INVALID, or a status the workflow refuses" }
+                    }
+                },
+                "get": {
+                    "summary": "List documents (published only; all with key)",
+                    "parameters": [
+                        { "name": "workspace_id", "in": "path", "required": true, "schema": { "type": "string" } },
+                        { "name": "key", "in": "query", "schema": { "type": "string" }, "description": "Manage key to include drafts" },
+                        { "name": "meta.{key}", "in": "query", "schema": { "type": "string" }, "description": "Front matter filter, e.g. meta.priority=high; dotted keys reach nested fields, arrays match any element" }
+                    ],
+                    "responses": { "200": { "description": "Array of documents" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/backlinks": {
+                "get": {
+                    "summary": "Documents whose [[wiki-links]] point here (published only; all with key)",
+                    "parameters": [{ "name": "key", "in": "query", "schema": { "type": "string" } }],
+                    "responses": { "200": { "description": "{slug, count, backlinks: [{id, title, slug, status, heading}]}" } }
+                }
+            },
+            "/workspaces/{workspace_id}/links/broken": {
+                "get": {
+                    "summary": "Wiki-links whose target document or heading does not exist",
+                    "parameters": [{ "name": "key", "in": "query", "schema": { "type": "string" } }],
+                    "responses": { "200": { "description": "{count, links: [{source_id, source_slug, source_title, target, heading, reason}]}" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}": {
+                "get": {
+                    "summary": "Get document by id or slug (old slugs of renamed documents resolve with redirected_from set); every /docs/{doc_id} route resolves the same way and 404s for other workspaces' documents",
+                    "parameters": [
+                        { "name": "format", "in": "query", "description": "Overrides Accept", "schema": { "type": "string", "enum": ["json", "markdown", "md", "html", "text", "txt"] } },
+                        { "name": "fields", "in": "query", "description": "JSON only: comma-separated top-level keys to return", "schema": { "type": "string" } }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "Document with rendered HTML and front matter metadata, or the negotiated representation",
+                            "content": {
+                                "application/json": { "schema": { "type": "object" } },
+                                "text/markdown": { "schema": { "type": "string" } },
+                                "text/html": { "schema": { "type": "string" } },
+                                "text/plain": { "schema": { "type": "string" } }
+                            }
+                        },
+                        "400": { "description": "UNSUPPORTED_FORMAT or UNKNOWN_FIELD" },
+                        "404": { "description": "NOT_FOUND" },
+                        "406": { "description": "NOT_ACCEPTABLE" }
+                    }
+                },
+                "patch": {
+                    "summary": "Update document (creates version); a new slug renames it and rewrites links to it",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "{status, version_number, slug, links_updated}" }, "409": { "description": "DUPLICATE_SLUG" } }
+                },
+                "delete": {
+                    "summary": "Delete document",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Deleted" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/versions": {
+                "get": {
+                    "summary": "List version history",
+                    "parameters": [
+                        { "name": "limit", "in": "query", "schema": { "type": "integer", "default": 20 } },
+                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } }
+                    ],
+                    "responses": { "200": { "description": "Array of versions" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/versions/{num}": {
+                "get": {
+                    "summary": "Get specific version",
+                    "responses": { "200": { "description": "Version content" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/versions/{num}/restore": {
+                "post": {
+                    "summary": "Restore document to this version",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Restored" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/diff": {
+                "get": {
+                    "summary": "Diff between two versions",
+                    "parameters": [
+                        { "name": "from", "in": "query", "required": true, "schema": { "type": "integer" } },
+                        { "name": "to", "in": "query", "required": true, "schema": { "type": "integer" } }
+                    ],
+                    "responses": { "200": { "description": "Unified diff + stats" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/comments": {
+                "post": {
+                    "summary": "Add comment (manage key needed only when the workspace disables anonymous comments)",
+                    "security": [{}, { "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateComment" } } } },
+                    "responses": {
+                        "201": { "description": "Comment created; includes edit_token (shown once) for the author's own edits" },
+                        "202": { "description": "Comment held for moderation (moderation_status: pending)" },
+                        "429": { "description": "Comment rate limit exceeded" },
+                        "401": { "description": "Anonymous comments disabled" },
+                        "404": { "description": "Document not in this workspace" },
+                        "422": { "description": "Invalid parent_id, too long, too deep, or blocked content" }
+                    }
+                },
+                "get": {
+                    "summary": "List comments (flat array, or nested threads with threaded=true)",
+                    "parameters": [
+                        { "name": "threaded", "in": "query", "schema": { "type": "boolean" }, "description": "Return {threads, total, limit, offset} with nested replies, depth and reply_count" },
+                        { "name": "resolved", "in": "query", "schema": { "type": "boolean" }, "description": "Only resolved / unresolved comments (root threads when threaded)" },
+                        { "name": "sort", "in": "query", "schema": { "type": "string", "enum": ["created", "activity"], "default": "created" } },
+                        { "name": "limit", "in": "query", "schema": { "type": "integer" } },
+                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } }
+                    ],
+                    "responses": { "200": { "description": "Comments with content_html and reactions" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/lock": {
+                "post": {
+                    "summary": "Acquire edit lock",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/AcquireLock" } } } },
+                    "responses": { "200": { "description": "Lock acquired" }, "409": { "description": "Lock conflict" } }
+                },
+                "delete": {
+                    "summary": "Release edit lock",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Lock released" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/lock/renew": {
+                "post": {
+                    "summary": "Renew edit lock TTL",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/AcquireLock" } } } },
+                    "responses": { "200": { "description": "Lock renewed" }, "409": { "description": "Lock not held by editor or expired" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/comments/{comment_id}": {
+                "patch": {
+                    "summary": "Update/resolve comment (the comment token may edit content; resolving needs the manage key)",
+                    "security": [{ "ManageKey": [] }, { "CommentToken": [] }],
+                    "requestBody": { "content": { "application/json": { "schema": { "type": "object", "properties": { "content": { "type": "string" }, "resolved": { "type": "boolean" } } } } } },
+                    "responses": { "200": { "description": "Comment updated" }, "404": { "description": "Comment not found" } }
+                },
+                "delete": {
+                    "summary": "Delete comment",
+                    "security": [{ "ManageKey": [] }, { "CommentToken": [] }],
+                    "responses": { "200": { "description": "Comment deleted" }, "404": { "description": "Comment not found" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/reactions": {
+                "post": {
+                    "summary": "React to a document (+1/-1 votes replace each other)",
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Reaction" } } } },
+                    "responses": { "201": { "description": "Reaction added" }, "200": { "description": "Author already had this reaction" } }
+                },
+                "delete": {
+                    "summary": "Remove a document reaction",
+                    "parameters": [
+                        { "name": "author_name", "in": "query", "required": true, "schema": { "type": "string" } },
+                        { "name": "reaction", "in": "query", "required": true, "schema": { "type": "string" }, "description": "URL-encode + as %2B" }
+                    ],
+                    "responses": { "200": { "description": "Removed" }, "404": { "description": "Reaction not found" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/comments/{comment_id}/reactions": {
+                "post": {
+                    "summary": "React to a comment",
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Reaction" } } } },
+                    "responses": { "201": { "description": "Reaction added" }, "200": { "description": "Author already had this reaction" } }
                 },
-                "get": {
-                    "summary": "List public workspaces",
-                    "responses": { "200": { "description": "Array of public workspaces" } }
+                "delete": {
+                    "summary": "Remove a comment reaction",
+                    "parameters": [
+                        { "name": "author_name", "in": "query", "required": true, "schema": { "type": "string" } },
+                        { "name": "reaction", "in": "query", "required": true, "schema": { "type": "string" } }
+                    ],
+                    "responses": { "200": { "description": "Removed" }, "404": { "description": "Reaction not found" } }
                 }
             },
-            "/workspaces/{workspace_id}": {
-                "get": {
-                    "summary": "Get workspace",
-                    "parameters": [{ "name": "workspace_id", "in": "path", "required": true, "schema": { "type": "string" } }],
-                    "responses": { "200": { "description": "Workspace details" } }
+            "/workspaces/{workspace_id}/docs/{doc_id}/suggestions": {
+                "post": {
+                    "summary": "Suggest an edit (anchor + replacement, or full content)",
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateSuggestion" } } } },
+                    "responses": { "201": { "description": "Suggestion created" }, "404": { "description": "Document or version not found" }, "422": { "description": "Quoted text not found" } }
                 },
-                "patch": {
-                    "summary": "Update workspace",
+                "get": {
+                    "summary": "List suggestions",
+                    "parameters": [
+                        { "name": "status", "in": "query", "schema": { "type": "string", "enum": ["pending", "accepted", "rejected"] } }
+                    ],
+                    "responses": { "200": { "description": "Array of suggestions" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/suggestions/{suggestion_id}/accept": {
+                "post": {
+                    "summary": "Accept suggestion (applies it as a new version)",
                     "security": [{ "ManageKey": [] }],
-                    "parameters": [{ "name": "workspace_id", "in": "path", "required": true, "schema": { "type": "string" } }],
-                    "responses": { "200": { "description": "Updated" } }
+                    "requestBody": { "content": { "application/json": { "schema": { "type": "object", "properties": { "reviewed_by": { "type": "string" }, "force": { "type": "boolean", "description": "Apply full-content suggestions even if the document changed since base_version" } } } } } },
+                    "responses": { "200": { "description": "Applied" }, "409": { "description": "Already reviewed or conflicts with the current version" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs": {
+            "/workspaces/{workspace_id}/docs/{doc_id}/suggestions/{suggestion_id}/reject": {
                 "post": {
-                    "summary": "Create document",
+                    "summary": "Reject suggestion",
                     "security": [{ "ManageKey": [] }],
-                    "responses": { "201": { "description": "Document created" } }
-                },
+                    "requestBody": { "content": { "application/json": { "schema": { "type": "object", "properties": { "reviewed_by": { "type": "string" }, "reason": { "type": "string" } } } } } },
+                    "responses": { "200": { "description": "Rejected" }, "409": { "description": "Already reviewed" } }
+                }
+            },
+            "/workspaces/{workspace_id}/activity": {
                 "get": {
-                    "summary": "List documents (published only; all with key)",
+                    "summary": "Persisted activity timeline (every event except presence pings), newest first; unpublished documents need the key",
                     "parameters": [
-                        { "name": "workspace_id", "in": "path", "required": true, "schema": { "type": "string" } },
-                        { "name": "key", "in": "query", "schema": { "type": "string" }, "description": "Manage key to include drafts" }
+                        { "name": "key", "in": "query", "schema": { "type": "string" }, "description": "Manage key; includes events about unpublished documents" },
+                        { "name": "types", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated event type patterns" },
+                        { "name": "docs", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated document ids" },
+                        { "name": "tags", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated tags" },
+                        { "name": "actor", "in": "query", "schema": { "type": "string" }, "description": "author_name / reviewer / locked_by (case-insensitive)" },
+                        { "name": "before", "in": "query", "schema": { "type": "integer" }, "description": "Only entries with seq < before" },
+                        { "name": "after", "in": "query", "schema": { "type": "integer" }, "description": "Only entries with seq > after" },
+                        { "name": "order", "in": "query", "schema": { "type": "string", "enum": ["desc", "asc"], "default": "desc" } },
+                        { "name": "limit", "in": "query", "schema": { "type": "integer", "default": 50 } }
                     ],
-                    "responses": { "200": { "description": "Array of documents" } }
+                    "responses": { "200": { "description": "{items: [{seq, event, document_id, actor, data, created_at}], next} — next is set when more may follow, even on a short page" }, "404": { "description": "Workspace not found" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{slug}": {
+            "/workspaces/{workspace_id}/inbox": {
                 "get": {
-                    "summary": "Get document by slug",
-                    "responses": { "200": { "description": "Document with rendered HTML" } }
+                    "summary": "Notifications (@mentions) addressed to a name; mentions in unpublished documents need the key",
+                    "parameters": [
+                        { "name": "for", "in": "query", "required": true, "schema": { "type": "string" }, "description": "Recipient name (case-insensitive)" },
+                        { "name": "key", "in": "query", "schema": { "type": "string" }, "description": "Manage key; includes mentions in unpublished documents" },
+                        { "name": "unread", "in": "query", "schema": { "type": "boolean" } },
+                        { "name": "limit", "in": "query", "schema": { "type": "integer", "default": 50 } },
+                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } }
+                    ],
+                    "responses": { "200": { "description": "Notifications with unread_count" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}": {
+            "/workspaces/{workspace_id}/inbox/read": {
+                "post": {
+                    "summary": "Mark notifications read (all, or the given ids); read=false marks unread",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "type": "object", "required": ["for"], "properties": { "for": { "type": "string" }, "ids": { "type": "array", "items": { "type": "string" } }, "read": { "type": "boolean", "default": true } } } } } },
+                    "responses": { "200": { "description": "Number updated" } }
+                }
+            },
+            "/workspaces/{workspace_id}/templates": {
+                "get": {
+                    "summary": "List workspace templates and the built-in library (ADR, incident report, run log, meeting notes)",
+                    "responses": { "200": { "description": "{templates}; each lists its variables with default and required" } }
+                },
+                "post": {
+                    "summary": "Create a workspace template",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Template" } } } },
+                    "responses": { "201": { "description": "Template created" }, "409": { "description": "Slug taken" } }
+                }
+            },
+            "/workspaces/{workspace_id}/templates/{template_id}": {
+                "get": {
+                    "summary": "Get a template by id, slug, or builtin:<slug>",
+                    "responses": { "200": { "description": "Template" }, "404": { "description": "Not found" } }
+                },
                 "patch": {
-                    "summary": "Update document (creates version)",
+                    "summary": "Update a workspace template (built-ins are read-only)",
                     "security": [{ "ManageKey": [] }],
-                    "responses": { "200": { "description": "Updated" } }
+                    "requestBody": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Template" } } } },
+                    "responses": { "200": { "description": "Updated" }, "403": { "description": "Built-in template" } }
                 },
                 "delete": {
-                    "summary": "Delete document",
+                    "summary": "Delete a workspace template",
                     "security": [{ "ManageKey": [] }],
-                    "responses": { "200": { "description": "Deleted" } }
+                    "responses": { "200": { "description": "Deleted" }, "403": { "description": "Built-in template" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/versions": {
+            "/workspaces/{workspace_id}/templates/{template_id}/render": {
+                "post": {
+                    "summary": "Preview a template with {variables, title?, author_name?}",
+                    "responses": { "200": { "description": "{title, content, content_html, tags}" }, "422": { "description": "MISSING_VARIABLES" } }
+                }
+            },
+            "/workspaces/{workspace_id}/attachments": {
                 "get": {
-                    "summary": "List version history",
+                    "summary": "List attachments",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "{count, total_bytes, attachments}" } }
+                },
+                "post": {
+                    "summary": "Upload an attachment: multipart/form-data with a file part, or the raw body with ?filename=",
+                    "security": [{ "ManageKey": [] }],
                     "parameters": [
-                        { "name": "limit", "in": "query", "schema": { "type": "integer", "default": 20 } },
-                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } }
+                        { "name": "filename", "in": "query", "schema": { "type": "string" } },
+                        { "name": "uploaded_by", "in": "query", "schema": { "type": "string" } }
                     ],
-                    "responses": { "200": { "description": "Array of versions" } }
+                    "requestBody": { "content": {
+                        "multipart/form-data": { "schema": { "type": "object", "properties": { "file": { "type": "string", "format": "binary" }, "uploaded_by": { "type": "string" } } } },
+                        "application/octet-stream": { "schema": { "type": "string", "format": "binary" } }
+                    } },
+                    "responses": { "201": { "description": "Attachment {id, url, markdown, content_type, size, sha256}" }, "200": { "description": "Existing attachment with the same bytes" }, "413": { "description": "ATTACHMENT_TOO_LARGE" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/versions/{num}": {
+            "/workspaces/{workspace_id}/attachments/{attachment_id}": {
                 "get": {
-                    "summary": "Get specific version",
-                    "responses": { "200": { "description": "Version content" } }
+                    "summary": "Download an attachment",
+                    "responses": { "200": { "description": "The file, with its sniffed content type" } }
+                },
+                "delete": {
+                    "summary": "Delete an attachment",
+                    "security": [{ "ManageKey": [] }],
+                    "parameters": [{ "name": "force", "in": "query", "schema": { "type": "boolean" } }],
+                    "responses": { "200": { "description": "{status, blob_deleted}" }, "409": { "description": "ATTACHMENT_IN_USE" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/versions/{num}/restore": {
+            "/workspaces/{workspace_id}/attachments/gc": {
                 "post": {
-                    "summary": "Restore document to this version",
+                    "summary": "Remove the workspace's unreferenced attachments, then blobs no attachment in any workspace uses",
                     "security": [{ "ManageKey": [] }],
-                    "responses": { "200": { "description": "Restored" } }
+                    "parameters": [
+                        { "name": "dry_run", "in": "query", "schema": { "type": "boolean" } },
+                        { "name": "min_age_minutes", "in": "query", "schema": { "type": "integer", "default": 60 } }
+                    ],
+                    "responses": { "200": { "description": "{dry_run, attachments_removed, blobs_removed, bytes_freed}" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/diff": {
+            "/workspaces/{workspace_id}/export": {
                 "get": {
-                    "summary": "Diff between two versions",
+                    "summary": "Stream the workspace as a zip of markdown files with front matter, or its published documents as a static HTML site",
+                    "security": [{ "ManageKey": [] }],
                     "parameters": [
-                        { "name": "from", "in": "query", "required": true, "schema": { "type": "integer" } },
-                        { "name": "to", "in": "query", "required": true, "schema": { "type": "integer" } }
+                        { "name": "format", "in": "query", "schema": { "type": "string", "enum": ["zip", "site"], "default": "zip" } },
+                        { "name": "versions", "in": "query", "schema": { "type": "boolean", "default": false } },
+                        { "name": "comments", "in": "query", "schema": { "type": "boolean", "default": false } },
+                        { "name": "attachments", "in": "query", "schema": { "type": "boolean", "default": true } },
+                        { "name": "template", "in": "query", "description": "format=site: id of an HTML attachment with a {{content}} slot", "schema": { "type": "string" } }
                     ],
-                    "responses": { "200": { "description": "Unified diff + stats" } }
+                    "responses": { "200": { "description": "application/zip archive" }, "400": { "description": "UNSUPPORTED_FORMAT or INVALID_TEMPLATE" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/comments": {
+            "/workspaces/{workspace_id}/import": {
                 "post": {
-                    "summary": "Add comment",
-                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateComment" } } } },
-                    "responses": { "201": { "description": "Comment created" } }
-                },
-                "get": {
-                    "summary": "List comments (threaded)",
-                    "responses": { "200": { "description": "Array of comments" } }
+                    "summary": "Create documents from a zip of markdown files (docs folder, Obsidian vault or export archive)",
+                    "security": [{ "ManageKey": [] }],
+                    "parameters": [
+                        { "name": "dry_run", "in": "query", "schema": { "type": "boolean", "default": false } },
+                        { "name": "on_conflict", "in": "query", "schema": { "type": "string", "enum": ["skip", "update", "rename"], "default": "skip" } },
+                        { "name": "author_name", "in": "query", "schema": { "type": "string" } }
+                    ],
+                    "requestBody": { "required": true, "content": { "application/zip": { "schema": { "type": "string", "format": "binary" } } } },
+                    "responses": { "200": { "description": "{dry_run, summary, files: [{path, result, slug, id, links_converted, unresolved_links, error, code}]}" }, "400": { "description": "INVALID_ARCHIVE" }, "413": { "description": "IMPORT_TOO_LARGE" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/lock": {
-                "post": {
-                    "summary": "Acquire edit lock",
+            "/workspaces/{workspace_id}/git": {
+                "get": {
+                    "summary": "Git mirror status: path, branch, head, pending_versions and pushed_commits",
                     "security": [{ "ManageKey": [] }],
-                    "requestBody": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/AcquireLock" } } } },
-                    "responses": { "200": { "description": "Lock acquired" }, "409": { "description": "Lock conflict" } }
+                    "responses": { "200": { "description": "{workspace_id, path, branch, head, synced_commit, commits, pending_versions, pushed_commits}" }, "404": { "description": "Not mirrored" } }
+                },
+                "put": {
+                    "summary": "Mirror the workspace to a bare git repository, one commit per version",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "201": { "description": "Mirror enabled" }, "200": { "description": "Already enabled" } }
                 },
                 "delete": {
-                    "summary": "Release edit lock",
+                    "summary": "Stop mirroring; the repository stays on disk",
                     "security": [{ "ManageKey": [] }],
-                    "responses": { "200": { "description": "Lock released" } }
+                    "responses": { "200": { "description": "{status: disabled}" }, "404": { "description": "Not mirrored" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/lock/renew": {
+            "/workspaces/{workspace_id}/git/sync": {
                 "post": {
-                    "summary": "Renew edit lock TTL",
+                    "summary": "Ingest commits pushed to main as new versions",
                     "security": [{ "ManageKey": [] }],
-                    "requestBody": { "content": { "application/json": { "schema": { "$ref": "#/components/schemas/AcquireLock" } } } },
-                    "responses": { "200": { "description": "Lock renewed" }, "409": { "description": "Lock not held by editor or expired" } }
+                    "parameters": [
+                        { "name": "dry_run", "in": "query", "schema": { "type": "boolean", "default": false } }
+                    ],
+                    "responses": { "200": { "description": "{dry_run, commits, head, summary, files: [{commit, path, result, slug, version_number, error, code}]}" }, "404": { "description": "Not mirrored" } }
                 }
             },
-            "/workspaces/{workspace_id}/docs/{doc_id}/comments/{comment_id}": {
+            "/workspaces/{workspace_id}/doc-types": {
+                "get": {
+                    "summary": "List document types",
+                    "responses": { "200": { "description": "{doc_types}" } }
+                },
+                "post": {
+                    "summary": "Create a document type",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/DocumentType" } } } },
+                    "responses": { "201": { "description": "Document type created" }, "400": { "description": "Invalid schema or headings" }, "409": { "description": "DUPLICATE_SLUG" } }
+                }
+            },
+            "/workspaces/{workspace_id}/doc-types/{type_id}": {
+                "get": {
+                    "summary": "Get a document type by id or slug",
+                    "responses": { "200": { "description": "Document type" } }
+                },
                 "patch": {
-                    "summary": "Update/resolve comment",
+                    "summary": "Update name, description, schema or required_headings (slug is fixed)",
                     "security": [{ "ManageKey": [] }],
-                    "requestBody": { "content": { "application/json": { "schema": { "type": "object", "properties": { "content": { "type": "string" }, "resolved": { "type": "boolean" } } } } } },
-                    "responses": { "200": { "description": "Comment updated" }, "404": { "description": "Comment not found" } }
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/DocumentType" } } } },
+                    "responses": { "200": { "description": "Updated" } }
                 },
                 "delete": {
-                    "summary": "Delete comment",
+                    "summary": "Delete a document type",
                     "security": [{ "ManageKey": [] }],
-                    "responses": { "200": { "description": "Comment deleted" }, "404": { "description": "Comment not found" } }
+                    "responses": { "200": { "description": "Deleted" }, "409": { "description": "DOC_TYPE_IN_USE" } }
+                }
+            },
+            "/workspaces/{workspace_id}/doc-types/{type_id}/validate": {
+                "post": {
+                    "summary": "Dry run: check {fields, content} against the type without saving",
+                    "responses": { "200": { "description": "{valid, errors: [{source, path, message}], metadata}" } }
+                }
+            },
+            "/workspaces/{workspace_id}/comment-policy": {
+                "get": {
+                    "summary": "Get the comment policy (limits, blocked words/patterns, moderation queue)",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "{policy}" } }
+                },
+                "put": {
+                    "summary": "Set the comment policy; omitted fields use defaults, {\"policy\": null} resets",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CommentPolicy" } } } },
+                    "responses": { "200": { "description": "Policy saved" }, "400": { "description": "Invalid policy or pattern" } }
+                }
+            },
+            "/workspaces/{workspace_id}/comments/pending": {
+                "get": {
+                    "summary": "Moderation queue: comments held until approved",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "{count, comments}" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/comments/{comment_id}/approve": {
+                "post": {
+                    "summary": "Approve a held comment (reject it with DELETE)",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Comment now visible" }, "409": { "description": "Not pending" } }
+                }
+            },
+            "/workspaces/{workspace_id}/workflow": {
+                "get": {
+                    "summary": "Get status workflow (null when statuses are free-form) and the default preset",
+                    "responses": { "200": { "description": "Workflow" }, "404": { "description": "Workspace not found" } }
+                },
+                "put": {
+                    "summary": "Set status workflow",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Workflow" } } } },
+                    "responses": { "200": { "description": "Workflow saved" }, "400": { "description": "Invalid workflow" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/approvals": {
+                "get": {
+                    "summary": "Approval summary for the current version plus full history",
+                    "responses": { "200": { "description": "Approvals" } }
+                },
+                "post": {
+                    "summary": "Approve or reject the current version",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "type": "object", "required": ["reviewer"], "properties": { "reviewer": { "type": "string" }, "decision": { "type": "string", "enum": ["approve", "reject"] }, "comment": { "type": "string", "description": "Required when rejecting" } } } } } },
+                    "responses": { "201": { "description": "Decision recorded" }, "403": { "description": "Not a named reviewer" }, "409": { "description": "No workflow configured" } }
                 }
             },
             "/workspaces/{workspace_id}/search": {
@@ -1034,11 +6525,88 @@ pub fn openapi_spec() -> (Status, (rocket::http::ContentType, String)) {
                     "parameters": [
                         { "name": "q", "in": "query", "required": true, "schema": { "type": "string" } },
                         { "name": "limit", "in": "query", "schema": { "type": "integer", "default": 20 } },
-                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } }
+                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } },
+                        { "name": "meta.{key}", "in": "query", "schema": { "type": "string" }, "description": "Front matter filter, e.g. meta.priority=high; dotted keys reach nested fields, arrays match any element" }
                     ],
                     "responses": { "200": { "description": "Search results" } }
                 }
             },
+            "/workspaces/{workspace_id}/events/stream": {
+                "get": {
+                    "summary": "SSE event stream (filterable)",
+                    "parameters": [
+                        { "name": "types", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated event type patterns, e.g. document.*,comment.created" },
+                        { "name": "docs", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated document ids" },
+                        { "name": "tags", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated document tags (any match)" }
+                    ],
+                    "responses": { "200": { "description": "text/event-stream" } }
+                }
+            },
+            "/workspaces/{workspace_id}/docs/{doc_id}/events/stream": {
+                "get": {
+                    "summary": "SSE event stream for a single document",
+                    "parameters": [
+                        { "name": "types", "in": "query", "schema": { "type": "string" }, "description": "Comma-separated event type patterns" },
+                        { "name": "tags", "in": "query", "schema": { "type": "string" }, "description": "Only while the document carries one of these comma-separated tags" }
+                    ],
+                    "responses": { "200": { "description": "text/event-stream" }, "404": { "description": "Document not found in workspace" } }
+                }
+            },
+            "/workspaces/{workspace_id}/webhooks": {
+                "post": {
+                    "summary": "Register webhook (secret returned once)",
+                    "security": [{ "ManageKey": [] }],
+                    "requestBody": { "required": true, "content": { "application/json": { "schema": { "$ref": "#/components/schemas/CreateWebhook" } } } },
+                    "responses": { "201": { "description": "Webhook created" } }
+                },
+                "get": {
+                    "summary": "List webhooks",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Array of webhooks" } }
+                }
+            },
+            "/workspaces/{workspace_id}/webhooks/{webhook_id}": {
+                "patch": {
+                    "summary": "Update webhook url/events/active",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Updated" }, "404": { "description": "Webhook not found" } }
+                },
+                "delete": {
+                    "summary": "Delete webhook",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "200": { "description": "Deleted" }, "404": { "description": "Webhook not found" } }
+                }
+            },
+            "/workspaces/{workspace_id}/webhooks/{webhook_id}/deliveries": {
+                "get": {
+                    "summary": "Delivery log (newest first)",
+                    "security": [{ "ManageKey": [] }],
+                    "parameters": [
+                        { "name": "limit", "in": "query", "schema": { "type": "integer", "default": 20 } },
+                        { "name": "offset", "in": "query", "schema": { "type": "integer", "default": 0 } }
+                    ],
+                    "responses": { "200": { "description": "Array of deliveries" } }
+                }
+            },
+            "/workspaces/{workspace_id}/webhooks/{webhook_id}/deliveries/{delivery_id}/redeliver": {
+                "post": {
+                    "summary": "Queue a new delivery of a past payload",
+                    "security": [{ "ManageKey": [] }],
+                    "responses": { "202": { "description": "Queued" }, "404": { "description": "Webhook or delivery not found" } }
+                }
+            },
+            "/workspaces/{workspace_id}/ws": {
+                "get": {
+                    "summary": "WebSocket event channel (upgrade). Same filters as the SSE stream; accepts subscribe, unsubscribe, presence, ping and lock.* messages",
+                    "parameters": [
+                        { "name": "types", "in": "query", "schema": { "type": "string" } },
+                        { "name": "docs", "in": "query", "schema": { "type": "string" } },
+                        { "name": "tags", "in": "query", "schema": { "type": "string" } },
+                        { "name": "key", "in": "query", "schema": { "type": "string" }, "description": "Manage key, required for lock messages" }
+                    ],
+                    "responses": { "101": { "description": "Switching protocols" }, "426": { "description": "Not a WebSocket request" } }
+                }
+            },
             "/health": {
                 "get": {
                     "summary": "Health check",
@@ -1059,6 +6627,12 @@ pub fn openapi_spec() -> (Status, (rocket::http::ContentType, String)) {
                     "in": "header",
                     "name": "Authorization",
                     "description": "Bearer <manage_key>, X-API-Key: <manage_key>, or ?key=<manage_key>"
+                },
+                "CommentToken": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "X-Comment-Token",
+                    "description": "edit_token returned when the comment was created, or ?comment_token=<token>"
                 }
             },
             "schemas": {
@@ -1071,13 +6645,133 @@ pub fn openapi_spec() -> (Status, (rocket::http::ContentType, String)) {
                         "is_public": { "type": "boolean", "default": false }
                     }
                 },
+                "CreateDocument": {
+                    "type": "object",
+                    "properties": {
+                        "title": { "type": "string", "description": "Required unless the template has a title pattern" },
+                        "content": { "type": "string", "description": "Markdown; may start with YAML front matter between --- lines" },
+                        "from_template": { "type": "string", "description": "Template id, slug, or builtin:<slug>; instead of content" },
+                        "variables": { "type": "object", "additionalProperties": { "type": "string" }, "description": "Template bindings; date and author_name are filled in" },
+                        "slug": { "type": "string" },
+                        "summary": { "type": "string" },
+                        "tags": { "type": "array", "items": { "type": "string" }, "description": "Defaults to the template's tags" },
+                        "status": { "type": "string" },
+                        "author_name": { "type": "string" },
+                        "doc_type": { "type": "string", "description": "Document type slug; fields and content are checked against it" },
+                        "fields": { "type": "object", "description": "Structured fields, validated by the type's JSON Schema" }
+                    }
+                },
+                "DocumentType": {
+                    "type": "object",
+                    "required": ["name"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "slug": { "type": "string", "description": "Defaults to a slug of name; fixed once created" },
+                        "description": { "type": "string" },
+                        "schema": { "type": "object", "description": "JSON Schema for fields (default {\"type\": \"object\"})" },
+                        "required_headings": { "type": "array", "items": { "type": "string" }, "description": "Headings the body must contain (any level, case-insensitive)" }
+                    }
+                },
+                "Template": {
+                    "type": "object",
+                    "required": ["name", "content"],
+                    "properties": {
+                        "name": { "type": "string" },
+                        "slug": { "type": "string", "description": "Defaults to a slug of name" },
+                        "description": { "type": "string" },
+                        "title": { "type": "string", "description": "Title pattern for new documents, e.g. \"ADR-{{number}}: {{decision}}\"" },
+                        "content": { "type": "string", "description": "Markdown with {{name}} or {{name|default}} placeholders" },
+                        "tags": { "type": "array", "items": { "type": "string" } },
+                        "variables": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "required": ["name"],
+                                "properties": { "name": { "type": "string" }, "default": { "type": "string" }, "description": { "type": "string" } }
+                            }
+                        }
+                    }
+                },
                 "CreateComment": {
                     "type": "object",
                     "required": ["author_name", "content"],
                     "properties": {
                         "author_name": { "type": "string" },
                         "content": { "type": "string" },
-                        "parent_id": { "type": "string", "description": "Reply to another comment" }
+                        "parent_id": { "type": "string", "description": "Reply to another comment" },
+                        "anchor": {
+                            "type": "object",
+                            "description": "Attach to a text range. Offsets are Unicode characters; give quote, or start and end",
+                            "properties": {
+                                "quote": { "type": "string" },
+                                "prefix": { "type": "string", "description": "Text just before the quote (captured if omitted)" },
+                                "suffix": { "type": "string", "description": "Text just after the quote (captured if omitted)" },
+                                "start": { "type": "integer" },
+                                "end": { "type": "integer" },
+                                "version_number": { "type": "integer", "description": "Version the offsets refer to (default latest)" }
+                            }
+                        }
+                    }
+                },
+                "CommentPolicy": {
+                    "type": "object",
+                    "description": "Length and depth limits apply to everyone; the rest only to comments without the manage key",
+                    "properties": {
+                        "max_length": { "type": "integer", "default": 10000, "description": "Characters" },
+                        "max_depth": { "type": "integer", "default": 10, "description": "Reply nesting; top-level comments are depth 0" },
+                        "blocked_words": { "type": "array", "items": { "type": "string" }, "description": "Case-insensitive whole words or phrases" },
+                        "blocked_patterns": { "type": "array", "items": { "type": "string" }, "description": "Regular expressions" },
+                        "rate_limit_per_ip": { "type": "integer", "default": 30, "description": "Comments per hour" },
+                        "rate_limit_per_document": { "type": "integer", "default": 120, "description": "Comments per hour" },
+                        "hold_anonymous": { "type": "boolean", "default": false, "description": "Hold comments for approval" }
+                    }
+                },
+                "Workflow": {
+                    "type": "object",
+                    "description": "Or {\"preset\": \"default\"}, or {\"workflow\": null} to remove",
+                    "required": ["states", "transitions"],
+                    "properties": {
+                        "states": { "type": "array", "items": { "type": "string" } },
+                        "initial": { "type": "string" },
+                        "transitions": { "type": "object", "additionalProperties": { "type": "array", "items": { "type": "string" } } },
+                        "approval": {
+                            "type": "object",
+                            "properties": {
+                                "reviewers": { "type": "array", "items": { "type": "string" } },
+                                "required": { "type": "integer", "default": 1 },
+                                "before": { "type": "array", "items": { "type": "string" }, "description": "States gated on approvals" },
+                                "reject_to": { "type": "string" }
+                            }
+                        }
+                    }
+                },
+                "Reaction": {
+                    "type": "object",
+                    "required": ["author_name", "reaction"],
+                    "properties": {
+                        "author_name": { "type": "string" },
+                        "reaction": { "type": "string", "description": "Emoji or token such as +1, -1, needs-info (max 32 chars, no whitespace)" }
+                    }
+                },
+                "CreateSuggestion": {
+                    "type": "object",
+                    "required": ["author_name"],
+                    "properties": {
+                        "author_name": { "type": "string" },
+                        "description": { "type": "string" },
+                        "anchor": { "type": "object", "description": "Range to replace; same shape as a comment anchor" },
+                        "replacement": { "type": "string", "description": "New text for the anchored range" },
+                        "content": { "type": "string", "description": "Full proposed content (instead of anchor + replacement)" },
+                        "base_version": { "type": "integer", "description": "Version the full content is based on (default latest)" }
+                    }
+                },
+                "CreateWebhook": {
+                    "type": "object",
+                    "required": ["url"],
+                    "properties": {
+                        "url": { "type": "string" },
+                        "events": { "type": "array", "items": { "type": "string" }, "description": "Event type patterns, e.g. document.*; empty = all" },
+                        "secret": { "type": "string", "description": "HMAC-SHA256 signing secret (generated if omitted)" }
                     }
                 },
                 "AcquireLock": {
@@ -1101,12 +6795,47 @@ pub fn openapi_spec() -> (Status, (rocket::http::ContentType, String)) {
 
 // --- SSE Event Stream ---
 
-#[get("/workspaces/<workspace_id>/events/stream")]
-pub fn event_stream(
+/// Workspace event stream. Optional filters (comma-separated):
+/// `types` (e.g. `document.*,comment.created`), `docs` (document ids), `tags`.
+#[get("/workspaces/<workspace_id>/events/stream?<types>&<docs>&<tags>")]
+pub fn event_stream<'a>(
+    workspace_id: &'a str,
+    types: Option<&'a str>,
+    docs: Option<&'a str>,
+    tags: Option<&'a str>,
+    db: &'a State<Db>,
+    event_bus: &'a State<EventBus>,
+    shutdown: Shutdown,
+) -> EventStream![Event + 'a] {
+    let filter = EventFilter::new(types, docs, tags);
+    filtered_event_stream(db, event_bus, workspace_id, filter, shutdown)
+}
+
+/// Event stream for a single document (same filters as the workspace stream, minus `docs`).
+#[get("/workspaces/<ws_id>/docs/<doc_id>/events/stream?<types>&<tags>")]
+pub fn document_event_stream<'a>(
+    ws_id: &'a str,
+    doc_id: &'a str,
+    types: Option<&'a str>,
+    tags: Option<&'a str>,
+    db: &'a State<Db>,
+    event_bus: &'a State<EventBus>,
+    shutdown: Shutdown,
+) -> Result<EventStream![Event + 'a], (Status, Json<Value>)> {
+    let doc = document_in_workspace(db, ws_id, doc_id)?;
+    let filter = EventFilter::new(types, doc["id"].as_str(), tags);
+    Ok(filtered_event_stream(
+        db, event_bus, ws_id, filter, shutdown,
+    ))
+}
+
+fn filtered_event_stream<'a>(
+    db: &'a Db,
+    event_bus: &EventBus,
     workspace_id: &str,
-    event_bus: &State<EventBus>,
+    filter: EventFilter,
     mut shutdown: Shutdown,
-) -> EventStream![] {
+) -> EventStream![Event + 'a] {
     let mut rx = event_bus.subscribe();
     let ws_id = workspace_id.to_string();
 
@@ -1118,7 +6847,9 @@ pub fn event_stream(
                 msg = rx.recv() => {
                     match msg {
                         Ok(evt) if evt.workspace_id == ws_id => {
-                            yield Event::json(&evt.data).event(evt.event_type);
+                            if filter.matches_in(db, &evt).await {
+                                yield Event::json(&evt.data).event(evt.event_type);
+                            }
                         }
                         Ok(_) => {}, // Different workspace, skip
                         Err(tokio::sync::broadcast::error::RecvError::Lagged(n)) => {
@@ -1140,3 +6871,56 @@ pub fn event_stream(
         }
     }
 }
+
+// --- WebSocket ---
+
+/// Bidirectional alternative to the SSE stream: same events and initial filters,
+/// plus client messages (subscribe/unsubscribe, presence, lock acquire/renew/release).
+/// Lock messages require the manage key on connect.
+#[get("/workspaces/<ws_id>/ws?<types>&<docs>&<tags>")]
+#[allow(clippy::too_many_arguments)]
+pub fn websocket(
+    db: &State<Db>,
+    ws_id: &str,
+    types: Option<&str>,
+    docs: Option<&str>,
+    tags: Option<&str>,
+    upgrade: Option<WebSocketKey>,
+    token: Option<WorkspaceToken>,
+    event_bus: &State<EventBus>,
+    shutdown: Shutdown,
+) -> Result<Channel, (Status, Json<Value>)> {
+    let authorized = match token {
+        Some(t) => match verify_workspace_auth(db, ws_id, &t) {
+            Ok(()) => true,
+            Err((status, err)) => return Err((status, Json(err))),
+        },
+        None => match crate::db::get_workspace(db, ws_id) {
+            Ok(Some(_)) => false,
+            Ok(None) => {
+                return Err((
+                    Status::NotFound,
+                    Json(json!({"error": "Workspace not found", "code": "NOT_FOUND"})),
+                ))
+            }
+            Err(e) => return Err((Status::InternalServerError, Json(json!({"error": e})))),
+        },
+    };
+
+    let key = upgrade.ok_or((
+        Status::UpgradeRequired,
+        Json(json!({"error": "WebSocket upgrade required", "code": "UPGRADE_REQUIRED"})),
+    ))?;
+
+    Ok(Channel {
+        key,
+        session: Session {
+            db: db.inner().clone(),
+            event_bus: event_bus.inner().clone(),
+            workspace_id: ws_id.to_string(),
+            filter: EventFilter::new(types, docs, tags),
+            authorized,
+            shutdown,
+        },
+    })
+}
@@ -0,0 +1,510 @@
+//! Static HTML site of a workspace's published documents.
+//!
+//! ```text
+//! index.html                     every document with its summary and tags
+//! <slug>.html                    a document
+//! tags/index.html                every tag
+//! tags/<tag>.html                documents with the tag
+//! search-index.json              [{slug, title, summary, tags, url, text}]
+//! assets/style.css               default styles
+//! assets/search.js               client-side search over search-index.json
+//! attachments/<id>/<filename>    files the documents reference
+//! ```
+//!
+//! Every page is rendered into a [`SiteTemplate`], so the same pages can be
+//! given a different look. Wiki-links between published documents become
+//! relative links; links to drafts render as plain labels.
+
+use crate::attachments::BlobStore;
+use crate::db::Db;
+use crate::links::escape_html;
+use pulldown_cmark::{Event, Options, Parser, TagEnd};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Slots a template can use. Only `{{content}}` is required.
+pub const SLOTS: [&str; 6] = [
+    "site_name",
+    "description",
+    "title",
+    "nav",
+    "content",
+    "root",
+];
+
+const DEFAULT_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{title}} · {{site_name}}</title>
+<link rel="stylesheet" href="{{root}}assets/style.css">
+</head>
+<body>
+<aside>
+<a class="site-name" href="{{root}}index.html">{{site_name}}</a>
+<input id="search" type="search" placeholder="Search" aria-label="Search">
+<ul id="search-results"></ul>
+{{nav}}
+</aside>
+<main>
+{{content}}
+</main>
+<script src="{{root}}assets/search.js" data-root="{{root}}"></script>
+</body>
+</html>
+"#;
+
+const STYLE: &str = "body{margin:0;display:flex;font:16px/1.6 system-ui,sans-serif;color:#222}
+aside{width:16rem;flex-shrink:0;padding:1rem;border-right:1px solid #ddd;min-height:100vh;box-sizing:border-box}
+aside ul{list-style:none;padding:0}
+aside a[aria-current]{font-weight:600}
+.site-name{display:block;font-weight:700;margin-bottom:1rem;color:inherit;text-decoration:none}
+#search{width:100%;box-sizing:border-box}
+main{padding:1rem 2rem;max-width:48rem}
+.tags a{margin-right:.5rem}
+.wiki-link.missing{color:#888}
+pre{background:#f5f5f5;padding:.75rem;overflow:auto}
+img{max-width:100%}
+";
+
+const SEARCH: &str = "(function () {
+  var root = document.currentScript.dataset.root || '';
+  var input = document.getElementById('search');
+  var list = document.getElementById('search-results');
+  if (!input || !list) return;
+  var index = null;
+  function show() {
+    var terms = input.value.toLowerCase().split(/\\s+/).filter(Boolean);
+    list.innerHTML = '';
+    if (!terms.length || !index) return;
+    index.filter(function (doc) {
+      var hay = [doc.title, doc.summary, doc.tags.join(' '), doc.text].join(' ').toLowerCase();
+      return terms.every(function (t) { return hay.indexOf(t) !== -1; });
+    }).slice(0, 20).forEach(function (doc) {
+      var li = document.createElement('li');
+      var a = document.createElement('a');
+      a.href = root + doc.url;
+      a.textContent = doc.title;
+      li.appendChild(a);
+      list.appendChild(li);
+    });
+  }
+  input.addEventListener('input', function () {
+    if (index) return show();
+    fetch(root + 'search-index.json').then(function (r) { return r.json(); })
+      .then(function (data) { index = data; show(); });
+  });
+})();
+";
+
+/// HTML page layout with `{{slot}}` placeholders (see [`SLOTS`]). Slot values
+/// are HTML; unknown placeholders are left as written.
+#[derive(Clone, Debug)]
+pub struct SiteTemplate {
+    html: String,
+}
+
+impl Default for SiteTemplate {
+    fn default() -> Self {
+        SiteTemplate {
+            html: DEFAULT_TEMPLATE.to_string(),
+        }
+    }
+}
+
+impl SiteTemplate {
+    pub fn parse(html: &str) -> Result<Self, String> {
+        if !html.contains("{{content}}") {
+            return Err("template must contain a {{content}} placeholder".to_string());
+        }
+        Ok(SiteTemplate {
+            html: html.to_string(),
+        })
+    }
+
+    /// Fill the template in one pass, so slot values are never re-scanned.
+    pub fn render(&self, slots: &HashMap<&str, String>) -> String {
+        let text = &self.html;
+        let mut out = String::with_capacity(text.len());
+        let mut pos = 0;
+        while let Some(open) = text[pos..].find("{{").map(|i| pos + i) {
+            let Some(close) = text[open + 2..].find("}}").map(|i| open + 2 + i) else {
+                break;
+            };
+            match slots.get(text[open + 2..close].trim()) {
+                Some(value) => {
+                    out.push_str(&text[pos..open]);
+                    out.push_str(value);
+                    pos = close + 2;
+                }
+                None => {
+                    out.push_str(&text[pos..open + 2]);
+                    pos = open + 2;
+                }
+            }
+        }
+        out.push_str(&text[pos..]);
+        out
+    }
+}
+
+/// One rendered document as a self-contained HTML page with inline styles.
+pub fn standalone_page(title: &str, content_html: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<link rel=\"alternate\" type=\"text/markdown\" href=\"?format=markdown\">\n\
+         <style>\n{}</style>\n</head>\n<body>\n<main>\n{}</main>\n</body>\n</html>\n",
+        escape_html(title),
+        STYLE,
+        content_html
+    )
+}
+
+/// Markdown as plain text: paragraphs, headings and list items on their own
+/// lines, with markup, HTML and front matter dropped and wiki-links reduced
+/// to their labels.
+pub fn plain_text(markdown: &str) -> String {
+    let (_, body) = crate::frontmatter::parse_lenient(markdown);
+    // Unresolved links render as a label inside inline HTML, which is dropped
+    let body = crate::links::resolve_with(body, |_| None);
+    let mut options = Options::empty();
+    options.insert(Options::ENABLE_TABLES);
+    options.insert(Options::ENABLE_STRIKETHROUGH);
+    options.insert(Options::ENABLE_TASKLISTS);
+    let mut out = String::new();
+    for event in Parser::new_ext(&body, options) {
+        match event {
+            Event::Text(text) | Event::Code(text) => out.push_str(&text),
+            Event::SoftBreak | Event::HardBreak => out.push('\n'),
+            Event::End(
+                TagEnd::Paragraph
+                | TagEnd::Heading(_)
+                | TagEnd::Item
+                | TagEnd::CodeBlock
+                | TagEnd::TableRow
+                | TagEnd::TableHead,
+            ) if !out.ends_with('\n') => out.push('\n'),
+            Event::End(TagEnd::TableCell) => out.push('\t'),
+            _ => {}
+        }
+    }
+    out.trim_end().to_string()
+}
+
+/// A generated site: page bytes, plus attachment files copied from blobs.
+pub struct Site {
+    pub pages: Vec<(String, Vec<u8>)>,
+    /// `(path, sha256)` of each attachment file.
+    pub attachments: Vec<(String, String)>,
+}
+
+// Helper: percent-encode a path segment for use in an href
+fn encode_segment(segment: &str) -> String {
+    let mut out = String::new();
+    for b in segment.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+// Helper: file name of a tag's page
+fn tag_page(tag: &str) -> String {
+    let slug = crate::links::anchor(tag);
+    if slug.is_empty() {
+        "tag".to_string()
+    } else {
+        slug
+    }
+}
+
+struct Doc {
+    slug: String,
+    title: String,
+    summary: String,
+    tags: Vec<String>,
+    updated_at: String,
+    body: String,
+}
+
+// Helper: links to tag pages, relative to `root`
+fn tag_links(root: &str, tags: &[String]) -> String {
+    let links: Vec<String> = tags
+        .iter()
+        .map(|t| {
+            format!(
+                "<a href=\"{}tags/{}.html\">{}</a>",
+                root,
+                tag_page(t),
+                escape_html(t)
+            )
+        })
+        .collect();
+    format!("<p class=\"tags\">{}</p>", links.join(""))
+}
+
+// Helper: a list of documents with summaries, relative to `root`
+fn doc_list(root: &str, docs: &[&Doc]) -> String {
+    let mut html = String::from("<ul class=\"documents\">\n");
+    for doc in docs {
+        html.push_str(&format!(
+            "<li><a href=\"{}{}.html\">{}</a>",
+            root,
+            encode_segment(&doc.slug),
+            escape_html(&doc.title)
+        ));
+        if !doc.summary.is_empty() {
+            html.push_str(&format!(" — {}", escape_html(&doc.summary)));
+        }
+        html.push_str("</li>\n");
+    }
+    html.push_str("</ul>\n");
+    html
+}
+
+/// Render the workspace's published documents into a site.
+pub fn build(db: &Db, workspace_id: &str, template: &SiteTemplate) -> Result<Site, String> {
+    let workspace = crate::db::get_workspace(db, workspace_id)?
+        .ok_or_else(|| "Workspace not found".to_string())?;
+    let site_name = workspace["name"].as_str().unwrap_or("").to_string();
+    let description = workspace["description"].as_str().unwrap_or("").to_string();
+
+    let mut docs = Vec::new();
+    for row in crate::db::list_documents(db, workspace_id, false, &[])? {
+        let Some(doc) = crate::db::get_document_by_id(db, row["id"].as_str().unwrap_or(""))? else {
+            continue;
+        };
+        let content = doc["content"].as_str().unwrap_or("");
+        let tags = doc["tags"]
+            .as_array()
+            .map(|t| {
+                t.iter()
+                    .filter_map(|v| v.as_str().map(String::from))
+                    .collect()
+            })
+            .unwrap_or_default();
+        docs.push(Doc {
+            slug: doc["slug"].as_str().unwrap_or("").to_string(),
+            title: doc["title"].as_str().unwrap_or("").to_string(),
+            summary: doc["summary"].as_str().unwrap_or("").to_string(),
+            tags,
+            updated_at: doc["updated_at"].as_str().unwrap_or("").to_string(),
+            body: crate::frontmatter::parse_lenient(content).1.to_string(),
+        });
+    }
+    docs.sort_by_key(|d| (d.title.to_lowercase(), d.slug.clone()));
+
+    // Attachments the published documents reference, by id
+    let stored: HashMap<String, Value> = crate::db::list_attachments(db, workspace_id)?
+        .into_iter()
+        .map(|a| (a["id"].as_str().unwrap_or("").to_string(), a))
+        .collect();
+    let mut attachments: BTreeMap<String, (String, String, String)> = BTreeMap::new();
+    for doc in &docs {
+        for id in crate::attachments::references(&doc.body) {
+            if let Some(a) = stored.get(&id) {
+                let filename = a["filename"].as_str().unwrap_or("file");
+                let path = format!("attachments/{}/{}", id, filename);
+                let href = format!("attachments/{}/{}", id, encode_segment(filename));
+                let sha256 = a["sha256"].as_str().unwrap_or("").to_string();
+                attachments.insert(id, (path, href, sha256));
+            }
+        }
+    }
+
+    let page = |root: &str, title: &str, current: Option<&str>, content: String| -> Vec<u8> {
+        let mut nav = String::from("<nav>\n<ul>\n");
+        for doc in &docs {
+            let current = if current == Some(doc.slug.as_str()) {
+                " aria-current=\"page\""
+            } else {
+                ""
+            };
+            nav.push_str(&format!(
+                "<li><a href=\"{}{}.html\"{}>{}</a></li>\n",
+                root,
+                encode_segment(&doc.slug),
+                current,
+                escape_html(&doc.title)
+            ));
+        }
+        nav.push_str(&format!(
+            "</ul>\n<a href=\"{}tags/index.html\">Tags</a>\n</nav>",
+            root
+        ));
+        let slots: HashMap<&str, String> = HashMap::from([
+            ("site_name", escape_html(&site_name)),
+            ("description", escape_html(&description)),
+            ("title", escape_html(title)),
+            ("nav", nav),
+            ("content", content),
+            ("root", root.to_string()),
+        ]);
+        template.render(&slots).into_bytes()
+    };
+
+    let mut pages = Vec::new();
+    let mut tags: BTreeMap<String, (String, Vec<&Doc>)> = BTreeMap::new();
+    let mut search = Vec::new();
+    let published: HashMap<&str, &Doc> = docs.iter().map(|d| (d.slug.as_str(), d)).collect();
+
+    for doc in &docs {
+        let markdown = crate::links::resolve_with(&doc.body, |slug| {
+            published
+                .contains_key(slug)
+                .then(|| format!("{}.html", encode_segment(slug)))
+        });
+        let html = crate::routes::render_markdown_with(&markdown, |dest| {
+            let id = dest.strip_prefix(crate::attachments::SCHEME)?;
+            attachments.get(id).map(|(_, href, _)| href.clone())
+        });
+        let mut content = format!("<h1>{}</h1>\n", escape_html(&doc.title));
+        if !doc.tags.is_empty() {
+            content.push_str(&tag_links("", &doc.tags));
+        }
+        content.push_str(&html);
+        content.push_str(&format!(
+            "<footer>Updated {}</footer>\n",
+            escape_html(&doc.updated_at)
+        ));
+        pages.push((
+            format!("{}.html", doc.slug),
+            page("", &doc.title, Some(&doc.slug), content),
+        ));
+        for tag in &doc.tags {
+            tags.entry(tag_page(tag))
+                .or_insert_with(|| (tag.clone(), Vec::new()))
+                .1
+                .push(doc);
+        }
+        search.push(json!({
+            "slug": doc.slug,
+            "title": doc.title,
+            "summary": doc.summary,
+            "tags": doc.tags,
+            "url": format!("{}.html", encode_segment(&doc.slug)),
+            "text": plain_text(&doc.body),
+        }));
+    }
+
+    let mut index = format!("<h1>{}</h1>\n", escape_html(&site_name));
+    if !description.is_empty() {
+        index.push_str(&format!("<p>{}</p>\n", escape_html(&description)));
+    }
+    index.push_str(&doc_list("", &docs.iter().collect::<Vec<_>>()));
+    pages.push(("index.html".to_string(), page("", &site_name, None, index)));
+
+    let mut tag_index = String::from("<h1>Tags</h1>\n<ul class=\"tags\">\n");
+    for (file, (tag, tagged)) in &tags {
+        tag_index.push_str(&format!(
+            "<li><a href=\"{}.html\">{}</a> ({})</li>\n",
+            file,
+            escape_html(tag),
+            tagged.len()
+        ));
+        let content = format!("<h1>{}</h1>\n{}", escape_html(tag), doc_list("../", tagged));
+        pages.push((
+            format!("tags/{}.html", file),
+            page("../", tag, None, content),
+        ));
+    }
+    tag_index.push_str("</ul>\n");
+    pages.push((
+        "tags/index.html".to_string(),
+        page("../", "Tags", None, tag_index),
+    ));
+
+    pages.push((
+        "search-index.json".to_string(),
+        serde_json::to_vec(&Value::Array(search)).unwrap_or_default(),
+    ));
+    pages.push(("assets/style.css".to_string(), STYLE.as_bytes().to_vec()));
+    pages.push(("assets/search.js".to_string(), SEARCH.as_bytes().to_vec()));
+
+    let attachments = attachments
+        .into_values()
+        .map(|(path, _, sha256)| (path, sha256))
+        .collect();
+    Ok(Site { pages, attachments })
+}
+
+impl Site {
+    /// Write the site under `dir`. Returns the number of files written.
+    pub fn write_dir(&self, blobs: &BlobStore, dir: &Path) -> Result<usize, String> {
+        let mut written = 0;
+        for (path, bytes) in &self.pages {
+            let target = dir.join(path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            std::fs::write(&target, bytes).map_err(|e| e.to_string())?;
+            written += 1;
+        }
+        for (path, sha256) in &self.attachments {
+            let mut blob = match blobs.open(sha256) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            let target = dir.join(path);
+            if let Some(parent) = target.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+            }
+            let mut file = std::fs::File::create(&target).map_err(|e| e.to_string())?;
+            io::copy(&mut blob, &mut file).map_err(|e| e.to_string())?;
+            written += 1;
+        }
+        Ok(written)
+    }
+
+    /// Write the site as a zip archive to `out`.
+    pub fn write_zip<W: Write>(&self, blobs: &BlobStore, out: W) -> Result<(), String> {
+        let mut zip = zip::ZipWriter::new_stream(out);
+        for (path, bytes) in &self.pages {
+            crate::export::add_file(&mut zip, path, bytes)?;
+        }
+        for (path, sha256) in &self.attachments {
+            let mut blob = match blobs.open(sha256) {
+                Ok(file) => file,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.to_string()),
+            };
+            crate::export::start_file(&mut zip, path)?;
+            io::copy(&mut blob, &mut zip).map_err(|e| e.to_string())?;
+        }
+        zip.finish().map_err(|e| format!("zip: {}", e))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_slots_once() {
+        let template = SiteTemplate::parse("<h1>{{ title }}</h1>{{content}}{{other}}").unwrap();
+        let slots = HashMap::from([
+            ("title", "A {{content}}".to_string()),
+            ("content", "<p>x</p>".to_string()),
+        ]);
+        assert_eq!(
+            template.render(&slots),
+            "<h1>A {{content}}</h1><p>x</p>{{other}}"
+        );
+        assert!(SiteTemplate::parse("<main></main>").is_err());
+    }
+
+    #[test]
+    fn strips_markup() {
+        let md = "---\ntitle: X\n---\n# Setup\n\nRun **`make`** then [docs](http://x).\n\n- one\n- [[two|Two]]\n\n<div>html</div>\n";
+        assert_eq!(plain_text(md), "Setup\nRun make then docs.\none\nTwo");
+    }
+}
@@ -0,0 +1,326 @@
+//! Document templates with `{{variable}}` placeholders.
+//!
+//! A placeholder is `{{name}}` or `{{name|default text}}`. Variables can also
+//! be declared on the template with a default and a description; a variable
+//! with no default anywhere is required. `date` and `author_name` are always
+//! available, and `title` holds the new document's title.
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub description: String,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Template {
+    pub slug: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Title for documents created from the template; may use placeholders.
+    #[serde(default)]
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub variables: Vec<Variable>,
+}
+
+/// Variables filled in by the server when the caller does not bind them.
+pub const BUILTIN_VARIABLES: [&str; 3] = ["date", "author_name", "title"];
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '-' || c == '.'
+}
+
+/// A placeholder occurrence: byte range in the text, name, inline default.
+struct Placeholder {
+    start: usize,
+    end: usize,
+    name: String,
+    default: Option<String>,
+}
+
+fn placeholders(text: &str) -> Vec<Placeholder> {
+    let mut found = Vec::new();
+    let mut pos = 0;
+    while let Some(open) = text[pos..].find("{{").map(|i| pos + i) {
+        let Some(close) = text[open + 2..].find("}}").map(|i| open + 2 + i) else {
+            break;
+        };
+        let inner = &text[open + 2..close];
+        let (name, default) = match inner.split_once('|') {
+            Some((n, d)) => (n.trim(), Some(d.trim().to_string())),
+            None => (inner.trim(), None),
+        };
+        if !name.is_empty() && name.chars().all(is_name_char) {
+            found.push(Placeholder {
+                start: open,
+                end: close + 2,
+                name: name.to_string(),
+                default,
+            });
+            pos = close + 2;
+        } else {
+            // Not a placeholder (e.g. literal braces); keep scanning after them
+            pos = open + 2;
+        }
+    }
+    found
+}
+
+impl Template {
+    /// Every variable the template uses: declared ones first, then
+    /// placeholders in the title and content, without duplicates.
+    pub fn all_variables(&self) -> Vec<Variable> {
+        let mut vars = self.variables.clone();
+        for p in placeholders(&self.title)
+            .into_iter()
+            .chain(placeholders(&self.content))
+        {
+            if !vars.iter().any(|v| v.name == p.name) {
+                vars.push(Variable {
+                    name: p.name,
+                    default: p.default,
+                    description: String::new(),
+                });
+            }
+        }
+        vars
+    }
+
+    /// Variables the caller must bind (no default and not built in).
+    pub fn required_variables(&self) -> Vec<String> {
+        self.all_variables()
+            .into_iter()
+            .filter(|v| v.default.is_none() && !BUILTIN_VARIABLES.contains(&v.name.as_str()))
+            .map(|v| v.name)
+            .collect()
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("name is required".to_string());
+        }
+        if self.slug.is_empty() || !self.slug.chars().all(is_name_char) {
+            return Err(format!("invalid slug '{}'", self.slug));
+        }
+        for (i, v) in self.variables.iter().enumerate() {
+            if v.name.is_empty() || !v.name.chars().all(is_name_char) {
+                return Err(format!("invalid variable name '{}'", v.name));
+            }
+            if self.variables[..i].iter().any(|o| o.name == v.name) {
+                return Err(format!("variable '{}' is declared twice", v.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Fill in `text` from `bindings`, then declared defaults, then inline
+    /// defaults. Returns the names of variables that had no value.
+    fn render(
+        &self,
+        text: &str,
+        bindings: &HashMap<String, String>,
+    ) -> Result<String, Vec<String>> {
+        let mut out = String::with_capacity(text.len());
+        let mut missing: Vec<String> = Vec::new();
+        let mut last = 0;
+        for p in placeholders(text) {
+            out.push_str(&text[last..p.start]);
+            let declared = self
+                .variables
+                .iter()
+                .find(|v| v.name == p.name)
+                .and_then(|v| v.default.as_ref());
+            match bindings.get(&p.name).or(declared).or(p.default.as_ref()) {
+                Some(value) => out.push_str(value),
+                None => {
+                    if !missing.contains(&p.name) {
+                        missing.push(p.name.clone());
+                    }
+                }
+            }
+            last = p.end;
+        }
+        out.push_str(&text[last..]);
+        if missing.is_empty() {
+            Ok(out)
+        } else {
+            Err(missing)
+        }
+    }
+
+    /// Title and content for a new document. `title` overrides the
+    /// template's title pattern; either way it is bound as `{{title}}`.
+    pub fn instantiate(
+        &self,
+        title: Option<&str>,
+        bindings: &HashMap<String, String>,
+    ) -> Result<(String, String), Vec<String>> {
+        let mut missing = Vec::new();
+        let title = match title {
+            Some(t) => t.to_string(),
+            None => self.render(&self.title, bindings).unwrap_or_else(|m| {
+                missing.extend(m);
+                String::new()
+            }),
+        };
+        let mut bindings = bindings.clone();
+        bindings
+            .entry("title".to_string())
+            .or_insert_with(|| title.clone());
+        let content = self.render(&self.content, &bindings).unwrap_or_else(|m| {
+            for name in m {
+                if !missing.contains(&name) {
+                    missing.push(name);
+                }
+            }
+            String::new()
+        });
+        if missing.is_empty() {
+            Ok((title.trim().to_string(), content))
+        } else {
+            Err(missing)
+        }
+    }
+}
+
+fn builtin(
+    slug: &str,
+    name: &str,
+    description: &str,
+    title: &str,
+    tags: &[&str],
+    content: &str,
+) -> Template {
+    Template {
+        slug: slug.to_string(),
+        name: name.to_string(),
+        description: description.to_string(),
+        title: title.to_string(),
+        content: content.to_string(),
+        tags: tags.iter().map(|t| t.to_string()).collect(),
+        variables: Vec::new(),
+    }
+}
+
+/// Templates available in every workspace. A workspace template with the
+/// same slug takes precedence; `builtin:<slug>` always names these.
+pub fn builtins() -> Vec<Template> {
+    vec![
+        builtin(
+            "adr",
+            "Architecture Decision Record",
+            "Record one architectural decision: context, decision, consequences.",
+            "ADR-{{number}}: {{decision}}",
+            &["adr"],
+            "# {{title}}\n\n\
+             - **Status:** {{status|Proposed}}\n\
+             - **Date:** {{date}}\n\
+             - **Author:** {{author_name}}\n\n\
+             ## Context\n\n{{context|What is the issue motivating this decision?}}\n\n\
+             ## Decision\n\n{{decision}}\n\n\
+             ## Consequences\n\n{{consequences|What becomes easier or harder because of this change?}}\n",
+        ),
+        builtin(
+            "incident-report",
+            "Incident Report",
+            "Post-incident write-up with timeline, impact and follow-ups.",
+            "Incident: {{summary}} ({{date}})",
+            &["incident"],
+            "# {{title}}\n\n\
+             - **Severity:** {{severity|SEV-3}}\n\
+             - **Detected:** {{detected_at|unknown}}\n\
+             - **Resolved:** {{resolved_at|ongoing}}\n\
+             - **Reported by:** {{author_name}}\n\n\
+             ## Summary\n\n{{summary}}\n\n\
+             ## Impact\n\n{{impact|Who or what was affected, and for how long?}}\n\n\
+             ## Timeline\n\n- {{detected_at|unknown}} — detected\n\n\
+             ## Root Cause\n\n{{root_cause|Under investigation.}}\n\n\
+             ## Follow-ups\n\n- [ ] \n",
+        ),
+        builtin(
+            "run-log",
+            "Run Log",
+            "Log of an agent or job run: goal, steps, results.",
+            "Run log: {{task}} ({{date}})",
+            &["run-log"],
+            "# {{title}}\n\n\
+             - **Agent:** {{author_name}}\n\
+             - **Date:** {{date}}\n\
+             - **Outcome:** {{outcome|in progress}}\n\n\
+             ## Goal\n\n{{task}}\n\n\
+             ## Steps\n\n1. \n\n\
+             ## Results\n\n{{results|Pending.}}\n",
+        ),
+        builtin(
+            "meeting-notes",
+            "Meeting Notes",
+            "Attendees, agenda, decisions and action items.",
+            "Meeting notes: {{topic}} ({{date}})",
+            &["meeting"],
+            "# {{title}}\n\n\
+             **Attendees:** {{attendees|}}\n\n\
+             ## Agenda\n\n{{agenda|- }}\n\n\
+             ## Decisions\n\n- \n\n\
+             ## Action Items\n\n- [ ] \n",
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bind(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn renders_bindings_and_defaults() {
+        let t = Template {
+            slug: "t".to_string(),
+            name: "T".to_string(),
+            description: String::new(),
+            title: "Report {{n}}".to_string(),
+            content: "# {{ title }}\nOwner: {{owner|nobody}}\nTeam: {{team}}\n{{ not a var }}"
+                .to_string(),
+            tags: Vec::new(),
+            variables: vec![Variable {
+                name: "team".to_string(),
+                default: Some("core".to_string()),
+                description: String::new(),
+            }],
+        };
+        assert_eq!(t.required_variables(), vec!["n"]);
+        let (title, content) = t.instantiate(None, &bind(&[("n", "7")])).unwrap();
+        assert_eq!(title, "Report 7");
+        assert_eq!(
+            content,
+            "# Report 7\nOwner: nobody\nTeam: core\n{{ not a var }}"
+        );
+        assert_eq!(t.instantiate(None, &HashMap::new()).unwrap_err(), vec!["n"]);
+        // An explicit title skips the title pattern
+        assert!(t.instantiate(Some("Mine"), &HashMap::new()).is_ok());
+    }
+
+    #[test]
+    fn builtins_are_valid_and_need_their_variables() {
+        for t in builtins() {
+            assert!(t.validate().is_ok(), "{}", t.slug);
+        }
+        let adr = builtins().into_iter().find(|t| t.slug == "adr").unwrap();
+        assert_eq!(adr.required_variables(), vec!["number", "decision"]);
+    }
+}
@@ -0,0 +1,282 @@
+use crate::db::{Db, PendingDelivery};
+use crate::events::{type_matches, EventBus, SseEvent};
+use hmac::{Hmac, Mac};
+use rocket::fairing::AdHoc;
+use rocket::serde::json::json;
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::Notify;
+
+/// Header carrying `sha256=<hex HMAC of the raw body>`.
+pub const SIGNATURE_HEADER: &str = "X-AgentDocs-Signature";
+pub const EVENT_HEADER: &str = "X-AgentDocs-Event";
+pub const DELIVERY_HEADER: &str = "X-AgentDocs-Delivery";
+
+/// Retry policy for outbound deliveries.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: i32,
+    pub base_delay_secs: u64,
+}
+
+impl RetryPolicy {
+    pub fn from_env() -> Self {
+        let max_attempts = std::env::var("WEBHOOK_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5);
+        let base_delay_secs = std::env::var("WEBHOOK_RETRY_BASE_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(30);
+        RetryPolicy {
+            max_attempts,
+            base_delay_secs,
+        }
+    }
+
+    /// Seconds to wait before the next attempt, given how many attempts have
+    /// already been made (including the one that just failed), or None when
+    /// the delivery should be given up. Doubles each time: base, 2×base, 4×base…
+    pub fn next_delay(&self, attempts_made: i32) -> Option<u64> {
+        if attempts_made >= self.max_attempts {
+            return None;
+        }
+        let exp = (attempts_made - 1).clamp(0, 16) as u32;
+        Some(self.base_delay_secs.saturating_mul(1u64 << exp))
+    }
+}
+
+/// Sign a payload with the webhook secret: `sha256=<hex>`.
+pub fn sign(secret: &str, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Generate a webhook signing secret.
+pub fn generate_secret() -> String {
+    format!(
+        "whsec_{}",
+        uuid::Uuid::new_v4().to_string().replace("-", "")
+    )
+}
+
+/// Queue a delivery of `evt` for every active webhook in its workspace whose
+/// event filter matches. An empty filter subscribes to everything.
+pub fn enqueue(db: &Db, evt: &SseEvent) -> Result<usize, String> {
+    let hooks = crate::db::active_webhook_filters(db, &evt.workspace_id)?;
+    let payload = json!({
+        "event": evt.event_type,
+        "workspace_id": evt.workspace_id,
+        "data": evt.data,
+        "timestamp": chrono::Utc::now().to_rfc3339(),
+    })
+    .to_string();
+
+    let mut queued = 0;
+    for (webhook_id, patterns) in hooks {
+        if !patterns.is_empty() && !patterns.iter().any(|p| type_matches(p, &evt.event_type)) {
+            continue;
+        }
+        let id = uuid::Uuid::new_v4().to_string();
+        crate::db::create_delivery(db, &id, &webhook_id, &evt.event_type, &payload)?;
+        queued += 1;
+    }
+    Ok(queued)
+}
+
+async fn attempt(
+    client: &reqwest::Client,
+    delivery: &PendingDelivery,
+) -> Result<u16, (Option<u16>, String)> {
+    let res = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header("User-Agent", "agent-docs-webhooks/0.1")
+        .header(EVENT_HEADER, &delivery.event_type)
+        .header(DELIVERY_HEADER, &delivery.id)
+        .header(SIGNATURE_HEADER, sign(&delivery.secret, &delivery.payload))
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+
+    let status = res.status().as_u16();
+    if res.status().is_success() {
+        Ok(status)
+    } else {
+        Err((Some(status), format!("HTTP {}", status)))
+    }
+}
+
+/// Send every delivery that is due, recording each outcome.
+pub async fn process_due(db: &Db, client: &reqwest::Client, policy: &RetryPolicy) {
+    let due = match crate::db::due_deliveries(db, 50) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("⚠️  Webhook queue error: {}", e);
+            return;
+        }
+    };
+
+    for delivery in due {
+        let result = match attempt(client, &delivery).await {
+            Ok(status) => {
+                crate::db::record_delivery_attempt(db, &delivery.id, true, Some(status), None, None)
+            }
+            Err((status, error)) => {
+                let retry = policy.next_delay(delivery.attempts + 1);
+                crate::db::record_delivery_attempt(
+                    db,
+                    &delivery.id,
+                    false,
+                    status,
+                    Some(&error),
+                    retry,
+                )
+            }
+        };
+        if let Err(e) = result {
+            eprintln!(
+                "⚠️  Failed to record webhook delivery {} ({}): {}",
+                delivery.id, delivery.webhook_id, e
+            );
+        }
+    }
+}
+
+/// Queue deliveries for everything journaled after `cursor`, advancing it.
+/// Only touches the database, so it never waits on a receiver.
+fn enqueue_journaled(db: &Db, cursor: &mut i64) -> usize {
+    let mut queued = 0;
+    loop {
+        let batch = match crate::db::activity_after(db, *cursor, 500) {
+            Ok(batch) => batch,
+            Err(e) => {
+                eprintln!("⚠️  Failed to read activity for webhooks: {}", e);
+                return queued;
+            }
+        };
+        let Some((last, _)) = batch.last() else {
+            return queued;
+        };
+        let last = *last;
+        for (_, evt) in &batch {
+            match enqueue(db, evt) {
+                Ok(n) => queued += n,
+                Err(e) => eprintln!("⚠️  Failed to queue webhook deliveries: {}", e),
+            }
+        }
+        *cursor = last;
+    }
+}
+
+/// Background worker. Deliveries are queued from the activity log, with bus
+/// events only as a wake-up, so events the receiver lagged past are still
+/// queued. A separate task sends whatever is due, so a slow receiver never
+/// holds up queueing.
+pub async fn run_worker(db: Db, event_bus: EventBus, policy: RetryPolicy) {
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .build()
+        .expect("Failed to build webhook HTTP client");
+    let mut rx = event_bus.subscribe();
+    let mut cursor = crate::db::last_activity_seq(&db).unwrap_or(0);
+    let wake = Arc::new(Notify::new());
+
+    let sender = {
+        let db = db.clone();
+        let wake = wake.clone();
+        tokio::spawn(async move {
+            let mut poll = tokio::time::interval(Duration::from_secs(1));
+            loop {
+                tokio::select! {
+                    _ = wake.notified() => {}
+                    _ = poll.tick() => {}
+                }
+                process_due(&db, &client, &policy).await;
+            }
+        })
+    };
+
+    let mut poll = tokio::time::interval(Duration::from_secs(1));
+    loop {
+        tokio::select! {
+            msg = rx.recv() => match msg {
+                Ok(_) => {}
+                Err(RecvError::Lagged(n)) => {
+                    eprintln!("⚠️  Webhook worker lagged {} events; catching up from the activity log", n);
+                }
+                Err(RecvError::Closed) => break,
+            },
+            _ = poll.tick() => {}
+        }
+        if enqueue_journaled(&db, &mut cursor) > 0 {
+            wake.notify_one();
+        }
+    }
+    sender.abort();
+}
+
+/// Fairing that starts the delivery worker once Rocket has launched.
+pub fn fairing() -> AdHoc {
+    AdHoc::on_liftoff("Webhook delivery worker", |rocket| {
+        Box::pin(async move {
+            let (Some(db), Some(bus)) = (rocket.state::<Db>(), rocket.state::<EventBus>()) else {
+                return;
+            };
+            tokio::spawn(run_worker(db.clone(), bus.clone(), RetryPolicy::from_env()));
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_then_gives_up() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            base_delay_secs: 10,
+        };
+        assert_eq!(policy.next_delay(1), Some(10));
+        assert_eq!(policy.next_delay(2), Some(20));
+        assert_eq!(policy.next_delay(3), Some(40));
+        assert_eq!(policy.next_delay(4), None);
+    }
+
+    #[test]
+    fn queues_from_the_activity_log() {
+        let db = Db::new(":memory:");
+        crate::db::create_workspace(&db, "ws", "WS", "", "hash", false).unwrap();
+        crate::db::create_webhook(&db, "hook", "ws", "http://127.0.0.1:9/", "[]", "s").unwrap();
+        let bus = EventBus::with_journal(db.clone());
+        let mut cursor = crate::db::last_activity_seq(&db).unwrap();
+        // Emitted with no receiver listening, as if the worker had lagged
+        for i in 0..3 {
+            bus.emit("ws", "document.created", json!({"id": i}));
+        }
+        assert_eq!(enqueue_journaled(&db, &mut cursor), 3);
+        assert_eq!(enqueue_journaled(&db, &mut cursor), 0);
+        assert_eq!(
+            crate::db::list_deliveries(&db, "hook", 10, 0)
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn signature_is_stable_hmac() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign("Jefe", "what do ya want for nothing?"),
+            "sha256=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+}
@@ -0,0 +1,264 @@
+//! Per-workspace document status workflow.
+//!
+//! Workspaces without a workflow keep free-form `status` strings. Once one is
+//! configured, document statuses must be one of `states`, changes must follow
+//! `transitions`, and states listed in `approval.before` can only be entered
+//! after enough reviewers approved the current version.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct ApprovalRule {
+    /// Names allowed to approve or reject. Empty means anyone with the manage key.
+    #[serde(default)]
+    pub reviewers: Vec<String>,
+    /// Distinct approvals needed on the current version.
+    #[serde(default = "default_required")]
+    pub required: usize,
+    /// States that cannot be entered until the approvals are in.
+    #[serde(default)]
+    pub before: Vec<String>,
+    /// State a rejection sends the document back to (default: `initial`).
+    #[serde(default)]
+    pub reject_to: Option<String>,
+}
+
+fn default_required() -> usize {
+    1
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct Workflow {
+    pub states: Vec<String>,
+    /// State for new documents (default: the first state).
+    #[serde(default)]
+    pub initial: Option<String>,
+    /// Allowed moves: state → states it may change to.
+    pub transitions: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub approval: Option<ApprovalRule>,
+}
+
+/// Why a status change was refused.
+#[derive(Debug, PartialEq)]
+pub enum TransitionError {
+    UnknownState(String),
+    NotAllowed {
+        from: String,
+        to: String,
+    },
+    ApprovalsRequired {
+        to: String,
+        have: usize,
+        need: usize,
+    },
+}
+
+impl TransitionError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            TransitionError::UnknownState(_) => "INVALID_STATUS",
+            TransitionError::NotAllowed { .. } => "INVALID_TRANSITION",
+            TransitionError::ApprovalsRequired { .. } => "APPROVALS_REQUIRED",
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            TransitionError::UnknownState(s) => format!("'{}' is not a state in this workflow", s),
+            TransitionError::NotAllowed { from, to } => {
+                format!("Cannot move from '{}' to '{}'", from, to)
+            }
+            TransitionError::ApprovalsRequired { to, have, need } => format!(
+                "'{}' needs {} approval(s) of the current version; have {}",
+                to, need, have
+            ),
+        }
+    }
+}
+
+impl Workflow {
+    /// draft → in_review → approved → published → archived, with one approval
+    /// required before `approved`.
+    pub fn preset() -> Self {
+        let t = |from: &str, to: &[&str]| {
+            (
+                from.to_string(),
+                to.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+            )
+        };
+        Workflow {
+            states: ["draft", "in_review", "approved", "published", "archived"]
+                .iter()
+                .map(|s| s.to_string())
+                .collect(),
+            initial: Some("draft".to_string()),
+            transitions: [
+                t("draft", &["in_review", "archived"]),
+                t("in_review", &["draft", "approved"]),
+                t("approved", &["published", "draft"]),
+                t("published", &["archived", "draft"]),
+                t("archived", &["draft"]),
+            ]
+            .into_iter()
+            .collect(),
+            approval: Some(ApprovalRule {
+                reviewers: Vec::new(),
+                required: 1,
+                before: vec!["approved".to_string()],
+                reject_to: Some("draft".to_string()),
+            }),
+        }
+    }
+
+    pub fn initial_state(&self) -> &str {
+        self.initial
+            .as_deref()
+            .or(self.states.first().map(|s| s.as_str()))
+            .unwrap_or("draft")
+    }
+
+    pub fn reject_state(&self) -> &str {
+        self.approval
+            .as_ref()
+            .and_then(|a| a.reject_to.as_deref())
+            .unwrap_or_else(|| self.initial_state())
+    }
+
+    /// Check the workflow is internally consistent.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.states.is_empty() {
+            return Err("workflow needs at least one state".to_string());
+        }
+        let known = |s: &str| self.states.iter().any(|x| x == s);
+        if !known(self.initial_state()) {
+            return Err(format!(
+                "initial state '{}' is not in states",
+                self.initial_state()
+            ));
+        }
+        for (from, tos) in &self.transitions {
+            if let Some(bad) = std::iter::once(from).chain(tos).find(|s| !known(s)) {
+                return Err(format!("transition uses unknown state '{}'", bad));
+            }
+        }
+        if let Some(rule) = &self.approval {
+            if let Some(bad) = rule.before.iter().find(|s| !known(s)) {
+                return Err(format!("approval.before uses unknown state '{}'", bad));
+            }
+            if !known(self.reject_state()) {
+                return Err(format!(
+                    "approval.reject_to '{}' is not in states",
+                    self.reject_state()
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn needs_approval(&self, to: &str) -> bool {
+        self.approval
+            .as_ref()
+            .is_some_and(|a| a.required > 0 && a.before.iter().any(|s| s == to))
+    }
+
+    pub fn required_approvals(&self) -> usize {
+        self.approval.as_ref().map(|a| a.required).unwrap_or(0)
+    }
+
+    pub fn is_reviewer(&self, name: &str) -> bool {
+        match &self.approval {
+            Some(a) if !a.reviewers.is_empty() => a.reviewers.iter().any(|r| r == name),
+            _ => true,
+        }
+    }
+
+    /// Check a status change. `approvals` is the number of distinct reviewers
+    /// who approved the current version.
+    pub fn check_transition(
+        &self,
+        from: &str,
+        to: &str,
+        approvals: usize,
+    ) -> Result<(), TransitionError> {
+        if !self.states.iter().any(|s| s == to) {
+            return Err(TransitionError::UnknownState(to.to_string()));
+        }
+        if from == to {
+            return Ok(());
+        }
+        // Documents created before the workflow may hold a status outside it;
+        // let them move to any state.
+        let allowed = match self.transitions.get(from) {
+            Some(tos) => tos.iter().any(|s| s == to),
+            None => !self.states.iter().any(|s| s == from),
+        };
+        if !allowed {
+            return Err(TransitionError::NotAllowed {
+                from: from.to_string(),
+                to: to.to_string(),
+            });
+        }
+        // Leaving a gated state for anything but `reject_to` also needs the
+        // approvals, so content edited after approval cannot skip review.
+        let gated =
+            self.needs_approval(to) || (self.needs_approval(from) && to != self.reject_state());
+        if gated && approvals < self.required_approvals() {
+            return Err(TransitionError::ApprovalsRequired {
+                to: to.to_string(),
+                have: approvals,
+                need: self.required_approvals(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preset_is_valid_and_gates_approval() {
+        let wf = Workflow::preset();
+        assert!(wf.validate().is_ok());
+        assert!(wf.check_transition("draft", "in_review", 0).is_ok());
+        assert_eq!(
+            wf.check_transition("draft", "published", 5)
+                .unwrap_err()
+                .code(),
+            "INVALID_TRANSITION"
+        );
+        assert_eq!(
+            wf.check_transition("in_review", "approved", 0)
+                .unwrap_err()
+                .code(),
+            "APPROVALS_REQUIRED"
+        );
+        assert!(wf.check_transition("in_review", "approved", 1).is_ok());
+        // An edit after approval leaves the new version unapproved
+        assert_eq!(
+            wf.check_transition("approved", "published", 0)
+                .unwrap_err()
+                .code(),
+            "APPROVALS_REQUIRED"
+        );
+        assert!(wf.check_transition("approved", "published", 1).is_ok());
+        assert!(wf.check_transition("approved", "draft", 0).is_ok());
+        assert_eq!(
+            wf.check_transition("draft", "bogus", 0).unwrap_err().code(),
+            "INVALID_STATUS"
+        );
+        // Legacy statuses may move anywhere
+        assert!(wf.check_transition("wip", "draft", 0).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_unknown_states() {
+        let mut wf = Workflow::preset();
+        wf.transitions
+            .insert("draft".to_string(), vec!["nowhere".to_string()]);
+        assert!(wf.validate().is_err());
+    }
+}
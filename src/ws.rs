@@ -0,0 +1,272 @@
+use crate::db::Db;
+use crate::events::{EventBus, EventFilter};
+use crate::routes::LockOp;
+use rocket::data::{IoHandler, IoStream};
+use rocket::futures::{SinkExt, StreamExt};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use rocket::serde::json::{json, Json, Value};
+use rocket::tokio::select;
+use rocket::tokio::time::{interval, Duration};
+use rocket::Shutdown;
+use std::io;
+use std::pin::Pin;
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+
+/// `Sec-WebSocket-Key` of a request asking to upgrade to WebSocket.
+/// Fails (so `Option<WebSocketKey>` is None) for ordinary HTTP requests.
+pub struct WebSocketKey(pub String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocketKey {
+    type Error = &'static str;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let wants_ws = req
+            .headers()
+            .get_one("Upgrade")
+            .is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+        match req.headers().get_one("Sec-WebSocket-Key") {
+            Some(key) if wants_ws && !key.trim().is_empty() => {
+                Outcome::Success(WebSocketKey(key.trim().to_string()))
+            }
+            _ => Outcome::Error((
+                rocket::http::Status::UpgradeRequired,
+                "Not a WebSocket request",
+            )),
+        }
+    }
+}
+
+/// One client's WebSocket session within a workspace.
+pub struct Session {
+    pub db: Db,
+    pub event_bus: EventBus,
+    pub workspace_id: String,
+    pub filter: EventFilter,
+    /// Whether the connection presented the workspace manage key (required for lock ops).
+    pub authorized: bool,
+    pub shutdown: Shutdown,
+}
+
+/// Responder that completes the WebSocket handshake and hands the
+/// upgraded connection to the session.
+pub struct Channel {
+    pub key: WebSocketKey,
+    pub session: Session,
+}
+
+impl<'r> Responder<'r, 'static> for Channel {
+    fn respond_to(self, _req: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .raw_header(
+                "Sec-WebSocket-Accept",
+                derive_accept_key(self.key.0.as_bytes()),
+            )
+            .upgrade("websocket", self.session)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for Session {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let ws = WebSocketStream::from_raw_socket(io, Role::Server, None).await;
+        Pin::into_inner(self).run(ws).await;
+        Ok(())
+    }
+}
+
+// Helper: read a string array field (a bare string counts as one item)
+fn string_list(msg: &Value, field: &str) -> Vec<String> {
+    match msg.get(field) {
+        Some(Value::Array(items)) => items
+            .iter()
+            .filter_map(|v| v.as_str())
+            .map(|s| s.to_string())
+            .collect(),
+        Some(Value::String(s)) => vec![s.clone()],
+        _ => Vec::new(),
+    }
+}
+
+impl Session {
+    async fn run(mut self, ws: WebSocketStream<IoStream>) {
+        let (mut sink, mut stream) = ws.split();
+        let mut rx = self.event_bus.subscribe();
+        let mut heartbeat = interval(Duration::from_secs(15));
+        let mut shutdown = self.shutdown.clone();
+
+        let welcome = json!({
+            "type": "welcome",
+            "workspace_id": self.workspace_id,
+            "authorized": self.authorized,
+            "subscription": self.subscription(),
+        });
+        if sink.send(Message::Text(welcome.to_string())).await.is_err() {
+            return;
+        }
+
+        loop {
+            let outgoing = select! {
+                msg = stream.next() => match msg {
+                    Some(Ok(Message::Text(text))) => Message::Text(self.handle_message(&text).to_string()),
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => continue, // ping/pong/binary
+                },
+                evt = rx.recv() => match evt {
+                    Ok(evt) if evt.workspace_id == self.workspace_id => {
+                        if !self.filter.matches_in(&self.db, &evt).await {
+                            continue;
+                        }
+                        let frame = json!({"type": "event", "event": evt.event_type, "data": evt.data});
+                        Message::Text(frame.to_string())
+                    }
+                    Ok(_) => continue, // Different workspace, skip
+                    Err(RecvError::Lagged(n)) => {
+                        Message::Text(json!({"type": "system", "warning": format!("Missed {} events", n)}).to_string())
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+                _ = heartbeat.tick() => Message::Ping(Vec::new()),
+                _ = &mut shutdown => {
+                    let _ = sink.send(Message::Text(json!({"type": "system", "message": "Server shutting down"}).to_string())).await;
+                    let _ = sink.send(Message::Close(None)).await;
+                    break;
+                }
+            };
+
+            if sink.send(outgoing).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    fn subscription(&self) -> Value {
+        json!({
+            "types": self.filter.types,
+            "docs": self.filter.document_ids,
+            "tags": self.filter.tags,
+        })
+    }
+
+    /// Handle one client message and build the reply frame.
+    pub fn handle_message(&mut self, text: &str) -> Value {
+        let msg: Value = match serde_json::from_str(text) {
+            Ok(v) => v,
+            Err(_) => {
+                return json!({"type": "error", "error": "Invalid JSON message", "code": "INVALID_MESSAGE"})
+            }
+        };
+        let op = msg.get("type").and_then(|v| v.as_str()).unwrap_or("");
+        let msg_id = msg.get("id").cloned().unwrap_or(Value::Null);
+
+        let result = match op {
+            "ping" => Ok(json!({})),
+            "subscribe" => {
+                for (list, field) in [
+                    (&mut self.filter.document_ids, "docs"),
+                    (&mut self.filter.types, "types"),
+                    (&mut self.filter.tags, "tags"),
+                ] {
+                    for item in string_list(&msg, field) {
+                        if !list.contains(&item) {
+                            list.push(item);
+                        }
+                    }
+                }
+                Ok(json!({"subscription": self.subscription()}))
+            }
+            "unsubscribe" => {
+                for (list, field) in [
+                    (&mut self.filter.document_ids, "docs"),
+                    (&mut self.filter.types, "types"),
+                    (&mut self.filter.tags, "tags"),
+                ] {
+                    let remove = string_list(&msg, field);
+                    list.retain(|item| !remove.contains(item));
+                }
+                Ok(json!({"subscription": self.subscription()}))
+            }
+            "presence" => self.presence(&msg),
+            "lock.acquire" | "lock.renew" | "lock.release" => self.lock_op(op, &msg),
+            _ => Err((format!("Unknown message type '{}'", op), "UNKNOWN_TYPE")),
+        };
+
+        match result {
+            Ok(mut reply) => {
+                reply["type"] = json!(if op == "ping" { "pong" } else { "ack" });
+                reply["op"] = json!(op);
+                reply["id"] = msg_id;
+                reply["ok"] = json!(true);
+                reply
+            }
+            Err((error, code)) => json!({
+                "type": "error",
+                "op": op,
+                "id": msg_id,
+                "ok": false,
+                "error": error,
+                "code": code,
+            }),
+        }
+    }
+
+    fn presence(&self, msg: &Value) -> Result<Value, (String, &'static str)> {
+        let name = match msg.get("name").and_then(|v| v.as_str()) {
+            Some(n) if !n.trim().is_empty() => n.trim(),
+            _ => return Err(("name is required".to_string(), "VALIDATION_ERROR")),
+        };
+        let document_id = msg.get("document_id").and_then(|v| v.as_str());
+        let status = msg
+            .get("status")
+            .and_then(|v| v.as_str())
+            .unwrap_or("active");
+
+        self.event_bus.emit(
+            &self.workspace_id,
+            "presence.ping",
+            json!({"name": name, "document_id": document_id, "status": status}),
+        );
+        Ok(json!({}))
+    }
+
+    fn lock_op(&self, op: &str, msg: &Value) -> Result<Value, (String, &'static str)> {
+        if !self.authorized {
+            return Err((
+                "Lock operations require the manage key on connect".to_string(),
+                "UNAUTHORIZED",
+            ));
+        }
+        let doc_ref = match msg.get("document_id").and_then(|v| v.as_str()) {
+            Some(d) => d,
+            None => return Err(("document_id is required".to_string(), "VALIDATION_ERROR")),
+        };
+        let op = match op {
+            "lock.acquire" => LockOp::Acquire,
+            "lock.renew" => LockOp::Renew,
+            _ => LockOp::Release,
+        };
+        crate::routes::apply_lock(
+            &self.db,
+            &self.event_bus,
+            &self.workspace_id,
+            doc_ref,
+            op,
+            msg.get("editor").and_then(|v| v.as_str()),
+            msg.get("ttl_seconds").and_then(|v| v.as_i64()),
+        )
+        .map_err(|(status, Json(err))| {
+            let code = match status.code {
+                404 => "NOT_FOUND",
+                409 => "LOCK_CONFLICT",
+                _ => "INTERNAL_ERROR",
+            };
+            (err["error"].as_str().unwrap_or_default().to_string(), code)
+        })
+    }
+}
@@ -1,6 +1,10 @@
 use rocket::http::{ContentType, Status};
 use rocket::local::blocking::Client;
 use serde_json::Value;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 fn test_client() -> Client {
     let db = agent_docs::db::Db::new(":memory:");
@@ -136,7 +140,7 @@ fn test_create_and_get_document() {
     assert_eq!(res.status(), Status::Ok);
     let body: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
     assert_eq!(body["title"], "Hello World");
-    assert!(body["content_html"].as_str().unwrap().contains("<h1>"));
+    assert!(body["content_html"].as_str().unwrap().contains("<h1 id=\"hello\">"));
 }
 
 #[test]
@@ -211,6 +215,7 @@ fn test_update_document_creates_version() {
 }
 
 #[test]
+#[allow(clippy::len_zero)]
 fn test_diff_versions() {
     let client = test_client();
     let ws = create_workspace(&client, "Diff WS");
@@ -678,6 +683,303 @@ fn test_comment_update_content() {
     assert_eq!(res.status(), Status::Unauthorized);
 }
 
+#[test]
+fn test_comment_ownership_and_membership() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Comment Auth WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let doc = create_doc(&client, ws_id, key, "Auth Doc", "Content");
+    let doc_id = doc["id"].as_str().unwrap();
+    let other_doc = create_doc(&client, ws_id, key, "Other Doc", "Content");
+    let other_doc_id = other_doc["id"].as_str().unwrap();
+    let other_ws = create_workspace(&client, "Other WS");
+    let other_ws_id = other_ws["id"].as_str().unwrap();
+    let other_key = other_ws["manage_key"].as_str().unwrap();
+
+    let comments_url = format!("/api/v1/workspaces/{}/docs/{}/comments", ws_id, doc_id);
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Agent1", "content": "First take"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let comment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let comment_id = comment["id"].as_str().unwrap();
+    let edit_token = comment["edit_token"].as_str().unwrap().to_string();
+
+    // A document from another workspace can't be commented through this one
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments",
+            other_ws_id, doc_id
+        ))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Agent1", "content": "Sneaky"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+
+    // Replies must stay on the parent's document
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments",
+            ws_id, other_doc_id
+        ))
+        .header(ContentType::JSON)
+        .body(format!(
+            r#"{{"author_name": "Agent2", "content": "Reply", "parent_id": "{}"}}"#,
+            comment_id
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+
+    // The comment is not reachable through another document or workspace,
+    // even with that workspace's key
+    let res = client
+        .patch(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments/{}",
+            ws_id, other_doc_id, comment_id
+        ))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"resolved": true}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+    let res = client
+        .delete(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments/{}",
+            other_ws_id, doc_id, comment_id
+        ))
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", other_key),
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+
+    // The author edits with their token, but can't resolve or use a wrong one
+    let comment_url = format!("{}/{}", comments_url, comment_id);
+    let res = client
+        .patch(&comment_url)
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "X-Comment-Token",
+            edit_token.clone(),
+        ))
+        .body(r#"{"content": "Second take"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client
+        .patch(&comment_url)
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "X-Comment-Token",
+            edit_token.clone(),
+        ))
+        .body(r#"{"resolved": true}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+    let res = client
+        .delete(format!("{}?comment_token=adoc_c_wrong", comment_url))
+        .dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+
+    let res = client.get(&comments_url).dispatch();
+    let comments: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(comments[0]["content"], "Second take");
+    assert!(comments[0].get("edit_token").is_none());
+
+    let res = client
+        .delete(format!("{}?comment_token={}", comment_url, edit_token))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    // Disable anonymous commenting
+    let res = client
+        .patch(format!("/api/v1/workspaces/{}", ws_id))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"allow_anonymous_comments": false}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client
+        .get(format!("/api/v1/workspaces/{}", ws_id))
+        .dispatch();
+    let info: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(info["allow_anonymous_comments"], false);
+
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Anon", "content": "Hello?"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let err: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(err["code"], "ANONYMOUS_COMMENTS_DISABLED");
+    let res = client
+        .post(comments_url.replace("/comments", "/suggestions"))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Anon", "content": "Rewritten"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .header(auth)
+        .body(r#"{"author_name": "Member", "content": "Hello"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+}
+
+#[test]
+fn test_comment_spam_protection() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Spam WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let doc = create_doc(&client, ws_id, key, "Spam Doc", "Content");
+    let doc_id = doc["id"].as_str().unwrap();
+    let policy_url = format!("/api/v1/workspaces/{}/comment-policy", ws_id);
+    let comments_url = format!("/api/v1/workspaces/{}/docs/{}/comments", ws_id, doc_id);
+
+    let res = client.get(&policy_url).dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res = client
+        .put(&policy_url)
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"blocked_patterns": ["(unclosed"]}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+    let res = client
+        .put(&policy_url)
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(
+            r#"{"max_length": 40, "max_depth": 1, "blocked_words": ["casino"],
+                "blocked_patterns": ["(?i)free\\s+money"], "rate_limit_per_document": 6}"#,
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let body: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(body["policy"]["rate_limit_per_ip"], 30);
+
+    let post = |body: String, with_key: bool| {
+        let mut req = client
+            .post(&comments_url)
+            .header(ContentType::JSON)
+            .body(body);
+        if with_key {
+            req = req.header(auth.clone());
+        }
+        let res = req.dispatch();
+        let status = res.status();
+        let value: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        (status, value)
+    };
+    let anon =
+        |content: &str| serde_json::json!({"author_name": "Anon", "content": content}).to_string();
+
+    let (status, err) = post(anon(&"x".repeat(41)), false);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(err["code"], "COMMENT_TOO_LONG");
+    let (status, err) = post(anon("Visit my Casino"), false);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(err["code"], "COMMENT_BLOCKED");
+    let (status, _) = post(anon("FREE   money inside"), false);
+    assert_eq!(status, Status::UnprocessableEntity);
+    // Moderators are trusted with the word list
+    let (status, _) = post(anon("The casino chapter is fine"), true);
+    assert_eq!(status, Status::Created);
+
+    // Thread depth
+    let (status, root) = post(anon("Root"), false);
+    assert_eq!(status, Status::Created);
+    let reply = |parent: &Value| {
+        serde_json::json!({"author_name": "Anon", "content": "Reply", "parent_id": parent["id"]})
+            .to_string()
+    };
+    let (status, child) = post(reply(&root), false);
+    assert_eq!(status, Status::Created);
+    let (status, err) = post(reply(&child), false);
+    assert_eq!(status, Status::UnprocessableEntity);
+    assert_eq!(err["code"], "THREAD_TOO_DEEP");
+
+    // Six anonymous attempts on this document so far: the next is throttled
+    let (status, err) = post(anon("One more"), false);
+    assert_eq!(status, Status::TooManyRequests);
+    assert_eq!(err["code"], "RATE_LIMIT_EXCEEDED");
+    let (status, _) = post(anon("Moderators are not throttled"), true);
+    assert_eq!(status, Status::Created);
+
+    // Suggestions are held to the same limits and word list
+    let suggest = |content: &str, with_key: bool| {
+        let mut req = client
+            .post(comments_url.replace("/comments", "/suggestions"))
+            .header(ContentType::JSON)
+            .body(serde_json::json!({"author_name": "Anon", "content": content}).to_string());
+        if with_key {
+            req = req.header(auth.clone());
+        }
+        req.dispatch().status()
+    };
+    assert_eq!(suggest("A fresh take", false), Status::TooManyRequests);
+    assert_eq!(suggest("The casino chapter", true), Status::Created);
+
+    // Token edits are held to the same word list
+    let res = client
+        .patch(format!("{}/{}", comments_url, root["id"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "X-Comment-Token",
+            root["edit_token"].as_str().unwrap().to_string(),
+        ))
+        .body(r#"{"content": "casino"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+
+    // Moderation queue
+    let res = client
+        .put(&policy_url)
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"hold_anonymous": true}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let (status, held) = post(anon("Please approve me"), false);
+    assert_eq!(status, Status::Accepted);
+    assert_eq!(held["moderation_status"], "pending");
+    let held_id = held["id"].as_str().unwrap();
+
+    let count_public = || {
+        let res = client.get(&comments_url).dispatch();
+        let list: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        list.as_array().unwrap().len()
+    };
+    assert_eq!(count_public(), 4);
+    let res = client.get(&comments_url).header(auth.clone()).dispatch();
+    let list: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(list.as_array().unwrap().len(), 5);
+
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/comments/pending", ws_id))
+        .header(auth.clone())
+        .dispatch();
+    let queue: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(queue["count"], 1);
+    assert_eq!(queue["comments"][0]["id"], held_id);
+
+    let approve_url = format!("{}/{}/approve", comments_url, held_id);
+    let res = client.post(&approve_url).header(auth.clone()).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(count_public(), 5);
+    let res = client.post(&approve_url).header(auth).dispatch();
+    assert_eq!(res.status(), Status::Conflict);
+}
+
 #[test]
 fn test_429_json_catcher() {
     std::env::set_var("WORKSPACE_RATE_LIMIT", "1");
@@ -965,3 +1267,3256 @@ fn test_lock_release_then_reacquire() {
         .dispatch();
     assert_eq!(res.status(), Status::Ok);
 }
+
+#[rocket::async_test]
+async fn test_sse_filtered_stream_delivery() {
+    use rocket::local::asynchronous::Client;
+    use rocket::tokio::io::AsyncReadExt;
+
+    let client = Client::tracked(agent_docs::build_rocket(agent_docs::db::Db::new(
+        ":memory:",
+    )))
+    .await
+    .unwrap();
+    let res = client
+        .post("/api/v1/workspaces")
+        .header(ContentType::JSON)
+        .body(r#"{"name": "SSE Delivery WS"}"#)
+        .dispatch()
+        .await;
+    let ws: Value = serde_json::from_str(&res.into_string().await.unwrap()).unwrap();
+    let ws_id = ws["id"].as_str().unwrap();
+    let auth = rocket::http::Header::new(
+        "Authorization",
+        format!("Bearer {}", ws["manage_key"].as_str().unwrap()),
+    );
+    let base = format!("/api/v1/workspaces/{}", ws_id);
+
+    let mut stream = client
+        .get(format!("{}/events/stream?types=document.*&tags=spec", base))
+        .dispatch()
+        .await;
+    assert_eq!(stream.status(), Status::Ok);
+
+    let post = |path: String, body: &'static str| {
+        client
+            .post(path)
+            .header(ContentType::JSON)
+            .header(auth.clone())
+            .body(body)
+            .dispatch()
+    };
+    // Tagged document: in. Untagged document and a comment: out.
+    let res = post(
+        format!("{}/docs", base),
+        r#"{"title": "Spec Doc", "content": "a", "tags": ["spec"], "status": "published"}"#,
+    )
+    .await;
+    let spec: Value = serde_json::from_str(&res.into_string().await.unwrap()).unwrap();
+    post(
+        format!("{}/docs", base),
+        r#"{"title": "Other Doc", "content": "b", "status": "published"}"#,
+    )
+    .await;
+    post(
+        format!("{}/docs/spec-doc/comments", base),
+        r#"{"author_name": "Ana", "content": "nice"}"#,
+    )
+    .await;
+    let res = client
+        .patch(format!("{}/docs/spec-doc", base))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"content": "a2"}"#)
+        .dispatch()
+        .await;
+    assert_eq!(res.status(), Status::Ok);
+
+    // Read until the update to the tagged document arrives
+    let mut received = String::new();
+    let mut buf = [0u8; 4096];
+    while !received.contains("event:document.updated") {
+        let n = rocket::tokio::time::timeout(Duration::from_secs(5), stream.read(&mut buf))
+            .await
+            .expect("filtered event is delivered")
+            .unwrap();
+        assert!(n > 0, "stream ended early: {}", received);
+        received.push_str(&String::from_utf8_lossy(&buf[..n]));
+    }
+    assert!(received.contains("event:document.created"));
+    assert!(received.contains(spec["id"].as_str().unwrap()));
+    assert!(!received.contains("other-doc"), "{}", received);
+    assert!(!received.contains("comment.created"), "{}", received);
+}
+
+#[test]
+fn test_sse_filters_and_document_stream() {
+    let client = test_client();
+    let ws = create_workspace(&client, "SSE Filter WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+
+    let doc = create_doc(&client, ws_id, key, "Streamed Doc", "Content");
+    let doc_id = doc["id"].as_str().unwrap();
+
+    // Filtered workspace stream
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/events/stream?types=document.*,comment.created&docs={}&tags=spec",
+            ws_id, doc_id
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    // Per-document stream, with the same type and tag filters
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/docs/{}/events/stream?types=comment.*&tags=spec",
+            ws_id, doc_id
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    // Per-document stream from another workspace is 404
+    let other = create_workspace(&client, "Other SSE WS");
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/docs/{}/events/stream",
+            other["id"].as_str().unwrap(),
+            doc_id
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+// --- Webhooks ---
+
+/// A request captured by the local webhook receiver: (headers lowercased, body).
+type Captured = Arc<Mutex<Vec<(Vec<(String, String)>, String)>>>;
+
+/// Minimal HTTP stand-in: replies with the queued statuses in order (200 once exhausted).
+fn spawn_webhook_receiver(statuses: Vec<u16>) -> (String, Captured) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/hook", listener.local_addr().unwrap());
+    let captured: Captured = Arc::new(Mutex::new(Vec::new()));
+    let sink = captured.clone();
+    std::thread::spawn(move || {
+        let mut statuses = statuses.into_iter();
+        for stream in listener.incoming() {
+            let mut stream = match stream {
+                Ok(s) => s,
+                Err(_) => break,
+            };
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut headers = Vec::new();
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            loop {
+                line.clear();
+                reader.read_line(&mut line).unwrap();
+                let trimmed = line.trim_end();
+                if trimmed.is_empty() {
+                    break;
+                }
+                if let Some((k, v)) = trimmed.split_once(':') {
+                    headers.push((k.trim().to_lowercase(), v.trim().to_string()));
+                }
+            }
+            let len: usize = headers
+                .iter()
+                .find(|(k, _)| k == "content-length")
+                .and_then(|(_, v)| v.parse().ok())
+                .unwrap_or(0);
+            let mut body = vec![0u8; len];
+            reader.read_exact(&mut body).unwrap();
+            sink.lock()
+                .unwrap()
+                .push((headers, String::from_utf8(body).unwrap()));
+
+            let status = statuses.next().unwrap_or(200);
+            let _ = write!(
+                stream,
+                "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                status
+            );
+        }
+    });
+    (url, captured)
+}
+
+fn wait_for<F: FnMut() -> bool>(mut cond: F) -> bool {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while Instant::now() < deadline {
+        if cond() {
+            return true;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+    false
+}
+
+fn register_webhook(client: &Client, ws_id: &str, key: &str, body: String) -> Value {
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/webhooks", ws_id))
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", key),
+        ))
+        .body(body)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    serde_json::from_str(&res.into_string().unwrap()).unwrap()
+}
+
+fn list_deliveries(client: &Client, ws_id: &str, key: &str, hook_id: &str) -> Vec<Value> {
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/webhooks/{}/deliveries?key={}",
+            ws_id, hook_id, key
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    serde_json::from_str(&res.into_string().unwrap()).unwrap()
+}
+
+#[test]
+fn test_webhook_signed_delivery() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Webhook WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let (url, captured) = spawn_webhook_receiver(vec![]);
+
+    let hook = register_webhook(
+        &client,
+        ws_id,
+        key,
+        format!(
+            r#"{{"url": "{}", "events": ["document.*"], "secret": "s3cret"}}"#,
+            url
+        ),
+    );
+    assert_eq!(hook["secret"], "s3cret");
+    let hook_id = hook["id"].as_str().unwrap();
+
+    // Listing requires the manage key and never exposes the secret
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/webhooks", ws_id))
+        .dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/webhooks?key={}", ws_id, key))
+        .dispatch();
+    let hooks: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(hooks.as_array().unwrap().len(), 1);
+    assert!(hooks[0].get("secret").is_none());
+
+    let doc = create_doc(&client, ws_id, key, "Hooked Doc", "Content");
+    assert!(wait_for(|| captured.lock().unwrap().len() == 1));
+
+    let (headers, body) = captured.lock().unwrap()[0].clone();
+    let header = |name: &str| {
+        headers
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.clone())
+            .unwrap_or_default()
+    };
+    assert_eq!(header("x-agentdocs-event"), "document.created");
+    assert_eq!(
+        header("x-agentdocs-signature"),
+        agent_docs::webhooks::sign("s3cret", &body)
+    );
+    let payload: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(payload["event"], "document.created");
+    assert_eq!(payload["data"]["id"], doc["id"]);
+
+    // Comments don't match the document.* filter
+    client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments",
+            ws_id,
+            doc["id"].as_str().unwrap()
+        ))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Agent1", "content": "Not delivered"}"#)
+        .dispatch();
+
+    assert!(wait_for(|| {
+        let log = list_deliveries(&client, ws_id, key, hook_id);
+        log.len() == 1 && log[0]["status"] == "delivered"
+    }));
+    assert_eq!(captured.lock().unwrap().len(), 1);
+}
+
+#[test]
+fn test_webhook_retry_and_redeliver() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Webhook Retry WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let (url, captured) = spawn_webhook_receiver(vec![500]);
+
+    let hook = register_webhook(&client, ws_id, key, format!(r#"{{"url": "{}"}}"#, url));
+    let hook_id = hook["id"].as_str().unwrap();
+    assert!(hook["secret"].as_str().unwrap().starts_with("whsec_"));
+
+    create_doc(&client, ws_id, key, "Flaky Doc", "Content");
+
+    // First attempt fails and is scheduled for a retry with backoff
+    let mut failed = Value::Null;
+    assert!(wait_for(|| {
+        let log = list_deliveries(&client, ws_id, key, hook_id);
+        if log.len() == 1 && log[0]["attempts"] == 1 {
+            failed = log[0].clone();
+            return true;
+        }
+        false
+    }));
+    assert_eq!(failed["status"], "pending");
+    assert_eq!(failed["response_status"], 500);
+
+    // Manual redelivery queues a new delivery that succeeds
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/webhooks/{}/deliveries/{}/redeliver",
+            ws_id,
+            hook_id,
+            failed["id"].as_str().unwrap()
+        ))
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", key),
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::Accepted);
+
+    assert!(wait_for(|| {
+        list_deliveries(&client, ws_id, key, hook_id)
+            .iter()
+            .any(|d| d["status"] == "delivered")
+    }));
+    assert_eq!(captured.lock().unwrap().len(), 2);
+}
+
+// --- WebSocket ---
+
+#[rocket::async_test]
+async fn test_websocket_session() {
+    use rocket::futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    // Seed a workspace + document directly, then serve on a real port (upgrades need a socket)
+    let db = agent_docs::db::Db::new(":memory:");
+    let key = agent_docs::auth::generate_key();
+    agent_docs::db::create_workspace(
+        &db,
+        "ws-1",
+        "WS",
+        "",
+        &agent_docs::auth::hash_key(&key),
+        true,
+    )
+    .unwrap();
+    agent_docs::db::create_document(
+        &db,
+        "doc-1",
+        "ws-1",
+        "Doc",
+        "doc",
+        "x",
+        "<p>x</p>",
+        "",
+        "[]",
+        "published",
+        "A",
+        1,
+        "{}",
+        None,
+        "{}",
+    )
+    .unwrap();
+
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let config = rocket::Config {
+        port,
+        address: std::net::Ipv4Addr::LOCALHOST.into(),
+        log_level: rocket::config::LogLevel::Off,
+        ..rocket::Config::debug_default()
+    };
+    rocket::tokio::spawn(agent_docs::build_rocket(db).configure(config).launch());
+
+    let url = format!(
+        "ws://127.0.0.1:{}/api/v1/workspaces/ws-1/ws?key={}",
+        port, key
+    );
+    let mut socket = None;
+    for _ in 0..100 {
+        if let Ok((s, _)) = tokio_tungstenite::connect_async(url.as_str()).await {
+            socket = Some(s);
+            break;
+        }
+        rocket::tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+    let mut socket = socket.expect("websocket connects");
+
+    async fn next_json(
+        socket: &mut tokio_tungstenite::WebSocketStream<
+            tokio_tungstenite::MaybeTlsStream<rocket::tokio::net::TcpStream>,
+        >,
+    ) -> Value {
+        loop {
+            match socket.next().await.unwrap().unwrap() {
+                Message::Text(t) => return serde_json::from_str(&t).unwrap(),
+                _ => continue,
+            }
+        }
+    }
+
+    let welcome = next_json(&mut socket).await;
+    assert_eq!(welcome["type"], "welcome");
+    assert_eq!(welcome["authorized"], true);
+
+    socket
+        .send(Message::Text(
+            r#"{"type": "subscribe", "id": 1, "docs": ["doc-1"]}"#.into(),
+        ))
+        .await
+        .unwrap();
+    let ack = next_json(&mut socket).await;
+    assert_eq!(ack["type"], "ack");
+    assert_eq!(ack["id"], 1);
+    assert_eq!(ack["subscription"]["docs"][0], "doc-1");
+
+    // Lock over the socket: ack, then the broadcast event for the subscribed doc
+    socket
+        .send(Message::Text(
+            r#"{"type": "lock.acquire", "id": 2, "document_id": "doc-1", "editor": "Agent1"}"#
+                .into(),
+        ))
+        .await
+        .unwrap();
+    let ack = next_json(&mut socket).await;
+    assert_eq!(ack["ok"], true);
+    assert_eq!(ack["locked_by"], "Agent1");
+    let evt = next_json(&mut socket).await;
+    assert_eq!(evt["type"], "event");
+    assert_eq!(evt["event"], "lock.acquired");
+
+    // Conflicting lock is reported as an error frame
+    socket
+        .send(Message::Text(
+            r#"{"type": "lock.acquire", "id": 3, "document_id": "doc-1", "editor": "Agent2"}"#
+                .into(),
+        ))
+        .await
+        .unwrap();
+    let err = next_json(&mut socket).await;
+    assert_eq!(err["type"], "error");
+    assert_eq!(err["code"], "LOCK_CONFLICT");
+
+    // Presence pings are broadcast as events
+    socket
+        .send(Message::Text(
+            r#"{"type": "presence", "name": "Agent1", "document_id": "doc-1"}"#.into(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(next_json(&mut socket).await["type"], "ack");
+    let evt = next_json(&mut socket).await;
+    assert_eq!(evt["event"], "presence.ping");
+    assert_eq!(evt["data"]["name"], "Agent1");
+
+    // Documents resolve by slug too, and TTLs are clamped like the REST routes
+    socket
+        .send(Message::Text(
+            r#"{"type": "lock.release", "id": 4, "document_id": "doc"}"#.into(),
+        ))
+        .await
+        .unwrap();
+    assert_eq!(next_json(&mut socket).await["ok"], true);
+    assert_eq!(next_json(&mut socket).await["event"], "lock.released");
+    socket
+        .send(Message::Text(
+            r#"{"type": "lock.acquire", "id": 5, "document_id": "doc", "editor": "Agent2", "ttl_seconds": 99999999999}"#
+                .into(),
+        ))
+        .await
+        .unwrap();
+    let ack = next_json(&mut socket).await;
+    assert_eq!(ack["ok"], true);
+    assert_eq!(ack["document_id"], "doc-1");
+    assert_eq!(ack["ttl_seconds"], 3600);
+}
+
+#[test]
+fn test_websocket_requires_upgrade() {
+    let client = test_client();
+    let ws = create_workspace(&client, "WS Upgrade");
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/ws",
+            ws["id"].as_str().unwrap()
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::UpgradeRequired);
+
+    let res = client.get("/api/v1/workspaces/nonexistent/ws").dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+#[test]
+fn test_rich_event_payloads() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Event Payload WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let (url, captured) = spawn_webhook_receiver(vec![]);
+    register_webhook(
+        &client,
+        ws_id,
+        key,
+        format!(
+            r#"{{"url": "{}", "events": ["document.updated", "workspace.updated", "comment.updated"]}}"#,
+            url
+        ),
+    );
+
+    let doc = create_doc(&client, ws_id, key, "Event Doc", "one two\\nthree");
+    let doc_id = doc["id"].as_str().unwrap();
+
+    let res = client
+        .patch(format!("/api/v1/workspaces/{}/docs/{}", ws_id, doc_id))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"content": "one two\nfour five six", "change_description": "Expand", "author_name": "Editor"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let body: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(body["version_number"], 2);
+
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/versions/1/restore",
+            ws_id, doc_id
+        ))
+        .header(auth.clone())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    client
+        .patch(format!("/api/v1/workspaces/{}", ws_id))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"description": "Now described"}"#)
+        .dispatch();
+
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments",
+            ws_id, doc_id
+        ))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Agent1", "content": "Hmm"}"#)
+        .dispatch();
+    let comment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    client
+        .patch(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments/{}",
+            ws_id,
+            doc_id,
+            comment["id"].as_str().unwrap()
+        ))
+        .header(ContentType::JSON)
+        .header(auth)
+        .body(r#"{"resolved": true}"#)
+        .dispatch();
+
+    assert!(wait_for(|| captured.lock().unwrap().len() == 4));
+    let events: Vec<Value> = captured
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, body)| serde_json::from_str(body).unwrap())
+        .collect();
+    let find = |pred: &dyn Fn(&Value) -> bool| events.iter().find(|e| pred(e)).cloned().unwrap();
+
+    let update =
+        find(&|e| e["event"] == "document.updated" && e["data"]["restored_from_version"].is_null());
+    let data = &update["data"];
+    assert_eq!(data["version_number"], 2);
+    assert_eq!(data["change_description"], "Expand");
+    assert_eq!(data["author_name"], "Editor");
+    assert_eq!(data["word_count_delta"], 2);
+    assert_eq!(data["stats"]["insertions"], 1);
+    assert_eq!(data["stats"]["removals"], 1);
+    assert_eq!(data["changed"], serde_json::json!(["content"]));
+    assert!(data["diff"].as_str().unwrap().contains("+four five six"));
+
+    let restore = find(&|e| e["data"]["restored_from_version"] == 1);
+    assert_eq!(restore["data"]["version_number"], 3);
+    assert_eq!(restore["data"]["word_count_delta"], -2);
+
+    let ws_update = find(&|e| e["event"] == "workspace.updated");
+    assert_eq!(ws_update["data"]["description"], "Now described");
+    assert_eq!(
+        ws_update["data"]["changed"],
+        serde_json::json!(["description"])
+    );
+
+    let comment_update = find(&|e| e["event"] == "comment.updated");
+    assert_eq!(comment_update["data"]["document_id"], doc_id);
+    assert_eq!(comment_update["data"]["resolved"], true);
+}
+
+#[test]
+fn test_anchored_comments_follow_edits() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Anchor WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let doc = create_doc(
+        &client,
+        ws_id,
+        key,
+        "Anchor Doc",
+        "Intro.\\nThe cache is slow.\\nBye.",
+    );
+    let doc_id = doc["id"].as_str().unwrap();
+    let comments_url = format!("/api/v1/workspaces/{}/docs/{}/comments", ws_id, doc_id);
+
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Reviewer", "content": "Why?", "anchor": {"quote": "cache is slow"}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let created: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(created["anchor"]["start"], 11);
+    assert_eq!(created["anchor"]["end"], 24);
+    assert_eq!(created["anchor"]["version_number"], 1);
+    assert_eq!(created["anchor"]["prefix"], "Intro.\nThe ");
+
+    // Offsets alone pick the quote out of the document
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Reviewer", "content": "Drop this", "anchor": {"start": 26, "end": 30}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let bye: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(bye["anchor"]["quote"], "Bye.");
+
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Reviewer", "content": "?", "anchor": {"quote": "not there"}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+
+    // Edit before the first quote and delete the second
+    let res = client
+        .patch(format!("/api/v1/workspaces/{}/docs/{}", ws_id, doc_id))
+        .header(ContentType::JSON)
+        .header(auth)
+        .body(r#"{"content": "A much longer introduction.\nThe cache is slow.\n"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    let res = client.get(&comments_url).dispatch();
+    let comments: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let find = |id: &Value| {
+        comments
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["id"] == *id)
+            .unwrap()
+            .clone()
+    };
+    let moved = find(&created["id"]);
+    assert_eq!(moved["anchor"]["status"], "anchored");
+    assert_eq!(moved["anchor"]["start"], 32);
+    assert_eq!(moved["anchor"]["version_number"], 2);
+    let orphaned = find(&bye["id"]);
+    assert_eq!(orphaned["anchor"]["status"], "orphaned");
+    assert_eq!(orphaned["anchor"]["version_number"], 1);
+
+    // Anchoring against an old version maps onto the current text
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Reviewer", "content": "Old", "anchor": {"quote": "cache", "version_number": 1}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let old: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(old["anchor"]["start"], 32);
+    assert_eq!(old["anchor"]["version_number"], 2);
+}
+
+#[test]
+fn test_suggestions_accept_and_reject() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Suggestion WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let doc = create_doc(&client, ws_id, key, "Suggest Doc", "The API is fast.");
+    let doc_id = doc["id"].as_str().unwrap();
+    let base = format!("/api/v1/workspaces/{}/docs/{}", ws_id, doc_id);
+
+    let res = client
+        .post(format!("{}/suggestions", base))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Outsider", "description": "Be precise", "anchor": {"quote": "fast"}, "replacement": "fast (p99 < 50ms)"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let range: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(range["kind"], "range");
+    assert_eq!(range["status"], "pending");
+    assert_eq!(range["base_version"], 1);
+
+    let res = client
+        .post(format!("{}/suggestions", base))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Outsider", "content": "Rewritten.", "base_version": 1}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let whole: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let res = client
+        .post(format!("{}/suggestions", base))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Outsider", "content": "Nope."}"#)
+        .dispatch();
+    let rejected: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+
+    // The document moves on before review
+    let res = client
+        .patch(&base)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "Intro. The API is fast."}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    // Accepting needs the manage key
+    let accept_range = format!(
+        "{}/suggestions/{}/accept",
+        base,
+        range["id"].as_str().unwrap()
+    );
+    let res = client.post(&accept_range).dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+
+    let res = client
+        .post(&accept_range)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"reviewed_by": "Editor"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let accepted: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(accepted["version_number"], 3);
+
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/docs/suggest-doc", ws_id))
+        .dispatch();
+    let current: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(current["content"], "Intro. The API is fast (p99 < 50ms).");
+    let res = client.get(format!("{}/versions/3", base)).dispatch();
+    let v3: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(v3["author_name"], "Outsider");
+    assert_eq!(v3["change_description"], "Accepted suggestion: Be precise");
+
+    // Reviewing twice conflicts
+    let res = client.post(&accept_range).header(auth()).dispatch();
+    assert_eq!(res.status(), Status::Conflict);
+
+    // Whole-content suggestions against an old base need force
+    let accept_whole = format!(
+        "{}/suggestions/{}/accept",
+        base,
+        whole["id"].as_str().unwrap()
+    );
+    let res = client.post(&accept_whole).header(auth()).dispatch();
+    assert_eq!(res.status(), Status::Conflict);
+    let res = client
+        .post(&accept_whole)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"force": true}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    let res = client
+        .post(format!(
+            "{}/suggestions/{}/reject",
+            base,
+            rejected["id"].as_str().unwrap()
+        ))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"reviewed_by": "Editor", "reason": "Out of scope"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    let res = client
+        .get(format!("{}/suggestions?status=rejected", base))
+        .dispatch();
+    let list: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(list.as_array().unwrap().len(), 1);
+    assert_eq!(list[0]["review_reason"], "Out of scope");
+    let res = client
+        .get(format!("{}/suggestions?status=accepted", base))
+        .dispatch();
+    let list: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(list.as_array().unwrap().len(), 2);
+    assert_eq!(list[0]["applied_version"], 3);
+}
+
+#[test]
+fn test_status_workflow_and_approvals() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Workflow WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+
+    let res = client
+        .put(format!("/api/v1/workspaces/{}/workflow", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"states": ["draft", "review", "published"], "transitions": {"draft": ["nowhere"]}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+
+    let res = client
+        .put(format!("/api/v1/workspaces/{}/workflow", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(
+            r#"{"states": ["draft", "review", "published"],
+                "transitions": {"draft": ["review"], "review": ["draft", "published"], "published": ["draft"]},
+                "approval": {"reviewers": ["alice", "bob"], "required": 2, "before": ["published"]}}"#,
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    // Documents start in the initial state; gated states are refused
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/docs", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Gated", "content": "Body", "status": "published"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Conflict);
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/docs", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Flow Doc", "content": "Body"}"#)
+        .dispatch();
+    let doc: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(doc["status"], "draft");
+    let doc_url = format!(
+        "/api/v1/workspaces/{}/docs/{}",
+        ws_id,
+        doc["id"].as_str().unwrap()
+    );
+    let set_status = |status: &str| {
+        client
+            .patch(&doc_url)
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(format!(r#"{{"status": "{}"}}"#, status))
+            .dispatch()
+            .status()
+    };
+    let review = |reviewer: &str, body: &str| {
+        client
+            .post(format!("{}/approvals", doc_url))
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(format!(r#"{{"reviewer": "{}", {}}}"#, reviewer, body))
+            .dispatch()
+    };
+
+    assert_eq!(set_status("published"), Status::UnprocessableEntity);
+    assert_eq!(set_status("bogus"), Status::UnprocessableEntity);
+    assert_eq!(set_status("review"), Status::Ok);
+    assert_eq!(set_status("published"), Status::Conflict);
+
+    assert_eq!(
+        review("mallory", r#""decision": "approve""#).status(),
+        Status::Forbidden
+    );
+    assert_eq!(
+        review("alice", r#""decision": "approve""#).status(),
+        Status::Created
+    );
+    assert_eq!(set_status("published"), Status::Conflict);
+
+    // Rejection needs a comment and sends the document back
+    assert_eq!(
+        review("bob", r#""decision": "reject""#).status(),
+        Status::BadRequest
+    );
+    let res = review("bob", r#""decision": "reject", "comment": "Needs numbers""#);
+    assert_eq!(res.status(), Status::Created);
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/docs/flow-doc", ws_id))
+        .dispatch();
+    let current: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(current["status"], "draft");
+
+    assert_eq!(set_status("review"), Status::Ok);
+    assert_eq!(
+        review("bob", r#""comment": "Fine now""#).status(),
+        Status::Created
+    );
+    let res = client.get(format!("{}/approvals", doc_url)).dispatch();
+    let summary: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(summary["approvals"], 2);
+    assert_eq!(summary["satisfied"], true);
+    assert_eq!(summary["history"].as_array().unwrap().len(), 3);
+    assert_eq!(set_status("published"), Status::Ok);
+
+    // A new version needs fresh approvals
+    assert_eq!(set_status("draft"), Status::Ok);
+    let res = client
+        .patch(&doc_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "Edited", "status": "review"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(set_status("published"), Status::Conflict);
+
+    // Removing the workflow restores free-form statuses
+    let res = client
+        .put(format!("/api/v1/workspaces/{}/workflow", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"workflow": null}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(set_status("anything"), Status::Ok);
+}
+
+#[test]
+fn test_edit_after_approval_needs_new_approval() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Approved Edit WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let res = client
+        .put(format!("/api/v1/workspaces/{}/workflow", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"preset": "default"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/docs", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Policy", "content": "Approved text"}"#)
+        .dispatch();
+    let doc: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let doc_url = format!(
+        "/api/v1/workspaces/{}/docs/{}",
+        ws_id,
+        doc["id"].as_str().unwrap()
+    );
+    let patch = |body: &str| {
+        client
+            .patch(&doc_url)
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(body)
+            .dispatch()
+    };
+
+    assert_eq!(patch(r#"{"status": "in_review"}"#).status(), Status::Ok);
+    let res = client
+        .post(format!("{}/approvals", doc_url))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"reviewer": "alice"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    assert_eq!(patch(r#"{"status": "approved"}"#).status(), Status::Ok);
+
+    // The edited version was never approved, so it cannot be published
+    assert_eq!(
+        patch(r#"{"content": "Unreviewed text"}"#).status(),
+        Status::Ok
+    );
+    let res = patch(r#"{"status": "published"}"#);
+    assert_eq!(res.status(), Status::Conflict);
+    let err: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(err["code"], "APPROVALS_REQUIRED");
+    let res = patch(r#"{"content": "Unreviewed again", "status": "published"}"#);
+    assert_eq!(res.status(), Status::Conflict);
+
+    // Approving the current version lets it through
+    let res = client
+        .post(format!("{}/approvals", doc_url))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"reviewer": "alice"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    assert_eq!(patch(r#"{"status": "published"}"#).status(), Status::Ok);
+}
+
+#[test]
+fn test_reactions_and_votes() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Reaction WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let doc = create_doc(&client, ws_id, key, "Vote Doc", "Proposal");
+    let doc_url = format!(
+        "/api/v1/workspaces/{}/docs/{}",
+        ws_id,
+        doc["id"].as_str().unwrap()
+    );
+
+    let res = client
+        .post(format!("{}/comments", doc_url))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Agent1", "content": "Use SQLite"}"#)
+        .dispatch();
+    let comment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let reactions_url = format!(
+        "{}/comments/{}/reactions",
+        doc_url,
+        comment["id"].as_str().unwrap()
+    );
+    let react = |url: &str, author: &str, reaction: &str| {
+        client
+            .post(url)
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"author_name": "{}", "reaction": "{}"}}"#,
+                author, reaction
+            ))
+            .dispatch()
+            .status()
+    };
+
+    assert_eq!(react(&reactions_url, "A", "+1"), Status::Created);
+    assert_eq!(react(&reactions_url, "A", "+1"), Status::Ok); // idempotent
+    assert_eq!(react(&reactions_url, "B", "+1"), Status::Created);
+    assert_eq!(react(&reactions_url, "C", "-1"), Status::Created);
+    assert_eq!(react(&reactions_url, "C", "🎉"), Status::Created);
+    assert_eq!(react(&reactions_url, "D", "has space"), Status::BadRequest);
+    // Voting the other way replaces the earlier vote
+    assert_eq!(react(&reactions_url, "B", "-1"), Status::Created);
+
+    let res = client.get(format!("{}/comments", doc_url)).dispatch();
+    let comments: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(comments[0]["reactions"]["+1"]["count"], 1);
+    assert_eq!(
+        comments[0]["reactions"]["-1"]["authors"],
+        serde_json::json!(["C", "B"])
+    );
+    assert_eq!(comments[0]["reactions"]["🎉"]["count"], 1);
+    assert_eq!(comments[0]["vote_score"], -1);
+
+    let res = client
+        .delete(format!("{}?author_name=B&reaction=%2D1", reactions_url))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client
+        .delete(format!("{}?author_name=B&reaction=%2D1", reactions_url))
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+
+    // Document reactions show up in get_document
+    assert_eq!(
+        react(&format!("{}/reactions", doc_url), "A", "needs-info"),
+        Status::Created
+    );
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/docs/vote-doc", ws_id))
+        .dispatch();
+    let fetched: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(fetched["reactions"]["needs-info"]["count"], 1);
+    assert_eq!(fetched["vote_score"], 0);
+
+    // Unknown comments are rejected
+    assert_eq!(
+        react(&format!("{}/comments/nope/reactions", doc_url), "A", "+1"),
+        Status::NotFound
+    );
+}
+
+#[test]
+fn test_mentions_inbox() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Mention WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let doc = create_doc(
+        &client,
+        ws_id,
+        key,
+        "Mention Doc",
+        "Draft for @bob, ping @TestAgent",
+    );
+    let doc_id = doc["id"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let inbox = |name: &str, query: &str| -> Value {
+        let res = client
+            .get(format!(
+                "/api/v1/workspaces/{}/inbox?for={}{}",
+                ws_id, name, query
+            ))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    // Authors are not notified about themselves
+    assert_eq!(
+        inbox("TestAgent", "")["notifications"]
+            .as_array()
+            .unwrap()
+            .len(),
+        0
+    );
+    let bob = inbox("bob", "");
+    assert_eq!(bob["unread_count"], 1);
+    assert_eq!(bob["notifications"][0]["document_id"], doc_id);
+    assert_eq!(
+        bob["notifications"][0]["excerpt"],
+        "Draft for @bob, ping @TestAgent"
+    );
+
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments",
+            ws_id, doc_id
+        ))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "alice", "content": "@Bob and @carol please look. `@ignored`"}"#)
+        .dispatch();
+    let comment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let bob = inbox("BOB", "");
+    assert_eq!(bob["unread_count"], 2);
+    assert_eq!(bob["notifications"][0]["comment_id"], comment["id"]);
+    assert_eq!(bob["notifications"][0]["author_name"], "alice");
+    assert_eq!(inbox("carol", "")["unread_count"], 1);
+    assert_eq!(inbox("ignored", "")["unread_count"], 0);
+
+    // Editing only notifies newly added mentions
+    let res = client
+        .patch(format!("/api/v1/workspaces/{}/docs/{}", ws_id, doc_id))
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", key),
+        ))
+        .body(r#"{"content": "Draft for @bob and @dave", "author_name": "alice"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(inbox("bob", "")["unread_count"], 2);
+    assert_eq!(inbox("dave", "")["unread_count"], 1);
+
+    // Read state needs the manage key
+    let first = bob["notifications"][0]["id"].as_str().unwrap().to_string();
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/inbox/read", ws_id))
+        .header(ContentType::JSON)
+        .body(r#"{"for": "bob"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/inbox/read", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(format!(r#"{{"for": "bob", "ids": ["{}"]}}"#, first))
+        .dispatch();
+    let marked: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(marked["updated"], 1);
+    let unread = inbox("bob", "&unread=true");
+    assert_eq!(unread["unread_count"], 1);
+    assert_eq!(unread["notifications"].as_array().unwrap().len(), 1);
+
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/inbox/read", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"for": "bob"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(inbox("bob", "")["unread_count"], 0);
+
+    // Mentions in unpublished documents only show with the key
+    let res = client
+        .patch(format!("/api/v1/workspaces/{}/docs/{}", ws_id, doc_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"status": "draft"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(inbox("dave", "")["unread_count"], 0);
+    assert_eq!(
+        inbox("dave", "")["notifications"].as_array().unwrap().len(),
+        0
+    );
+    let dave = inbox("dave", &format!("&key={}", key));
+    assert_eq!(dave["unread_count"], 1);
+    assert_eq!(
+        dave["notifications"][0]["excerpt"],
+        "Draft for @bob and @dave"
+    );
+
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/inbox", ws_id))
+        .dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+}
+
+#[test]
+fn test_comment_html_is_escaped() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Escape WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let doc = create_doc(&client, ws_id, key, "Escape Doc", "Body");
+    let comments_url = format!(
+        "/api/v1/workspaces/{}/docs/{}/comments",
+        ws_id,
+        doc["id"].as_str().unwrap()
+    );
+    let content =
+        "<script>alert(1)</script>\n\nHi <img src=x onerror=alert(2)> [me](javascript:alert(3))";
+    let res = client
+        .post(&comments_url)
+        .header(ContentType::JSON)
+        .body(serde_json::json!({"author_name": "Anon", "content": content}).to_string())
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+
+    let res = client.get(&comments_url).dispatch();
+    let comments: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let html = comments[0]["content_html"].as_str().unwrap();
+    assert!(
+        html.contains("&lt;script&gt;alert(1)&lt;/script&gt;"),
+        "{}",
+        html
+    );
+    assert!(
+        html.contains("&lt;img src=x onerror=alert(2)&gt;"),
+        "{}",
+        html
+    );
+    assert!(
+        !html.contains("<script") && !html.contains("<img"),
+        "{}",
+        html
+    );
+    assert!(!html.contains("javascript:"), "{}", html);
+    assert_eq!(comments[0]["content"], content);
+}
+
+#[test]
+fn test_threaded_comments() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Thread WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let doc = create_doc(&client, ws_id, key, "Thread Doc", "Body");
+    let comments_url = format!(
+        "/api/v1/workspaces/{}/docs/{}/comments",
+        ws_id,
+        doc["id"].as_str().unwrap()
+    );
+    let post = |content: &str, parent: Option<&str>| -> String {
+        let parent = parent
+            .map(|p| format!(r#", "parent_id": "{}""#, p))
+            .unwrap_or_default();
+        let res = client
+            .post(&comments_url)
+            .header(ContentType::JSON)
+            .body(format!(
+                r#"{{"author_name": "A", "content": "{}"{}}}"#,
+                content, parent
+            ))
+            .dispatch();
+        let c: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        c["id"].as_str().unwrap().to_string()
+    };
+    let get = |query: &str| -> Value {
+        let res = client.get(format!("{}{}", comments_url, query)).dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    let first = post("**First** thread", None);
+    let reply = post("reply", Some(&first));
+    post("nested reply", Some(&reply));
+    let second = post("Second thread", None);
+    client
+        .patch(format!("{}/{}", comments_url, second))
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", key),
+        ))
+        .body(r#"{"resolved": true}"#)
+        .dispatch();
+
+    // Flat listing stays an array, now with rendered markdown
+    let flat = get("");
+    assert_eq!(flat.as_array().unwrap().len(), 4);
+    assert_eq!(
+        flat[0]["content_html"],
+        "<p><strong>First</strong> thread</p>\n"
+    );
+
+    let tree = get("?threaded=true");
+    assert_eq!(tree["total"], 2);
+    assert_eq!(tree["threads"][0]["id"], first.as_str());
+    assert_eq!(tree["threads"][0]["reply_count"], 2);
+    assert_eq!(tree["threads"][0]["replies"][0]["depth"], 1);
+    assert_eq!(tree["threads"][0]["replies"][0]["replies"][0]["depth"], 2);
+
+    let open = get("?threaded=true&resolved=false");
+    assert_eq!(open["total"], 1);
+    assert_eq!(open["threads"][0]["id"], first.as_str());
+    assert_eq!(get("?resolved=true").as_array().unwrap().len(), 1);
+
+    let page = get("?threaded=true&limit=1&offset=1");
+    assert_eq!(page["threads"].as_array().unwrap().len(), 1);
+    assert_eq!(page["threads"][0]["id"], second.as_str());
+
+    // A new reply makes the first thread the most active
+    std::thread::sleep(Duration::from_millis(1100));
+    post("late reply", Some(&second));
+    std::thread::sleep(Duration::from_millis(1100));
+    post("later reply", Some(&reply));
+    let active = get("?threaded=true&sort=activity");
+    assert_eq!(active["threads"][0]["id"], first.as_str());
+    assert_eq!(active["threads"][0]["reply_count"], 3);
+
+    assert_eq!(
+        client
+            .get(format!("{}?sort=bogus", comments_url))
+            .dispatch()
+            .status(),
+        Status::BadRequest
+    );
+}
+
+#[test]
+fn test_activity_feed() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Activity WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let doc = create_doc(&client, ws_id, key, "Activity Doc", "v1");
+    let doc_id = doc["id"].as_str().unwrap();
+    let other = create_doc(&client, ws_id, key, "Other Doc", "x");
+
+    client
+        .patch(format!("/api/v1/workspaces/{}/docs/{}", ws_id, doc_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "v2", "author_name": "Editor"}"#)
+        .dispatch();
+    let res = client
+        .post(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments",
+            ws_id, doc_id
+        ))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Reviewer", "content": "ok"}"#)
+        .dispatch();
+    let comment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    client
+        .patch(format!(
+            "/api/v1/workspaces/{}/docs/{}/comments/{}",
+            ws_id,
+            doc_id,
+            comment["id"].as_str().unwrap()
+        ))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"resolved": true}"#)
+        .dispatch();
+    client
+        .post(format!("/api/v1/workspaces/{}/docs/{}/lock", ws_id, doc_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"editor": "Editor"}"#)
+        .dispatch();
+
+    let feed = |query: &str| -> Value {
+        let res = client
+            .get(format!("/api/v1/workspaces/{}/activity{}", ws_id, query))
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+    let events = |page: &Value| -> Vec<String> {
+        page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|i| i["event"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let all = feed("");
+    assert_eq!(
+        events(&all),
+        vec![
+            "lock.acquired",
+            "comment.updated",
+            "comment.created",
+            "document.updated",
+            "document.created",
+            "document.created",
+            "workspace.created",
+        ]
+    );
+    assert_eq!(all["next"], Value::Null);
+    assert_eq!(all["items"][1]["data"]["resolved"], true);
+
+    let doc_only = feed(&format!("?docs={}&types=document.*,comment.*", doc_id));
+    assert_eq!(
+        events(&doc_only),
+        vec![
+            "comment.updated",
+            "comment.created",
+            "document.updated",
+            "document.created"
+        ]
+    );
+    assert_eq!(events(&feed("?actor=reviewer")), vec!["comment.created"]);
+    assert_eq!(
+        feed(&format!("?docs={}", other["id"].as_str().unwrap()))["items"]
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // Page backwards, then poll forwards from the newest entry
+    let page1 = feed("?limit=3");
+    assert_eq!(page1["items"].as_array().unwrap().len(), 3);
+    let before = page1["next"]["before"].as_i64().unwrap();
+    let page2 = feed(&format!("?limit=3&before={}", before));
+    assert_eq!(
+        events(&page2),
+        vec!["document.updated", "document.created", "document.created"]
+    );
+    let newest = page1["items"][0]["seq"].as_i64().unwrap();
+    assert_eq!(
+        feed(&format!("?order=asc&after={}", newest))["items"]
+            .as_array()
+            .unwrap()
+            .len(),
+        0
+    );
+    let oldest_first = feed("?order=asc&limit=1");
+    assert_eq!(events(&oldest_first), vec!["workspace.created"]);
+    assert!(oldest_first["next"]["after"].is_i64());
+
+    // Drafts only show up with the manage key
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/docs", ws_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Secret Plan", "content": "draft", "status": "draft"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    assert_eq!(events(&feed("?limit=1")), vec!["lock.acquired"]);
+    let with_key = feed(&format!("?limit=1&key={}", key));
+    assert_eq!(events(&with_key), vec!["document.created"]);
+    assert_eq!(with_key["items"][0]["data"]["slug"], "secret-plan");
+
+    let res = client.get("/api/v1/workspaces/nope/activity").dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+#[test]
+fn test_templates() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Template WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let templates_url = format!("/api/v1/workspaces/{}/templates", ws_id);
+    let docs_url = format!("/api/v1/workspaces/{}/docs", ws_id);
+    let json_of = |res: rocket::local::blocking::LocalResponse| -> Value {
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    // Built-ins are listed with their variables
+    let list = json_of(client.get(&templates_url).dispatch());
+    let adr = list["templates"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["id"] == "builtin:adr")
+        .unwrap()
+        .clone();
+    assert_eq!(adr["builtin"], true);
+    let required: Vec<&str> = adr["variables"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|v| v["required"] == true)
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(required, vec!["number", "decision"]);
+
+    // Required variables are enforced
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"from_template": "adr", "variables": {"number": 7}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    let err = json_of(res);
+    assert_eq!(err["code"], "MISSING_VARIABLES");
+    assert_eq!(err["missing"], serde_json::json!(["decision"]));
+
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(
+            r#"{"from_template": "adr", "author_name": "Planner",
+                "variables": {"number": 7, "decision": "Use SQLite"}}"#,
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let doc = json_of(res);
+    assert_eq!(doc["title"], "ADR-7: Use SQLite");
+    assert_eq!(doc["from_template"], "adr");
+    let doc = json_of(
+        client
+            .get(format!("{}/{}", docs_url, doc["slug"].as_str().unwrap()))
+            .dispatch(),
+    );
+    let content = doc["content"].as_str().unwrap();
+    assert!(content.starts_with("# ADR-7: Use SQLite\n"));
+    assert!(content.contains("**Status:** Proposed"));
+    assert!(content.contains("**Author:** Planner"));
+    assert_eq!(doc["tags"], serde_json::json!(["adr"]));
+
+    // Workspace templates shadow built-ins with the same slug
+    let res = client
+        .post(&templates_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(
+            r#"{"name": "Run Log", "slug": "run-log", "title": "{{agent}} run",
+                "content": "Agent {{agent}}: {{notes|no notes}}",
+                "variables": [{"name": "agent", "description": "Who ran"}]}"#,
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let custom = json_of(res);
+    assert_eq!(custom["builtin"], false);
+    let custom_url = format!("{}/{}", templates_url, custom["id"].as_str().unwrap());
+
+    let preview = |reference: &str| {
+        json_of(
+            client
+                .post(format!("{}/{}/render", templates_url, reference))
+                .header(ContentType::JSON)
+                .body(r#"{"variables": {"agent": "bot-1", "task": "index"}}"#)
+                .dispatch(),
+        )
+    };
+    assert_eq!(preview("run-log")["content"], "Agent bot-1: no notes");
+    assert_eq!(preview("run-log")["title"], "bot-1 run");
+    assert!(preview("builtin:run-log")["content"]
+        .as_str()
+        .unwrap()
+        .contains("## Goal\n\nindex"));
+
+    // Built-ins are read-only; workspace templates can be edited and deleted
+    let res = client
+        .patch(format!("{}/builtin:adr", templates_url))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "nope"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Forbidden);
+    let res = client
+        .patch(&custom_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "Agent {{agent}} finished"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(preview("run-log")["content"], "Agent bot-1 finished");
+
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"from_template": "run-log", "content": "both"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"from_template": "nope"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+
+    let res = client.delete(&custom_url).header(auth()).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client.get(&custom_url).dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+}
+
+#[test]
+fn test_front_matter_metadata() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Metadata WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let docs_url = format!("/api/v1/workspaces/{}/docs", ws_id);
+    let json_of = |res: rocket::local::blocking::LocalResponse| -> Value {
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    let high = create_doc(
+        &client,
+        ws_id,
+        key,
+        "Fix login",
+        "---\\nowner: alice\\npriority: high\\nlabels: [auth, urgent]\\nticket: 42\\n---\\n# Fix login\\n\\nSoon.",
+    );
+    assert_eq!(high["metadata"]["owner"], "alice");
+    assert_eq!(high["word_count"], 4);
+    create_doc(
+        &client,
+        ws_id,
+        key,
+        "Tidy docs",
+        "---\\npriority: low\\n---\\nLater.",
+    );
+    create_doc(&client, ws_id, key, "Plain", "No front matter here.");
+
+    // Front matter is kept in content but not rendered
+    let doc = json_of(client.get(format!("{}/fix-login", docs_url)).dispatch());
+    assert_eq!(doc["metadata"]["priority"], "high");
+    assert_eq!(doc["metadata"]["ticket"], 42);
+    assert!(doc["content"].as_str().unwrap().starts_with("---\nowner"));
+    let html = doc["content_html"].as_str().unwrap();
+    assert!(html.starts_with("<h1 id=\"fix-login\">Fix login</h1>"));
+    assert!(!html.contains("alice"));
+
+    // Filter the list and search results
+    let slugs = |docs: &Value| -> Vec<String> {
+        docs.as_array()
+            .unwrap()
+            .iter()
+            .map(|d| d["slug"].as_str().unwrap().to_string())
+            .collect()
+    };
+    let list = json_of(
+        client
+            .get(format!("{}?meta.priority=HIGH", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(slugs(&list), vec!["fix-login"]);
+    let list = json_of(
+        client
+            .get(format!("{}?meta.labels=urgent&meta.ticket=42", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(slugs(&list), vec!["fix-login"]);
+    let list = json_of(
+        client
+            .get(format!("{}?meta.owner=bob", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(slugs(&list), Vec::<String>::new());
+    let found = json_of(
+        client
+            .get(format!(
+                "/api/v1/workspaces/{}/search?q=e&meta.priority=low",
+                ws_id
+            ))
+            .dispatch(),
+    );
+    assert_eq!(slugs(&found["results"]), vec!["tidy-docs"]);
+    let res = client.get(format!("{}?meta.a..b=1", docs_url)).dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+    assert_eq!(json_of(res)["code"], "INVALID_FILTER");
+
+    // Invalid front matter is rejected; editing content updates metadata
+    let doc_url = format!("{}/{}", docs_url, high["id"].as_str().unwrap());
+    let res = client
+        .patch(&doc_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "---\n- not\n- a mapping\n---\nBody"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    assert_eq!(json_of(res)["code"], "INVALID_FRONT_MATTER");
+    let res = client
+        .patch(&doc_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "---\npriority: low\n---\nDone."}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let list = json_of(
+        client
+            .get(format!("{}?meta.priority=low", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(slugs(&list).len(), 2);
+    assert_eq!(list[0]["metadata"], serde_json::json!({"priority": "low"}));
+}
+
+#[test]
+fn test_document_types() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Types WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let types_url = format!("/api/v1/workspaces/{}/doc-types", ws_id);
+    let docs_url = format!("/api/v1/workspaces/{}/docs", ws_id);
+    let json_of = |res: rocket::local::blocking::LocalResponse| -> Value {
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    // Schemas that do not compile are refused
+    let res = client
+        .post(&types_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"name": "Broken", "schema": {"type": 12}}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+
+    let res = client
+        .post(&types_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(
+            r#"{
+                "name": "Incident",
+                "schema": {
+                    "type": "object",
+                    "required": ["severity", "owner"],
+                    "properties": {
+                        "severity": {"enum": ["sev1", "sev2", "sev3"]},
+                        "owner": {"type": "string"}
+                    }
+                },
+                "required_headings": ["Timeline"]
+            }"#,
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let incident = json_of(res);
+    assert_eq!(incident["slug"], "incident");
+    let list = json_of(client.get(&types_url).dispatch());
+    assert_eq!(list["doc_types"].as_array().unwrap().len(), 1);
+
+    // Dry run reports every problem
+    let res = client
+        .post(format!("{}/incident/validate", types_url))
+        .header(ContentType::JSON)
+        .body(r##"{"fields": {"severity": "sev9"}, "content": "# Outage"}"##)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let report = json_of(res);
+    assert_eq!(report["valid"], false);
+    let errors = report["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 3);
+    assert!(errors
+        .iter()
+        .any(|e| e["source"] == "fields" && e["path"] == "/severity"));
+    assert!(errors
+        .iter()
+        .any(|e| e["source"] == "content" && e["path"] == "Timeline"));
+
+    // Create is enforced
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r###"{"title": "Outage", "doc_type": "incident", "fields": {"severity": "sev1"}, "content": "## Timeline\n"}"###)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    let err = json_of(res);
+    assert_eq!(err["code"], "DOCUMENT_INVALID");
+    assert_eq!(err["errors"].as_array().unwrap().len(), 1);
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Outage", "doc_type": "nope", "content": ""}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    assert_eq!(json_of(res)["code"], "UNKNOWN_DOC_TYPE");
+    let res = client
+        .post(&docs_url)
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r###"{"title": "Outage", "doc_type": "incident", "fields": {"severity": "sev1", "owner": "ops"}, "content": "## Timeline\n\n- 10:00 paged"}"###)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let doc = json_of(res);
+    let doc_url = format!("{}/{}", docs_url, doc["id"].as_str().unwrap());
+    let fetched = json_of(client.get(format!("{}/outage", docs_url)).dispatch());
+    assert_eq!(fetched["doc_type"], "incident");
+    assert_eq!(fetched["fields"]["owner"], "ops");
+
+    // Updates are checked against stored fields and content
+    let patch = |body: &str| {
+        client
+            .patch(&doc_url)
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(body)
+            .dispatch()
+    };
+    let res = patch(r#"{"content": "No timeline any more"}"#);
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    let res = patch(r#"{"fields": {"severity": "sev4", "owner": "ops"}}"#);
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    let res = patch(r#"{"fields": {"severity": "sev2", "owner": "ops"}}"#);
+    assert_eq!(res.status(), Status::Ok);
+
+    // So are saves that bypass PATCH, such as accepted suggestions
+    let res = client
+        .post(format!("{}/suggestions", doc_url))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Outsider", "content": "No timeline any more"}"#)
+        .dispatch();
+    let suggestion = json_of(res);
+    let suggestion_url = format!(
+        "{}/suggestions/{}",
+        doc_url,
+        suggestion["id"].as_str().unwrap()
+    );
+    let res = client
+        .post(format!("{}/accept", suggestion_url))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"reviewed_by": "Editor"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::UnprocessableEntity);
+    assert_eq!(json_of(res)["code"], "DOCUMENT_INVALID");
+    let pending = json_of(client.get(format!("{}/suggestions", doc_url)).dispatch());
+    assert_eq!(pending[0]["status"], "pending");
+
+    // A type in use cannot be deleted; dropping the type frees it
+    let res = client
+        .delete(format!("{}/incident", types_url))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Conflict);
+    let res = patch(r#"{"doc_type": null, "content": "No timeline any more"}"#);
+    assert_eq!(res.status(), Status::Ok);
+    let res = client
+        .delete(format!("{}/incident", types_url))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[test]
+fn test_wiki_links_and_backlinks() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Links WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let docs_url = format!("/api/v1/workspaces/{}/docs", ws_id);
+    let json_of = |res: rocket::local::blocking::LocalResponse| -> Value {
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    create_doc(
+        &client,
+        ws_id,
+        key,
+        "Guide",
+        "# Guide\\n\\n## Setup\\n\\nInstall it.",
+    );
+    let index = create_doc(
+        &client,
+        ws_id,
+        key,
+        "Index",
+        "Start with [[guide#Setup|setup]], then [[guide]]. Later: [[roadmap]] and [[guide#Nope]]. `[[in-code]]`",
+    );
+
+    // Links render to workspace URLs
+    let doc = json_of(client.get(format!("{}/index", docs_url)).dispatch());
+    let html = doc["content_html"].as_str().unwrap();
+    assert!(html.contains(&format!(
+        "<a class=\"wiki-link\" href=\"/workspace/{}/doc/guide#setup\">setup</a>",
+        ws_id
+    )));
+    assert!(html.contains("<code>[[in-code]]</code>"));
+
+    // ...and the target heading carries the matching id
+    let guide = json_of(client.get(format!("{}/guide", docs_url)).dispatch());
+    assert!(guide["content_html"]
+        .as_str()
+        .unwrap()
+        .contains("<h2 id=\"setup\">Setup</h2>"));
+
+    let backlinks = json_of(
+        client
+            .get(format!("{}/guide/backlinks", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(backlinks["count"], 3);
+    assert_eq!(backlinks["backlinks"][0]["slug"], "index");
+    let res = client
+        .get(format!("{}/missing/backlinks", docs_url))
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+
+    // Missing documents and headings are reported as broken
+    let broken_url = format!("/api/v1/workspaces/{}/links/broken", ws_id);
+    let broken = json_of(client.get(&broken_url).dispatch());
+    let reasons: Vec<(String, String)> = broken["links"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|l| {
+            (
+                l["target"].as_str().unwrap().to_string(),
+                l["reason"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        reasons,
+        vec![
+            ("guide".to_string(), "missing_heading".to_string()),
+            ("roadmap".to_string(), "missing_document".to_string()),
+        ]
+    );
+
+    // Creating the target fixes the link; editing content replaces the link set
+    create_doc(&client, ws_id, key, "Roadmap", "Soon.");
+    let res = client
+        .patch(format!("{}/{}", docs_url, index["id"].as_str().unwrap()))
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", key),
+        ))
+        .body(r#"{"content": "Only [[roadmap]] now"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let broken = json_of(client.get(&broken_url).dispatch());
+    assert_eq!(broken["count"], 0);
+    let backlinks = json_of(
+        client
+            .get(format!("{}/guide/backlinks", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(backlinks["count"], 0);
+    let backlinks = json_of(
+        client
+            .get(format!("{}/roadmap/backlinks", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(backlinks["count"], 1);
+}
+
+#[test]
+fn test_rename_document_slug() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Rename WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let docs_url = format!("/api/v1/workspaces/{}/docs", ws_id);
+    let json_of = |res: rocket::local::blocking::LocalResponse| -> Value {
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    let doc = create_doc(&client, ws_id, key, "Setup Guide", "## Install\\n\\nSteps.");
+    let doc_url = format!("{}/{}", docs_url, doc["id"].as_str().unwrap());
+    create_doc(&client, ws_id, key, "Taken", "x");
+    let linker = create_doc(
+        &client,
+        ws_id,
+        key,
+        "Index",
+        "See [[setup-guide#Install|install]] and [[setup-guide]].",
+    );
+    let rename = |slug: &str| {
+        client
+            .patch(&doc_url)
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(format!(r#"{{"slug": "{}", "author_name": "Ed"}}"#, slug))
+            .dispatch()
+    };
+
+    // Invalid and taken slugs are refused
+    assert_eq!(rename("Not A Slug").status(), Status::BadRequest);
+    let res = rename("taken");
+    assert_eq!(res.status(), Status::Conflict);
+    assert_eq!(json_of(res)["code"], "DUPLICATE_SLUG");
+
+    let res = rename("install-guide");
+    assert_eq!(res.status(), Status::Ok);
+    let body = json_of(res);
+    assert_eq!(body["slug"], "install-guide");
+    assert_eq!(body["links_updated"], 1);
+
+    // The old slug still resolves, flagged as a redirect
+    let old = json_of(client.get(format!("{}/setup-guide", docs_url)).dispatch());
+    assert_eq!(old["slug"], "install-guide");
+    assert_eq!(old["redirected_from"], "setup-guide");
+    let current = json_of(client.get(format!("{}/install-guide", docs_url)).dispatch());
+    assert!(current.get("redirected_from").is_none());
+
+    // HTML views redirect permanently
+    let res = client
+        .get(format!("/workspace/{}/doc/setup-guide", ws_id))
+        .dispatch();
+    assert_eq!(res.status(), Status::MovedPermanently);
+    assert_eq!(
+        res.headers().get_one("Location").unwrap(),
+        format!("/workspace/{}/doc/install-guide", ws_id)
+    );
+    let res = client
+        .get(format!("/workspace/{}/doc/install-guide", ws_id))
+        .dispatch();
+    assert_ne!(res.status(), Status::MovedPermanently);
+
+    // Links to the old slug were rewritten
+    let index = json_of(client.get(format!("{}/index", docs_url)).dispatch());
+    assert_eq!(
+        index["content"],
+        "See [[install-guide#Install|install]] and [[install-guide]]."
+    );
+    assert_eq!(index["id"], linker["id"]);
+    let backlinks = json_of(
+        client
+            .get(format!("{}/install-guide/backlinks", docs_url))
+            .dispatch(),
+    );
+    assert_eq!(backlinks["count"], 2);
+
+    // The rename is announced
+    let feed = json_of(
+        client
+            .get(format!(
+                "/api/v1/workspaces/{}/activity?types=document.renamed",
+                ws_id
+            ))
+            .dispatch(),
+    );
+    let item = &feed["items"][0];
+    assert_eq!(item["data"]["from"], "setup-guide");
+    assert_eq!(item["data"]["to"], "install-guide");
+
+    // A new document can claim the old slug
+    create_doc(&client, ws_id, key, "Setup Guide", "New one");
+    let reclaimed = json_of(client.get(format!("{}/setup-guide", docs_url)).dispatch());
+    assert_ne!(reclaimed["id"], doc["id"]);
+    assert!(reclaimed.get("redirected_from").is_none());
+}
+
+#[test]
+fn test_document_routes_resolve_ids_and_slugs_within_workspace() {
+    let client = test_client();
+    let ws_a = create_workspace(&client, "Resolve A");
+    let ws_b = create_workspace(&client, "Resolve B");
+    let (a_id, a_key) = (
+        ws_a["id"].as_str().unwrap(),
+        ws_a["manage_key"].as_str().unwrap(),
+    );
+    let (b_id, b_key) = (
+        ws_b["id"].as_str().unwrap(),
+        ws_b["manage_key"].as_str().unwrap(),
+    );
+    let auth = rocket::http::Header::new("Authorization", format!("Bearer {}", a_key));
+
+    create_doc(&client, a_id, a_key, "Runbook", "Restart it.");
+    let other = create_doc(&client, b_id, b_key, "Secret Plan", "Top secret.");
+    let other_id = other["id"].as_str().unwrap();
+
+    // Workspace A's key cannot reach B's document through A's routes, by id or slug
+    for target in [other_id, "secret-plan"] {
+        let base = format!("/api/v1/workspaces/{}/docs/{}", a_id, target);
+        let responses = [
+            client.get(base.clone()).dispatch(),
+            client
+                .patch(base.clone())
+                .header(ContentType::JSON)
+                .header(auth.clone())
+                .body(r#"{"content": "Leaked"}"#)
+                .dispatch(),
+            client.get(format!("{}/versions", base)).dispatch(),
+            client.get(format!("{}/versions/1", base)).dispatch(),
+            client.get(format!("{}/diff?from=1&to=1", base)).dispatch(),
+            client
+                .post(format!("{}/versions/1/restore", base))
+                .header(auth.clone())
+                .dispatch(),
+            client
+                .post(format!("{}/lock", base))
+                .header(ContentType::JSON)
+                .header(auth.clone())
+                .body(r#"{"editor": "mallory"}"#)
+                .dispatch(),
+            client
+                .delete(format!("{}/lock", base))
+                .header(auth.clone())
+                .dispatch(),
+            client.get(format!("{}/comments", base)).dispatch(),
+            client.get(format!("{}/backlinks", base)).dispatch(),
+            client.delete(base.clone()).header(auth.clone()).dispatch(),
+        ];
+        for res in responses {
+            assert_eq!(res.status(), Status::NotFound);
+        }
+    }
+    let res = client
+        .get(format!("/api/v1/workspaces/{}/docs/{}", b_id, other_id))
+        .dispatch();
+    let untouched: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(untouched["content"], "Top secret.");
+
+    // Within its own workspace a document answers to its slug as well as its id
+    let base = format!("/api/v1/workspaces/{}/docs/runbook", a_id);
+    let res = client
+        .patch(base.clone())
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"content": "Restart it twice."}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client.get(format!("{}/versions", base)).dispatch();
+    let versions: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(versions.as_array().unwrap().len(), 2);
+    let res = client.get(format!("{}/diff?from=1&to=2", base)).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client
+        .post(format!("{}/lock", base))
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"editor": "ed"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    // Old slugs keep resolving after a rename
+    let res = client
+        .patch(base.clone())
+        .header(ContentType::JSON)
+        .header(auth.clone())
+        .body(r#"{"slug": "ops-runbook"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client.get(format!("{}/versions/2", base)).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let res = client.delete(base).header(auth).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+}
+
+#[test]
+fn test_attachments() {
+    let blob_dir = std::env::temp_dir().join(format!("attachments-{}", uuid::Uuid::new_v4()));
+    let db = agent_docs::db::Db::new(":memory:");
+    let store = agent_docs::attachments::BlobStore::new(&blob_dir, 1024);
+    let client = Client::tracked(agent_docs::build_rocket_with_blobs(db, store)).unwrap();
+    let ws = create_workspace(&client, "Attach WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let base = format!("/api/v1/workspaces/{}/attachments", ws_id);
+    let json_of = |res: rocket::local::blocking::LocalResponse| -> Value {
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+    let png = b"\x89PNG\r\n\x1a\n fake image data".to_vec();
+
+    // Raw body upload; the type comes from the bytes, not the header
+    let res = client
+        .post(format!("{}?filename=diagram.png&uploaded_by=Ana", base))
+        .header(ContentType::Plain)
+        .header(auth())
+        .body(png.clone())
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let image = json_of(res);
+    let image_id = image["id"].as_str().unwrap().to_string();
+    assert_eq!(image["content_type"], "image/png");
+    assert_eq!(image["size"], png.len());
+    assert_eq!(image["uploaded_by"], "Ana");
+    assert_eq!(
+        image["markdown"],
+        format!("![diagram.png](attachment:{})", image_id)
+    );
+
+    // The same bytes again return the existing attachment
+    let res = client
+        .post(format!("{}?filename=copy.png", base))
+        .header(auth())
+        .body(png.clone())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(json_of(res)["id"], image_id.as_str());
+
+    // Multipart upload; HTML is stored as plain text and paths are stripped
+    let boundary = "XBOUNDARYX";
+    let multipart = |filename: &str, content_type: &str, body: &str| {
+        format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\n\
+             Content-Type: {t}\r\n\r\n{body}\r\n--{b}\r\n\
+             Content-Disposition: form-data; name=\"uploaded_by\"\r\n\r\nBo\r\n--{b}--\r\n",
+            b = boundary,
+            f = filename,
+            t = content_type,
+            body = body
+        )
+    };
+    let form_type = ContentType::new("multipart", "form-data").with_params(("boundary", boundary));
+    let res = client
+        .post(&base)
+        .header(form_type.clone())
+        .header(auth())
+        .body(multipart(
+            "../notes.html",
+            "text/html",
+            "<script>alert(1)</script>",
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let notes = json_of(res);
+    let notes_id = notes["id"].as_str().unwrap().to_string();
+    assert_eq!(notes["filename"], "notes.html");
+    assert_eq!(notes["content_type"], "text/plain");
+    assert_eq!(notes["uploaded_by"], "Bo");
+
+    // Size limits, empty bodies and auth
+    let res = client
+        .post(format!("{}?filename=big.bin", base))
+        .header(auth())
+        .body(vec![7u8; 2000])
+        .dispatch();
+    assert_eq!(res.status(), Status::PayloadTooLarge);
+    assert_eq!(json_of(res)["code"], "ATTACHMENT_TOO_LARGE");
+    let res = client
+        .post(&base)
+        .header(form_type)
+        .header(auth())
+        .body(multipart("big.txt", "text/plain", &"x".repeat(2000)))
+        .dispatch();
+    assert_eq!(res.status(), Status::PayloadTooLarge);
+    let res = client.post(&base).header(auth()).body("").dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+    let res = client.post(&base).body(png.clone()).dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+
+    // Served with the stored type; other workspaces cannot reach it
+    let res = client.get(format!("{}/{}", base, image_id)).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::PNG));
+    assert_eq!(
+        res.headers().get_one("X-Content-Type-Options"),
+        Some("nosniff")
+    );
+    assert!(res.headers().get_one("ETag").is_some());
+    assert_eq!(res.into_bytes().unwrap(), png);
+    let other = create_workspace(&client, "Other WS");
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/attachments/{}",
+            other["id"].as_str().unwrap(),
+            image_id
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+
+    // Markdown references render as served URLs
+    let doc = create_doc(
+        &client,
+        ws_id,
+        key,
+        "Architecture",
+        &format!("![diagram](attachment:{})", image_id),
+    );
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/docs/{}",
+            ws_id,
+            doc["slug"].as_str().unwrap()
+        ))
+        .dispatch();
+    let html = json_of(res)["content_html"].as_str().unwrap().to_string();
+    assert!(
+        html.contains(&format!("src=\"{}/{}\"", base, image_id)),
+        "{}",
+        html
+    );
+
+    // In-use attachments are protected from plain deletes
+    let res = client
+        .delete(format!("{}/{}", base, image_id))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Conflict);
+    assert_eq!(json_of(res)["code"], "ATTACHMENT_IN_USE");
+
+    let listed = json_of(client.get(&base).header(auth()).dispatch());
+    assert_eq!(listed["count"], 2);
+
+    // GC collects the unreferenced upload and its blob; dry runs change nothing
+    let gc = |dry_run: bool| {
+        json_of(
+            client
+                .post(format!("{}/gc?min_age_minutes=0&dry_run={}", base, dry_run))
+                .header(auth())
+                .dispatch(),
+        )
+    };
+    let preview = gc(true);
+    assert_eq!(preview["attachments_removed"].as_array().unwrap().len(), 1);
+    assert_eq!(preview["attachments_removed"][0]["id"], notes_id.as_str());
+    assert_eq!(preview["blobs_removed"], 1);
+    let res = client.get(format!("{}/{}", base, notes_id)).dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let collected = gc(false);
+    assert_eq!(collected["blobs_removed"], 1);
+    let res = client.get(format!("{}/{}", base, notes_id)).dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+    assert_eq!(gc(false)["blobs_removed"], 0);
+
+    let res = client
+        .delete(format!("{}/{}?force=true", base, image_id))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(json_of(res)["blob_deleted"], true);
+    let res = client.get(format!("{}/{}", base, image_id)).dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+
+    std::fs::remove_dir_all(blob_dir).ok();
+}
+
+#[test]
+fn test_export_workspace_zip() {
+    let blob_dir = std::env::temp_dir().join(format!("export-{}", uuid::Uuid::new_v4()));
+    let db = agent_docs::db::Db::new(":memory:");
+    let store = agent_docs::attachments::BlobStore::new(&blob_dir, 1024 * 1024);
+    let client = Client::tracked(agent_docs::build_rocket_with_blobs(db, store)).unwrap();
+    let ws = create_workspace(&client, "Export WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let base = format!("/api/v1/workspaces/{}", ws_id);
+
+    let res = client
+        .post(format!("{}/attachments?filename=logo.png", base))
+        .header(auth())
+        .body(b"\x89PNG\r\n\x1a\nlogo")
+        .dispatch();
+    let attachment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let attachment_id = attachment["id"].as_str().unwrap();
+    let res = client
+        .post(format!("{}/docs", base))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(
+            serde_json::json!({
+                "title": "Runbook",
+                "content": format!("---\nowner: ops\n---\n# Runbook\n\n![logo](attachment:{})\n", attachment_id),
+                "tags": ["ops", "oncall"],
+                "status": "published",
+                "author_name": "Ana",
+            })
+            .to_string(),
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let runbook: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let runbook_id = runbook["id"].as_str().unwrap();
+    client
+        .patch(format!("{}/docs/{}", base, runbook_id))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"content": "---\nowner: ops\n---\n# Runbook v2\n", "author_name": "Bo"}"#)
+        .dispatch();
+    client
+        .post(format!("{}/docs/{}/comments", base, runbook_id))
+        .header(ContentType::JSON)
+        .body(r#"{"author_name": "Cy", "content": "Looks good"}"#)
+        .dispatch();
+    client
+        .post(format!("{}/docs", base))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Draft Notes", "content": "wip", "status": "draft"}"#)
+        .dispatch();
+
+    // Manage key required; only zip is supported
+    let res = client.get(format!("{}/export", base)).dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res = client
+        .get(format!("{}/export?format=tar", base))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::BadRequest);
+
+    let res = client
+        .get(format!(
+            "{}/export?format=zip&versions=true&comments=true",
+            base
+        ))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::ZIP));
+    assert!(res
+        .headers()
+        .get_one("Content-Disposition")
+        .unwrap()
+        .contains("export-ws-"));
+    let bytes = res.into_bytes().unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut read = |name: &str| {
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap_or_else(|_| panic!("missing {}", name))
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    };
+
+    let manifest: Value = serde_json::from_str(&read("workspace.json")).unwrap();
+    assert_eq!(manifest["workspace"]["name"], "Export WS");
+    assert_eq!(manifest["documents"], 2);
+
+    let runbook_md = read("docs/runbook.md");
+    assert!(runbook_md.starts_with("---\n"));
+    assert!(
+        runbook_md.ends_with("---\n# Runbook v2\n"),
+        "{}",
+        runbook_md
+    );
+    assert!(runbook_md.contains("owner: ops"));
+    assert!(runbook_md.contains("title: Runbook"));
+    assert!(runbook_md.contains("- oncall"));
+    assert!(runbook_md.contains("status: published"));
+    assert!(runbook_md.contains("author_name: Ana"));
+    assert!(read("docs/draft-notes.md").contains("status: draft"));
+
+    let versions: Value = serde_json::from_str(&read("docs/runbook.versions.json")).unwrap();
+    assert_eq!(versions.as_array().unwrap().len(), 2);
+    assert_eq!(versions[0]["version_number"], 1);
+    assert!(versions[0]["content"]
+        .as_str()
+        .unwrap()
+        .contains("attachment:"));
+    let comments: Value = serde_json::from_str(&read("docs/runbook.comments.json")).unwrap();
+    assert_eq!(comments[0]["content"], "Looks good");
+
+    let attachments: Value = serde_json::from_str(&read("attachments.json")).unwrap();
+    let path = attachments[0]["path"].as_str().unwrap().to_string();
+    assert_eq!(path, format!("attachments/{}/logo.png", attachment_id));
+    let mut logo = Vec::new();
+    archive
+        .by_name(&path)
+        .unwrap()
+        .read_to_end(&mut logo)
+        .unwrap();
+    assert_eq!(logo, b"\x89PNG\r\n\x1a\nlogo");
+
+    // Sidecars and attachments are optional
+    let res = client
+        .get(format!("{}/export?attachments=false", base))
+        .header(auth())
+        .dispatch();
+    let archive = zip::ZipArchive::new(std::io::Cursor::new(res.into_bytes().unwrap())).unwrap();
+    let mut names: Vec<&str> = archive.file_names().collect();
+    names.sort();
+    assert_eq!(
+        names,
+        vec!["docs/draft-notes.md", "docs/runbook.md", "workspace.json"]
+    );
+
+    std::fs::remove_dir_all(blob_dir).ok();
+}
+
+fn zip_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    for (path, bytes) in files {
+        zip.start_file(*path, zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(bytes).unwrap();
+    }
+    zip.finish().unwrap().into_inner()
+}
+
+#[test]
+fn test_import_markdown_zip() {
+    let blob_dir = std::env::temp_dir().join(format!("import-{}", uuid::Uuid::new_v4()));
+    let db = agent_docs::db::Db::new(":memory:");
+    let store = agent_docs::attachments::BlobStore::new(&blob_dir, 1024 * 1024);
+    let client = Client::tracked(agent_docs::build_rocket_with_blobs(db, store)).unwrap();
+    let ws = create_workspace(&client, "Vault");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let base = format!("/api/v1/workspaces/{}", ws_id);
+    let import = |query: &str, bytes: Vec<u8>| -> (Status, Value) {
+        let res = client
+            .post(format!("{}/import{}", base, query))
+            .header(auth())
+            .body(bytes)
+            .dispatch();
+        let status = res.status();
+        (
+            status,
+            serde_json::from_str(&res.into_string().unwrap()).unwrap(),
+        )
+    };
+    let result_of = |report: &Value, path: &str| -> Value {
+        report["files"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|f| f["path"] == path)
+            .cloned()
+            .unwrap_or_else(|| panic!("no result for {}", path))
+    };
+
+    let vault = zip_of(&[
+        (
+            "vault/Home.md",
+            b"---\ntags: start, overview\nstatus: published\n---\n# Welcome\n\nRead [the setup](guides/setup.md#install) and [old](gone.md).\n\n![logo](img/logo.png)\n",
+        ),
+        ("vault/guides/setup.md", b"# Setup\n\nBack to [[Home]]. ![[img/logo.png]]\n"),
+        ("vault/guides/home.md", b"Guides overview"),
+        ("vault/img/logo.png", b"\x89PNG\r\n\x1a\nlogo"),
+        ("vault/notes.txt", b"not referenced"),
+        ("vault/.obsidian/app.json", b"{}"),
+        ("vault/broken.md", b"---\ntitle: [unclosed\n---\n"),
+    ]);
+
+    // Auth, archive and option checks
+    let res = client
+        .post(format!("{}/import", base))
+        .body(vault.clone())
+        .dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let (status, body) = import("", b"not a zip".to_vec());
+    assert_eq!(status, Status::BadRequest);
+    assert_eq!(body["code"], "INVALID_ARCHIVE");
+    let (status, _) = import("?on_conflict=merge", vault.clone());
+    assert_eq!(status, Status::BadRequest);
+
+    // Dry run reports everything and writes nothing
+    let (status, report) = import("?dry_run=true", vault.clone());
+    assert_eq!(status, Status::Ok);
+    assert_eq!(report["dry_run"], true);
+    assert_eq!(report["summary"]["created"], 3);
+    assert_eq!(report["summary"]["failed"], 1);
+    assert_eq!(report["summary"]["attached"], 1);
+    assert_eq!(report["summary"]["ignored"], 2);
+    assert_eq!(
+        result_of(&report, "broken.md")["code"],
+        "INVALID_FRONT_MATTER"
+    );
+    assert_eq!(result_of(&report, "guides/home.md")["slug"], "guides-home");
+    let docs = client.get(format!("{}/docs?key={}", base, key)).dispatch();
+    let docs: Value = serde_json::from_str(&docs.into_string().unwrap()).unwrap();
+    assert_eq!(docs.as_array().unwrap().len(), 0);
+    let res = client
+        .get(format!("{}/attachments", base))
+        .header(auth())
+        .dispatch();
+    let listed: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(listed["attachments"].as_array().unwrap().len(), 0);
+
+    // The real import
+    let (status, report) = import("?author_name=Migrator", vault.clone());
+    assert_eq!(status, Status::Ok);
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(report["summary"]["created"], 3);
+    let home = result_of(&report, "Home.md");
+    assert_eq!(home["result"], "created");
+    assert_eq!(home["title"], "Welcome");
+    assert_eq!(home["links_converted"], 1);
+    assert_eq!(home["unresolved_links"], serde_json::json!(["gone.md"]));
+    let logo_id = result_of(&report, "img/logo.png")["id"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert_eq!(result_of(&report, "notes.txt")["result"], "ignored");
+
+    let res = client.get(format!("{}/docs/home", base)).dispatch();
+    let doc: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(doc["status"], "published");
+    assert_eq!(doc["tags"], serde_json::json!(["start", "overview"]));
+    assert_eq!(doc["author_name"], "Migrator");
+    let content = doc["content"].as_str().unwrap();
+    assert!(
+        content.contains("[[setup#install|the setup]]"),
+        "{}",
+        content
+    );
+    assert!(content.contains(&format!("![logo](attachment:{})", logo_id)));
+    let res = client
+        .get(format!("{}/docs/setup?key={}", base, key))
+        .dispatch();
+    let setup: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(setup["status"], "draft");
+    assert_eq!(setup["metadata"]["folder"], "guides");
+    let setup_content = setup["content"].as_str().unwrap();
+    assert!(setup_content.contains("Back to [[home]]."));
+    assert!(setup_content.contains(&format!("![img/logo.png](attachment:{})", logo_id)));
+    let res = client
+        .get(format!("{}/docs/setup/backlinks?key={}", base, key))
+        .dispatch();
+    let backlinks: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(backlinks["count"], 1);
+    let res = client
+        .get(format!("{}/docs?key={}&meta.folder=guides", base, key))
+        .dispatch();
+    let in_folder: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(in_folder.as_array().unwrap().len(), 2);
+
+    // Existing slugs: skipped by default, updated or renamed on request
+    let (_, report) = import("", vault.clone());
+    assert_eq!(report["summary"]["skipped"], 3);
+    assert_eq!(result_of(&report, "Home.md")["code"], "DUPLICATE_SLUG");
+    let changed = zip_of(&[
+        (
+            "Home.md",
+            b"---\nstatus: published\n---\n# Welcome back\n".as_slice(),
+        ),
+        (
+            "guides/setup.md",
+            b"# Setup\n\nBack to [[Home]]. ![[img/logo.png]]\n",
+        ),
+        ("img/logo.png", b"\x89PNG\r\n\x1a\nlogo"),
+    ]);
+    let (_, report) = import("?on_conflict=update", changed.clone());
+    assert_eq!(result_of(&report, "Home.md")["result"], "updated");
+    assert_eq!(result_of(&report, "guides/setup.md")["result"], "unchanged");
+    assert_eq!(result_of(&report, "img/logo.png")["id"], logo_id.as_str());
+    let res = client
+        .get(format!("{}/docs/home/versions", base))
+        .dispatch();
+    let versions: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(versions.as_array().unwrap().len(), 2);
+    let (_, report) = import("?on_conflict=rename", changed);
+    assert_eq!(result_of(&report, "Home.md")["slug"], "home-2");
+    assert_eq!(result_of(&report, "guides/setup.md")["slug"], "setup-2");
+
+    // Updates go through the same checks as PATCH, dry run included
+    let res = client
+        .put(format!("{}/workflow", base))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"preset": "default"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let restatused = |status: &str| {
+        let home = format!("---\nstatus: {}\n---\n# Welcome back\n", status);
+        zip_of(&[("Home.md", home.as_bytes())])
+    };
+    let (_, report) = import("?on_conflict=update&dry_run=true", restatused("approved"));
+    let home = result_of(&report, "Home.md");
+    assert_eq!(home["result"], "failed");
+    assert_eq!(home["code"], "INVALID_TRANSITION");
+    let (_, report) = import("?on_conflict=update", restatused("draft"));
+    let home = result_of(&report, "Home.md");
+    assert_eq!(home["result"], "updated");
+    assert_eq!(
+        home["status_change"],
+        serde_json::json!({"from": "published", "to": "draft"})
+    );
+    let res = client
+        .get(format!("{}/docs/home?key={}", base, key))
+        .dispatch();
+    let doc: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(doc["status"], "draft");
+    let (_, report) = import("?on_conflict=update", restatused("draft"));
+    assert_eq!(result_of(&report, "Home.md")["result"], "unchanged");
+
+    // An export imports into another workspace with its attachments
+    let res = client
+        .get(format!("{}/export", base))
+        .header(auth())
+        .dispatch();
+    let archive = res.into_bytes().unwrap();
+    let other = create_workspace(&client, "Copy");
+    let other_id = other["id"].as_str().unwrap();
+    let other_key = other["manage_key"].as_str().unwrap();
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/import", other_id))
+        .header(rocket::http::Header::new(
+            "X-API-Key",
+            other_key.to_string(),
+        ))
+        .body(archive)
+        .dispatch();
+    let report: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(report["summary"]["created"], 5);
+    assert_eq!(report["summary"]["failed"], 0);
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/docs/setup?key={}",
+            other_id, other_key
+        ))
+        .dispatch();
+    let copied: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    assert_eq!(copied["metadata"]["folder"], "guides");
+    let new_logo = agent_docs::attachments::references(copied["content"].as_str().unwrap());
+    assert_eq!(new_logo.len(), 1);
+    assert_ne!(new_logo[0], logo_id);
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/attachments/{}",
+            other_id, new_logo[0]
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+
+    std::fs::remove_dir_all(blob_dir).ok();
+}
+
+// Clone the mirror, write `files`, commit as `author` and push back to main
+fn push_to_mirror(repo_path: &str, files: &[(&str, &str)], author: &str, message: &str) {
+    let workdir = std::env::temp_dir().join(format!("clone-{}", uuid::Uuid::new_v4()));
+    let repo = git2::Repository::clone(repo_path, &workdir).unwrap();
+    for (path, content) in files {
+        std::fs::write(workdir.join(path), content).unwrap();
+    }
+    let mut index = repo.index().unwrap();
+    index
+        .add_all(["*"], git2::IndexAddOption::DEFAULT, None)
+        .unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let parent = repo.head().unwrap().peel_to_commit().unwrap();
+    let sig = git2::Signature::now(author, "dev@example.com").unwrap();
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &[&parent])
+        .unwrap();
+    repo.find_remote("origin")
+        .unwrap()
+        .push(&["refs/heads/main:refs/heads/main"], None)
+        .unwrap();
+    std::fs::remove_dir_all(workdir).ok();
+}
+
+#[test]
+fn test_git_mirror() {
+    let dir = std::env::temp_dir().join(format!("mirror-{}", uuid::Uuid::new_v4()));
+    let db = agent_docs::db::Db::new(":memory:");
+    let blobs = agent_docs::attachments::BlobStore::new(dir.join("blobs"), 1024);
+    let mirrors = agent_docs::gitmirror::GitMirrors::new(dir.join("git"));
+    let client =
+        Client::tracked(agent_docs::build_rocket_with_storage(db, blobs, mirrors)).unwrap();
+    let ws = create_workspace(&client, "Mirrored");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let base = format!("/api/v1/workspaces/{}", ws_id);
+    let get_json = |path: String| -> (Status, Value) {
+        let res = client.get(path).header(auth()).dispatch();
+        let status = res.status();
+        (
+            status,
+            serde_json::from_str(&res.into_string().unwrap()).unwrap(),
+        )
+    };
+    let patch_doc = |slug: &str, body: Value| {
+        let res = client
+            .patch(format!("{}/docs/{}", base, slug))
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(body.to_string())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+    };
+    let sync = |query: &str| -> Value {
+        let res = client
+            .post(format!("{}/git/sync{}", base, query))
+            .header(auth())
+            .dispatch();
+        assert_eq!(res.status(), Status::Ok);
+        serde_json::from_str(&res.into_string().unwrap()).unwrap()
+    };
+
+    let res = client
+        .post(format!("{}/docs", base))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r##"{"title": "Runbook", "content": "# Runbook\n", "author_name": "Ana"}"##)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    patch_doc(
+        "runbook",
+        serde_json::json!({"content": "# Runbook\n\n1. Page\n", "author_name": "Bo", "change_description": "Add steps"}),
+    );
+
+    let (status, _) = get_json(format!("{}/git", base));
+    assert_eq!(status, Status::NotFound);
+    let res = client.put(format!("{}/git", base)).dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res = client
+        .put(format!("{}/git", base))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let mirror: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+    let repo_path = mirror["path"].as_str().unwrap().to_string();
+    assert_eq!(mirror["commits"], 2);
+
+    // Every version is a commit by its author with its change description
+    let repo = git2::Repository::open_bare(&repo_path).unwrap();
+    let tip = repo
+        .find_reference("refs/heads/main")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    assert_eq!(tip.id().to_string(), mirror["head"].as_str().unwrap());
+    assert_eq!(tip.author().name(), Some("Bo"));
+    assert_eq!(tip.message(), Some("Add steps"));
+    let first = tip.parent(0).unwrap();
+    assert_eq!(first.author().name(), Some("Ana"));
+    assert_eq!(first.message(), Some("Initial version"));
+    let blob = tip.tree().unwrap().get_name("runbook.md").unwrap().id();
+    assert_eq!(
+        repo.find_blob(blob).unwrap().content(),
+        b"# Runbook\n\n1. Page\n"
+    );
+
+    // New versions are mirrored in the background
+    patch_doc(
+        "runbook",
+        serde_json::json!({"content": "# Runbook\n\n1. Page\n2. Fix\n", "author_name": "Cy"}),
+    );
+    let deadline = Instant::now() + Duration::from_secs(5);
+    loop {
+        let (_, status) = get_json(format!("{}/git", base));
+        if status["pending_versions"] == 0 {
+            assert_eq!(status["commits"], 3);
+            break;
+        }
+        assert!(
+            Instant::now() < deadline,
+            "version was not mirrored: {}",
+            status
+        );
+        std::thread::sleep(Duration::from_millis(20));
+    }
+
+    // Pushed commits come back as versions
+    push_to_mirror(
+        &repo_path,
+        &[
+            ("runbook.md", "# Runbook\n\n1. Page\n2. Fix\n3. Verify\n"),
+            ("notes.md", "not a document"),
+        ],
+        "Dee",
+        "Add verification",
+    );
+    let (_, status) = get_json(format!("{}/git", base));
+    assert_eq!(status["pushed_commits"], 1);
+    let preview = sync("?dry_run=true");
+    assert_eq!(preview["summary"]["ingested"], 1);
+    assert_eq!(preview["summary"]["skipped"], 1);
+    let (_, doc) = get_json(format!("{}/docs/runbook", base));
+    assert!(!doc["content"].as_str().unwrap().contains("Verify"));
+
+    let report = sync("");
+    assert_eq!(report["dry_run"], false);
+    assert_eq!(report["summary"]["ingested"], 1);
+    let notes = report["files"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|f| f["path"] == "notes.md")
+        .unwrap();
+    assert_eq!(notes["code"], "UNKNOWN_DOCUMENT");
+    let (_, doc) = get_json(format!("{}/docs/runbook", base));
+    assert_eq!(doc["content"], "# Runbook\n\n1. Page\n2. Fix\n3. Verify\n");
+    let (_, versions) = get_json(format!("{}/docs/runbook/versions", base));
+    assert_eq!(versions[0]["version_number"], 4);
+    assert_eq!(versions[0]["author_name"], "Dee");
+    assert_eq!(versions[0]["change_description"], "Add verification");
+    let report = sync("");
+    assert_eq!(report["commits"], 0);
+    assert_eq!(report["summary"]["committed"], 0);
+
+    // A push based on content the API has since replaced is a conflict
+    push_to_mirror(
+        &repo_path,
+        &[("runbook.md", "# Runbook\n\nfrom git\n")],
+        "Dee",
+        "Rewrite",
+    );
+    patch_doc(
+        "runbook",
+        serde_json::json!({"content": "# Runbook\n\nfrom the API\n", "author_name": "Eve"}),
+    );
+    let report = sync("");
+    assert_eq!(report["summary"]["conflict"], 1);
+    let conflict = &report["files"][0];
+    assert_eq!(conflict["code"], "GIT_CONFLICT");
+    assert_eq!(conflict["path"], "runbook.md");
+    let (_, doc) = get_json(format!("{}/docs/runbook", base));
+    assert_eq!(doc["content"], "# Runbook\n\nfrom the API\n");
+    let tip = repo
+        .find_reference("refs/heads/main")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let blob = tip.tree().unwrap().get_name("runbook.md").unwrap().id();
+    assert_eq!(
+        repo.find_blob(blob).unwrap().content(),
+        b"# Runbook\n\nfrom the API\n"
+    );
+
+    // Renames and deletions are commits too
+    patch_doc("runbook", serde_json::json!({"slug": "on-call"}));
+    let res = client
+        .post(format!("{}/docs", base))
+        .header(ContentType::JSON)
+        .header(auth())
+        .body(r#"{"title": "Scratch", "content": "tmp"}"#)
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let res = client
+        .delete(format!("{}/docs/scratch", base))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    sync("");
+    let tip = repo
+        .find_reference("refs/heads/main")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    let tree = tip.tree().unwrap();
+    assert!(tree.get_name("on-call.md").is_some());
+    assert!(tree.get_name("runbook.md").is_none());
+    assert!(tree.get_name("scratch.md").is_none());
+    assert!(tree.get_name("notes.md").is_some());
+
+    let res = client
+        .delete(format!("{}/git", base))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let (status, _) = get_json(format!("{}/git", base));
+    assert_eq!(status, Status::NotFound);
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_export_static_site() {
+    let dir = std::env::temp_dir().join(format!("site-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db_path = dir.join("docs.db");
+    let db = agent_docs::db::Db::new(db_path.to_str().unwrap());
+    let store = agent_docs::attachments::BlobStore::new(dir.join("blobs"), 1024 * 1024);
+    let client = Client::tracked(agent_docs::build_rocket_with_blobs(db, store)).unwrap();
+    let ws = create_workspace(&client, "Ops Handbook");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let auth = || rocket::http::Header::new("Authorization", format!("Bearer {}", key));
+    let base = format!("/api/v1/workspaces/{}", ws_id);
+    let upload = |filename: &str, body: &[u8]| -> String {
+        let res = client
+            .post(format!("{}/attachments?filename={}", base, filename))
+            .header(auth())
+            .body(body)
+            .dispatch();
+        let attachment: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        attachment["id"].as_str().unwrap().to_string()
+    };
+    let create = |body: Value| {
+        let res = client
+            .post(format!("{}/docs", base))
+            .header(ContentType::JSON)
+            .header(auth())
+            .body(body.to_string())
+            .dispatch();
+        assert_eq!(res.status(), Status::Created);
+    };
+
+    let logo = upload("logo.png", b"\x89PNG\r\n\x1a\nlogo");
+    create(serde_json::json!({
+        "title": "Runbook",
+        "content": format!(
+            "---\nowner: ops\n---\n# Paging\n\nSee [[escalation#Tiers|tiers]] and [[draft-notes]].\n\n![logo](attachment:{})\n",
+            logo
+        ),
+        "summary": "What to do when paged",
+        "tags": ["ops", "On Call"],
+        "status": "published",
+    }));
+    create(serde_json::json!({
+        "title": "Escalation",
+        "content": "## Tiers\n\nPage the **secondary** after 15 minutes.\n",
+        "tags": ["On Call"],
+        "status": "published",
+    }));
+    create(
+        serde_json::json!({"title": "Draft Notes", "content": "secret plans", "status": "draft"}),
+    );
+
+    let res = client
+        .get(format!("{}/export?format=site", base))
+        .dispatch();
+    assert_eq!(res.status(), Status::Unauthorized);
+    let res = client
+        .get(format!("{}/export?format=site", base))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    assert_eq!(res.content_type(), Some(ContentType::ZIP));
+    assert!(res
+        .headers()
+        .get_one("Content-Disposition")
+        .unwrap()
+        .contains("ops-handbook-site-"));
+    let bytes = res.into_bytes().unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut names: Vec<String> = archive.file_names().map(String::from).collect();
+    names.sort();
+    assert_eq!(
+        names,
+        [
+            "assets/search.js",
+            "assets/style.css",
+            &format!("attachments/{}/logo.png", logo),
+            "escalation.html",
+            "index.html",
+            "runbook.html",
+            "search-index.json",
+            "tags/index.html",
+            "tags/on-call.html",
+            "tags/ops.html",
+        ]
+    );
+    let mut read = |name: &str| {
+        let mut text = String::new();
+        archive
+            .by_name(name)
+            .unwrap_or_else(|_| panic!("missing {}", name))
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    };
+
+    // Published documents only, linked to each other relative to the page
+    let runbook = read("runbook.html");
+    assert!(runbook.contains("<h1>Runbook</h1>"));
+    assert!(runbook.contains("href=\"escalation.html#tiers\">tiers</a>"));
+    assert!(runbook.contains("<span class=\"wiki-link missing\">draft-notes</span>"));
+    assert!(runbook.contains(&format!("src=\"attachments/{}/logo.png\"", logo)));
+    assert!(runbook.contains("href=\"tags/on-call.html\">On Call</a>"));
+    assert!(runbook.contains("href=\"runbook.html\" aria-current=\"page\">Runbook</a>"));
+    assert!(!runbook.contains("owner: ops"));
+    let index = read("index.html");
+    assert!(index.contains("<h1>Ops Handbook</h1>"));
+    assert!(index.contains("Runbook</a> — What to do when paged"));
+    assert!(!index.contains("Draft Notes"));
+    let tag = read("tags/on-call.html");
+    assert!(tag.contains("href=\"../escalation.html\""));
+    assert!(tag.contains("href=\"../runbook.html\""));
+    assert!(tag.contains("href=\"../assets/style.css\""));
+    let search: Value = serde_json::from_str(&read("search-index.json")).unwrap();
+    assert_eq!(search.as_array().unwrap().len(), 2);
+    assert_eq!(search[0]["slug"], "escalation");
+    assert_eq!(search[0]["url"], "escalation.html");
+    assert_eq!(
+        search[0]["text"],
+        "Tiers\nPage the secondary after 15 minutes."
+    );
+
+    // A custom template is an uploaded HTML attachment
+    let template = upload(
+        "layout.html",
+        b"<html><title>{{title}} | {{site_name}}</title><body>{{content}}</body></html>",
+    );
+    let res = client
+        .get(format!("{}/export?format=site&template={}", base, template))
+        .header(auth())
+        .dispatch();
+    assert_eq!(res.status(), Status::Ok);
+    let bytes = res.into_bytes().unwrap();
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes)).unwrap();
+    let mut page = String::new();
+    archive
+        .by_name("escalation.html")
+        .unwrap()
+        .read_to_string(&mut page)
+        .unwrap();
+    assert!(page.starts_with("<html><title>Escalation | Ops Handbook</title><body><h1>"));
+    assert!(!page.contains("<nav>"));
+    let bad = upload("bad.html", b"<html>no slot</html>");
+    for id in [bad.as_str(), "missing"] {
+        let res = client
+            .get(format!("{}/export?format=site&template={}", base, id))
+            .header(auth())
+            .dispatch();
+        assert_eq!(res.status(), Status::BadRequest);
+        let body: Value = serde_json::from_str(&res.into_string().unwrap()).unwrap();
+        assert_eq!(body["code"], "INVALID_TEMPLATE");
+    }
+
+    // The same site from the command line
+    let out = dir.join("site");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_agent-docs"))
+        .args(["site", ws_id, out.to_str().unwrap()])
+        .env("DATABASE_PATH", &db_path)
+        .env("ATTACHMENTS_DIR", dir.join("blobs"))
+        .status()
+        .unwrap();
+    assert!(status.success());
+    let page = std::fs::read_to_string(out.join("runbook.html")).unwrap();
+    assert!(page.contains("href=\"escalation.html#tiers\">tiers</a>"));
+    assert!(out.join(format!("attachments/{}/logo.png", logo)).exists());
+    assert!(out.join("tags/ops.html").exists());
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_agent-docs"))
+        .args(["site", ws_id])
+        .env("DATABASE_PATH", &db_path)
+        .status()
+        .unwrap();
+    assert!(!status.success());
+
+    std::fs::remove_dir_all(dir).ok();
+}
+
+#[test]
+fn test_document_content_negotiation() {
+    let client = test_client();
+    let ws = create_workspace(&client, "Negotiation WS");
+    let ws_id = ws["id"].as_str().unwrap();
+    let key = ws["manage_key"].as_str().unwrap();
+    let content = "---\nowner: ops\n---\n# Runbook\n\nRun **`make`** & see [[setup]].\n";
+    let res = client
+        .post(format!("/api/v1/workspaces/{}/docs", ws_id))
+        .header(ContentType::JSON)
+        .header(rocket::http::Header::new(
+            "Authorization",
+            format!("Bearer {}", key),
+        ))
+        .body(
+            serde_json::json!({"title": "Runbook", "content": content, "status": "published"})
+                .to_string(),
+        )
+        .dispatch();
+    assert_eq!(res.status(), Status::Created);
+    let url = format!("/api/v1/workspaces/{}/docs/runbook", ws_id);
+    let get = |query: &str, accept: Option<&str>| {
+        let mut req = client.get(format!("{}{}", url, query));
+        if let Some(accept) = accept {
+            req = req.header(rocket::http::Header::new("Accept", accept.to_string()));
+        }
+        let res = req.dispatch();
+        let status = res.status();
+        let content_type = res
+            .content_type()
+            .map(|c| c.to_string())
+            .unwrap_or_default();
+        assert_eq!(res.headers().get_one("Vary"), Some("Accept"));
+        (status, content_type, res.into_string().unwrap())
+    };
+
+    // JSON by default and for */*
+    for accept in [None, Some("*/*"), Some("application/json")] {
+        let (status, content_type, body) = get("", accept);
+        assert_eq!(status, Status::Ok);
+        assert!(content_type.starts_with("application/json"));
+        let doc: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(doc["content"], content);
+        assert!(doc["content_html"].is_string());
+    }
+
+    let (status, content_type, body) = get("", Some("text/markdown"));
+    assert_eq!(status, Status::Ok);
+    assert!(content_type.starts_with("text/markdown"));
+    assert_eq!(body, content);
+
+    let browser = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+    let (_, content_type, body) = get("", Some(browser));
+    assert!(content_type.starts_with("text/html"));
+    assert!(body.starts_with("<!DOCTYPE html>"));
+    assert!(body.contains("<title>Runbook</title>"));
+    assert!(body.contains("<strong><code>make</code></strong>"));
+    assert!(body.contains(&format!("href=\"/workspace/{}/doc/setup\"", ws_id)));
+
+    let (_, content_type, body) = get("", Some("text/plain"));
+    assert!(content_type.starts_with("text/plain"));
+    assert_eq!(body, "Runbook\nRun make & see setup.");
+
+    // ?format= wins over Accept
+    let (_, content_type, body) = get("?format=md", Some("application/json"));
+    assert!(content_type.starts_with("text/markdown"));
+    assert_eq!(body, content);
+    let (_, content_type, _) = get("?format=json", Some(browser));
+    assert!(content_type.starts_with("application/json"));
+
+    // Field selection on JSON
+    let (status, _, body) = get("?fields=title,slug,word_count", None);
+    assert_eq!(status, Status::Ok);
+    let doc: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(
+        doc,
+        serde_json::json!({"title": "Runbook", "slug": "runbook", "word_count": doc["word_count"]})
+    );
+    assert!(doc["word_count"].is_number());
+    let (_, _, body) = get("?fields=id,redirected_from", None);
+    let doc: Value = serde_json::from_str(&body).unwrap();
+    assert_eq!(doc.as_object().unwrap().len(), 1);
+
+    // Errors stay JSON
+    let (status, content_type, body) = get("?fields=title,nope", None);
+    assert_eq!(status, Status::BadRequest);
+    assert!(content_type.starts_with("application/json"));
+    assert!(body.contains("UNKNOWN_FIELD"));
+    let (status, _, body) = get("?format=pdf", None);
+    assert_eq!(status, Status::BadRequest);
+    assert!(body.contains("UNSUPPORTED_FORMAT"));
+    let (status, _, body) = get("", Some("image/png"));
+    assert_eq!(status, Status::NotAcceptable);
+    assert!(body.contains("NOT_ACCEPTABLE"));
+    let res = client
+        .get(format!(
+            "/api/v1/workspaces/{}/docs/missing?format=markdown",
+            ws_id
+        ))
+        .dispatch();
+    assert_eq!(res.status(), Status::NotFound);
+}